@@ -3,6 +3,202 @@ use anchor_lang::solana_program::pubkey;
 
 pub const PRESALE_SEED: &[u8] = b"presale";
 
-pub const USDC_ADDRESS: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+pub const BUYER_SEED: &[u8] = b"buyer";
 
-pub const USDT_ADDRESS: Pubkey = pubkey!("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB");
+pub const RECEIPT_SEED: &[u8] = b"receipt";
+
+pub const ESCROW_SEED: &[u8] = b"escrow";
+
+pub const REGISTRY_SEED: &[u8] = b"registry";
+
+pub const REGISTRY_PAGE_CAPACITY: usize = 200;
+
+pub const RECEIPT_NAME: &str = "NLOV Presale Receipt";
+
+pub const RECEIPT_SYMBOL: &str = "NLOVR";
+
+pub const RECEIPT_METADATA_URI: &str = "https://neurolov.ai/presale/receipt.json";
+
+pub const WSOL_MINT: Pubkey = pubkey!("So11111111111111111111111111111111111111112"); // Wrapped SOL (native mint)
+
+pub const PAYMENT_TYPE_WEB2: u8 = 1; // Paid off-chain, recorded by `record_web2_purchase`
+pub const PAYMENT_TYPE_WRAPPED_SOL: u8 = 2; // `buy_tokens` paid from a wSOL token account
+
+pub const CURRENCY_SOL: u8 = 0;
+
+pub const CURRENCY_USDC: u8 = 1;
+
+pub const CURRENCY_USDT: u8 = 2;
+
+pub const CURRENCY_PYUSD: u8 = 3; // PayPal USD, a Token-2022 mint
+
+pub const CURRENCY_TOKEN: u8 = 4; // A payment token registered through `add_payment_token`, priced via its oracle
+
+pub const STABLE_COIN_DECIMALS: u8 = 6; // USDC, USDT and PYUSD; quotes assume it unless given the mint's decimals
+
+pub const USD_MICROS_PER_DOLLAR: u64 = 1_000_000; // USD prices are in micro-dollars, i.e. raw stablecoin units
+
+pub const USD_MICROS_PER_CENT: u64 = 10_000;
+
+pub const USD_PRICING_VERSION: u8 = 1; // USD prices in micro-dollars (0 = legacy cents)
+
+pub const TOKEN_ROUNDING_FLOOR: u8 = 0; // Round tokens out down to whole tokens
+
+pub const TOKEN_ROUNDING_NEAREST: u8 = 1; // Round tokens out to the nearest whole token, halves up
+
+pub const TOKEN_ROUNDING_CEIL: u8 = 2; // Round tokens out up to whole tokens
+
+pub const BPS_DENOMINATOR: u16 = 10_000;
+
+pub const PURCHASE_HISTORY_CAPACITY: usize = 32;
+
+pub const COMPLIANCE_NOTE_LEN: usize = 64;
+
+pub const LOCKDOWN_LIFT_DELAY: i64 = 48 * 3600; // 48 hours
+
+pub const TICKET_SEED: &[u8] = b"ticket";
+
+pub const TICKET_TTL: i64 = 10 * 60; // Reservations expire after 10 minutes
+
+pub const CAP_SALE_INACTIVE: u8 = 0;
+
+pub const CAP_HARDCAP: u8 = 1;
+
+pub const CAP_SUPPLY: u8 = 2;
+
+pub const CAP_WALLET: u8 = 3;
+
+pub const CAP_VOLUME_STEP: u8 = 4;
+
+pub const CAP_WHITELIST_TIER: u8 = 5;
+
+pub const CAP_PURCHASE_LIMIT: u8 = 6;
+
+pub const HEALTH_WALLET_UNDERFUNDED: u32 = 1 << 0; // Presale wallet holds less NLOV than is owed
+
+pub const HEALTH_ESCROW_SHORTFALL: u32 = 1 << 1; // Escrow holds less than the recorded escrowed payments
+
+pub const HEALTH_SCHEDULE_OVERDUE: u32 = 1 << 2; // Current sale stage has run past its scheduled end
+
+pub const HEALTH_LOCKED_DOWN: u32 = 1 << 3;
+
+pub const HEALTH_CLAIMS_PAUSED: u32 = 1 << 4;
+
+pub const HEALTH_ORACLE_STALE: u32 = 1 << 5; // Every configured SOL/USD feed passed in is older than max_price_age
+
+pub const STREAM_TRANCHE_SECONDS: i64 = 30 * 86400; // One tranche per 30-day month
+
+pub const VESTING_MODE_LINEAR: u8 = 0; // Continuous linear unlocks
+
+pub const VESTING_MODE_MONTHLY: u8 = 1; // Equal discrete tranches, one per month
+
+pub const VESTING_TRANCHE_SECONDS: i64 = 30 * 86400; // One monthly vesting tranche per 30 days
+
+pub const DEFAULT_STAGE_ROLLBACK_WINDOW: i64 = 15 * 60; // 15 minutes
+
+pub const MAX_STAGE_ROLLBACK_WINDOW: i64 = 24 * 3600; // A stage transition is final after a day at most
+
+pub const IDENTITY_MODE_NONE: u8 = 0;
+
+pub const IDENTITY_MODE_ACCOUNT: u8 = 1; // Attestation account owned by the verifier, naming the buyer
+
+pub const IDENTITY_MODE_CPI: u8 = 2; // Verifier program approves the buyer via CPI
+
+pub const IDENTITY_MODE_GATEWAY: u8 = 3; // Civic gateway token issued in the verifier's gatekeeper network
+
+pub const GATEWAY_PROGRAM_ID: Pubkey = pubkey!("gatem74V238djXdzWnJf94Wo1DcnuGkfijbf3AuBhfs"); // Civic gateway program
+
+pub const GATEWAY_TOKEN_STATE_ACTIVE: u8 = 0;
+
+pub const KYC_ATTESTATION_LEN: usize = 32 + 32 + 8 + 8; // Buyer, presale, expiry, region bitmask
+
+pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ"); // Pyth pull oracle receiver
+
+pub const PYTH_PRICE_UPDATE_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205]; // PriceUpdateV2
+
+pub const PYTH_VERIFICATION_FULL: u8 = 1; // VerificationLevel::Full
+
+pub const SOL_USD_FEED_ID: [u8; 32] = [
+    0xef, 0x0d, 0x8b, 0x6f, 0xda, 0x2c, 0xeb, 0xa4, 0x1d, 0xa1, 0x5d, 0x40, 0x95, 0xd1, 0xda, 0x39,
+    0x2a, 0x0d, 0x2f, 0x8e, 0xd0, 0xc6, 0xc7, 0xbc, 0x0f, 0x4c, 0xfa, 0xc8, 0xc2, 0x80, 0xb5, 0x6d,
+]; // Pyth SOL/USD price feed ID
+
+pub const SWITCHBOARD_ON_DEMAND_PROGRAM_ID: Pubkey = pubkey!("SBondMDrcV3K4kxZR1HNVT7osZxAHVHgYXL5Ze1oMUv"); // Switchboard on-demand
+
+pub const SWITCHBOARD_PULL_FEED_DISCRIMINATOR: [u8; 8] = [196, 27, 108, 196, 10, 215, 219, 40]; // PullFeedAccountData
+
+pub const SWITCHBOARD_LAST_UPDATE_OFFSET: usize = 2216; // PullFeedAccountData::last_update_timestamp
+
+pub const SWITCHBOARD_RESULT_OFFSET: usize = 2264; // PullFeedAccountData::result.value
+
+pub const SWITCHBOARD_DECIMALS: i32 = 18; // Switchboard results are fixed-point with 18 decimals
+
+pub const RAYDIUM_CLMM_PROGRAM_ID: Pubkey = pubkey!("CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK"); // Raydium concentrated liquidity
+
+pub const RAYDIUM_OBSERVATION_DISCRIMINATOR: [u8; 8] = [122, 174, 197, 53, 129, 9, 165, 132]; // ObservationState
+
+pub const RAYDIUM_OBSERVATION_INDEX_OFFSET: usize = 17; // ObservationState::observation_index
+
+pub const RAYDIUM_OBSERVATIONS_OFFSET: usize = 51; // ObservationState::observations
+
+pub const RAYDIUM_OBSERVATION_LEN: usize = 4 + 8 + 32; // Block timestamp, tick cumulative, padding
+
+pub const RAYDIUM_OBSERVATION_NUM: usize = 100; // Observations kept per pool
+
+pub const TWAP_PRICE_DECIMALS: i32 = 12; // Fixed-point precision of prices derived from pool ticks
+
+pub const PURCHASE_REVERSAL_WINDOW: i64 = 60 * 60; // Fat-finger reversals within 1 hour of purchase
+
+pub const JOURNAL_SEED: &[u8] = b"journal";
+
+pub const PURCHASE_JOURNAL_CAPACITY: usize = 64;
+
+pub const RECENT_PURCHASES_LIMIT: usize = 16; // Keeps `get_recent_purchases` within return-data limits
+
+pub const TERMS_SEED: &[u8] = b"terms";
+
+pub const VESTING_SCHEDULE_SEED: &[u8] = b"vesting_schedule";
+
+pub const VESTING_STATS_SEED: &[u8] = b"vesting_stats";
+
+pub const WHITELIST_SEED: &[u8] = b"whitelist";
+
+pub const WHITELIST_TIER_COUNT: usize = 4; // e.g. Gold, Silver, Bronze, plus an uncapped default tier
+
+pub const MAX_WHITELIST_PHASES: usize = 4;
+
+pub const MAX_STAGE_PRICES: usize = 2; // One entry each for the private and public stages
+
+pub const MAX_DISCOUNT_TIERS: usize = 4;
+
+pub const MAX_TREASURY_WALLETS: usize = 5; // e.g. marketing, development and liquidity
+
+pub const MAX_PROTOCOL_FEE_BPS: u16 = 1_000; // At most 10% of each payment
+
+pub const MAX_REFERRAL_COMMISSION_BPS: u16 = 2_000; // At most 20% of each referred purchase
+
+pub const MAX_PUBLIC_PRICE_RAMP_BPS: u16 = 10_000; // The public round ends at most at twice the base price
+
+pub const BLACKLIST_SEED: &[u8] = b"blacklist";
+
+pub const REGISTRATION_SEED: &[u8] = b"registration";
+
+pub const COMMITMENT_SEED: &[u8] = b"commitment";
+
+pub const MAX_VESTING_MILESTONES: usize = 16;
+
+pub const VESTING_SCHEDULE_VERSION: u8 = 1; // Current VestingSchedule layout
+
+pub const SETTLEMENT_SEED: &[u8] = b"settlement";
+
+pub const ACCEPTED_CURRENCY_SEED: &[u8] = b"accepted_currency";
+
+pub const PAYMENT_TOKEN_SEED: &[u8] = b"payment_token";
+
+pub const TOKEN_CONTRIBUTION_SEED: &[u8] = b"token_contribution";
+
+pub const WEB2_PURCHASE_SEED: &[u8] = b"web2_purchase";
+
+pub const MAX_WEB2_CHARGEBACK_WINDOW: i64 = 180 * 24 * 3600; // Card chargebacks settle within ~180 days
+
+pub const MAX_PRICE_CHANGE_DELAY: i64 = 30 * 24 * 3600; // A pending price change applies within 30 days
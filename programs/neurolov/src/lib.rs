@@ -1,52 +1,96 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    ed25519_program,
+    instruction::{AccountMeta, Instruction},
+    keccak,
+    program::{invoke, invoke_signed},
+    sysvar::{
+        self,
+        instructions::{load_current_index_checked, load_instruction_at_checked},
+    },
+};
+use anchor_lang::system_program::{self, Allocate, Assign, Transfer as SystemTransfer};
 use anchor_spl::{
-    associated_token::AssociatedToken,
-    token::{self, Mint, Token, TokenAccount, Transfer as TokenTransfer},
+    associated_token::{
+        self, get_associated_token_address, get_associated_token_address_with_program_id, AssociatedToken,
+    },
+    metadata::{
+        create_master_edition_v3, create_metadata_accounts_v3, mpl_token_metadata::types::DataV2,
+        CreateMasterEditionV3, CreateMetadataAccountsV3, Metadata,
+    },
+    token::{self, Burn, CloseAccount, FreezeAccount, Mint, MintTo, Token, TokenAccount, Transfer as TokenTransfer},
+    token_2022::{self, Token2022},
+    token_interface::{
+        self, Mint as InterfaceMint, TokenAccount as InterfaceTokenAccount, TokenInterface, TransferChecked,
+    },
 };
-use anchor_lang::solana_program::{program::invoke, system_instruction}; 
 
 pub mod constant;
 use constant::*; 
 
 declare_id!("Duf9UdBXfrxgBeZgZ2DUxRgFSZ4qCzEgGyxFmuQHGHZH"); 
 
+// Anchor's generated IDL handlers still call the deprecated `AccountInfo::realloc`, and the
+// `#[program]` expansion lands next to the module it annotates
+#[allow(deprecated)]
+mod handlers {
+use super::*;
+
 #[program]
 pub mod presale {
     use super::*;
 
     /// Initializes the presale contract with specified parameters.
     /// This function sets up the admin, prices, sale durations, hardcap, and wallet accounts.
+    /// `stage_prices` optionally schedules the prices of the private and public rounds, which take
+    /// effect automatically when the sale enters each stage.
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize(
         ctx: Context<Initialize>,
-        usd_price_cents_per_nlov: u64, 
+        usd_price_micros_per_nlov: u64, 
         sol_price_lamports_per_nlov: u64, 
         private_sale_duration_days: i64,
         public_sale_duration_days: i64,
         hardcap_tokens: u64, 
+        immutable_pricing: bool,
+        max_end_timestamp: i64,
+        stage_prices: Vec<StagePrice>,
     ) -> Result<()> {
         let presale = &mut ctx.accounts.presale;
 
-        let bump = ctx.bumps.presale;
-
-        presale.admin = ctx.accounts.admin.key();
-        presale.usd_price_cents_per_nlov = usd_price_cents_per_nlov;
+        presale.reset_for_new_sale(ctx.accounts.admin.key(), Clock::get()?.unix_timestamp);
+        presale.usd_price_micros_per_nlov = usd_price_micros_per_nlov;
         presale.sol_price_lamports_per_nlov = sol_price_lamports_per_nlov;
-        presale.presale_start = Clock::get()?.unix_timestamp;
-        presale.private_sale_duration = private_sale_duration_days * 86400; 
-        presale.public_sale_duration = public_sale_duration_days * 86400; 
-        presale.sale_stage = 0; 
-        presale.total_sold = 0;
-        presale.pool_created = false;
-        presale.hardcap_tokens = hardcap_tokens; 
-
-        presale.presale_wallet = ctx.accounts.presale_wallet.key();
-        presale.merchant_wallet = ctx.accounts.merchant_wallet.key();
-
-        presale.bump = bump;
+        presale.private_sale_duration = days_to_seconds(private_sale_duration_days)?;
+        presale.public_sale_duration = days_to_seconds(public_sale_duration_days)?;
+        presale.hardcap_tokens = hardcap_tokens;
+        presale.immutable_pricing = immutable_pricing; // Fixed for the life of the sale
+        require!(max_end_timestamp >= 0, PresaleError::InvalidMaxEndTimestamp);
+        presale.max_end_timestamp = max_end_timestamp; // Fixed for the life of the sale
+        presale.set_stage_prices(&stage_prices)?; // Fixed for the life of the sale
+
+        presale.ensure_within_max_end(
+            presale.private_sale_duration,
+            presale.public_sale_duration,
+            presale.presale_start,
+        )?;
+
+        open_sale(
+            presale,
+            ctx.bumps.presale,
+            &ctx.accounts.registry_page,
+            &ctx.accounts.presale_wallet,
+            &ctx.accounts.merchant_wallet,
+            &ctx.accounts.escrow,
+            ctx.bumps.escrow,
+            ctx.accounts.fee_authority.as_ref(),
+            &ctx.accounts.admin,
+            &ctx.accounts.system_program,
+        )?;
 
         msg!(
-            "Presale contract initialized! USD Price: {} cents/NLOV, SOL Price: {} lamports/NLOV, Private Duration: {} days, Public Duration: {} days, Hardcap Tokens: {}",
-            usd_price_cents_per_nlov,
+            "Presale contract initialized! USD Price: {} micro-USD/NLOV, SOL Price: {} lamports/NLOV, Private Duration: {} days, Public Duration: {} days, Hardcap Tokens: {}",
+            usd_price_micros_per_nlov,
             sol_price_lamports_per_nlov,
             private_sale_duration_days,
             public_sale_duration_days,
@@ -56,22 +100,144 @@ pub mod presale {
         Ok(())
     }
 
+    /// Creates a new sale for the signing admin with the parameters of `template_sale` (pricing,
+    /// durations, caps, vesting, early-claim penalty, identity checks), applying `overrides` on top.
+    /// Progress, roles and absolute timestamps (TGE, milestones, max end) are not copied.
+    pub fn clone_sale(ctx: Context<CloneSale>, overrides: SaleOverrides) -> Result<()> {
+        let template = &ctx.accounts.template_sale;
+        let presale = &mut ctx.accounts.presale;
+
+        presale.reset_for_new_sale(ctx.accounts.admin.key(), Clock::get()?.unix_timestamp);
+
+        presale.usd_price_micros_per_nlov = overrides
+            .usd_price_micros_per_nlov
+            .unwrap_or(template.usd_price_micros_per_nlov);
+        presale.sol_price_lamports_per_nlov = overrides
+            .sol_price_lamports_per_nlov
+            .unwrap_or(template.sol_price_lamports_per_nlov);
+        presale.immutable_pricing = template.immutable_pricing;
+        presale.private_sale_duration = overrides
+            .private_sale_duration_days
+            .map_or(Ok(template.private_sale_duration), days_to_seconds)?;
+        presale.public_sale_duration = overrides
+            .public_sale_duration_days
+            .map_or(Ok(template.public_sale_duration), days_to_seconds)?;
+        presale.hardcap_tokens = overrides.hardcap_tokens.unwrap_or(template.hardcap_tokens);
+        presale.softcap_tokens = overrides.softcap_tokens.unwrap_or(template.softcap_tokens);
+        presale.max_allocation_per_buyer = overrides
+            .max_allocation_per_buyer
+            .unwrap_or(template.max_allocation_per_buyer);
+        presale.max_end_timestamp = overrides.max_end_timestamp.unwrap_or(0);
+        presale.referral_commission_bps = template.referral_commission_bps;
+        presale.vesting_duration = template.vesting_duration;
+        presale.vesting_cliff = template.vesting_cliff;
+        presale.tge_unlock_bps = template.tge_unlock_bps;
+        presale.private_round_vesting = template.private_round_vesting;
+        presale.public_round_vesting = template.public_round_vesting;
+        presale.early_claim_penalty_bps = template.early_claim_penalty_bps;
+        presale.penalty_wallet = template.penalty_wallet;
+        presale.identity_mode = template.identity_mode;
+        presale.identity_verifier = template.identity_verifier;
+        presale.identity_subject_offset = template.identity_subject_offset;
+        presale.kyc_authority = template.kyc_authority;
+        presale.purchase_cooldown = template.purchase_cooldown;
+        presale.commit_reveal_delay = template.commit_reveal_delay;
+        presale.private_round_purchase_limit = template.private_round_purchase_limit;
+        presale.public_round_purchase_limit = template.public_round_purchase_limit;
+        presale.blocked_regions = template.blocked_regions;
+        presale.gatekeeper = template.gatekeeper;
+        presale.web2_recorder = template.web2_recorder;
+        presale.web2_chargeback_window = template.web2_chargeback_window;
+        presale.stage_rollback_window = template.stage_rollback_window;
+        presale.sol_usd_price_feed = template.sol_usd_price_feed;
+        presale.sol_usd_fallback_feed = template.sol_usd_fallback_feed;
+        presale.max_price_age = template.max_price_age;
+        presale.max_confidence_bps = template.max_confidence_bps;
+        presale.stage_prices = template.stage_prices;
+        presale.volume_price_step_tokens = template.volume_price_step_tokens;
+        presale.volume_price_step_bps = template.volume_price_step_bps;
+        presale.public_price_ramp_bps = template.public_price_ramp_bps;
+        presale.discount_tiers = template.discount_tiers;
+        presale.price_guardrails = template.price_guardrails;
+        presale.sol_usd_twap_observation = template.sol_usd_twap_observation;
+        presale.twap_window = template.twap_window;
+        presale.max_twap_deviation_bps = template.max_twap_deviation_bps;
+        presale.token_rounding_mode = template.token_rounding_mode;
+        presale.price_change_delay = template.price_change_delay;
+        presale.whitelist_discount_bps = template.whitelist_discount_bps;
+
+        require!(
+            presale.usd_price_micros_per_nlov > 0 && presale.sol_price_lamports_per_nlov > 0,
+            PresaleError::InvalidPrice
+        );
+        require!(
+            presale.softcap_tokens <= presale.hardcap_tokens,
+            PresaleError::InvalidSoftcap
+        );
+        require!(presale.max_end_timestamp >= 0, PresaleError::InvalidMaxEndTimestamp);
+        presale.ensure_within_max_end(
+            presale.private_sale_duration,
+            presale.public_sale_duration,
+            presale.presale_start,
+        )?;
+
+        open_sale(
+            presale,
+            ctx.bumps.presale,
+            &ctx.accounts.registry_page,
+            &ctx.accounts.presale_wallet,
+            &ctx.accounts.merchant_wallet,
+            &ctx.accounts.escrow,
+            ctx.bumps.escrow,
+            ctx.accounts.fee_authority.as_ref(),
+            &ctx.accounts.admin,
+            &ctx.accounts.system_program,
+        )?;
+
+        emit!(SaleClonedEvent {
+            admin: ctx.accounts.admin.key(),
+            template_sale: template.key(),
+            presale: presale.key(),
+            usd_price_micros_per_nlov: presale.usd_price_micros_per_nlov,
+            sol_price_lamports_per_nlov: presale.sol_price_lamports_per_nlov,
+            hardcap_tokens: presale.hardcap_tokens,
+        });
+
+        msg!(
+            "Presale cloned from {}: USD Price: {} micro-USD/NLOV, SOL Price: {} lamports/NLOV, Hardcap Tokens: {}",
+            template.key(),
+            presale.usd_price_micros_per_nlov,
+            presale.sol_price_lamports_per_nlov,
+            presale.hardcap_tokens,
+        );
+
+        Ok(())
+    }
+
     /// Advances the sale stage of the presale contract.
     /// Stages: 0 (Not Started) -> 1 (Private Sale) -> 2 (Public Sale) -> 3 (Ended).
     /// Requires the admin to perform this action and checks sale duration.
     pub fn set_stage(ctx: Context<SetStage>) -> Result<()> {
         let presale = &mut ctx.accounts.presale;
 
+        presale.ensure_not_locked_down()?;
+
         require!(
             presale.admin == ctx.accounts.admin.key(),
             PresaleError::Unauthorized
         );
 
         let clock = Clock::get()?;
+        let previous_stage = presale.sale_stage;
 
         match presale.sale_stage {
             0 => {
                 // Not Started -> Start Private Sale
+                presale.ensure_within_max_end(
+                    presale.private_sale_duration,
+                    presale.public_sale_duration,
+                    clock.unix_timestamp,
+                )?;
                 presale.presale_start = clock.unix_timestamp; // Set start time when sale actually begins
                 presale.sale_stage = 1;
                 msg!("Private sale started at {}", presale.presale_start);
@@ -102,6 +268,12 @@ pub mod presale {
             }
         }
 
+        // Remember the transition so it can be rolled back within the grace window
+        presale.previous_stage = previous_stage;
+        presale.stage_changed_at = clock.unix_timestamp;
+        presale.purchases_in_stage = 0;
+        presale.apply_stage_price(clock.unix_timestamp);
+
         Ok(())
     }
 
@@ -114,6 +286,8 @@ pub mod presale {
     ) -> Result<()> {
         let presale = &mut ctx.accounts.presale;
 
+        presale.ensure_not_locked_down()?;
+
         require!(
             presale.admin == ctx.accounts.admin.key(),
             PresaleError::Unauthorized
@@ -122,8 +296,18 @@ pub mod presale {
         // Ensure the presale has not already ended
         require!(presale.sale_stage < 3, PresaleError::SaleAlreadyEnded);
 
-        presale.private_sale_duration = new_private_sale_duration_days * 86400;
-        presale.public_sale_duration = new_public_sale_duration_days * 86400;
+        let new_private_sale_duration = days_to_seconds(new_private_sale_duration_days)?;
+        let new_public_sale_duration = days_to_seconds(new_public_sale_duration_days)?;
+
+        // Never past the end date buyers were promised at initialize
+        presale.ensure_within_max_end(
+            new_private_sale_duration,
+            new_public_sale_duration,
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        presale.private_sale_duration = new_private_sale_duration;
+        presale.public_sale_duration = new_public_sale_duration;
 
         msg!(
             "Updated sale period: Private Sale = {} days, Public Sale = {} days",
@@ -135,17 +319,25 @@ pub mod presale {
     }
 
     /// Allows a buyer to purchase tokens using SOL.
-    /// The function supports Web3 (on-chain SOL transfer) and Web2 (exchange) payment types.
+    /// The function supports Web3 (on-chain SOL transfer) and wrapped SOL payments; Web2 (exchange) purchases
+    /// are recorded by the backend through `record_web2_purchase`.
     /// Calculates tokens based on SOL amount and current price, updates total_sold.
+    /// Only the exact cost of the whole tokens bought is charged; unconvertible lamports stay with the buyer.
     pub fn buy_tokens(
         ctx: Context<BuyTokens>,
-        payment_type: u8, // 0 = Web3, 1 = Web2 (for tracking, no on-chain SOL transfer for Web2)
+        payment_type: u8, // 0 = Web3, 2 = wrapped SOL
         lamports_sent: u64,
+        valid_until_timestamp: Option<i64>, // Abort if the purchase lands after this (Unix time)
+        whitelist_proof: Option<WhitelistProof>, // Buyer's tier and merkle proof, checked in the private stage
+        min_tokens_out: Option<u64>, // Abort if the purchase would buy fewer tokens (raw units) than this
     ) -> Result<()> {
         let presale = &mut ctx.accounts.presale;
         let buyer = &ctx.accounts.buyer;
         let token_decimals = ctx.accounts.token_mint.decimals; 
 
+        presale.ensure_not_locked_down()?;
+        ensure_purchase_not_expired(valid_until_timestamp)?;
+
         require!(
             presale.sale_stage == 1 || presale.sale_stage == 2,
             PresaleError::PresaleNotActive
@@ -153,88 +345,293 @@ pub mod presale {
 
         // Calculate tokens to purchase based on SOL amount and the new SOL price per NLOV.
         // NLOV price is 0.182 SOL, which is 182_000_000 lamports.
-        // We'll calculate total lamports needed for 1 NLOV based on the stored sol_price_lamports_per_nlov
-        let tokens_to_purchase_user_units = lamports_sent
-            .checked_div(presale.sol_price_lamports_per_nlov)
-            .ok_or(PresaleError::InvalidPrice)?; 
+        // We'll calculate total lamports needed for 1 NLOV based on the stored sol_price_lamports_per_nlov,
+        // or on the USD price and the SOL/USD oracle once one is configured
+        let sol_price_lamports_per_nlov = purchase_price(
+            presale,
+            CURRENCY_SOL,
+            ctx.accounts.sol_usd_price_update.as_ref(),
+            ctx.accounts.sol_usd_fallback_feed.as_ref(),
+            ctx.accounts.sol_usd_twap_observation.as_ref(),
+        )?;
+        let discount_bps = purchase_discount_bps(
+            presale,
+            CURRENCY_SOL,
+            lamports_sent,
+            sol_price_lamports_per_nlov,
+            &buyer.key(),
+            whitelist_proof.as_ref(),
+            ctx.accounts.whitelist_entry.is_some(),
+        )?;
+        let tokens_to_purchase_user_units =
+            tokens_for_payment(lamports_sent, sol_price_lamports_per_nlov, discount_bps, presale.token_rounding_mode)?;
 
         require!(tokens_to_purchase_user_units >= 1, PresaleError::InvalidPrice);
 
 
         // Convert user-facing units to raw token units (with decimals) for internal tracking
         let tokens_to_purchase_raw =
-            tokens_to_purchase_user_units.checked_mul(10u64.pow(token_decimals as u32)).unwrap();
-
-
-        // Enforce hardcap: Check if this purchase would exceed the hardcap
-        require!(
-            presale
-                .total_sold
-                .checked_add(tokens_to_purchase_raw)
-                .unwrap_or(u64::MAX)
-                <= presale.hardcap_tokens,
-            PresaleError::HardcapReached
-        );
-
-        // Ensure enough tokens exist in the presale wallet for this purchase
-        let available_presale_tokens_raw = ctx.accounts.presale_wallet.amount;
-        let tokens_currently_sold_raw = presale.total_sold; 
-
-        require!(
-            available_presale_tokens_raw.saturating_sub(tokens_currently_sold_raw)
-                >= tokens_to_purchase_raw,
-            PresaleError::InsufficientTokens
-        );
+            tokens_to_purchase_user_units.checked_mul(10u64.pow(token_decimals as u32)).ok_or(PresaleError::MathOverflow)?;
+        ensure_min_tokens_out(tokens_to_purchase_raw, min_tokens_out)?;
+
+        // Only the exact cost of the tokens is taken; lamports that don't convert into a whole token stay
+        // with the buyer
+        let lamports_charged =
+            payment_for_tokens(tokens_to_purchase_user_units, sol_price_lamports_per_nlov, discount_bps)?;
+        require!(lamports_charged <= lamports_sent, PresaleError::InvalidPrice);
+        // The protocol fee is taken out of on-chain payments; only the rest is escrowed as proceeds
+        let protocol_fee = if paid_into_escrow(payment_type) {
+            presale.protocol_fee(lamports_charged)?
+        } else {
+            0
+        };
+        let lamports_escrowed = lamports_charged - protocol_fee;
+
+
+        let (referral_commission_raw, referrer) = check_purchase(
+            presale,
+            &PurchaseAccounts {
+                buyer,
+                buyer_account: &ctx.accounts.buyer_account,
+                presale_wallet: &ctx.accounts.presale_wallet,
+                ticket: ctx.accounts.ticket.as_ref(),
+                attestation: ctx.accounts.attestation.as_ref(),
+                identity_verifier_program: ctx.accounts.identity_verifier_program.as_ref(),
+                instructions_sysvar: ctx.accounts.instructions_sysvar.as_ref(),
+                referrer_account: ctx.accounts.referrer_account.as_ref(),
+                whitelist_entry: ctx.accounts.whitelist_entry.as_ref(),
+                blacklist: ctx.accounts.blacklist.as_ref(),
+                gatekeeper_signed: ctx.accounts.gatekeeper.is_some(),
+                purchase_commitment: ctx.accounts.purchase_commitment.as_ref(),
+            },
+            lamports_sent,
+            whitelist_proof.as_ref(),
+            tokens_to_purchase_raw,
+            Clock::get()?.unix_timestamp,
+        )?;
 
         if payment_type == 0 {
-            // Web3 payment: Transfer SOL on-chain
-            invoke(
-                &system_instruction::transfer(
-                    &buyer.key(),
-                    &presale.merchant_wallet,
-                    lamports_sent,
+            // Web3 payment: Transfer SOL on-chain into the escrow PDA
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    SystemTransfer {
+                        from: ctx.accounts.buyer.to_account_info(),
+                        to: ctx.accounts.escrow.to_account_info(),
+                    },
                 ),
-                &[
-                    ctx.accounts.buyer.to_account_info(),
-                    ctx.accounts.merchant_wallet.to_account_info(),
+                lamports_charged,
+            )?;
+            presale.escrowed_lamports = presale.escrowed_lamports.checked_add(lamports_escrowed).ok_or(PresaleError::MathOverflow)?;
+        } else if payment_type == PAYMENT_TYPE_WRAPPED_SOL {
+            // Wrapped SOL payment: move the wSOL into the escrow's wSOL account, then close that into the
+            // escrow to unwrap it. Only the wrapped payment stays in the escrow; the account's rent, and any
+            // lamports it was pre-funded with, go back to the buyer
+            let (Some(buyer_wsol_account), Some(escrow_wsol_account), Some(wsol_mint)) = (
+                ctx.accounts.buyer_wsol_account.as_ref(),
+                ctx.accounts.escrow_wsol_account.as_ref(),
+                ctx.accounts.wsol_mint.as_ref(),
+            ) else {
+                return Err(PresaleError::WrappedSolAccountsRequired.into());
+            };
+            let presale_key = presale.key();
+            let escrow_seeds: &[&[u8]] = &[ESCROW_SEED, presale_key.as_ref(), &[presale.escrow_bump]];
+            let signer_seeds: &[&[&[u8]]] = &[escrow_seeds];
+
+            associated_token::create_idempotent(CpiContext::new(
+                ctx.accounts.associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: buyer.to_account_info(),
+                    associated_token: escrow_wsol_account.to_account_info(),
+                    authority: ctx.accounts.escrow.to_account_info(),
+                    mint: wsol_mint.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                },
+            ))?;
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    TokenTransfer {
+                        from: buyer_wsol_account.to_account_info(),
+                        to: escrow_wsol_account.to_account_info(),
+                        authority: buyer.to_account_info(),
+                    },
+                ),
+                lamports_charged,
+            )?;
+            let escrow_lamports_before = ctx.accounts.escrow.lamports();
+            token::close_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: escrow_wsol_account.to_account_info(),
+                    destination: ctx.accounts.escrow.to_account_info(),
+                    authority: ctx.accounts.escrow.to_account_info(),
+                },
+                signer_seeds,
+            ))?;
+            let unwrapped_lamports = ctx
+                .accounts
+                .escrow
+                .lamports()
+                .checked_sub(escrow_lamports_before)
+                .ok_or(PresaleError::MathOverflow)?;
+            system_program::transfer(
+                CpiContext::new_with_signer(
                     ctx.accounts.system_program.to_account_info(),
-                ],
+                    SystemTransfer {
+                        from: ctx.accounts.escrow.to_account_info(),
+                        to: buyer.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                unwrapped_lamports.checked_sub(lamports_charged).ok_or(PresaleError::MathOverflow)?,
             )?;
-        } else if payment_type == 1 {
-            // Web2 payment: Assume off-chain payment occurred, no on-chain SOL transfer
-            msg!(
-                "Web2 payment type selected. Assuming off-chain SOL payment of {} lamports.",
-                lamports_sent
-            );
+            presale.escrowed_lamports = presale.escrowed_lamports.checked_add(lamports_escrowed).ok_or(PresaleError::MathOverflow)?;
         } else {
             return Err(PresaleError::InvalidPaymentType.into());
         }
 
+        if protocol_fee > 0 {
+            let fee_wallet = ctx.accounts.fee_wallet.as_ref().ok_or(PresaleError::FeeAccountRequired)?;
+            let presale_key = presale.key();
+            let escrow_seeds: &[&[u8]] = &[ESCROW_SEED, presale_key.as_ref(), &[presale.escrow_bump]];
+            system_program::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.system_program.to_account_info(),
+                    SystemTransfer {
+                        from: ctx.accounts.escrow.to_account_info(),
+                        to: fee_wallet.to_account_info(),
+                    },
+                    &[escrow_seeds],
+                ),
+                protocol_fee,
+            )?;
+        }
+
         // Update `total_sold` with raw token units
         presale.total_sold = presale
             .total_sold
             .checked_add(tokens_to_purchase_raw)
-            .unwrap();
+            .ok_or(PresaleError::MathOverflow)?;
+        presale.purchases_in_stage = presale.purchases_in_stage.checked_add(1).ok_or(PresaleError::MathOverflow)?;
+
+        // Track the purchase against the buyer's own allocation
+        let first_purchase = ctx.accounts.buyer_account.record_purchase(
+            presale.key(),
+            buyer.key(),
+            ctx.bumps.buyer_account,
+            PurchaseRecord {
+                timestamp: Clock::get()?.unix_timestamp,
+                currency: CURRENCY_SOL,
+                amount_paid: lamports_escrowed,
+                tokens: tokens_to_purchase_raw,
+                stage: presale.sale_stage,
+                payment_type,
+                reversed: false,
+                config_version: presale.config_version,
+                referrer,
+                referral_commission: referral_commission_raw,
+            },
+        )?;
+        sync_buyer_registry(
+            presale,
+            &mut ctx.accounts.buyer_account,
+            &ctx.accounts.registry_page,
+            first_purchase,
+        )?;
+
+        // Track refundable on-chain payments
+        if paid_into_escrow(payment_type) {
+            ctx.accounts.buyer_account.record_contribution(CURRENCY_SOL, lamports_escrowed)?;
+            ctx.accounts.buyer_account.record_protocol_fee(CURRENCY_SOL, protocol_fee)?;
+        }
+
+        // Credit the referrer's commission
+        credit_referrer(
+            presale,
+            ctx.accounts.referrer_account.as_mut(),
+            buyer.key(),
+            referral_commission_raw,
+        )?;
+
+        // Feed the recent purchases ticker
+        record_in_journal(
+            presale,
+            ctx.accounts.purchase_journal.as_ref(),
+            JournalEntry {
+                buyer: buyer.key(),
+                timestamp: Clock::get()?.unix_timestamp,
+                tokens: tokens_to_purchase_raw,
+                amount_paid: lamports_charged,
+                currency: CURRENCY_SOL,
+                _padding: [0; 7],
+            },
+        )?;
 
         emit!(BuyTokensEvent {
             buyer: buyer.key(),
             tokens_purchased: tokens_to_purchase_user_units, 
-            sol_spent: lamports_sent,
-            sol_price_lamports_per_nlov: presale.sol_price_lamports_per_nlov, 
+            sol_spent: lamports_charged,
+            sol_price_lamports_per_nlov, 
             payment_type,
+            discount_bps,
+            rounding_mode: presale.token_rounding_mode,
+            protocol_fee,
         });
 
         msg!(
             "Buyer {} purchased {} tokens for {} lamports using payment_type: {}",
             buyer.key(),
             tokens_to_purchase_user_units,
-            lamports_sent,
+            lamports_charged,
             payment_type
         );
 
         Ok(())
     }
 
+    /// Buys exactly `desired_tokens` whole NLOV with SOL (Web3). The program computes the payment with the
+    /// same math as the purchase itself, so frontend estimates can't round to a different token count, and
+    /// fails if it exceeds `max_payment` lamports.
+    pub fn buy_exact_tokens(
+        ctx: Context<BuyTokens>,
+        desired_tokens: u64, // Whole NLOV to buy
+        max_payment: u64, // Abort if the purchase would cost more lamports than this
+        valid_until_timestamp: Option<i64>, // Abort if the purchase lands after this (Unix time)
+        whitelist_proof: Option<WhitelistProof>, // Buyer's tier and merkle proof, checked in the private stage
+    ) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+        let token_unit = 10u64.pow(ctx.accounts.token_mint.decimals as u32);
+
+        require!(desired_tokens >= 1, PresaleError::InvalidPrice);
+        let sol_price_lamports_per_nlov = purchase_price(
+            presale,
+            CURRENCY_SOL,
+            ctx.accounts.sol_usd_price_update.as_ref(),
+            ctx.accounts.sol_usd_fallback_feed.as_ref(),
+            ctx.accounts.sol_usd_twap_observation.as_ref(),
+        )?;
+        let whitelist_discount = whitelist_discount_bps(
+            presale,
+            &ctx.accounts.buyer.key(),
+            whitelist_proof.as_ref(),
+            ctx.accounts.whitelist_entry.is_some(),
+        );
+        let payment = payment_for_desired_tokens(
+            presale,
+            CURRENCY_SOL,
+            desired_tokens,
+            sol_price_lamports_per_nlov,
+            whitelist_discount,
+            Ok,
+        )?;
+        require!(payment <= max_payment, PresaleError::MaxPaymentExceeded);
+
+        let desired_tokens_raw = desired_tokens.checked_mul(token_unit).ok_or(PresaleError::MathOverflow)?;
+        buy_tokens(ctx, 0, payment, valid_until_timestamp, whitelist_proof, Some(desired_tokens_raw))
+    }
+
     /// Checks the remaining token balance in the presale wallet.
     /// Returns the balance in user-facing units.
     pub fn check_presale_token_balance(ctx: Context<CheckPresaleTokenBalance>) -> Result<u64> {
@@ -243,7 +640,8 @@ pub mod presale {
         let available_tokens_raw = ctx.accounts.presale_wallet.amount;
 
         // Calculate remaining tokens in raw units (with decimals)
-        let remaining_tokens_raw = available_tokens_raw.saturating_sub(presale.total_sold);
+        let remaining_tokens_raw =
+            available_tokens_raw.saturating_sub(presale.outstanding_allocations());
 
         // Convert to user-facing units for display
         let remaining_tokens_user_units = remaining_tokens_raw / 10u64.pow(token_decimals as u32);
@@ -258,34 +656,76 @@ pub mod presale {
     }
 
     /// Allows the admin to update the current sale price.
-    /// Can only be done while the presale is active (stages 1 or 2).
-    pub fn update_sale_price(ctx: Context<UpdateSalePrice>, new_usd_price_cents: u64, new_sol_price_lamports: u64) -> Result<()> {
+    /// Can only be done while the presale is active (stages 1 or 2), within the price guardrails.
+    pub fn update_sale_price(ctx: Context<UpdateSalePrice>, new_usd_price_micros: u64, new_sol_price_lamports: u64) -> Result<()> {
         let presale = &mut ctx.accounts.presale;
 
+        presale.ensure_not_locked_down()?;
+
         require!(
             presale.admin == ctx.accounts.admin.key(),
             PresaleError::Unauthorized
         );
 
+        require!(!presale.immutable_pricing, PresaleError::PricingImmutable);
+        presale.ensure_usd_pricing_current()?;
+
         require!(
             presale.sale_stage == 1 || presale.sale_stage == 2,
             PresaleError::PresaleNotActive
         );
 
-        presale.usd_price_cents_per_nlov = new_usd_price_cents;
+        // A due scheduled update is already in effect; settle it so it can't override this one
+        let now = Clock::get()?.unix_timestamp;
+        presale.apply_scheduled_price(now);
+
+        // Catch fat-fingered prices before anyone can buy at them
+        presale.ensure_price_within_guardrails(new_usd_price_micros, new_sol_price_lamports)?;
+
+        // Under a price change delay the new prices only take effect once it has elapsed
+        if presale.price_change_delay > 0 {
+            let effective_at = now
+                .checked_add(presale.price_change_delay)
+                .ok_or(PresaleError::MathOverflow)?;
+            presale.scheduled_price = ScheduledPrice {
+                usd_price_micros_per_nlov: new_usd_price_micros,
+                sol_price_lamports_per_nlov: new_sol_price_lamports,
+                effective_at,
+            };
+            presale.bump_config_version()?;
+
+            emit!(SchedulePriceUpdateEvent {
+                admin: ctx.accounts.admin.key(),
+                new_usd_price_micros,
+                new_sol_price_lamports,
+                effective_at,
+            });
+
+            msg!(
+                "Sale price update to {} micro-USD/NLOV (USD) and {} lamports/NLOV (SOL) pending until {}",
+                new_usd_price_micros,
+                new_sol_price_lamports,
+                effective_at
+            );
+
+            return Ok(());
+        }
+
+        presale.usd_price_micros_per_nlov = new_usd_price_micros;
         presale.sol_price_lamports_per_nlov = new_sol_price_lamports;
+        presale.bump_config_version()?;
 
 
         emit!(UpdateSalePriceEvent {
             admin: ctx.accounts.admin.key(),
-            new_usd_price_cents,
+            new_usd_price_micros,
             new_sol_price_lamports,
             sale_stage: presale.sale_stage,
         });
 
         msg!(
-            "Sale price updated to {} cents/NLOV (USD) and {} lamports/NLOV (SOL) for stage {}",
-            new_usd_price_cents,
+            "Sale price updated to {} micro-USD/NLOV (USD) and {} lamports/NLOV (SOL) for stage {}",
+            new_usd_price_micros,
             new_sol_price_lamports,
             presale.sale_stage
         );
@@ -293,419 +733,12615 @@ pub mod presale {
         Ok(())
     }
 
-    /// Allows a buyer to purchase tokens using a stablecoin (e.g., USDC or USDT).
-    /// Supports Web3 (on-chain stablecoin transfer) and Web2 (off-chain assumed) payment types.
-    /// Calculates tokens based on stablecoin amount and current price, updates total_sold.
-    pub fn buy_tokens_by_stable_coin(
-        ctx: Context<BuyTokensByStableCoin>,
-        payment_type: u8, // 0 = Web3, 1 = Web2 (for tracking, no on-chain stablecoin transfer for Web2)
-        stable_coin_amount_user_units: u64, // Amount in user-facing units 
+    /// Queues a price update that takes effect at `effective_at`, so price changes can be announced in
+    /// advance; purchases switch to the new prices once the clock reaches it. Replaces any pending update,
+    /// and `effective_at` = 0 cancels it. Checked against the price guardrails when scheduled.
+    /// Only the admin can perform this action, and not under immutable pricing.
+    pub fn schedule_price_update(
+        ctx: Context<SchedulePriceUpdate>,
+        new_usd_price_micros: u64,
+        new_sol_price_lamports: u64,
+        effective_at: i64,
     ) -> Result<()> {
         let presale = &mut ctx.accounts.presale;
-        let buyer = &ctx.accounts.buyer;
-        let token_decimals = ctx.accounts.token_mint.decimals; 
-        let stable_coin_decimals = ctx.accounts.stable_coin_mint.decimals; 
 
-        // Check if the stablecoin mint is either USDC or USDT
-        require!(
-            ctx.accounts.stable_coin_mint.key() == USDC_ADDRESS
-                || ctx.accounts.stable_coin_mint.key() == USDT_ADDRESS,
-            PresaleError::InvalidStableToken
-        );
+        presale.ensure_not_locked_down()?;
 
         require!(
-            stable_coin_amount_user_units >= 1,
-            PresaleError::InvalidPrice
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
         );
+        require!(!presale.immutable_pricing, PresaleError::PricingImmutable);
+        presale.ensure_usd_pricing_current()?;
+
+        let now = Clock::get()?.unix_timestamp;
+        presale.apply_scheduled_price(now);
+
+        if effective_at == 0 {
+            presale.scheduled_price = ScheduledPrice::default();
+            presale.bump_config_version()?;
+
+            emit!(SchedulePriceUpdateEvent {
+                admin: ctx.accounts.admin.key(),
+                new_usd_price_micros: 0,
+                new_sol_price_lamports: 0,
+                effective_at: 0,
+            });
+
+            msg!("Scheduled price update cancelled");
+            return Ok(());
+        }
 
         require!(
-            presale.sale_stage == 1 || presale.sale_stage == 2,
-            PresaleError::PresaleNotActive
+            effective_at > now
+                && effective_at >= now.saturating_add(presale.price_change_delay)
+                && new_usd_price_micros > 0
+                && new_sol_price_lamports > 0,
+            PresaleError::InvalidPriceSchedule
         );
+        presale.ensure_price_within_guardrails(new_usd_price_micros, new_sol_price_lamports)?;
+
+        presale.scheduled_price = ScheduledPrice {
+            usd_price_micros_per_nlov: new_usd_price_micros,
+            sol_price_lamports_per_nlov: new_sol_price_lamports,
+            effective_at,
+        };
+        presale.bump_config_version()?;
+
+        emit!(SchedulePriceUpdateEvent {
+            admin: ctx.accounts.admin.key(),
+            new_usd_price_micros,
+            new_sol_price_lamports,
+            effective_at,
+        });
 
-        // Convert user-facing stable coin amount to raw units
-        let stable_coin_amount_raw =
-            stable_coin_amount_user_units.checked_mul(10u64.pow(stable_coin_decimals as u32)).unwrap();
+        msg!(
+            "Price update to {} micro-USD/NLOV (USD) and {} lamports/NLOV (SOL) scheduled for {}",
+            new_usd_price_micros,
+            new_sol_price_lamports,
+            effective_at
+        );
 
-        // Calculate tokens to purchase based on stablecoin amount and the new USD price per NLOV.
-        // Convert stablecoin amount to cents (e.g., 1 USDC = 100 cents) then divide by NLOV price in cents.
-        let stable_coin_amount_cents = stable_coin_amount_user_units.checked_mul(100).unwrap(); // Stables have 6 decimals and are treated as 1:1 USD
+        Ok(())
+    }
 
-        let tokens_to_purchase_user_units = stable_coin_amount_cents
-            .checked_div(presale.usd_price_cents_per_nlov)
-            .ok_or(PresaleError::InvalidPrice)?; 
+    /// Sets a discount, in basis points, for whitelisted wallets buying in the public round, as a reward for
+    /// early community members (0 = off). Buyers qualify through their `WhitelistEntry` or a merkle proof
+    /// against the private-round root. It doesn't stack with bulk discounts; the larger one applies. Only
+    /// the admin can perform this action, and not under immutable pricing.
+    pub fn set_whitelist_discount(ctx: Context<SetWhitelistDiscount>, discount_bps: u16) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
 
-        // Convert user-facing units to raw token units (with decimals) for internal tracking
-        let tokens_to_purchase_raw =
-            tokens_to_purchase_user_units.checked_mul(10u64.pow(token_decimals as u32)).unwrap();
+        presale.ensure_not_locked_down()?;
 
-        // Enforce hardcap: Check if this purchase would exceed the hardcap
         require!(
-            presale
-                .total_sold
-                .checked_add(tokens_to_purchase_raw)
-                .unwrap_or(u64::MAX)
-                <= presale.hardcap_tokens,
-            PresaleError::HardcapReached
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
         );
+        require!(!presale.immutable_pricing, PresaleError::PricingImmutable);
+        require!(discount_bps < BPS_DENOMINATOR, PresaleError::InvalidWhitelistDiscount);
 
-        // Ensure enough tokens exist in the presale wallet
-        let available_presale_tokens_raw = ctx.accounts.presale_wallet.amount;
-        let tokens_currently_sold_raw = presale.total_sold;
+        presale.whitelist_discount_bps = discount_bps;
+        presale.bump_config_version()?;
+
+        emit!(WhitelistDiscountUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            discount_bps,
+        });
+
+        msg!("Public-round whitelist discount set to {} bps", discount_bps);
+
+        Ok(())
+    }
+
+    /// Sets how purchases round the tokens a payment buys to whole tokens: `TOKEN_ROUNDING_FLOOR` (the
+    /// default), `TOKEN_ROUNDING_NEAREST` or `TOKEN_ROUNDING_CEIL`. Tokens are always charged in full, so
+    /// payments a mode rounds up to an unpaid token are rejected rather than credited, and payments below
+    /// one token's price buy nothing in any mode. Only the admin can perform this action, and not under
+    /// immutable pricing.
+    pub fn set_token_rounding_mode(ctx: Context<SetTokenRoundingMode>, rounding_mode: u8) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
 
         require!(
-            available_presale_tokens_raw.saturating_sub(tokens_currently_sold_raw)
-                >= tokens_to_purchase_raw,
-            PresaleError::InsufficientTokens
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(!presale.immutable_pricing, PresaleError::PricingImmutable);
+        require!(
+            rounding_mode <= TOKEN_ROUNDING_CEIL,
+            PresaleError::InvalidRoundingMode
         );
 
-        if payment_type == 0 {
-            // Web3 payment: Transfer stable coins on-chain
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    TokenTransfer {
-                        from: ctx.accounts.buyer_stable_coin_account.to_account_info(),
-                        to: ctx.accounts.merchant_stable_coin_account.to_account_info(),
-                        authority: ctx.accounts.buyer.to_account_info(),
-                    },
-                ),
-                stable_coin_amount_raw,     
-            )?;
-        } else if payment_type == 1 {
-            // Web2 payment: Assume off-chain payment occurred, no on-chain stablecoin transfer
-            msg!("Web2 payment type selected. Assuming off-chain stablecoin payment of {} (raw: {}).", stable_coin_amount_user_units, stable_coin_amount_raw);
-        } else {
-            return Err(PresaleError::InvalidPaymentType.into());
-        }
-
-        // Update `total_sold` with raw token units
-        presale.total_sold = presale
-            .total_sold
-            .checked_add(tokens_to_purchase_raw)
-            .unwrap();
+        presale.token_rounding_mode = rounding_mode;
+        presale.bump_config_version()?;
 
-        emit!(BuyTokensByStableCoinEvent {
-            buyer: buyer.key(),
-            tokens_purchased: tokens_to_purchase_user_units,
-            stable_coin_amount: stable_coin_amount_user_units,
-            payment_type,
+        emit!(TokenRoundingModeUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            rounding_mode,
         });
 
-        let stable_coin_symbol = if ctx.accounts.stable_coin_mint.key() == USDC_ADDRESS {
-            "USDC"
-        } else {
-            "USDT"
-        };
-        msg!(
-            "Buyer {} purchased {} tokens with {} {} (raw: {}) using payment_type: {}",
-            buyer.key(),
-            tokens_to_purchase_user_units,
-            stable_coin_amount_user_units,
-            stable_coin_symbol,
-            stable_coin_amount_raw,
-            payment_type
-        );
+        msg!("Token rounding mode set to {}", rounding_mode);
 
         Ok(())
     }
 
-    /// Finalizes the presale by transferring any unsold tokens from the presale wallet
-    /// to a designated liquidity wallet.
-    /// This can only be done by the admin after the sale has ended and before a liquidity pool is created.
-    pub fn finalize_presale(ctx: Context<FinalizePresale>) -> Result<()> {
+    /// Sets the delay, in seconds, between a price change and the moment it takes effect: `update_sale_price`
+    /// then queues the new prices as a scheduled update, and `schedule_price_update` can't schedule sooner.
+    /// The delay can only be raised, so it can't be dropped just before a surprise change, and is capped at
+    /// `MAX_PRICE_CHANGE_DELAY` so pricing can't be frozen for good. Only the admin can perform this action.
+    pub fn set_price_change_delay(ctx: Context<SetPriceChangeDelay>, delay_seconds: i64) -> Result<()> {
         let presale = &mut ctx.accounts.presale;
-        let admin_key = ctx.accounts.admin.key();
-        let bump = ctx.bumps.presale;
-        let token_decimals = ctx.accounts.token_mint.decimals; 
-
-        require!(presale.admin == admin_key, PresaleError::Unauthorized);
 
-        require!(presale.sale_stage == 3, PresaleError::PresaleActive);
+        presale.ensure_not_locked_down()?;
 
         require!(
-            !presale.pool_created,
-            PresaleError::LiquidityPoolAlreadyCreated
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(
+            (presale.price_change_delay..=MAX_PRICE_CHANGE_DELAY).contains(&delay_seconds),
+            PresaleError::InvalidPriceChangeDelay
         );
 
-        // Calculate unsold presale tokens in raw units
-        let available_presale_tokens_raw = ctx.accounts.presale_wallet.amount;
-        let unsold_presale_tokens_raw =
-            available_presale_tokens_raw.saturating_sub(presale.total_sold);
+        presale.price_change_delay = delay_seconds;
+        presale.bump_config_version()?;
 
-        let seeds: &[&[u8]] = &[PRESALE_SEED, admin_key.as_ref(), &[bump]];
-        let signer_seeds: &[&[&[u8]]] = &[&seeds[..]];
+        emit!(PriceChangeDelayUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            delay_seconds,
+        });
 
-        // Transfer unsold presale tokens to liquidity wallet if any exist
-        if unsold_presale_tokens_raw > 0 {
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    TokenTransfer {
-                        from: ctx.accounts.presale_wallet.to_account_info(),
-                        to: ctx.accounts.liquidity_wallet.to_account_info(),
-                        authority: presale.to_account_info(),
-                    },
-                    signer_seeds,
-                ),
-                unsold_presale_tokens_raw,
-            )?;
-        }
+        msg!("Price change delay set to {} seconds", delay_seconds);
 
-        presale.pool_created = true;
+        Ok(())
+    }
 
-        emit!(FinalizePresaleEvent {
+    /// Sets guardrails on `update_sale_price`: absolute bounds on each price (0 = unbounded) and the
+    /// largest change a single update may make, in basis points of the current price (0 = unlimited).
+    /// Only the admin can perform this action.
+    pub fn set_price_guardrails(ctx: Context<SetPriceGuardrails>, guardrails: PriceGuardrails) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        presale.ensure_usd_pricing_current()?;
+        require!(
+            (guardrails.max_usd_price_micros == 0 || guardrails.min_usd_price_micros <= guardrails.max_usd_price_micros)
+                && (guardrails.max_sol_price_lamports == 0
+                    || guardrails.min_sol_price_lamports <= guardrails.max_sol_price_lamports),
+            PresaleError::InvalidPriceGuardrails
+        );
+
+        presale.price_guardrails = guardrails;
+        presale.bump_config_version()?;
+
+        emit!(PriceGuardrailsUpdatedEvent {
             admin: ctx.accounts.admin.key(),
-            unsold_presale_tokens: unsold_presale_tokens_raw / 10u64.pow(token_decimals as u32), // Emit user-facing units
+            guardrails,
         });
 
         msg!(
-            "Presale finalized! {} unsold presale tokens moved to liquidity wallet.",
-            unsold_presale_tokens_raw / 10u64.pow(token_decimals as u32),
+            "Price guardrails set: {}-{} micro-USD/NLOV, {}-{} lamports/NLOV, max change {} bps",
+            guardrails.min_usd_price_micros,
+            guardrails.max_usd_price_micros,
+            guardrails.min_sol_price_lamports,
+            guardrails.max_sol_price_lamports,
+            guardrails.max_change_bps
         );
 
         Ok(())
     }
-}
 
-/// Accounts for the `initialize` instruction.
-#[derive(Accounts)]
-#[instruction(usd_price_cents_per_nlov: u64, sol_price_lamports_per_nlov: u64, private_sale_duration_days: i64, public_sale_duration_days: i64, hardcap_tokens: u64)]
-pub struct Initialize<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>, // Admin who deploys the contract
+    /// Prices SOL purchases from a Pyth SOL/USD price feed instead of `sol_price_lamports_per_nlov`:
+    /// `buy_tokens` then derives the lamport price from `usd_price_micros_per_nlov` on-chain, rejecting prices
+    /// older than `max_price_age` seconds or with a confidence interval wider than `max_confidence_bps` of the
+    /// price (0 = no limit). `price_feed` is the Pyth price update account to read (default pubkey = manual
+    /// pricing); `fallback_feed` is a Switchboard SOL/USD feed used while the Pyth price is unavailable
+    /// (default pubkey = no fallback). Only the admin can perform this action, and not under immutable pricing.
+    pub fn configure_sol_oracle(
+        ctx: Context<ConfigureSolOracle>,
+        price_feed: Pubkey,
+        fallback_feed: Pubkey,
+        max_price_age: i64,
+        max_confidence_bps: u16,
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
 
-    #[account(
-        init,
-        payer = admin,
-        seeds = [PRESALE_SEED, admin.key().as_ref()], // Derive Presale PDA
-        bump,
-        space = 8 +  // Discriminator
-               32 +  // Admin pubkey
-               8 +   // Presale start
-               8 +   // USD price (cents)
-               8 +   // SOL price (lamports)
-               8 +   // Private sale duration (in seconds)
-               8 +   // Public sale duration (in seconds)
-               1 +   // Sale stage
-               8 +   // Total sold (raw units with decimals)
-               1 +   // Pool created flag
-               32 +  // Presale wallet Pubkey
-               32 +  // Merchant wallet Pubkey
-               8 +   // Hardcap tokens
-               1     // Bump
-    )]
-    pub presale: Account<'info, Presale>, 
+        presale.ensure_not_locked_down()?;
 
-    pub token_mint: Account<'info, Mint>, 
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(!presale.immutable_pricing, PresaleError::PricingImmutable);
+        require!(max_price_age > 0, PresaleError::InvalidOracleConfig);
+        require!(max_confidence_bps <= BPS_DENOMINATOR, PresaleError::InvalidOracleConfig);
+        require!(
+            fallback_feed == Pubkey::default() || price_feed != Pubkey::default(),
+            PresaleError::InvalidOracleConfig
+        );
 
-    #[account(init, payer = admin, token::mint = token_mint, token::authority = presale)]
-    pub presale_wallet: Account<'info, TokenAccount>,
+        presale.sol_usd_price_feed = price_feed;
+        presale.sol_usd_fallback_feed = fallback_feed;
+        presale.max_price_age = max_price_age;
+        presale.max_confidence_bps = max_confidence_bps;
+        presale.bump_config_version()?;
 
-    /// CHECK: This is a system account for receiving SOL. No specific constraints needed beyond its address.
-    #[account(mut)]
+        emit!(SolOracleConfiguredEvent {
+            admin: ctx.accounts.admin.key(),
+            price_feed,
+            fallback_feed,
+            max_price_age,
+            max_confidence_bps,
+        });
+
+        msg!(
+            "SOL/USD price feed set to {} with fallback {} (max age {} seconds, max confidence {} bps)",
+            price_feed,
+            fallback_feed,
+            max_price_age,
+            max_confidence_bps
+        );
+
+        Ok(())
+    }
+
+    /// Prices public-round SOL purchases from a Raydium CLMM SOL/stablecoin pool's time-weighted average
+    /// price over `twap_window` seconds instead of the oracle price, rejecting a TWAP more than
+    /// `max_deviation_bps` away from the oracle price. `observation` is the pool's observation account
+    /// (default pubkey = off) and needs a SOL/USD price feed configured for the sanity check. Only the
+    /// admin can perform this action, and not under immutable pricing.
+    pub fn configure_sol_twap(
+        ctx: Context<ConfigureSolTwap>,
+        observation: Pubkey,
+        twap_window: i64,
+        max_deviation_bps: u16,
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(!presale.immutable_pricing, PresaleError::PricingImmutable);
+        require!(
+            observation == Pubkey::default()
+                || (presale.sol_usd_price_feed != Pubkey::default()
+                    && twap_window > 0
+                    && max_deviation_bps > 0
+                    && max_deviation_bps <= BPS_DENOMINATOR),
+            PresaleError::InvalidOracleConfig
+        );
+
+        presale.sol_usd_twap_observation = observation;
+        presale.twap_window = twap_window;
+        presale.max_twap_deviation_bps = max_deviation_bps;
+        presale.bump_config_version()?;
+
+        emit!(SolTwapConfiguredEvent {
+            admin: ctx.accounts.admin.key(),
+            observation,
+            twap_window,
+            max_deviation_bps,
+        });
+
+        msg!(
+            "SOL/USD TWAP set to {} over {} seconds (max deviation {} bps)",
+            observation,
+            twap_window,
+            max_deviation_bps
+        );
+
+        Ok(())
+    }
+
+    /// Turns on volume-based pricing: prices rise by `step_bps` for every `step_tokens` (raw) sold, so
+    /// e.g. 500 bps per 10M tokens makes each 10M tranche 5% dearer than the base price. Both zero turns
+    /// it off. Only the admin can perform this action, and not under immutable pricing.
+    pub fn set_volume_pricing(ctx: Context<SetVolumePricing>, step_tokens: u64, step_bps: u16) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(!presale.immutable_pricing, PresaleError::PricingImmutable);
+        require!(
+            (step_tokens == 0) == (step_bps == 0),
+            PresaleError::InvalidVolumePricing
+        );
+
+        presale.volume_price_step_tokens = step_tokens;
+        presale.volume_price_step_bps = step_bps;
+        presale.bump_config_version()?;
+
+        emit!(VolumePricingUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            step_tokens,
+            step_bps,
+        });
+
+        msg!("Volume pricing set to +{} bps per {} tokens (raw) sold", step_bps, step_tokens);
+
+        Ok(())
+    }
+
+    /// Ramps prices up linearly over the public round, from the base price at its scheduled start to the
+    /// base price marked up by `end_markup_bps` (at most `MAX_PUBLIC_PRICE_RAMP_BPS`) at its scheduled end,
+    /// so earlier buyers get a better rate without manual price updates. 0 turns the ramp off. Only the
+    /// admin can perform this action, and not under immutable pricing.
+    pub fn set_public_price_ramp(ctx: Context<SetPublicPriceRamp>, end_markup_bps: u16) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(!presale.immutable_pricing, PresaleError::PricingImmutable);
+        require!(end_markup_bps <= MAX_PUBLIC_PRICE_RAMP_BPS, PresaleError::InvalidPriceRamp);
+
+        presale.public_price_ramp_bps = end_markup_bps;
+        presale.bump_config_version()?;
+
+        emit!(PublicPriceRampUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            end_markup_bps,
+        });
+
+        msg!("Public round price ramp set to +{} bps by the end of the round", end_markup_bps);
+
+        Ok(())
+    }
+
+    /// Sets bulk-purchase discount tiers by payment size, e.g. 2% off from $5k and 5% off from $25k. The
+    /// largest tier a purchase reaches applies in both buy instructions. Tiers must have increasing
+    /// thresholds and discounts; an empty list turns discounts off. Only the admin can perform this
+    /// action, and not under immutable pricing.
+    pub fn set_discount_tiers(ctx: Context<SetDiscountTiers>, tiers: Vec<DiscountTier>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(!presale.immutable_pricing, PresaleError::PricingImmutable);
+        require!(tiers.len() <= MAX_DISCOUNT_TIERS, PresaleError::InvalidDiscountTiers);
+
+        let mut schedule = [DiscountTier::default(); MAX_DISCOUNT_TIERS];
+        let mut previous = DiscountTier::default();
+        for (slot, tier) in tiers.iter().enumerate() {
+            require!(
+                tier.min_usd_cents > previous.min_usd_cents
+                    && tier.discount_bps > previous.discount_bps
+                    && tier.discount_bps < BPS_DENOMINATOR,
+                PresaleError::InvalidDiscountTiers
+            );
+            schedule[slot] = *tier;
+            previous = *tier;
+        }
+
+        presale.discount_tiers = schedule;
+        presale.bump_config_version()?;
+
+        emit!(DiscountTiersUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            tiers: tiers.clone(),
+        });
+
+        msg!("{} bulk-purchase discount tiers set", tiers.len());
+
+        Ok(())
+    }
+
+    /// Allows a buyer to purchase tokens using a stablecoin (e.g., USDC, USDT or PYUSD).
+    /// Supports Web3 (on-chain stablecoin transfer) payments; Web2 purchases go through `record_web2_purchase`.
+    /// Calculates tokens based on stablecoin amount and current price, updates total_sold.
+    pub fn buy_tokens_by_stable_coin(
+        ctx: Context<BuyTokensByStableCoin>,
+        payment_type: u8, // 0 = Web3
+        stable_coin_amount: u64, // Amount in the stablecoin mint's raw units
+        valid_until_timestamp: Option<i64>, // Abort if the purchase lands after this (Unix time)
+        whitelist_proof: Option<WhitelistProof>, // Buyer's tier and merkle proof, checked in the private stage
+        min_tokens_out: Option<u64>, // Abort if the purchase would buy fewer tokens (raw units) than this
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let buyer = &ctx.accounts.buyer;
+        let token_decimals = ctx.accounts.token_mint.decimals; 
+        let stable_coin_decimals = ctx.accounts.stable_coin_mint.decimals;
+
+        presale.ensure_not_locked_down()?;
+        ensure_purchase_not_expired(valid_until_timestamp)?;
+
+        // The stablecoin mint must be registered, and enabled, as an accepted currency
+        let accepted_currency = &ctx.accounts.accepted_currency;
+        require!(accepted_currency.enabled, PresaleError::CurrencyDisabled);
+        let currency = accepted_currency.currency;
+
+        // Normalize the raw amount to micro-dollars with the mint's own decimals, so stablecoins with
+        // other than 6 decimals are valued correctly. Stables are treated as 1:1 USD
+        let stable_coin_amount_micros = stable_coin_raw_to_micros(stable_coin_amount, stable_coin_decimals)?;
+        require!(
+            stable_coin_amount_micros >= 1,
+            PresaleError::InvalidPrice
+        );
+
+        require!(
+            presale.sale_stage == 1 || presale.sale_stage == 2,
+            PresaleError::PresaleNotActive
+        );
+
+        // Calculate tokens to purchase by dividing the micro-dollar amount by the NLOV price in micro-dollars
+        let usd_price_micros_per_nlov = purchase_price(presale, currency, None, None, None)?;
+        let discount_bps = purchase_discount_bps(
+            presale,
+            currency,
+            stable_coin_amount_micros,
+            usd_price_micros_per_nlov,
+            &buyer.key(),
+            whitelist_proof.as_ref(),
+            ctx.accounts.whitelist_entry.is_some(),
+        )?;
+        let tokens_to_purchase_user_units =
+            tokens_for_payment(stable_coin_amount_micros, usd_price_micros_per_nlov, discount_bps, presale.token_rounding_mode)?;
+
+        // Convert user-facing units to raw token units (with decimals) for internal tracking
+        let tokens_to_purchase_raw =
+            tokens_to_purchase_user_units.checked_mul(10u64.pow(token_decimals as u32)).ok_or(PresaleError::MathOverflow)?;
+        ensure_min_tokens_out(tokens_to_purchase_raw, min_tokens_out)?;
+
+        let (referral_commission_raw, referrer) = check_purchase(
+            presale,
+            &PurchaseAccounts {
+                buyer,
+                buyer_account: &ctx.accounts.buyer_account,
+                presale_wallet: &ctx.accounts.presale_wallet,
+                ticket: ctx.accounts.ticket.as_ref(),
+                attestation: ctx.accounts.attestation.as_ref(),
+                identity_verifier_program: ctx.accounts.identity_verifier_program.as_ref(),
+                instructions_sysvar: ctx.accounts.instructions_sysvar.as_ref(),
+                referrer_account: ctx.accounts.referrer_account.as_ref(),
+                whitelist_entry: ctx.accounts.whitelist_entry.as_ref(),
+                blacklist: ctx.accounts.blacklist.as_ref(),
+                gatekeeper_signed: ctx.accounts.gatekeeper.is_some(),
+                purchase_commitment: ctx.accounts.purchase_commitment.as_ref(),
+            },
+            stable_coin_amount,
+            whitelist_proof.as_ref(),
+            tokens_to_purchase_raw,
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        // Only the exact cost of the tokens is taken, rounded up to the mint's raw units; the part that
+        // doesn't convert into a whole token stays with the buyer
+        let stable_coin_charged = micros_to_stable_coin_raw(
+            payment_for_tokens(tokens_to_purchase_user_units, usd_price_micros_per_nlov, discount_bps)?,
+            stable_coin_decimals,
+        )?;
+        require!(stable_coin_charged <= stable_coin_amount, PresaleError::InvalidPrice);
+        // The protocol fee is taken out of on-chain payments; only the rest is escrowed as proceeds
+        let protocol_fee = if payment_type == 0 { presale.protocol_fee(stable_coin_charged)? } else { 0 };
+        let amount_escrowed = stable_coin_charged - protocol_fee;
+
+        if payment_type == 0 {
+            // Web3 payment: Transfer stable coins on-chain into the escrow vault. Token-2022 mints may
+            // charge a transfer fee, so check the buyer was debited, and the vault credited, the exact amount
+            let buyer_balance_before = ctx.accounts.buyer_stable_coin_account.amount;
+            let escrow_balance_before = ctx.accounts.escrow_stable_coin_account.amount;
+            transfer_stable_coin(
+                &ctx.accounts.token_program,
+                &ctx.accounts.stable_coin_mint,
+                ctx.accounts.buyer_stable_coin_account.to_account_info(),
+                ctx.accounts.escrow_stable_coin_account.to_account_info(),
+                ctx.accounts.buyer.to_account_info(),
+                &[],
+                amount_escrowed,
+            )?;
+            ctx.accounts.buyer_stable_coin_account.reload()?;
+            ctx.accounts.escrow_stable_coin_account.reload()?;
+            require!(
+                buyer_balance_before.saturating_sub(ctx.accounts.buyer_stable_coin_account.amount) == amount_escrowed
+                    && ctx.accounts.escrow_stable_coin_account.amount.saturating_sub(escrow_balance_before) == amount_escrowed,
+                PresaleError::TransferFeeNotSupported
+            );
+            if protocol_fee > 0 {
+                let fee_account = ctx
+                    .accounts
+                    .fee_stable_coin_account
+                    .as_ref()
+                    .ok_or(PresaleError::FeeAccountRequired)?;
+                transfer_stable_coin(
+                    &ctx.accounts.token_program,
+                    &ctx.accounts.stable_coin_mint,
+                    ctx.accounts.buyer_stable_coin_account.to_account_info(),
+                    fee_account.to_account_info(),
+                    ctx.accounts.buyer.to_account_info(),
+                    &[],
+                    protocol_fee,
+                )?;
+            }
+            let escrowed = presale.escrowed_stable_coin(currency);
+            *escrowed = escrowed.checked_add(amount_escrowed).ok_or(PresaleError::MathOverflow)?;
+        } else {
+            return Err(PresaleError::InvalidPaymentType.into());
+        }
+
+        // Update `total_sold` with raw token units
+        presale.total_sold = presale
+            .total_sold
+            .checked_add(tokens_to_purchase_raw)
+            .ok_or(PresaleError::MathOverflow)?;
+        presale.purchases_in_stage = presale.purchases_in_stage.checked_add(1).ok_or(PresaleError::MathOverflow)?;
+
+        // Track the purchase against the buyer's own allocation
+        let first_purchase = ctx.accounts.buyer_account.record_purchase(
+            presale.key(),
+            buyer.key(),
+            ctx.bumps.buyer_account,
+            PurchaseRecord {
+                timestamp: Clock::get()?.unix_timestamp,
+                currency,
+                amount_paid: amount_escrowed,
+                tokens: tokens_to_purchase_raw,
+                stage: presale.sale_stage,
+                payment_type,
+                reversed: false,
+                config_version: presale.config_version,
+                referrer,
+                referral_commission: referral_commission_raw,
+            },
+        )?;
+        sync_buyer_registry(
+            presale,
+            &mut ctx.accounts.buyer_account,
+            &ctx.accounts.registry_page,
+            first_purchase,
+        )?;
+
+        // Track refundable on-chain payments
+        if payment_type == 0 {
+            ctx.accounts.buyer_account.record_contribution(currency, amount_escrowed)?;
+            ctx.accounts.buyer_account.record_protocol_fee(currency, protocol_fee)?;
+        }
+
+        // Credit the referrer's commission
+        credit_referrer(
+            presale,
+            ctx.accounts.referrer_account.as_mut(),
+            buyer.key(),
+            referral_commission_raw,
+        )?;
+
+        // Feed the recent purchases ticker
+        record_in_journal(
+            presale,
+            ctx.accounts.purchase_journal.as_ref(),
+            JournalEntry {
+                buyer: buyer.key(),
+                timestamp: Clock::get()?.unix_timestamp,
+                tokens: tokens_to_purchase_raw,
+                amount_paid: stable_coin_charged,
+                currency,
+                _padding: [0; 7],
+            },
+        )?;
+
+        emit!(BuyTokensByStableCoinEvent {
+            buyer: buyer.key(),
+            tokens_purchased: tokens_to_purchase_user_units,
+            stable_coin_amount: stable_coin_charged,
+            payment_type,
+            discount_bps,
+            rounding_mode: presale.token_rounding_mode,
+            protocol_fee,
+        });
+
+        let stable_coin_symbol = match currency {
+            CURRENCY_USDC => "USDC",
+            CURRENCY_USDT => "USDT",
+            CURRENCY_PYUSD => "PYUSD",
+            _ => return Err(PresaleError::InvalidStableToken.into()),
+        };
+        msg!(
+            "Buyer {} purchased {} tokens with {} {} (raw units, {} decimals) using payment_type: {}",
+            buyer.key(),
+            tokens_to_purchase_user_units,
+            stable_coin_charged,
+            stable_coin_symbol,
+            stable_coin_decimals,
+            payment_type
+        );
+
+        Ok(())
+    }
+
+    /// Allows a buyer to purchase tokens in the public round with any SPL token registered through
+    /// `add_payment_token` (e.g. JUP or BONK), valuing `amount` (raw units) in USD at the token's Pyth price.
+    /// The payment is held in escrow, refundable through `claim_token_refund`, and recorded as `CURRENCY_TOKEN`.
+    pub fn buy_tokens_with_token(
+        ctx: Context<BuyTokensWithToken>,
+        amount: u64, // Amount in the payment token's raw units
+        valid_until_timestamp: Option<i64>, // Abort if the purchase lands after this (Unix time)
+        whitelist_proof: Option<WhitelistProof>, // Buyer's tier and merkle proof, checked while a whitelist is active
+        min_tokens_out: Option<u64>, // Abort if the purchase would buy fewer tokens (raw units) than this
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let buyer = &ctx.accounts.buyer;
+        let token_decimals = ctx.accounts.token_mint.decimals;
+        let now = Clock::get()?.unix_timestamp;
+
+        presale.ensure_not_locked_down()?;
+        ensure_purchase_not_expired(valid_until_timestamp)?;
+
+        let payment_token = &ctx.accounts.payment_token;
+        require!(payment_token.enabled, PresaleError::CurrencyDisabled);
+        require!(presale.sale_stage == 2, PresaleError::PublicRoundOnly);
+
+        // Value the payment in micro-dollars at the token's oracle price
+        let (token_price, token_price_exponent) = read_payment_token_price(payment_token, &ctx.accounts.price_update, now)?;
+        let payment_micros = token_amount_to_usd_micros(amount, payment_token.decimals, token_price, token_price_exponent)?;
+        require!(payment_micros >= 1, PresaleError::InvalidPrice);
+
+        let usd_price_micros_per_nlov = purchase_price(presale, CURRENCY_TOKEN, None, None, None)?;
+        let discount_bps = purchase_discount_bps(
+            presale,
+            CURRENCY_TOKEN,
+            payment_micros,
+            usd_price_micros_per_nlov,
+            &buyer.key(),
+            whitelist_proof.as_ref(),
+            ctx.accounts.whitelist_entry.is_some(),
+        )?;
+        let tokens_to_purchase_user_units =
+            tokens_for_payment(payment_micros, usd_price_micros_per_nlov, discount_bps, presale.token_rounding_mode)?;
+        let tokens_to_purchase_raw =
+            tokens_to_purchase_user_units.checked_mul(10u64.pow(token_decimals as u32)).ok_or(PresaleError::MathOverflow)?;
+        ensure_min_tokens_out(tokens_to_purchase_raw, min_tokens_out)?;
+
+        let (referral_commission_raw, referrer) = check_purchase(
+            presale,
+            &PurchaseAccounts {
+                buyer,
+                buyer_account: &ctx.accounts.buyer_account,
+                presale_wallet: &ctx.accounts.presale_wallet,
+                ticket: ctx.accounts.ticket.as_ref(),
+                attestation: ctx.accounts.attestation.as_ref(),
+                identity_verifier_program: ctx.accounts.identity_verifier_program.as_ref(),
+                instructions_sysvar: ctx.accounts.instructions_sysvar.as_ref(),
+                referrer_account: ctx.accounts.referrer_account.as_ref(),
+                whitelist_entry: ctx.accounts.whitelist_entry.as_ref(),
+                blacklist: ctx.accounts.blacklist.as_ref(),
+                gatekeeper_signed: ctx.accounts.gatekeeper.is_some(),
+                purchase_commitment: ctx.accounts.purchase_commitment.as_ref(),
+            },
+            amount,
+            whitelist_proof.as_ref(),
+            tokens_to_purchase_raw,
+            now,
+        )?;
+
+        // Only the exact cost of the tokens is taken, rounded up to the token's raw units. It is escrowed
+        // less the protocol fee, refundable to this buyer until the sale is finalized
+        let amount_charged = usd_micros_to_token_amount(
+            payment_for_tokens(tokens_to_purchase_user_units, usd_price_micros_per_nlov, discount_bps)?,
+            ctx.accounts.payment_token.decimals,
+            token_price,
+            token_price_exponent,
+        )?;
+        require!(amount_charged <= amount, PresaleError::InvalidPrice);
+        let protocol_fee = presale.protocol_fee(amount_charged)?;
+        let amount_escrowed = amount_charged - protocol_fee;
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TokenTransfer {
+                    from: ctx.accounts.buyer_payment_account.to_account_info(),
+                    to: ctx.accounts.escrow_payment_account.to_account_info(),
+                    authority: buyer.to_account_info(),
+                },
+            ),
+            amount_escrowed,
+        )?;
+        if protocol_fee > 0 {
+            let fee_account = ctx.accounts.fee_payment_account.as_ref().ok_or(PresaleError::FeeAccountRequired)?;
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    TokenTransfer {
+                        from: ctx.accounts.buyer_payment_account.to_account_info(),
+                        to: fee_account.to_account_info(),
+                        authority: buyer.to_account_info(),
+                    },
+                ),
+                protocol_fee,
+            )?;
+        }
+        let payment_token = &mut ctx.accounts.payment_token;
+        let escrowed_before = payment_token.escrowed;
+        payment_token.escrowed = payment_token.escrowed.checked_add(amount_escrowed).ok_or(PresaleError::MathOverflow)?;
+        presale.track_payment_token_escrow(escrowed_before, payment_token.escrowed)?;
+        let token_contribution = &mut ctx.accounts.token_contribution;
+        token_contribution.presale = presale.key();
+        token_contribution.buyer = buyer.key();
+        token_contribution.mint = payment_token.mint;
+        token_contribution.amount = token_contribution.amount.checked_add(amount_escrowed).ok_or(PresaleError::MathOverflow)?;
+        token_contribution.bump = ctx.bumps.token_contribution;
+
+        presale.total_sold = presale.total_sold.checked_add(tokens_to_purchase_raw).ok_or(PresaleError::MathOverflow)?;
+        presale.purchases_in_stage = presale.purchases_in_stage.checked_add(1).ok_or(PresaleError::MathOverflow)?;
+
+        let first_purchase = ctx.accounts.buyer_account.record_purchase(
+            presale.key(),
+            buyer.key(),
+            ctx.bumps.buyer_account,
+            PurchaseRecord {
+                timestamp: now,
+                currency: CURRENCY_TOKEN,
+                amount_paid: amount_escrowed,
+                tokens: tokens_to_purchase_raw,
+                stage: presale.sale_stage,
+                payment_type: 0,
+                reversed: false,
+                config_version: presale.config_version,
+                referrer,
+                referral_commission: referral_commission_raw,
+            },
+        )?;
+        sync_buyer_registry(
+            presale,
+            &mut ctx.accounts.buyer_account,
+            &ctx.accounts.registry_page,
+            first_purchase,
+        )?;
+
+        credit_referrer(
+            presale,
+            ctx.accounts.referrer_account.as_mut(),
+            buyer.key(),
+            referral_commission_raw,
+        )?;
+
+        record_in_journal(
+            presale,
+            ctx.accounts.purchase_journal.as_ref(),
+            JournalEntry {
+                buyer: buyer.key(),
+                timestamp: now,
+                tokens: tokens_to_purchase_raw,
+                amount_paid: amount_charged,
+                currency: CURRENCY_TOKEN,
+                _padding: [0; 7],
+            },
+        )?;
+
+        emit!(BuyTokensWithTokenEvent {
+            buyer: buyer.key(),
+            mint: payment_token.mint,
+            tokens_purchased: tokens_to_purchase_user_units,
+            amount: amount_charged,
+            usd_micros: payment_micros,
+            discount_bps,
+            rounding_mode: presale.token_rounding_mode,
+            protocol_fee,
+        });
+
+        msg!(
+            "Buyer {} purchased {} tokens with {} (raw) of {}, worth {} USD micro-dollars",
+            buyer.key(),
+            tokens_to_purchase_user_units,
+            amount_charged,
+            payment_token.mint,
+            payment_micros
+        );
+
+        Ok(())
+    }
+
+    /// Registers `stable_coin_mint` as the stablecoin accepted for `currency` (`CURRENCY_USDC`,
+    /// `CURRENCY_USDT` or `CURRENCY_PYUSD`), enabled and with the mint's decimals, so devnet or replacement
+    /// mints can be taken without redeploying. PYUSD is a Token-2022 mint; USDC and USDT must be SPL Token
+    /// mints. Only the admin can perform this action.
+    pub fn add_accepted_currency(ctx: Context<AddAcceptedCurrency>, currency: u8) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(
+            currency == CURRENCY_USDC || currency == CURRENCY_USDT || currency == CURRENCY_PYUSD,
+            PresaleError::InvalidCurrency
+        );
+
+        let stable_coin_mint = &ctx.accounts.stable_coin_mint;
+        let expected_token_program = if currency == CURRENCY_PYUSD { token_2022::ID } else { token::ID };
+        require!(
+            *stable_coin_mint.to_account_info().owner == expected_token_program,
+            PresaleError::InvalidStableToken
+        );
+        let accepted_currency = &mut ctx.accounts.accepted_currency;
+        accepted_currency.presale = presale.key();
+        accepted_currency.currency = currency;
+        accepted_currency.mint = stable_coin_mint.key();
+        accepted_currency.decimals = stable_coin_mint.decimals;
+        accepted_currency.enabled = true;
+        accepted_currency.bump = ctx.bumps.accepted_currency;
+
+        presale.set_stable_coin_mint(currency, stable_coin_mint.key());
+
+        emit!(AcceptedCurrencyAddedEvent {
+            admin: ctx.accounts.admin.key(),
+            currency,
+            mint: stable_coin_mint.key(),
+            decimals: stable_coin_mint.decimals,
+        });
+
+        msg!(
+            "Mint {} accepted as currency {} ({} decimals)",
+            stable_coin_mint.key(),
+            currency,
+            stable_coin_mint.decimals
+        );
+
+        Ok(())
+    }
+
+    /// Pauses or resumes purchases in an accepted stablecoin. Escrowed proceeds in it can still be
+    /// refunded, reversed and withdrawn. Only the admin can perform this action.
+    pub fn set_accepted_currency_enabled(ctx: Context<UpdateAcceptedCurrency>, enabled: bool) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        let accepted_currency = &mut ctx.accounts.accepted_currency;
+        accepted_currency.enabled = enabled;
+
+        emit!(AcceptedCurrencyEnabledEvent {
+            admin: ctx.accounts.admin.key(),
+            currency: accepted_currency.currency,
+            mint: accepted_currency.mint,
+            enabled,
+        });
+
+        msg!("Currency {} enabled: {}", accepted_currency.currency, enabled);
+
+        Ok(())
+    }
+
+    /// Removes an accepted stablecoin, closing its `AcceptedCurrency` entry and refunding the rent to the
+    /// admin, so another mint can be registered for the currency. Only once nothing in it is escrowed.
+    /// Only the admin can perform this action.
+    pub fn remove_accepted_currency(ctx: Context<RemoveAcceptedCurrency>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let currency = ctx.accounts.accepted_currency.currency;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(*presale.escrowed_stable_coin(currency) == 0, PresaleError::CurrencyStillEscrowed);
+
+        presale.set_stable_coin_mint(currency, Pubkey::default());
+
+        emit!(AcceptedCurrencyRemovedEvent {
+            admin: ctx.accounts.admin.key(),
+            currency,
+            mint: ctx.accounts.accepted_currency.mint,
+        });
+
+        msg!("Currency {} ({}) removed", currency, ctx.accounts.accepted_currency.mint);
+
+        Ok(())
+    }
+
+    /// Registers `payment_mint` as a token `buy_tokens_with_token` accepts in the public round, priced by the
+    /// Pyth `PriceUpdateV2` account `price_feed` for feed `feed_id`, rejecting prices older than
+    /// `max_price_age` seconds or with a confidence interval wider than `max_confidence_bps` of the price
+    /// (0 = no limit). Only the admin can perform this action.
+    pub fn add_payment_token(
+        ctx: Context<AddPaymentToken>,
+        price_feed: Pubkey,
+        feed_id: [u8; 32],
+        max_price_age: i64,
+        max_confidence_bps: u16,
+    ) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(price_feed != Pubkey::default(), PresaleError::InvalidOracleConfig);
+        require!(max_price_age > 0, PresaleError::InvalidOracleConfig);
+        require!(max_confidence_bps <= BPS_DENOMINATOR, PresaleError::InvalidOracleConfig);
+
+        let payment_mint = &ctx.accounts.payment_mint;
+        let payment_token = &mut ctx.accounts.payment_token;
+        payment_token.presale = presale.key();
+        payment_token.mint = payment_mint.key();
+        payment_token.decimals = payment_mint.decimals;
+        payment_token.price_feed = price_feed;
+        payment_token.feed_id = feed_id;
+        payment_token.max_price_age = max_price_age;
+        payment_token.max_confidence_bps = max_confidence_bps;
+        payment_token.enabled = true;
+        payment_token.escrowed = 0;
+        payment_token.bump = ctx.bumps.payment_token;
+
+        emit!(PaymentTokenAddedEvent {
+            admin: ctx.accounts.admin.key(),
+            mint: payment_mint.key(),
+            decimals: payment_mint.decimals,
+            price_feed,
+            max_price_age,
+            max_confidence_bps,
+        });
+
+        msg!(
+            "Mint {} accepted as payment, priced by {} (max age {} seconds, max confidence {} bps)",
+            payment_mint.key(),
+            price_feed,
+            max_price_age,
+            max_confidence_bps
+        );
+
+        Ok(())
+    }
+
+    /// Pauses or resumes purchases in a payment token. Escrowed payments in it can still be refunded
+    /// and withdrawn. Only the admin can perform this action.
+    pub fn set_payment_token_enabled(ctx: Context<UpdatePaymentToken>, enabled: bool) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        let payment_token = &mut ctx.accounts.payment_token;
+        payment_token.enabled = enabled;
+
+        emit!(PaymentTokenEnabledEvent {
+            admin: ctx.accounts.admin.key(),
+            mint: payment_token.mint,
+            enabled,
+        });
+
+        msg!("Payment token {} enabled: {}", payment_token.mint, enabled);
+
+        Ok(())
+    }
+
+    /// Finalizes the presale by transferring any unsold tokens from the presale wallet
+    /// to a designated liquidity wallet.
+    /// This can only be done by the admin after the sale has ended and before a liquidity pool is created.
+    pub fn finalize_presale(ctx: Context<FinalizePresale>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let admin_key = ctx.accounts.admin.key();
+        let bump = ctx.bumps.presale;
+        let token_decimals = ctx.accounts.token_mint.decimals; 
+
+        presale.ensure_not_locked_down()?;
+
+        require!(presale.admin == admin_key, PresaleError::Unauthorized);
+
+        require!(presale.sale_stage == 3, PresaleError::PresaleActive);
+
+        require!(!presale.cancelled, PresaleError::SaleCancelled);
+        require!(
+            presale.total_sold >= presale.softcap_tokens,
+            PresaleError::SoftcapNotMet
+        );
+
+        require!(
+            !presale.pool_created,
+            PresaleError::LiquidityPoolAlreadyCreated
+        );
+
+        // Calculate unsold presale tokens in raw units
+        let available_presale_tokens_raw = ctx.accounts.presale_wallet.amount;
+        let unsold_presale_tokens_raw =
+            available_presale_tokens_raw.saturating_sub(presale.outstanding_allocations());
+
+        let seeds: &[&[u8]] = &[PRESALE_SEED, admin_key.as_ref(), &[bump]];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        let now = Clock::get()?.unix_timestamp;
+
+        if presale.unsold_stream_months > 0 {
+            // Fix the stream schedule; tranches are released by `release_next_tranche`
+            presale.unsold_stream_total = unsold_presale_tokens_raw;
+            presale.unsold_stream_start = now;
+            presale.unsold_stream_released = 0;
+
+            emit!(UnsoldStreamScheduledEvent {
+                community_rewards_wallet: presale.community_rewards_wallet,
+                total: unsold_presale_tokens_raw,
+                months: presale.unsold_stream_months,
+                start: now,
+            });
+        } else if unsold_presale_tokens_raw > 0 {
+            // Transfer unsold presale tokens to liquidity wallet if any exist
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TokenTransfer {
+                        from: ctx.accounts.presale_wallet.to_account_info(),
+                        to: ctx.accounts.liquidity_wallet.to_account_info(),
+                        authority: presale.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                unsold_presale_tokens_raw,
+            )?;
+        }
+
+        presale.pool_created = true;
+        if presale.vesting_start == 0 {
+            presale.vesting_start = now; // Vesting runs from finalization unless scheduled
+        }
+
+        emit!(FinalizePresaleEvent {
+            admin: ctx.accounts.admin.key(),
+            unsold_presale_tokens: unsold_presale_tokens_raw / 10u64.pow(token_decimals as u32), // Emit user-facing units
+        });
+
+        if presale.unsold_stream_months > 0 {
+            msg!(
+                "Presale finalized! {} unsold presale tokens streaming to the community rewards wallet over {} months.",
+                unsold_presale_tokens_raw / 10u64.pow(token_decimals as u32),
+                presale.unsold_stream_months,
+            );
+        } else {
+            msg!(
+                "Presale finalized! {} unsold presale tokens moved to liquidity wallet.",
+                unsold_presale_tokens_raw / 10u64.pow(token_decimals as u32),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Mints a non-transferable receipt NFT to a buyer, encoding their allocation at mint time.
+    /// One receipt per wallet; the buyer must have made at least one purchase.
+    pub fn mint_purchase_receipt(ctx: Context<MintPurchaseReceipt>) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+        let buyer_account = &mut ctx.accounts.buyer_account;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            buyer_account.tokens_purchased > 0,
+            PresaleError::NoPurchaseRecorded
+        );
+        require!(
+            !buyer_account.receipt_minted,
+            PresaleError::ReceiptAlreadyMinted
+        );
+
+        let seeds: &[&[u8]] = &[PRESALE_SEED, presale.admin.as_ref(), &[presale.bump]];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        // Mint the single receipt token to the buyer
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.receipt_mint.to_account_info(),
+                    to: ctx.accounts.receipt_token_account.to_account_info(),
+                    authority: presale.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            1,
+        )?;
+
+        // Freeze the buyer's receipt account so the NFT cannot be transferred.
+        // This must happen before the master edition takes over the freeze authority.
+        token::freeze_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            FreezeAccount {
+                account: ctx.accounts.receipt_token_account.to_account_info(),
+                mint: ctx.accounts.receipt_mint.to_account_info(),
+                authority: presale.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
+
+        create_metadata_accounts_v3(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_metadata_program.to_account_info(),
+                CreateMetadataAccountsV3 {
+                    metadata: ctx.accounts.metadata.to_account_info(),
+                    mint: ctx.accounts.receipt_mint.to_account_info(),
+                    mint_authority: presale.to_account_info(),
+                    payer: ctx.accounts.buyer.to_account_info(),
+                    update_authority: presale.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    rent: ctx.accounts.rent.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            DataV2 {
+                name: RECEIPT_NAME.to_string(),
+                symbol: RECEIPT_SYMBOL.to_string(),
+                uri: format!(
+                    "{}?allocation={}",
+                    RECEIPT_METADATA_URI, buyer_account.tokens_purchased
+                ),
+                seller_fee_basis_points: 0,
+                creators: None,
+                collection: None,
+                uses: None,
+            },
+            false, // Receipts are immutable
+            true,
+            None,
+        )?;
+
+        create_master_edition_v3(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_metadata_program.to_account_info(),
+                CreateMasterEditionV3 {
+                    edition: ctx.accounts.master_edition.to_account_info(),
+                    mint: ctx.accounts.receipt_mint.to_account_info(),
+                    update_authority: presale.to_account_info(),
+                    mint_authority: presale.to_account_info(),
+                    payer: ctx.accounts.buyer.to_account_info(),
+                    metadata: ctx.accounts.metadata.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    rent: ctx.accounts.rent.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            Some(0), // No prints: the receipt is unique
+        )?;
+
+        buyer_account.receipt_minted = true;
+
+        emit!(ReceiptMintedEvent {
+            buyer: buyer_account.buyer,
+            receipt_mint: ctx.accounts.receipt_mint.key(),
+            tokens_purchased: buyer_account.tokens_purchased,
+        });
+
+        msg!(
+            "Receipt NFT {} minted for buyer {} (allocation raw: {})",
+            ctx.accounts.receipt_mint.key(),
+            buyer_account.buyer,
+            buyer_account.tokens_purchased
+        );
+
+        Ok(())
+    }
+
+    /// Returns the escrow PDA's SOL balance and each stablecoin vault balance, alongside
+    /// the outstanding refund obligations for each currency. Registered payment tokens are reported for
+    /// each (`PaymentToken`, escrow vault) pair passed as remaining accounts.
+    /// Anyone can call this to verify that escrow covers what buyers could be owed.
+    pub fn get_escrow_balances<'info>(ctx: Context<'_, '_, 'info, 'info, GetEscrowBalances<'info>>) -> Result<EscrowBalances> {
+        let presale = &ctx.accounts.presale;
+
+        // Escrowed funds stay refundable until the sale is finalized
+        let (sol_obligations, usdc_obligations, usdt_obligations, pyusd_obligations) = if presale.pool_created {
+            (0, 0, 0, 0)
+        } else {
+            (presale.escrowed_lamports, presale.escrowed_usdc, presale.escrowed_usdt, presale.escrowed_pyusd)
+        };
+
+        let balances = EscrowBalances {
+            sol_balance: ctx.accounts.escrow.lamports(),
+            usdc_balance: ctx.accounts.escrow_usdc_account.as_ref().map_or(0, |vault| vault.amount),
+            usdt_balance: ctx.accounts.escrow_usdt_account.as_ref().map_or(0, |vault| vault.amount),
+            sol_obligations,
+            usdc_obligations,
+            usdt_obligations,
+            pyusd_balance: ctx.accounts.escrow_pyusd_account.as_ref().map_or(0, |vault| vault.amount),
+            pyusd_obligations,
+            payment_tokens: escrowed_payment_token_balances(
+                presale,
+                &ctx.accounts.escrow.key(),
+                ctx.remaining_accounts,
+            )?,
+        };
+
+        msg!(
+            "Escrow balances: {} lamports, {} USDC (raw), {} USDT (raw), {} PYUSD (raw). Obligations: {} lamports, {} USDC (raw), {} USDT (raw), {} PYUSD (raw)",
+            balances.sol_balance,
+            balances.usdc_balance,
+            balances.usdt_balance,
+            balances.pyusd_balance,
+            balances.sol_obligations,
+            balances.usdc_obligations,
+            balances.usdt_obligations,
+            balances.pyusd_obligations
+        );
+        for payment_token in &balances.payment_tokens {
+            msg!(
+                "Escrow balance of {}: {} (raw). Obligations: {} (raw)",
+                payment_token.mint,
+                payment_token.balance,
+                payment_token.obligations
+            );
+        }
+
+        Ok(balances)
+    }
+
+    /// Takes a protocol fee of `fee_bps` (at most `MAX_PROTOCOL_FEE_BPS`) out of every on-chain payment,
+    /// routed to `fee_wallet` (or its token account for the paid mint) instead of escrow, so the launchpad
+    /// hosting the sale takes its cut on-chain. Set `fee_bps` to 0 to stop charging it.
+    /// Only the fee authority that co-signed the sale's creation can do this, not the admin.
+    pub fn set_protocol_fee(ctx: Context<SetProtocolFee>, fee_bps: u16, fee_wallet: Pubkey) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            fee_bps <= MAX_PROTOCOL_FEE_BPS && (fee_bps == 0 || fee_wallet != Pubkey::default()),
+            PresaleError::InvalidProtocolFee
+        );
+
+        presale.protocol_fee_bps = fee_bps;
+        presale.fee_wallet = fee_wallet;
+        presale.bump_config_version()?;
+
+        emit!(ProtocolFeeUpdatedEvent {
+            fee_authority: ctx.accounts.fee_authority.key(),
+            fee_bps,
+            fee_wallet,
+        });
+
+        msg!("Protocol fee set to {} bps, paid to {}", fee_bps, fee_wallet);
+
+        Ok(())
+    }
+
+    /// Splits proceeds paid out by `withdraw_proceeds` and `settle_period` across up to
+    /// `MAX_TREASURY_WALLETS` treasury wallets (e.g. marketing, development, liquidity) by basis points,
+    /// instead of paying everything to the merchant wallet. Shares must be positive, go to distinct
+    /// wallets and add up to 100%; an empty list pays the merchant wallet again. A SOL share must leave
+    /// its wallet rent-exempt, so new treasury wallets need funding before small payouts.
+    /// Only the admin can do this.
+    pub fn set_treasury_splits(ctx: Context<SetTreasurySplits>, splits: Vec<TreasurySplit>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(splits.len() <= MAX_TREASURY_WALLETS, PresaleError::InvalidTreasurySplits);
+
+        let mut schedule = [TreasurySplit::default(); MAX_TREASURY_WALLETS];
+        let mut total_bps: u32 = 0;
+        for (slot, split) in splits.iter().enumerate() {
+            require!(
+                split.wallet != Pubkey::default()
+                    && split.share_bps > 0
+                    && !splits[..slot].iter().any(|other| other.wallet == split.wallet),
+                PresaleError::InvalidTreasurySplits
+            );
+            total_bps += split.share_bps as u32;
+            schedule[slot] = *split;
+        }
+        require!(
+            splits.is_empty() || total_bps == BPS_DENOMINATOR as u32,
+            PresaleError::InvalidTreasurySplits
+        );
+
+        presale.treasury_splits = schedule;
+        presale.bump_config_version()?;
+
+        emit!(TreasurySplitsUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            splits: splits.clone(),
+        });
+
+        msg!("Proceeds split across {} treasury wallets", splits.len());
+
+        Ok(())
+    }
+
+    /// Moves `amount` of the escrowed proceeds of one currency (lamports, or raw token units) to the
+    /// merchant wallet, emitting a `ProceedsWithdrawnEvent` for every withdrawal. At most the proceeds
+    /// recorded as escrowed can leave, and the escrow keeps its rent-exempt reserve.
+    /// Only the admin can do this, and only once the presale has been finalized.
+    pub fn withdraw_proceeds<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawProceeds<'info>>,
+        currency: u8,
+        amount: u64,
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let presale_key = presale.key();
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        require!(presale.pool_created, PresaleError::PresaleNotFinalized);
+        require!(!presale.refunds_enabled(), PresaleError::SaleCancelled);
+        require!(amount > 0, PresaleError::NothingToWithdraw);
+
+        let escrow_seeds: &[&[u8]] = &[ESCROW_SEED, presale_key.as_ref(), &[presale.escrow_bump]];
+        let signer_seeds: &[&[&[u8]]] = &[escrow_seeds];
+        let mut treasury_accounts = ctx.remaining_accounts.iter();
+
+        let (mint, remaining) = match currency {
+            CURRENCY_SOL => {
+                // The escrow keeps its rent-exempt reserve, and lamports sent to it outside a purchase stay put
+                let balance = ctx.accounts.escrow.lamports();
+                let available = balance
+                    .saturating_sub(Rent::get()?.minimum_balance(0))
+                    .min(presale.escrowed_lamports);
+                require!(amount <= available, PresaleError::InsufficientEscrowBalance);
+
+                let payouts = treasury_payouts(
+                    presale,
+                    amount,
+                    Some(ctx.accounts.merchant_wallet.to_account_info()),
+                    &mut treasury_accounts,
+                    None,
+                )?;
+                for (recipient, share) in payouts {
+                    system_program::transfer(
+                        CpiContext::new_with_signer(
+                            ctx.accounts.system_program.to_account_info(),
+                            SystemTransfer {
+                                from: ctx.accounts.escrow.to_account_info(),
+                                to: recipient,
+                            },
+                            signer_seeds,
+                        ),
+                        share,
+                    )?;
+                }
+                presale.escrowed_lamports = presale.escrowed_lamports.checked_sub(amount).ok_or(PresaleError::MathOverflow)?;
+                (Pubkey::default(), balance - amount)
+            }
+            CURRENCY_USDC | CURRENCY_USDT | CURRENCY_PYUSD => {
+                let stable_coin_mint = presale.stable_coin_mint(currency);
+                let mint_account = ctx
+                    .accounts
+                    .stable_coin_mint
+                    .as_ref()
+                    .ok_or(PresaleError::InvalidStableToken)?;
+                let escrow_vault = ctx
+                    .accounts
+                    .escrow_stable_coin_account
+                    .as_ref()
+                    .ok_or(PresaleError::InvalidTokenAccount)?;
+                let merchant_account = ctx.accounts.merchant_stable_coin_account.as_ref();
+
+                require!(
+                    stable_coin_mint != Pubkey::default()
+                        && mint_account.key() == stable_coin_mint
+                        && escrow_vault.mint == stable_coin_mint
+                        && merchant_account.is_none_or(|account| account.mint == stable_coin_mint),
+                    PresaleError::InvalidStableToken
+                );
+
+                let balance = escrow_vault.amount;
+                require!(
+                    amount <= balance.min(*presale.escrowed_stable_coin(currency)),
+                    PresaleError::InsufficientEscrowBalance
+                );
+
+                let payouts = treasury_payouts(
+                    presale,
+                    amount,
+                    merchant_account.map(|account| account.to_account_info()),
+                    &mut treasury_accounts,
+                    Some(mint_account.key()),
+                )?;
+                for (recipient, share) in payouts {
+                    transfer_stable_coin(
+                        &ctx.accounts.token_program,
+                        mint_account,
+                        escrow_vault.to_account_info(),
+                        recipient,
+                        ctx.accounts.escrow.to_account_info(),
+                        signer_seeds,
+                        share,
+                    )?;
+                }
+                let escrowed = presale.escrowed_stable_coin(currency);
+                *escrowed = escrowed.checked_sub(amount).ok_or(PresaleError::MathOverflow)?;
+                (stable_coin_mint, balance - amount)
+            }
+            CURRENCY_TOKEN => {
+                let payment_token = ctx
+                    .accounts
+                    .payment_token
+                    .as_mut()
+                    .ok_or(PresaleError::InvalidStableToken)?;
+                let mint_account = ctx
+                    .accounts
+                    .stable_coin_mint
+                    .as_ref()
+                    .ok_or(PresaleError::InvalidStableToken)?;
+                let escrow_vault = ctx
+                    .accounts
+                    .escrow_stable_coin_account
+                    .as_ref()
+                    .ok_or(PresaleError::InvalidTokenAccount)?;
+                let merchant_account = ctx.accounts.merchant_stable_coin_account.as_ref();
+
+                require!(
+                    mint_account.key() == payment_token.mint
+                        && escrow_vault.mint == payment_token.mint
+                        && merchant_account.is_none_or(|account| account.mint == payment_token.mint),
+                    PresaleError::InvalidStableToken
+                );
+
+                let balance = escrow_vault.amount;
+                require!(
+                    amount <= balance.min(payment_token.escrowed),
+                    PresaleError::InsufficientEscrowBalance
+                );
+
+                let payouts = treasury_payouts(
+                    presale,
+                    amount,
+                    merchant_account.map(|account| account.to_account_info()),
+                    &mut treasury_accounts,
+                    Some(mint_account.key()),
+                )?;
+                for (recipient, share) in payouts {
+                    transfer_stable_coin(
+                        &ctx.accounts.token_program,
+                        mint_account,
+                        escrow_vault.to_account_info(),
+                        recipient,
+                        ctx.accounts.escrow.to_account_info(),
+                        signer_seeds,
+                        share,
+                    )?;
+                }
+                let escrowed_before = payment_token.escrowed;
+                payment_token.escrowed = payment_token.escrowed.checked_sub(amount).ok_or(PresaleError::MathOverflow)?;
+                presale.track_payment_token_escrow(escrowed_before, payment_token.escrowed)?;
+                (payment_token.mint, balance - amount)
+            }
+            _ => return Err(PresaleError::InvalidCurrency.into()),
+        };
+
+        emit!(ProceedsWithdrawnEvent {
+            admin: ctx.accounts.admin.key(),
+            currency,
+            mint,
+            amount,
+            remaining,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Withdrew {} (raw) of currency {} from escrow to merchant wallet, {} left",
+            amount,
+            currency,
+            remaining
+        );
+
+        Ok(())
+    }
+
+    /// Pays the merchant the proceeds escrowed since the last settlement, netted of in-kind reversals,
+    /// in a single transfer per currency, and records the period in a `Settlement` account.
+    /// Every registered payment token with escrowed proceeds is settled too: after any treasury
+    /// recipients for SOL and the stablecoins, `remaining_accounts` holds a (`PaymentToken`, mint,
+    /// escrow vault) group per such token, followed by the merchant's token account for it, or by one
+    /// recipient per treasury wallet when proceeds are split.
+    /// Settled purchases can no longer be reversed, and once anything is settled the sale can no
+    /// longer be cancelled or fail on the softcap, so settling waits for the sale to end (or be
+    /// finalized) with the softcap met. Only the admin can do this.
+    pub fn settle_period<'info>(ctx: Context<'_, '_, 'info, 'info, SettlePeriod<'info>>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let presale_key = presale.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(!presale.refunds_enabled(), PresaleError::SaleCancelled);
+        require!(presale.sale_stage == 3 || presale.pool_created, PresaleError::SaleNotEnded);
+        require!(presale.total_sold >= presale.softcap_tokens, PresaleError::SoftcapNotMet);
+
+        let net_lamports = presale.escrowed_lamports;
+        let net_usdc = presale.escrowed_usdc;
+        let net_usdt = presale.escrowed_usdt;
+        let net_pyusd = presale.escrowed_pyusd;
+        require!(
+            net_lamports > 0 || net_usdc > 0 || net_usdt > 0 || net_pyusd > 0 || presale.escrowed_payment_tokens > 0,
+            PresaleError::NothingToWithdraw
+        );
+
+        let escrow_seeds: &[&[u8]] = &[ESCROW_SEED, presale_key.as_ref(), &[presale.escrow_bump]];
+        let signer_seeds: &[&[&[u8]]] = &[escrow_seeds];
+        // With treasury splits, each paid currency takes one recipient per treasury wallet, in order
+        let mut treasury_accounts = ctx.remaining_accounts.iter();
+
+        if net_lamports > 0 {
+            let payouts = treasury_payouts(
+                presale,
+                net_lamports,
+                Some(ctx.accounts.merchant_wallet.to_account_info()),
+                &mut treasury_accounts,
+                None,
+            )?;
+            for (recipient, share) in payouts {
+                system_program::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.system_program.to_account_info(),
+                        SystemTransfer {
+                            from: ctx.accounts.escrow.to_account_info(),
+                            to: recipient,
+                        },
+                        signer_seeds,
+                    ),
+                    share,
+                )?;
+            }
+        }
+
+        // PYUSD is a Token-2022 mint, settled through its own program
+        let stable_coin_legs = [
+            (
+                net_usdc,
+                ctx.accounts.usdc_mint.as_ref(),
+                ctx.accounts.escrow_usdc_account.as_ref().map(|vault| vault.to_account_info()),
+                ctx.accounts.merchant_usdc_account.as_ref().map(|account| account.to_account_info()),
+                Some(ctx.accounts.token_program.to_account_info()),
+            ),
+            (
+                net_usdt,
+                ctx.accounts.usdt_mint.as_ref(),
+                ctx.accounts.escrow_usdt_account.as_ref().map(|vault| vault.to_account_info()),
+                ctx.accounts.merchant_usdt_account.as_ref().map(|account| account.to_account_info()),
+                Some(ctx.accounts.token_program.to_account_info()),
+            ),
+            (
+                net_pyusd,
+                ctx.accounts.pyusd_mint.as_ref(),
+                ctx.accounts.escrow_pyusd_account.as_ref().map(|vault| vault.to_account_info()),
+                ctx.accounts.merchant_pyusd_account.as_ref().map(|account| account.to_account_info()),
+                ctx.accounts.token_2022_program.as_ref().map(|program| program.to_account_info()),
+            ),
+        ];
+        for (amount, mint_account, escrow_vault, merchant_account, token_program) in stable_coin_legs {
+            if amount == 0 {
+                continue;
+            }
+            let mint_account = mint_account.ok_or(PresaleError::InvalidStableToken)?;
+            let escrow_vault = escrow_vault.ok_or(PresaleError::InvalidTokenAccount)?;
+            let token_program = token_program.ok_or(PresaleError::InvalidTokenAccount)?;
+
+            let payouts = treasury_payouts(
+                presale,
+                amount,
+                merchant_account,
+                &mut treasury_accounts,
+                Some(mint_account.key()),
+            )?;
+            for (recipient, share) in payouts {
+                transfer_stable_coin(
+                    &token_program,
+                    mint_account,
+                    escrow_vault.clone(),
+                    recipient,
+                    ctx.accounts.escrow.to_account_info(),
+                    signer_seeds,
+                    share,
+                )?;
+            }
+        }
+
+        // Registered payment tokens follow as (`PaymentToken`, mint, escrow vault) groups, each paid out
+        // in full; a token settled twice has nothing left in escrow the second time
+        let mut payment_tokens_settled = 0u32;
+        while let Some(payment_token_info) = treasury_accounts.next() {
+            let mint_info = treasury_accounts.next().ok_or(PresaleError::InvalidPaymentTokenSettlement)?;
+            let escrow_vault_info = treasury_accounts.next().ok_or(PresaleError::InvalidPaymentTokenSettlement)?;
+            let mut payment_token = Account::<PaymentToken>::try_from(payment_token_info)?;
+            let mint_account = InterfaceAccount::<InterfaceMint>::try_from(mint_info)?;
+            require!(
+                payment_token.presale == presale_key
+                    && payment_token.escrowed > 0
+                    && mint_account.key() == payment_token.mint
+                    && escrow_vault_info.key()
+                        == get_associated_token_address(&ctx.accounts.escrow.key(), &payment_token.mint),
+                PresaleError::InvalidPaymentTokenSettlement
+            );
+
+            let merchant_account = if presale.treasury_splits().is_empty() {
+                let merchant_info = treasury_accounts.next().ok_or(PresaleError::InvalidPaymentTokenSettlement)?;
+                let merchant_account = InterfaceAccount::<InterfaceTokenAccount>::try_from(merchant_info)?;
+                require!(
+                    merchant_account.mint == payment_token.mint && merchant_account.owner == presale.merchant_wallet,
+                    PresaleError::InvalidPaymentTokenSettlement
+                );
+                Some(merchant_info.clone())
+            } else {
+                None
+            };
+
+            let amount = payment_token.escrowed;
+            let payouts = treasury_payouts(
+                presale,
+                amount,
+                merchant_account,
+                &mut treasury_accounts,
+                Some(payment_token.mint),
+            )?;
+            for (recipient, share) in payouts {
+                transfer_stable_coin(
+                    &ctx.accounts.token_program,
+                    &mint_account,
+                    escrow_vault_info.clone(),
+                    recipient,
+                    ctx.accounts.escrow.to_account_info(),
+                    signer_seeds,
+                    share,
+                )?;
+            }
+            payment_token.escrowed = 0;
+            payment_token.exit(&crate::ID)?;
+            payment_tokens_settled += 1;
+
+            emit!(PaymentTokenSettledEvent {
+                index: presale.settlement_count,
+                mint: payment_token.mint,
+                amount,
+            });
+        }
+        require!(
+            payment_tokens_settled == presale.escrowed_payment_tokens,
+            PresaleError::InvalidPaymentTokenSettlement
+        );
+
+        let settlement = &mut ctx.accounts.settlement;
+        settlement.presale = presale_key;
+        settlement.index = presale.settlement_count;
+        settlement.period_start = if presale.settlement_count == 0 {
+            presale.presale_start
+        } else {
+            presale.last_settlement_at
+        };
+        settlement.period_end = now;
+        settlement.net_lamports = net_lamports;
+        settlement.net_usdc = net_usdc;
+        settlement.net_usdt = net_usdt;
+        settlement.reversed_lamports = presale.unsettled_reversed_lamports;
+        settlement.reversed_usdc = presale.unsettled_reversed_usdc;
+        settlement.reversed_usdt = presale.unsettled_reversed_usdt;
+        settlement.bump = ctx.bumps.settlement;
+        settlement.net_pyusd = net_pyusd;
+        settlement.reversed_pyusd = presale.unsettled_reversed_pyusd;
+        settlement.payment_tokens = payment_tokens_settled;
+
+        presale.escrowed_lamports = 0;
+        presale.escrowed_usdc = 0;
+        presale.escrowed_usdt = 0;
+        presale.unsettled_reversed_lamports = 0;
+        presale.unsettled_reversed_usdc = 0;
+        presale.unsettled_reversed_usdt = 0;
+        presale.escrowed_pyusd = 0;
+        presale.unsettled_reversed_pyusd = 0;
+        presale.escrowed_payment_tokens = 0;
+        presale.settlement_count = presale.settlement_count.checked_add(1).ok_or(PresaleError::MathOverflow)?;
+        presale.last_settlement_at = now;
+
+        emit!(PeriodSettledEvent {
+            index: settlement.index,
+            period_start: settlement.period_start,
+            period_end: now,
+            net_lamports,
+            net_usdc,
+            net_usdt,
+            net_pyusd,
+            payment_tokens: payment_tokens_settled,
+        });
+
+        msg!(
+            "Settled period {}: {} lamports, {} USDC (raw), {} USDT (raw), {} PYUSD (raw) and {} payment tokens to the treasury",
+            settlement.index,
+            net_lamports,
+            net_usdc,
+            net_usdt,
+            net_pyusd,
+            payment_tokens_settled
+        );
+
+        Ok(())
+    }
+
+    /// Opens the next page of the buyer registry once the current page is full.
+    /// Permissionless: whoever needs the new page (usually the next new buyer) pays for it.
+    pub fn create_registry_page(ctx: Context<CreateRegistryPage>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            ctx.accounts.current_page.load()?.count as usize == REGISTRY_PAGE_CAPACITY,
+            PresaleError::RegistryPageNotFull
+        );
+
+        let page_index = presale.registry_page_count;
+        let mut new_page = ctx.accounts.new_page.load_init()?;
+        new_page.presale = presale.key();
+        new_page.page_index = page_index;
+
+        presale.registry_page_count = page_index.checked_add(1).ok_or(PresaleError::MathOverflow)?;
+
+        msg!("Buyer registry page {} created", page_index);
+
+        Ok(())
+    }
+
+    /// Configures the vesting schedule (TGE unlock, cliff, linear duration) and the early-claim penalty.
+    /// Only the admin can do this, and only before the presale is finalized.
+    /// Omitting `penalty_wallet` burns forfeited tokens instead of sending them to a treasury.
+    pub fn configure_vesting(
+        ctx: Context<ConfigureVesting>,
+        vesting_duration_days: i64,
+        early_claim_penalty_bps: u16, // 0 disables early claims
+        vesting_cliff_days: i64,      // Nothing beyond the TGE unlock vests before the cliff
+        tge_unlock_bps: u16,          // Share of each allocation unlocked at the vesting start
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        require!(!presale.pool_created, PresaleError::VestingScheduleLocked);
+        require!(vesting_duration_days >= 0, PresaleError::InvalidVestingSchedule);
+        require!(
+            vesting_cliff_days >= 0 && vesting_cliff_days <= vesting_duration_days,
+            PresaleError::InvalidVestingSchedule
+        );
+        require!(
+            tge_unlock_bps <= BPS_DENOMINATOR,
+            PresaleError::InvalidVestingSchedule
+        );
+        require!(
+            early_claim_penalty_bps <= BPS_DENOMINATOR,
+            PresaleError::InvalidPenalty
+        );
+
+        presale.vesting_duration = days_to_seconds(vesting_duration_days)?;
+        presale.vesting_cliff = days_to_seconds(vesting_cliff_days)?;
+        presale.tge_unlock_bps = tge_unlock_bps;
+        presale.early_claim_penalty_bps = early_claim_penalty_bps;
+        presale.bump_config_version()?;
+        presale.penalty_wallet = ctx
+            .accounts
+            .penalty_wallet
+            .as_ref()
+            .map_or(Pubkey::default(), |wallet| wallet.key());
+
+        msg!(
+            "Vesting configured: {} bps at TGE, {} day cliff, {} days, early claim penalty {} bps, forfeits to {}",
+            tge_unlock_bps,
+            vesting_cliff_days,
+            vesting_duration_days,
+            early_claim_penalty_bps,
+            if presale.penalty_wallet == Pubkey::default() {
+                "burn".to_string()
+            } else {
+                presale.penalty_wallet.to_string()
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Overrides the vesting schedule for tokens bought in one round (1 = private, 2 = public), e.g.
+    /// 12 months for the private round while the public round unlocks at TGE. Tokens from a round
+    /// without an override, bonuses and referral commissions follow the `configure_vesting` schedule.
+    /// Only the admin can do this, and only before the presale is finalized.
+    pub fn configure_round_vesting(
+        ctx: Context<ConfigureRoundVesting>,
+        stage: u8,
+        vesting_duration_days: i64,
+        vesting_cliff_days: i64,
+        tge_unlock_bps: u16,
+        vesting_mode: u8,
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        require!(!presale.pool_created, PresaleError::VestingScheduleLocked);
+        require!(stage == 1 || stage == 2, PresaleError::InvalidVestingSchedule);
+        require!(
+            vesting_mode == VESTING_MODE_LINEAR
+                || (vesting_mode == VESTING_MODE_MONTHLY
+                    && vesting_duration_days > 0
+                    && days_to_seconds(vesting_duration_days)? % VESTING_TRANCHE_SECONDS == 0),
+            PresaleError::InvalidVestingSchedule
+        );
+        require!(vesting_duration_days >= 0, PresaleError::InvalidVestingSchedule);
+        require!(
+            vesting_cliff_days >= 0 && vesting_cliff_days <= vesting_duration_days,
+            PresaleError::InvalidVestingSchedule
+        );
+        require!(
+            tge_unlock_bps <= BPS_DENOMINATOR,
+            PresaleError::InvalidVestingSchedule
+        );
+
+        let schedule = LinearVesting {
+            configured: true,
+            duration: days_to_seconds(vesting_duration_days)?,
+            cliff: days_to_seconds(vesting_cliff_days)?,
+            tge_unlock_bps,
+            mode: vesting_mode,
+        };
+        if stage == 1 {
+            presale.private_round_vesting = schedule;
+        } else {
+            presale.public_round_vesting = schedule;
+        }
+        presale.bump_config_version()?;
+
+        msg!(
+            "{} round vesting configured: {} bps at TGE, {} day cliff, {} days{}",
+            if stage == 1 { "Private" } else { "Public" },
+            tge_unlock_bps,
+            vesting_cliff_days,
+            vesting_duration_days,
+            if vesting_mode == VESTING_MODE_MONTHLY { " in monthly tranches" } else { "" }
+        );
+
+        Ok(())
+    }
+
+    /// Replaces linear vesting with admin-defined milestones, e.g. 25% quarterly unlocks, stored in
+    /// the presale's `VestingSchedule` account. Timestamps must be strictly increasing and the shares
+    /// must add up to 100%. An empty list switches back to the linear schedules.
+    /// Only the admin can do this, and only before the presale is finalized.
+    pub fn set_vesting_milestones(
+        ctx: Context<SetVestingMilestones>,
+        milestones: Vec<VestingMilestone>,
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        require!(!presale.pool_created, PresaleError::VestingScheduleLocked);
+        validate_vesting_milestones(&milestones)?;
+
+        let vesting_schedule = &mut ctx.accounts.vesting_schedule;
+        vesting_schedule.presale = presale.key();
+        vesting_schedule.buyer = Pubkey::default();
+        vesting_schedule.milestones = milestones;
+        vesting_schedule.bump = ctx.bumps.vesting_schedule;
+        vesting_schedule.version = VESTING_SCHEDULE_VERSION;
+
+        presale.milestone_vesting = !vesting_schedule.milestones.is_empty();
+        presale.bump_config_version()?;
+
+        msg!(
+            "Vesting milestones set: {} milestones",
+            vesting_schedule.milestones.len()
+        );
+
+        Ok(())
+    }
+
+    /// Gives one buyer negotiated milestone vesting (e.g. a strategic investor), stored in a
+    /// `VestingSchedule` PDA of their own that takes precedence over the sale's round, sale-wide and
+    /// milestone schedules. Same rules as `set_vesting_milestones`; an empty list removes the override.
+    /// Only the admin can do this, and only before the presale is finalized.
+    pub fn set_buyer_vesting_schedule(
+        ctx: Context<SetBuyerVestingSchedule>,
+        milestones: Vec<VestingMilestone>,
+    ) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+        let buyer_account = &mut ctx.accounts.buyer_account;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        require!(!presale.pool_created, PresaleError::VestingScheduleLocked);
+        validate_vesting_milestones(&milestones)?;
+
+        let vesting_schedule = &mut ctx.accounts.vesting_schedule;
+        vesting_schedule.presale = presale.key();
+        vesting_schedule.buyer = buyer_account.buyer;
+        vesting_schedule.milestones = milestones;
+        vesting_schedule.bump = ctx.bumps.vesting_schedule;
+        vesting_schedule.version = VESTING_SCHEDULE_VERSION;
+
+        buyer_account.vesting_override = !vesting_schedule.milestones.is_empty();
+
+        msg!(
+            "Vesting override for buyer {}: {} milestones",
+            buyer_account.buyer,
+            vesting_schedule.milestones.len()
+        );
+
+        Ok(())
+    }
+
+    /// Brings a `VestingSchedule` created under an older layout up to date: reallocs it to the current
+    /// size (e.g. after the milestone capacity grows), with the admin topping up rent, keeps its
+    /// milestones and writes the current version tag. Schedules must be migrated before they can be
+    /// amended. Only the admin can perform this action.
+    pub fn migrate_vesting_schedule(ctx: Context<MigrateVestingSchedule>) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        let schedule_info = ctx.accounts.vesting_schedule.to_account_info();
+        let previous_len = schedule_info.data_len();
+        if previous_len < VestingSchedule::LEN {
+            let rent_due = Rent::get()?
+                .minimum_balance(VestingSchedule::LEN)
+                .saturating_sub(schedule_info.lamports());
+            if rent_due > 0 {
+                system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        SystemTransfer {
+                            from: ctx.accounts.admin.to_account_info(),
+                            to: schedule_info.clone(),
+                        },
+                    ),
+                    rent_due,
+                )?;
+            }
+            schedule_info.resize(VestingSchedule::LEN)?; // New bytes are zeroed, so older layouts read as version 0
+        }
+
+        let mut vesting_schedule = VestingSchedule::try_deserialize(&mut &schedule_info.try_borrow_data()?[..])?;
+        require!(
+            vesting_schedule.presale == presale.key(),
+            PresaleError::InvalidVestingSchedule
+        );
+        let previous_version = vesting_schedule.version;
+        vesting_schedule.version = VESTING_SCHEDULE_VERSION;
+        vesting_schedule.try_serialize(&mut &mut schedule_info.try_borrow_mut_data()?[..])?;
+
+        msg!(
+            "Vesting schedule migrated from version {} ({} bytes) to version {} ({} bytes)",
+            previous_version,
+            previous_len,
+            VESTING_SCHEDULE_VERSION,
+            VestingSchedule::LEN
+        );
+
+        Ok(())
+    }
+
+    /// Brings a presale created when USD prices were stored in cents up to date: reallocs it to the
+    /// current size, with the admin topping up rent, converts its USD prices to micro-dollars and
+    /// writes the current pricing version. Purchases and USD price changes are rejected until then.
+    /// Only the admin can perform this action.
+    pub fn migrate_usd_pricing(ctx: Context<MigrateUsdPricing>) -> Result<()> {
+        let presale_info = ctx.accounts.presale.to_account_info();
+        let previous_len = presale_info.data_len();
+        if previous_len < Presale::LEN {
+            let rent_due = Rent::get()?
+                .minimum_balance(Presale::LEN)
+                .saturating_sub(presale_info.lamports());
+            if rent_due > 0 {
+                system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        SystemTransfer {
+                            from: ctx.accounts.admin.to_account_info(),
+                            to: presale_info.clone(),
+                        },
+                    ),
+                    rent_due,
+                )?;
+            }
+            presale_info.resize(Presale::LEN)?; // New bytes are zeroed, so older layouts read as version 0
+        }
+
+        let mut presale = Presale::try_deserialize(&mut &presale_info.try_borrow_data()?[..])?;
+        presale.ensure_not_locked_down()?;
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        // A baseline sale ends at `bump`: give it the escrow and registry a new sale gets at initialize
+        if previous_len <= Presale::BASELINE_LEN {
+            presale.init_appended_fields(ctx.accounts.escrow.key(), ctx.bumps.escrow);
+            fund_escrow_rent(&ctx.accounts.admin, &ctx.accounts.escrow, &ctx.accounts.system_program)?;
+
+            let mut registry_page = ctx.accounts.registry_page.load_init()?;
+            registry_page.presale = presale_info.key();
+            registry_page.page_index = 0;
+            presale.registry_page_count = 1;
+        }
+
+        let previous_version = presale.usd_pricing_version;
+        if previous_version < USD_PRICING_VERSION {
+            presale.convert_usd_prices_from_cents()?;
+            presale.usd_pricing_version = USD_PRICING_VERSION;
+            presale.bump_config_version()?;
+        }
+        presale.try_serialize(&mut &mut presale_info.try_borrow_mut_data()?[..])?;
+
+        msg!(
+            "USD pricing migrated from version {} to version {}: {} micro-USD/NLOV",
+            previous_version,
+            USD_PRICING_VERSION,
+            presale.usd_price_micros_per_nlov
+        );
+
+        Ok(())
+    }
+
+    /// Claims `amount` (raw units) of the buyer's unlocked, not yet claimed tokens to their claim recipient.
+    /// Buyers can claim in installments; claiming more than is currently unlocked is rejected.
+    /// Vesting starts when the presale is finalized and unlocks linearly over `vesting_duration`.
+    pub fn claim_tokens(ctx: Context<ClaimTokens>, amount: u64) -> Result<()> {
+        ctx.accounts.presale.ensure_not_locked_down()?;
+
+        require!(
+            ctx.accounts.buyer_account.claim_authority == Pubkey::default(),
+            PresaleError::ClaimAuthorityRequired
+        );
+        check_not_blacklisted(&ctx.accounts.presale, ctx.accounts.blacklist.as_ref())?;
+
+        claim_unlocked(
+            &mut ctx.accounts.presale,
+            &mut ctx.accounts.buyer_account,
+            &ctx.accounts.presale_wallet,
+            &ctx.accounts.recipient_token_account,
+            &ctx.accounts.token_program,
+            ctx.accounts.vesting_schedule.as_deref(),
+            ctx.accounts.vesting_stats.as_mut(),
+            amount,
+        )
+    }
+
+    /// Claims `amount` (raw units) of unlocked tokens on the buyer's behalf, signed by the allocation's
+    /// claim authority (e.g. a custodian). Tokens still go to the buyer's claim recipient.
+    pub fn claim_tokens_as_authority(ctx: Context<ClaimTokensAsAuthority>, amount: u64) -> Result<()> {
+        ctx.accounts.presale.ensure_not_locked_down()?;
+
+        check_not_blacklisted(&ctx.accounts.presale, ctx.accounts.blacklist.as_ref())?;
+
+        claim_unlocked(
+            &mut ctx.accounts.presale,
+            &mut ctx.accounts.buyer_account,
+            &ctx.accounts.presale_wallet,
+            &ctx.accounts.recipient_token_account,
+            &ctx.accounts.token_program,
+            ctx.accounts.vesting_schedule.as_deref(),
+            ctx.accounts.vesting_stats.as_mut(),
+            amount,
+        )
+    }
+
+    /// Claims `amount` (raw units) of unlocked tokens straight into the staking program instead of the
+    /// buyer's wallet: the tokens, plus `stake_bonus_bps` of them as a bonus while unsold tokens last, go to
+    /// the staking vault, and the staking program is invoked with `[presale (signer), buyer, stake_account,
+    /// staking_vault]` and the staked amount (u64, little-endian) as data to credit the buyer's stake account.
+    pub fn claim_and_stake(ctx: Context<ClaimAndStake>, amount: u64) -> Result<()> {
+        ctx.accounts.presale.ensure_not_locked_down()?;
+
+        require!(
+            ctx.accounts.presale.staking_program != Pubkey::default(),
+            PresaleError::StakingNotConfigured
+        );
+        require!(
+            ctx.accounts.buyer_account.claim_authority == Pubkey::default(),
+            PresaleError::ClaimAuthorityRequired
+        );
+        check_not_blacklisted(&ctx.accounts.presale, ctx.accounts.blacklist.as_ref())?;
+
+        // The bonus comes out of tokens no buyer is owed and not reserved for the unsold-token stream
+        let presale = &ctx.accounts.presale;
+        let unsold_raw = ctx
+            .accounts
+            .presale_wallet
+            .amount
+            .saturating_sub(presale.outstanding_allocations())
+            .saturating_sub(presale.unsold_stream_total.saturating_sub(presale.unsold_stream_released));
+        let bonus_raw = mul_div(amount, ctx.accounts.presale.stake_bonus_bps as u64, BPS_DENOMINATOR as u64)?
+            .min(unsold_raw);
+
+        claim_unlocked(
+            &mut ctx.accounts.presale,
+            &mut ctx.accounts.buyer_account,
+            &ctx.accounts.presale_wallet,
+            &ctx.accounts.staking_vault,
+            &ctx.accounts.token_program,
+            ctx.accounts.vesting_schedule.as_deref(),
+            ctx.accounts.vesting_stats.as_mut(),
+            amount,
+        )?;
+
+        let presale = &mut ctx.accounts.presale;
+        let seeds: &[&[u8]] = &[PRESALE_SEED, presale.admin.as_ref(), &[presale.bump]];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        if bonus_raw > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TokenTransfer {
+                        from: ctx.accounts.presale_wallet.to_account_info(),
+                        to: ctx.accounts.staking_vault.to_account_info(),
+                        authority: presale.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                bonus_raw,
+            )?;
+        }
+
+        let staked_raw = amount.checked_add(bonus_raw).ok_or(PresaleError::MathOverflow)?;
+        invoke_signed(
+            &Instruction {
+                program_id: presale.staking_program,
+                accounts: vec![
+                    AccountMeta::new_readonly(presale.key(), true),
+                    AccountMeta::new_readonly(ctx.accounts.buyer.key(), false),
+                    AccountMeta::new(ctx.accounts.stake_account.key(), false),
+                    AccountMeta::new_readonly(ctx.accounts.staking_vault.key(), false),
+                ],
+                data: staked_raw.to_le_bytes().to_vec(),
+            },
+            &[
+                presale.to_account_info(),
+                ctx.accounts.buyer.to_account_info(),
+                ctx.accounts.stake_account.to_account_info(),
+                ctx.accounts.staking_vault.to_account_info(),
+                ctx.accounts.staking_program.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+        presale.total_stake_bonus = presale.total_stake_bonus.checked_add(bonus_raw).ok_or(PresaleError::MathOverflow)?;
+
+        emit!(TokensStakedEvent {
+            buyer: ctx.accounts.buyer.key(),
+            stake_account: ctx.accounts.stake_account.key(),
+            tokens_claimed: amount,
+            stake_bonus: bonus_raw,
+        });
+
+        msg!(
+            "Buyer {} staked {} claimed tokens (raw) with a {} token bonus",
+            ctx.accounts.buyer.key(),
+            amount,
+            bonus_raw
+        );
+
+        Ok(())
+    }
+
+    /// Opt-in early exit: claims the whole remaining allocation now, forfeiting
+    /// `early_claim_penalty_bps` of the still-unvested part. Already vested tokens are not penalized.
+    /// Forfeited tokens go to the configured penalty wallet, or are burned if none is set.
+    pub fn claim_early(ctx: Context<ClaimEarly>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let buyer_account = &mut ctx.accounts.buyer_account;
+
+        presale.ensure_not_locked_down()?;
+        require!(
+            buyer_account.claim_authority == Pubkey::default(),
+            PresaleError::ClaimAuthorityRequired
+        );
+        check_not_blacklisted(presale, ctx.accounts.blacklist.as_ref())?;
+
+        require!(presale.pool_created, PresaleError::PresaleNotFinalized);
+        presale.ensure_tge_reached()?;
+        presale.ensure_claims_not_paused()?;
+        require!(
+            presale.early_claim_penalty_bps > 0,
+            PresaleError::EarlyClaimDisabled
+        );
+
+        let vested_raw = presale.buyer_vested_amount(
+            buyer_account,
+            ctx.accounts.vesting_schedule.as_deref(),
+            Clock::get()?.unix_timestamp,
+        )?;
+        let settled_raw = buyer_account.settled_tokens()?;
+        let vested_unclaimed_raw = vested_raw.saturating_sub(settled_raw);
+        let unvested_raw = buyer_account
+            .total_allocation()?
+            .saturating_sub(vested_raw.max(settled_raw));
+        require!(unvested_raw > 0, PresaleError::NothingToClaim);
+
+        // Penalty math: only the unvested part is subject to forfeiture
+        let forfeited_raw = mul_div(unvested_raw, presale.early_claim_penalty_bps as u64, BPS_DENOMINATOR as u64)?;
+        let released_raw = vested_unclaimed_raw
+            .checked_add(unvested_raw - forfeited_raw)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        let seeds: &[&[u8]] = &[PRESALE_SEED, presale.admin.as_ref(), &[presale.bump]];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        if released_raw > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TokenTransfer {
+                        from: ctx.accounts.presale_wallet.to_account_info(),
+                        to: ctx.accounts.recipient_token_account.to_account_info(),
+                        authority: presale.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                released_raw,
+            )?;
+        }
+
+        let burned = presale.penalty_wallet == Pubkey::default();
+        if forfeited_raw > 0 {
+            if burned {
+                token::burn(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        Burn {
+                            mint: ctx.accounts.token_mint.to_account_info(),
+                            from: ctx.accounts.presale_wallet.to_account_info(),
+                            authority: presale.to_account_info(),
+                        },
+                        signer_seeds,
+                    ),
+                    forfeited_raw,
+                )?;
+            } else {
+                let penalty_wallet = ctx
+                    .accounts
+                    .penalty_wallet
+                    .as_ref()
+                    .ok_or(PresaleError::InvalidTokenAccount)?;
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        TokenTransfer {
+                            from: ctx.accounts.presale_wallet.to_account_info(),
+                            to: penalty_wallet.to_account_info(),
+                            authority: presale.to_account_info(),
+                        },
+                        signer_seeds,
+                    ),
+                    forfeited_raw,
+                )?;
+            }
+        }
+
+        buyer_account.tokens_claimed = buyer_account.tokens_claimed.checked_add(released_raw).ok_or(PresaleError::MathOverflow)?;
+        buyer_account.tokens_forfeited = buyer_account.tokens_forfeited.checked_add(forfeited_raw).ok_or(PresaleError::MathOverflow)?;
+        buyer_account.config_version = presale.config_version; // Settled under the final vesting/penalty config
+        presale.total_claimed = presale.total_claimed.checked_add(released_raw).ok_or(PresaleError::MathOverflow)?;
+        presale.total_forfeited = presale.total_forfeited.checked_add(forfeited_raw).ok_or(PresaleError::MathOverflow)?;
+        let vested_delta_raw = buyer_account.record_vested(vested_raw)?;
+        record_vesting_stats(presale, ctx.accounts.vesting_stats.as_mut(), vested_delta_raw)?;
+
+        emit!(TokensForfeitedEvent {
+            buyer: buyer_account.buyer,
+            tokens_released: released_raw,
+            tokens_forfeited: forfeited_raw,
+            penalty_bps: presale.early_claim_penalty_bps,
+            burned,
+        });
+
+        msg!(
+            "Buyer {} claimed early: {} tokens released, {} forfeited (raw), burned: {}",
+            buyer_account.buyer,
+            released_raw,
+            forfeited_raw,
+            burned
+        );
+
+        Ok(())
+    }
+
+    /// Nominates the wallet (e.g. a cold wallet) that receives the buyer's claimed tokens.
+    /// Only the buyer can change it.
+    pub fn set_claim_recipient(ctx: Context<SetClaimRecipient>, recipient: Pubkey) -> Result<()> {
+        let buyer_account = &mut ctx.accounts.buyer_account;
+
+        ctx.accounts.presale.ensure_not_locked_down()?;
+
+        require!(
+            recipient != Pubkey::default(),
+            PresaleError::InvalidClaimRecipient
+        );
+
+        let previous_recipient = buyer_account.claim_recipient;
+        buyer_account.claim_recipient = recipient;
+
+        emit!(ClaimRecipientUpdatedEvent {
+            buyer: buyer_account.buyer,
+            previous_recipient,
+            new_recipient: recipient,
+        });
+
+        msg!(
+            "Buyer {} set claim recipient to {}",
+            buyer_account.buyer,
+            recipient
+        );
+
+        Ok(())
+    }
+
+    /// Sets the operator wallet allowed to perform day-to-day tasks such as compliance annotations.
+    /// Only the admin can perform this action.
+    pub fn set_operator(ctx: Context<SetOperator>, new_operator: Pubkey) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        let previous_operator = presale.operator;
+        presale.operator = new_operator;
+
+        emit!(OperatorUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            previous_operator,
+            operator: new_operator,
+        });
+
+        msg!("Operator set to {}", new_operator);
+
+        Ok(())
+    }
+
+    /// Writes a compliance annotation (e.g. a KYC case ID or sanction-screen reference hash)
+    /// on a buyer's account. Only the operator or the admin can perform this action.
+    pub fn set_compliance_note(
+        ctx: Context<SetComplianceNote>,
+        note: [u8; COMPLIANCE_NOTE_LEN],
+    ) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+        let buyer_account = &mut ctx.accounts.buyer_account;
+        let signer = ctx.accounts.operator.key();
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            signer == presale.operator || signer == presale.admin,
+            PresaleError::Unauthorized
+        );
+
+        let previous_note = buyer_account.compliance_note;
+        buyer_account.compliance_note = note;
+
+        emit!(ComplianceNoteUpdatedEvent {
+            buyer: buyer_account.buyer,
+            operator: signer,
+            previous_note,
+            note,
+        });
+
+        msg!("Compliance note updated for buyer {} by {}", buyer_account.buyer, signer);
+
+        Ok(())
+    }
+
+    /// Sets the guardian wallet that must co-sign a lockdown.
+    /// Only the admin can perform this action.
+    pub fn set_guardian(ctx: Context<SetGuardian>, new_guardian: Pubkey) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        presale.guardian = new_guardian;
+
+        emit!(GuardianUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            guardian: new_guardian,
+        });
+
+        msg!("Guardian set to {}", new_guardian);
+
+        Ok(())
+    }
+
+    /// Global kill switch for a suspected key compromise: freezes every state-changing
+    /// instruction except refunds. Requires both the admin and the guardian to sign.
+    pub fn lockdown(ctx: Context<Lockdown>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key()
+                && presale.guardian == ctx.accounts.guardian.key(),
+            PresaleError::Unauthorized
+        );
+        require!(!presale.locked_down, PresaleError::LockedDown);
+
+        presale.locked_down = true;
+        presale.lockdown_lift_at = 0;
+
+        emit!(LockdownEvent {
+            admin: ctx.accounts.admin.key(),
+            guardian: ctx.accounts.guardian.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Presale locked down");
+
+        Ok(())
+    }
+
+    /// Starts the timelock for lifting a lockdown. The lockdown can be lifted
+    /// `LOCKDOWN_LIFT_DELAY` seconds later. Only the admin can perform this action.
+    pub fn request_lockdown_lift(ctx: Context<UpdateLockdown>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(presale.locked_down, PresaleError::NotLockedDown);
+
+        presale.lockdown_lift_at = Clock::get()?
+            .unix_timestamp
+            .checked_add(LOCKDOWN_LIFT_DELAY)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        emit!(LockdownLiftRequestedEvent {
+            admin: ctx.accounts.admin.key(),
+            lift_at: presale.lockdown_lift_at,
+        });
+
+        msg!("Lockdown lift requested, executable at {}", presale.lockdown_lift_at);
+
+        Ok(())
+    }
+
+    /// Lifts the lockdown once the requested timelock has elapsed.
+    /// Only the admin can perform this action.
+    pub fn lift_lockdown(ctx: Context<UpdateLockdown>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(presale.locked_down, PresaleError::NotLockedDown);
+        require!(
+            presale.lockdown_lift_at > 0
+                && Clock::get()?.unix_timestamp >= presale.lockdown_lift_at,
+            PresaleError::LockdownTimelockActive
+        );
+
+        presale.locked_down = false;
+        presale.lockdown_lift_at = 0;
+
+        emit!(LockdownLiftedEvent {
+            admin: ctx.accounts.admin.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Lockdown lifted");
+
+        Ok(())
+    }
+
+    /// Reserves hardcap headroom for an upcoming purchase by grabbing the next sequence ticket.
+    /// Near the hardcap, reservations are granted strictly in ticket order: a ticket is only issued
+    /// while unreserved headroom remains, and purchases without a ticket cannot eat into reserved headroom.
+    /// The ticket expires after `TICKET_TTL` seconds.
+    pub fn reserve_purchase_ticket(ctx: Context<ReservePurchaseTicket>, tokens_raw: u64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.sale_stage == 1 || presale.sale_stage == 2,
+            PresaleError::PresaleNotActive
+        );
+        require!(tokens_raw > 0, PresaleError::InvalidPrice);
+        require!(
+            presale
+                .total_sold
+                .saturating_add(presale.provisional_tokens)
+                .saturating_add(presale.reserved_tokens)
+                .saturating_add(tokens_raw)
+                <= presale.hardcap_tokens,
+            PresaleError::HardcapReached
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.presale = presale.key();
+        ticket.buyer = ctx.accounts.buyer.key();
+        ticket.sequence = presale.next_ticket_sequence;
+        ticket.tokens = tokens_raw;
+        ticket.expires_at = now.checked_add(TICKET_TTL).ok_or(PresaleError::MathOverflow)?;
+        ticket.bump = ctx.bumps.ticket;
+
+        presale.next_ticket_sequence = presale.next_ticket_sequence.checked_add(1).ok_or(PresaleError::MathOverflow)?;
+        presale.reserved_tokens = presale.reserved_tokens.checked_add(tokens_raw).ok_or(PresaleError::MathOverflow)?;
+
+        emit!(TicketReservedEvent {
+            buyer: ticket.buyer,
+            sequence: ticket.sequence,
+            tokens: tokens_raw,
+            expires_at: ticket.expires_at,
+        });
+
+        msg!(
+            "Ticket {} reserved {} tokens (raw) for buyer {}",
+            ticket.sequence,
+            tokens_raw,
+            ticket.buyer
+        );
+
+        Ok(())
+    }
+
+    /// Releases an expired, unused reservation so its headroom returns to the pool.
+    /// Permissionless; the ticket rent is returned to the buyer who reserved it.
+    pub fn release_expired_ticket(ctx: Context<ReleaseExpiredTicket>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let ticket = &ctx.accounts.ticket;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            Clock::get()?.unix_timestamp >= ticket.expires_at,
+            PresaleError::TicketNotExpired
+        );
+
+        presale.reserved_tokens = presale.reserved_tokens.saturating_sub(ticket.tokens);
+
+        msg!("Expired ticket {} released {} tokens (raw)", ticket.sequence, ticket.tokens);
+
+        Ok(())
+    }
+
+    /// Commits to an upcoming public-round purchase: `commitment` is keccak(buyer || amount (u64,
+    /// little-endian) || salt), where `amount` is the payment amount the purchase will pass. It can be
+    /// revealed once `commit_reveal_delay` has passed.
+    pub fn commit_purchase(ctx: Context<CommitPurchase>, commitment: [u8; 32]) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        // Only public-round purchases spend commitments
+        require!(presale.sale_stage == 2, PresaleError::PresaleNotActive);
+
+        let purchase_commitment = &mut ctx.accounts.purchase_commitment;
+        purchase_commitment.presale = presale.key();
+        purchase_commitment.buyer = ctx.accounts.buyer.key();
+        purchase_commitment.commitment = commitment;
+        purchase_commitment.committed_at = Clock::get()?.unix_timestamp;
+        purchase_commitment.revealed = false;
+        purchase_commitment.amount = 0;
+        purchase_commitment.bump = ctx.bumps.purchase_commitment;
+
+        msg!("Buyer {} committed to a purchase", purchase_commitment.buyer);
+
+        Ok(())
+    }
+
+    /// Reveals a purchase commitment at least `commit_reveal_delay` after it was made. The next purchase
+    /// must pay exactly `amount` and consumes the commitment, so typically both go in one transaction.
+    pub fn reveal_purchase(ctx: Context<RevealPurchase>, amount: u64, salt: [u8; 32]) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+        let purchase_commitment = &mut ctx.accounts.purchase_commitment;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(!presale.cancelled, PresaleError::SaleCancelled);
+        require!(
+            presale.sale_stage == 1 || presale.sale_stage == 2,
+            PresaleError::PresaleNotActive
+        );
+        require!(
+            Clock::get()?.unix_timestamp
+                >= purchase_commitment.committed_at.saturating_add(presale.commit_reveal_delay),
+            PresaleError::CommitmentTooRecent
+        );
+        let expected = keccak::hashv(&[purchase_commitment.buyer.as_ref(), &amount.to_le_bytes(), &salt]);
+        require!(
+            expected.to_bytes() == purchase_commitment.commitment,
+            PresaleError::CommitmentMismatch
+        );
+
+        purchase_commitment.revealed = true;
+        purchase_commitment.amount = amount;
+
+        msg!("Buyer {} revealed a purchase of {}", purchase_commitment.buyer, amount);
+
+        Ok(())
+    }
+
+    /// Moves all or part of a buyer's unclaimed allocation to another wallet's buyer account,
+    /// creating it if needed. Used to rotate wallets before TGE.
+    pub fn transfer_allocation(
+        ctx: Context<TransferAllocation>,
+        amount: u64,
+        whitelist_proof: Option<WhitelistProof>, // Recipient's tier and merkle proof, checked when private-round tokens move
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let from_account = &mut ctx.accounts.from_account;
+        let to_account = &mut ctx.accounts.to_account;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(!presale.cancelled, PresaleError::SaleCancelled);
+        require!(amount > 0, PresaleError::InsufficientAllocation);
+        require!(
+            ctx.accounts.recipient.key() != ctx.accounts.owner.key(),
+            PresaleError::InvalidTransferRecipient
+        );
+        require!(
+            amount <= from_account.unclaimed_tokens()?,
+            PresaleError::InsufficientAllocation
+        );
+        require!(!to_account.revoked, PresaleError::AllocationRevoked);
+
+        // Blacklisted wallets can neither move their allocation out nor receive one
+        check_not_blacklisted(presale, ctx.accounts.from_blacklist.as_ref())?;
+        check_not_blacklisted(presale, ctx.accounts.to_blacklist.as_ref())?;
+
+        // A custodial allocation only moves with its claim authority's signature, and stays custodial
+        let claim_authority = from_account.claim_authority;
+        if claim_authority != Pubkey::default() {
+            require!(
+                ctx.accounts
+                    .claim_authority
+                    .as_ref()
+                    .is_some_and(|signer| signer.key() == claim_authority),
+                PresaleError::ClaimAuthorityRequired
+            );
+            require!(
+                to_account.claim_authority == Pubkey::default()
+                    || to_account.claim_authority == claim_authority,
+                PresaleError::ClaimAuthorityAlreadySet
+            );
+            to_account.claim_authority = claim_authority;
+        }
+
+        // The allocation keeps its round breakdown as it moves
+        let (private_raw, public_raw, bonus_raw, referral_raw) = from_account.debit_allocation(amount)?;
+        let purchased_raw = amount - bonus_raw - referral_raw;
+
+        // The recipient stays within the per-buyer cap, as if they had bought the tokens themselves
+        require!(
+            presale.wallet_headroom(to_account.tokens_purchased) >= purchased_raw,
+            PresaleError::WalletCapExceeded
+        );
+
+        let is_new = to_account.credit_allocation(
+            presale.key(),
+            ctx.accounts.recipient.key(),
+            ctx.bumps.to_account,
+            purchased_raw,
+        )?;
+        to_account.credit_round_tokens(private_raw, public_raw)?;
+
+        // Private-round tokens stay within the recipient's whitelist tier cap
+        if private_raw > 0 && presale.whitelist_enabled() {
+            let tier = private_round_tier(
+                presale,
+                &ctx.accounts.recipient.key(),
+                ctx.accounts.to_whitelist_entry.as_ref(),
+                whitelist_proof.as_ref(),
+            )?;
+            check_tier_cap(presale, tier, to_account.private_round_tokens)?;
+        }
+        to_account.bonus_tokens = to_account.bonus_tokens.checked_add(bonus_raw).ok_or(PresaleError::MathOverflow)?;
+        to_account.referral_tokens = to_account.referral_tokens.checked_add(referral_raw).ok_or(PresaleError::MathOverflow)?;
+
+        // Keep both registry entries in sync with the new allocations
+        sync_buyer_registry(presale, from_account, &ctx.accounts.from_registry_page, false)?;
+        sync_buyer_registry(presale, to_account, &ctx.accounts.to_registry_page, is_new)?;
+
+        emit!(AllocationTransferredEvent {
+            from: from_account.buyer,
+            to: to_account.buyer,
+            amount,
+        });
+
+        msg!(
+            "Transferred {} tokens (raw) of allocation from {} to {}",
+            amount,
+            from_account.buyer,
+            to_account.buyer
+        );
+
+        Ok(())
+    }
+
+    /// Returns how much more `buyer` can purchase right now, the headroom left in each cap
+    /// dimension, and which constraint is binding, so the UI can show an exact maximum.
+    /// Pass the buyer's `WhitelistEntry`, or the tier of their merkle proof as `whitelist_tier`, to
+    /// include their private-round tier cap.
+    pub fn get_cap_status(ctx: Context<GetCapStatus>, buyer: Pubkey, whitelist_tier: Option<u8>) -> Result<CapStatus> {
+        let presale = &ctx.accounts.presale;
+        let token_unit = 10u64.pow(ctx.accounts.token_mint.decimals as u32);
+
+        let hardcap_headroom = presale
+            .hardcap_tokens
+            .saturating_sub(presale.total_sold)
+            .saturating_sub(presale.provisional_tokens)
+            .saturating_sub(presale.reserved_tokens);
+        let supply_headroom = ctx
+            .accounts
+            .presale_wallet
+            .amount
+            .saturating_sub(presale.outstanding_allocations());
+        let buyer_account = ctx.accounts.buyer_account.as_deref();
+        let buyer_tokens_purchased = buyer_account.map_or(0, |account| account.tokens_purchased);
+        let wallet_headroom = presale.wallet_headroom(buyer_tokens_purchased);
+        let volume_step_headroom = presale.volume_step_headroom();
+        let whitelist_tier = ctx.accounts.whitelist_entry.as_ref().map(|entry| entry.tier).or(whitelist_tier);
+        let tier_cap_headroom = match whitelist_tier {
+            Some(tier) if presale.sale_stage == 1 => presale.tier_cap_headroom(
+                tier,
+                buyer_account.map_or(0, |account| account.private_round_tokens),
+            )?,
+            _ => u64::MAX,
+        };
+        let purchases_remaining = presale.purchases_remaining(buyer_account);
+
+        // The tightest dimension wins
+        let mut binding_constraint = CAP_HARDCAP;
+        let mut max_tokens = hardcap_headroom;
+        if supply_headroom < max_tokens {
+            binding_constraint = CAP_SUPPLY;
+            max_tokens = supply_headroom;
+        }
+        if wallet_headroom < max_tokens {
+            binding_constraint = CAP_WALLET;
+            max_tokens = wallet_headroom;
+        }
+        if volume_step_headroom < max_tokens {
+            binding_constraint = CAP_VOLUME_STEP;
+            max_tokens = volume_step_headroom;
+        }
+        if tier_cap_headroom < max_tokens {
+            binding_constraint = CAP_WHITELIST_TIER;
+            max_tokens = tier_cap_headroom;
+        }
+        if purchases_remaining == 0 {
+            binding_constraint = CAP_PURCHASE_LIMIT;
+            max_tokens = 0;
+        }
+        if presale.sale_stage != 1 && presale.sale_stage != 2 {
+            binding_constraint = CAP_SALE_INACTIVE;
+            max_tokens = 0;
+        }
+
+        let max_tokens_user_units = max_tokens / token_unit;
+        let (usd_price_micros_per_nlov, sol_price_lamports_per_nlov) =
+            presale.active_prices(Clock::get()?.unix_timestamp);
+        let status = CapStatus {
+            buyer,
+            buyer_tokens_purchased,
+            hardcap_headroom,
+            supply_headroom,
+            wallet_headroom,
+            volume_step_headroom,
+            tier_cap_headroom,
+            purchases_remaining,
+            currencies_open: presale.currencies_open(),
+            binding_constraint,
+            max_tokens,
+            max_usd_cents: max_tokens_user_units
+                .checked_mul(usd_price_micros_per_nlov)
+                .ok_or(PresaleError::MathOverflow)?
+                / USD_MICROS_PER_CENT,
+            max_lamports: max_tokens_user_units
+                .checked_mul(sol_price_lamports_per_nlov)
+                .ok_or(PresaleError::MathOverflow)?,
+        };
+
+        msg!(
+            "Cap status for {}: max {} tokens (raw), binding constraint {}",
+            buyer,
+            status.max_tokens,
+            status.binding_constraint
+        );
+
+        Ok(status)
+    }
+
+    /// Quotes a purchase of `amount` (lamports for `CURRENCY_SOL`, raw stablecoin units for
+    /// `CURRENCY_USDC`/`CURRENCY_USDT`/`CURRENCY_PYUSD`, raw payment token units for `CURRENCY_TOKEN`, as the
+    /// buy instructions take them) with the same math as the buy instructions, so the UI never disagrees
+    /// with the program. Pass the configured oracle accounts to quote oracle-priced SOL purchases, the
+    /// currency's `AcceptedCurrency` entry to quote a stablecoin with other than 6 decimals, the
+    /// `PaymentToken` entry and its price update to quote a payment token, and the buyer with their
+    /// `WhitelistEntry` or `whitelist_proof` to include their public-round whitelist discount.
+    pub fn quote_purchase(
+        ctx: Context<QuotePurchase>,
+        currency: u8,
+        amount: u64,
+        whitelist_proof: Option<WhitelistProof>,
+    ) -> Result<PurchaseQuote> {
+        let presale = &ctx.accounts.presale;
+        let token_unit = 10u64.pow(ctx.accounts.token_mint.decimals as u32);
+
+        require!(currency <= CURRENCY_TOKEN, PresaleError::InvalidCurrency);
+        let effective_price = purchase_price(
+            presale,
+            currency,
+            ctx.accounts.sol_usd_price_update.as_ref(),
+            ctx.accounts.sol_usd_fallback_feed.as_ref(),
+            ctx.accounts.sol_usd_twap_observation.as_ref(),
+        )?;
+
+        // Stablecoins are priced in micro-dollars, treated 1:1 with USD, and payment tokens at their oracle price
+        let payment_token_price = if currency == CURRENCY_TOKEN {
+            let (payment_token, price_update) = quoted_payment_token(ctx.accounts)?;
+            let (price, exponent) = read_payment_token_price(payment_token, price_update, Clock::get()?.unix_timestamp)?;
+            Some((payment_token.decimals, price, exponent))
+        } else {
+            None
+        };
+        let stable_coin_decimals = if currency == CURRENCY_SOL || currency == CURRENCY_TOKEN {
+            STABLE_COIN_DECIMALS
+        } else {
+            quoted_stable_coin_decimals(ctx.accounts.accepted_currency.as_ref(), currency)?
+        };
+        let payment = match (currency, payment_token_price) {
+            (CURRENCY_SOL, _) => amount,
+            (_, Some((decimals, price, exponent))) => token_amount_to_usd_micros(amount, decimals, price, exponent)?,
+            _ => stable_coin_raw_to_micros(amount, stable_coin_decimals)?,
+        };
+        let discount_bps = purchase_discount_bps(
+            presale,
+            currency,
+            payment,
+            effective_price,
+            &quoted_buyer(ctx.accounts),
+            whitelist_proof.as_ref(),
+            quoted_whitelist_entry(ctx.accounts)?,
+        )?;
+        let tokens_user_units = tokens_for_payment(payment, effective_price, discount_bps, presale.token_rounding_mode)?;
+        let exact_cost = payment_for_tokens(tokens_user_units, effective_price, discount_bps)?;
+        // As in the buy instructions, the fee is taken on the exact cost of the tokens, rounded up to the
+        // payment's raw units, rather than on the whole amount sent
+        let amount_charged = match (currency, payment_token_price) {
+            (CURRENCY_SOL, _) => exact_cost,
+            (_, Some((decimals, price, exponent))) => usd_micros_to_token_amount(exact_cost, decimals, price, exponent)?,
+            _ => micros_to_stable_coin_raw(exact_cost, stable_coin_decimals)?,
+        };
+        let protocol_fee = presale.protocol_fee(amount.min(amount_charged))?;
+        let quote = PurchaseQuote {
+            currency,
+            amount,
+            tokens_out: tokens_user_units.checked_mul(token_unit).ok_or(PresaleError::MathOverflow)?,
+            effective_price,
+            unconverted: payment.saturating_sub(exact_cost),
+            protocol_fee,
+            discount_bps,
+        };
+
+        msg!(
+            "Quote: {} (currency {}) buys {} tokens (raw) at {} per NLOV, {} unconverted, {} protocol fee",
+            amount,
+            currency,
+            quote.tokens_out,
+            effective_price,
+            quote.unconverted,
+            quote.protocol_fee
+        );
+
+        Ok(quote)
+    }
+
+    /// Quotes the payment needed to buy `desired_tokens` whole NLOV in `currency`, rounded up so the
+    /// purchase yields at least that many tokens. Takes the same optional accounts as `quote_purchase`.
+    pub fn quote_payment(
+        ctx: Context<QuotePurchase>,
+        currency: u8,
+        desired_tokens: u64,
+        whitelist_proof: Option<WhitelistProof>,
+    ) -> Result<PaymentQuote> {
+        let presale = &ctx.accounts.presale;
+        let token_unit = 10u64.pow(ctx.accounts.token_mint.decimals as u32);
+
+        require!(currency <= CURRENCY_TOKEN, PresaleError::InvalidCurrency);
+        require!(desired_tokens >= 1, PresaleError::InvalidPrice);
+        let effective_price = purchase_price(
+            presale,
+            currency,
+            ctx.accounts.sol_usd_price_update.as_ref(),
+            ctx.accounts.sol_usd_fallback_feed.as_ref(),
+            ctx.accounts.sol_usd_twap_observation.as_ref(),
+        )?;
+
+        // Stablecoins and payment tokens are paid in the mint's raw units, so round up to the next one:
+        // stablecoins are treated 1:1 with USD, payment tokens priced at their oracle price
+        let payment_token_price = if currency == CURRENCY_TOKEN {
+            let (payment_token, price_update) = quoted_payment_token(ctx.accounts)?;
+            let (price, exponent) = read_payment_token_price(payment_token, price_update, Clock::get()?.unix_timestamp)?;
+            Some((payment_token.decimals, price, exponent))
+        } else {
+            None
+        };
+        let stable_coin_decimals = if currency == CURRENCY_SOL || currency == CURRENCY_TOKEN {
+            STABLE_COIN_DECIMALS
+        } else {
+            quoted_stable_coin_decimals(ctx.accounts.accepted_currency.as_ref(), currency)?
+        };
+        let payment_amount = |payment: u64| -> Result<u64> {
+            match (currency, payment_token_price) {
+                (CURRENCY_SOL, _) => Ok(payment),
+                (_, Some((decimals, price, exponent))) => usd_micros_to_token_amount(payment, decimals, price, exponent),
+                _ => micros_to_stable_coin_raw(payment, stable_coin_decimals),
+            }
+        };
+        let round_up = |payment: u64| -> Result<u64> {
+            match (currency, payment_token_price) {
+                (CURRENCY_SOL, _) => Ok(payment),
+                (_, Some((decimals, price, exponent))) => {
+                    token_amount_to_usd_micros(payment_amount(payment)?, decimals, price, exponent)
+                }
+                _ => stable_coin_raw_to_micros(payment_amount(payment)?, stable_coin_decimals),
+            }
+        };
+
+        let buyer = quoted_buyer(ctx.accounts);
+        let has_entry = quoted_whitelist_entry(ctx.accounts)?;
+        let whitelist_discount = whitelist_discount_bps(presale, &buyer, whitelist_proof.as_ref(), has_entry);
+        let payment =
+            payment_for_desired_tokens(presale, currency, desired_tokens, effective_price, whitelist_discount, round_up)?;
+        let discount_bps = purchase_discount_bps(
+            presale,
+            currency,
+            payment,
+            effective_price,
+            &buyer,
+            whitelist_proof.as_ref(),
+            has_entry,
+        )?;
+        let tokens_user_units = tokens_for_payment(payment, effective_price, discount_bps, presale.token_rounding_mode)?;
+
+        let amount = payment_amount(payment)?;
+        let quote = PaymentQuote {
+            currency,
+            amount,
+            tokens_out: tokens_user_units.checked_mul(token_unit).ok_or(PresaleError::MathOverflow)?,
+            effective_price,
+            discount_bps,
+        };
+
+        msg!(
+            "Quote: {} NLOV costs {} (currency {}, raw units) at {} per NLOV",
+            desired_tokens,
+            amount,
+            currency,
+            effective_price
+        );
+
+        Ok(quote)
+    }
+
+    /// Read-only health check for monitoring: returns a bitmask of `HEALTH_*` warning conditions
+    /// (presale wallet under-funded, escrow shortfall, stage transition overdue, lockdown, paused
+    /// claims) so a single RPC call can drive alerting. Stablecoin escrow is only checked for the
+    /// vaults passed in.
+    pub fn health_check(ctx: Context<HealthCheck>) -> Result<SaleHealth> {
+        let presale = &ctx.accounts.presale;
+        let now = Clock::get()?.unix_timestamp;
+        let mut warnings: u32 = 0;
+
+        // The presale wallet must cover every allocation still owed plus the unreleased unsold stream
+        let token_obligations = presale
+            .outstanding_allocations()
+            .saturating_add(presale.unsold_stream_total.saturating_sub(presale.unsold_stream_released));
+        let token_shortfall = token_obligations.saturating_sub(ctx.accounts.presale_wallet.amount);
+        if token_shortfall > 0 {
+            warnings |= HEALTH_WALLET_UNDERFUNDED;
+        }
+
+        let lamport_shortfall = presale
+            .escrowed_lamports
+            .saturating_sub(ctx.accounts.escrow.lamports());
+        let usdc_shortfall = ctx
+            .accounts
+            .escrow_usdc_account
+            .as_ref()
+            .map_or(0, |vault| presale.escrowed_usdc.saturating_sub(vault.amount));
+        let usdt_shortfall = ctx
+            .accounts
+            .escrow_usdt_account
+            .as_ref()
+            .map_or(0, |vault| presale.escrowed_usdt.saturating_sub(vault.amount));
+        let pyusd_shortfall = ctx
+            .accounts
+            .escrow_pyusd_account
+            .as_ref()
+            .map_or(0, |vault| presale.escrowed_pyusd.saturating_sub(vault.amount));
+        if lamport_shortfall > 0 || usdc_shortfall > 0 || usdt_shortfall > 0 || pyusd_shortfall > 0 {
+            warnings |= HEALTH_ESCROW_SHORTFALL;
+        }
+
+        let private_sale_end = presale.presale_start.saturating_add(presale.private_sale_duration);
+        let public_sale_end = private_sale_end.saturating_add(presale.public_sale_duration);
+        if (presale.sale_stage == 1 && now >= private_sale_end)
+            || (presale.sale_stage == 2 && now >= public_sale_end)
+        {
+            warnings |= HEALTH_SCHEDULE_OVERDUE;
+        }
+
+        if presale.locked_down {
+            warnings |= HEALTH_LOCKED_DOWN;
+        }
+        if presale.claims_paused {
+            warnings |= HEALTH_CLAIMS_PAUSED;
+        }
+
+        // SOL buys fail once every configured SOL/USD feed is older than max_price_age
+        let mut oracle_last_update = 0;
+        if presale.sol_usd_price_feed != Pubkey::default() && ctx.accounts.sol_usd_price_update.is_some() {
+            match sol_oracle_last_update(
+                presale,
+                ctx.accounts.sol_usd_price_update.as_ref(),
+                ctx.accounts.sol_usd_fallback_feed.as_ref(),
+            ) {
+                Some(last_update) => {
+                    oracle_last_update = last_update;
+                    if now > last_update.saturating_add(presale.max_price_age) {
+                        warnings |= HEALTH_ORACLE_STALE;
+                    }
+                }
+                None => warnings |= HEALTH_ORACLE_STALE,
+            }
+        }
+
+        msg!("Health check: warnings {:#x}", warnings);
+
+        Ok(SaleHealth {
+            warnings,
+            sale_stage: presale.sale_stage,
+            token_shortfall,
+            lamport_shortfall,
+            usdc_shortfall,
+            usdt_shortfall,
+            timestamp: now,
+            pyusd_shortfall,
+            oracle_last_update,
+        })
+    }
+
+    /// Sets the softcap: if fewer tokens than this are sold by the end of the sale,
+    /// the presale cannot be finalized and buyers can claim refunds.
+    /// Only the admin can do this, and only before the sale has ended.
+    pub fn set_softcap(ctx: Context<SetSoftcap>, softcap_tokens: u64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(presale.sale_stage < 3, PresaleError::SaleAlreadyEnded);
+        require!(
+            softcap_tokens <= presale.hardcap_tokens,
+            PresaleError::InvalidSoftcap
+        );
+
+        presale.softcap_tokens = softcap_tokens;
+
+        msg!("Softcap set to {} tokens (raw)", softcap_tokens);
+
+        Ok(())
+    }
+
+    /// Cancels the sale: purchases stop, the presale can no longer be finalized,
+    /// and buyers can reclaim their escrowed payments with `claim_refund`.
+    /// Only the admin can perform this action, and only before finalization.
+    pub fn cancel_presale(ctx: Context<CancelPresale>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(
+            !presale.pool_created,
+            PresaleError::LiquidityPoolAlreadyCreated
+        );
+        require!(!presale.cancelled, PresaleError::SaleCancelled);
+        require!(presale.settlement_count == 0, PresaleError::ProceedsAlreadySettled);
+
+        presale.cancelled = true;
+        presale.sale_stage = 3;
+        presale.stage_changed_at = 0; // A cancellation cannot be rolled back
+
+        emit!(PresaleCancelledEvent {
+            admin: ctx.accounts.admin.key(),
+            total_sold: presale.total_sold,
+        });
+
+        msg!("Presale cancelled with {} tokens (raw) sold", presale.total_sold);
+
+        Ok(())
+    }
+
+    /// Returns a buyer's escrowed SOL and stablecoin payments when the sale was cancelled
+    /// or ended below the softcap, and zeroes their contribution and allocation. Buyers with nothing in
+    /// escrow keep their allocation. Refunds remain available during a lockdown.
+    pub fn claim_refund<'info>(ctx: Context<'_, '_, 'info, 'info, ClaimRefund<'info>>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let presale_key = presale.key();
+        let buyer_account = &mut ctx.accounts.buyer_account;
+
+        require!(presale.refunds_enabled(), PresaleError::RefundsNotAvailable);
+        // Web2, imported and faucet allocations were never paid into escrow, so they keep their tokens
+        require!(buyer_account.has_refundable_contribution(), PresaleError::NothingToRefund);
+
+        let escrow_seeds: &[&[u8]] = &[ESCROW_SEED, presale_key.as_ref(), &[presale.escrow_bump]];
+        let signer_seeds: &[&[&[u8]]] = &[escrow_seeds];
+
+        let refund_lamports = buyer_account.contributed_lamports;
+        if refund_lamports > 0 {
+            system_program::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.system_program.to_account_info(),
+                    SystemTransfer {
+                        from: ctx.accounts.escrow.to_account_info(),
+                        to: ctx.accounts.buyer.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                refund_lamports,
+            )?;
+            presale.escrowed_lamports = presale.escrowed_lamports.saturating_sub(refund_lamports);
+        }
+
+        let refund_usdc = buyer_account.contributed_usdc;
+        if refund_usdc > 0 {
+            let escrow_vault = ctx
+                .accounts
+                .escrow_usdc_account
+                .as_ref()
+                .ok_or(PresaleError::InvalidTokenAccount)?;
+            let buyer_vault = ctx
+                .accounts
+                .buyer_usdc_account
+                .as_ref()
+                .ok_or(PresaleError::InvalidTokenAccount)?;
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TokenTransfer {
+                        from: escrow_vault.to_account_info(),
+                        to: buyer_vault.to_account_info(),
+                        authority: ctx.accounts.escrow.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                refund_usdc,
+            )?;
+            presale.escrowed_usdc = presale.escrowed_usdc.saturating_sub(refund_usdc);
+        }
+
+        let refund_usdt = buyer_account.contributed_usdt;
+        if refund_usdt > 0 {
+            let escrow_vault = ctx
+                .accounts
+                .escrow_usdt_account
+                .as_ref()
+                .ok_or(PresaleError::InvalidTokenAccount)?;
+            let buyer_vault = ctx
+                .accounts
+                .buyer_usdt_account
+                .as_ref()
+                .ok_or(PresaleError::InvalidTokenAccount)?;
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TokenTransfer {
+                        from: escrow_vault.to_account_info(),
+                        to: buyer_vault.to_account_info(),
+                        authority: ctx.accounts.escrow.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                refund_usdt,
+            )?;
+            presale.escrowed_usdt = presale.escrowed_usdt.saturating_sub(refund_usdt);
+        }
+
+        let refund_pyusd = buyer_account.contributed_pyusd;
+        if refund_pyusd > 0 {
+            let escrow_vault = ctx
+                .accounts
+                .escrow_pyusd_account
+                .as_ref()
+                .ok_or(PresaleError::InvalidTokenAccount)?;
+            let buyer_vault = ctx
+                .accounts
+                .buyer_pyusd_account
+                .as_ref()
+                .ok_or(PresaleError::InvalidTokenAccount)?;
+            let pyusd_mint = ctx.accounts.pyusd_mint.as_ref().ok_or(PresaleError::InvalidStableToken)?;
+            let token_2022_program = ctx
+                .accounts
+                .token_2022_program
+                .as_ref()
+                .ok_or(PresaleError::InvalidTokenAccount)?;
+            transfer_stable_coin(
+                token_2022_program,
+                pyusd_mint,
+                escrow_vault.to_account_info(),
+                buyer_vault.to_account_info(),
+                ctx.accounts.escrow.to_account_info(),
+                signer_seeds,
+                refund_pyusd,
+            )?;
+            presale.escrowed_pyusd = presale.escrowed_pyusd.saturating_sub(refund_pyusd);
+        }
+
+        buyer_account.contributed_lamports = 0;
+        buyer_account.contributed_usdc = 0;
+        buyer_account.contributed_usdt = 0;
+        buyer_account.contributed_pyusd = 0;
+
+        // The refunded allocation is released
+        let tokens_released =
+            release_refunded_allocation(presale, buyer_account, &ctx.accounts.registry_page, ctx.remaining_accounts)?;
+
+        // Protocol fees were paid out to the fee wallet at purchase time and are not returned
+        emit!(RefundClaimedEvent {
+            buyer: buyer_account.buyer,
+            lamports: refund_lamports,
+            usdc: refund_usdc,
+            usdt: refund_usdt,
+            tokens_released,
+            pyusd: refund_pyusd,
+            protocol_fee_lamports: buyer_account.protocol_fee_lamports,
+            protocol_fee_usdc: buyer_account.protocol_fee_usdc,
+            protocol_fee_usdt: buyer_account.protocol_fee_usdt,
+            protocol_fee_pyusd: buyer_account.protocol_fee_pyusd,
+        });
+
+        msg!(
+            "Refunded buyer {}: {} lamports, {} USDC (raw), {} USDT (raw), {} PYUSD (raw)",
+            buyer_account.buyer,
+            refund_lamports,
+            refund_usdc,
+            refund_usdt,
+            refund_pyusd
+        );
+
+        Ok(())
+    }
+
+    /// Returns a buyer's escrowed payments in one payment token when the sale was cancelled or ended below
+    /// the softcap, closing their `TokenContribution` record. The buyer's allocation is released by
+    /// whichever refund the buyer claims first, this one or `claim_refund`. Refunds remain available
+    /// during a lockdown.
+    pub fn claim_token_refund<'info>(ctx: Context<'_, '_, 'info, 'info, ClaimTokenRefund<'info>>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let presale_key = presale.key();
+        let buyer_account = &mut ctx.accounts.buyer_account;
+        let amount = ctx.accounts.token_contribution.amount;
+
+        require!(presale.refunds_enabled(), PresaleError::RefundsNotAvailable);
+        require!(amount > 0, PresaleError::NothingToRefund);
+
+        let escrow_seeds: &[&[u8]] = &[ESCROW_SEED, presale_key.as_ref(), &[presale.escrow_bump]];
+        let signer_seeds: &[&[&[u8]]] = &[escrow_seeds];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TokenTransfer {
+                    from: ctx.accounts.escrow_payment_account.to_account_info(),
+                    to: ctx.accounts.buyer_payment_account.to_account_info(),
+                    authority: ctx.accounts.escrow.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+
+        let payment_token = &mut ctx.accounts.payment_token;
+        let escrowed_before = payment_token.escrowed;
+        payment_token.escrowed = payment_token.escrowed.saturating_sub(amount);
+        presale.track_payment_token_escrow(escrowed_before, payment_token.escrowed)?;
+
+        // The refunded allocation is released, unless an earlier refund already did
+        let tokens_released = if buyer_account.total_allocation()? > 0 {
+            release_refunded_allocation(presale, buyer_account, &ctx.accounts.registry_page, ctx.remaining_accounts)?
+        } else {
+            0
+        };
+
+        emit!(TokenRefundClaimedEvent {
+            buyer: buyer_account.buyer,
+            mint: payment_token.mint,
+            amount,
+            tokens_released,
+        });
+
+        msg!(
+            "Refunded buyer {}: {} (raw) of {}, {} tokens released",
+            ctx.accounts.buyer.key(),
+            amount,
+            payment_token.mint,
+            tokens_released
+        );
+
+        Ok(())
+    }
+
+    /// Opts into streaming unsold tokens linearly to a community rewards wallet over `months`
+    /// instead of moving them to the liquidity wallet in one lump at finalization.
+    /// `months = 0` restores the lump-sum transfer. Only the admin can do this, before finalization.
+    pub fn configure_unsold_stream(ctx: Context<ConfigureUnsoldStream>, months: u8) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(
+            !presale.pool_created,
+            PresaleError::LiquidityPoolAlreadyCreated
+        );
+
+        presale.unsold_stream_months = months;
+        presale.community_rewards_wallet = ctx.accounts.community_rewards_wallet.key();
+
+        msg!(
+            "Unsold tokens will stream to {} over {} months",
+            presale.community_rewards_wallet,
+            months
+        );
+
+        Ok(())
+    }
+
+    /// Permissionless crank: releases every unsold-token tranche that has come due since the last release.
+    pub fn release_next_tranche(ctx: Context<ReleaseNextTranche>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(presale.pool_created, PresaleError::PresaleNotFinalized);
+        require!(presale.unsold_stream_months > 0, PresaleError::NoUnsoldStream);
+
+        let elapsed = Clock::get()?.unix_timestamp.saturating_sub(presale.unsold_stream_start);
+        let tranches_due = ((elapsed / STREAM_TRANCHE_SECONDS) as u64).min(presale.unsold_stream_months as u64);
+        let due_total = ((presale.unsold_stream_total as u128) * (tranches_due as u128)
+            / (presale.unsold_stream_months as u128)) as u64;
+        let release_amount = due_total.saturating_sub(presale.unsold_stream_released);
+        require!(release_amount > 0, PresaleError::NoTrancheDue);
+
+        let seeds: &[&[u8]] = &[PRESALE_SEED, presale.admin.as_ref(), &[presale.bump]];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TokenTransfer {
+                    from: ctx.accounts.presale_wallet.to_account_info(),
+                    to: ctx.accounts.community_rewards_wallet.to_account_info(),
+                    authority: presale.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            release_amount,
+        )?;
+
+        presale.unsold_stream_released = presale.unsold_stream_released.checked_add(release_amount).ok_or(PresaleError::MathOverflow)?;
+
+        emit!(TrancheReleasedEvent {
+            tranches_released: tranches_due,
+            amount: release_amount,
+            total_released: presale.unsold_stream_released,
+        });
+
+        msg!(
+            "Released {} unsold tokens (raw) to the community rewards wallet ({} of {} tranches due)",
+            release_amount,
+            tranches_due,
+            presale.unsold_stream_months
+        );
+
+        Ok(())
+    }
+
+    /// Compliance action: revokes a buyer's unclaimed allocation (e.g. failed KYC or sanctions match),
+    /// moves the tokens to an admin-controlled pool, and blocks further purchases by that wallet.
+    /// The reason is stored as the buyer's compliance note. Only the admin can perform this action.
+    pub fn revoke_allocation(
+        ctx: Context<RevokeAllocation>,
+        reason: [u8; COMPLIANCE_NOTE_LEN],
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let buyer_account = &mut ctx.accounts.buyer_account;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(!buyer_account.revoked, PresaleError::AllocationRevoked);
+
+        let revoked_raw = buyer_account.unclaimed_tokens()?;
+
+        if revoked_raw > 0 {
+            let seeds: &[&[u8]] = &[PRESALE_SEED, presale.admin.as_ref(), &[presale.bump]];
+            let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TokenTransfer {
+                        from: ctx.accounts.presale_wallet.to_account_info(),
+                        to: ctx.accounts.revocation_pool.to_account_info(),
+                        authority: presale.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                revoked_raw,
+            )?;
+        }
+
+        buyer_account.debit_allocation(revoked_raw)?;
+        buyer_account.tokens_revoked = buyer_account.tokens_revoked.checked_add(revoked_raw).ok_or(PresaleError::MathOverflow)?;
+        buyer_account.revoked = true;
+        buyer_account.compliance_note = reason;
+        presale.total_revoked = presale.total_revoked.checked_add(revoked_raw).ok_or(PresaleError::MathOverflow)?;
+        sync_buyer_registry(presale, buyer_account, &ctx.accounts.registry_page, false)?;
+
+        emit!(AllocationRevokedEvent {
+            admin: ctx.accounts.admin.key(),
+            buyer: buyer_account.buyer,
+            tokens_revoked: revoked_raw,
+            revocation_pool: ctx.accounts.revocation_pool.key(),
+            reason,
+        });
+
+        msg!(
+            "Revoked {} tokens (raw) from buyer {}",
+            revoked_raw,
+            buyer_account.buyer
+        );
+
+        Ok(())
+    }
+
+    /// Compliance action for vesting agreements: revokes the unvested remainder of a buyer's allocation
+    /// and moves it to an admin-controlled pool. Tokens vested so far stay claimable, and the buyer's
+    /// remaining allocation counts as fully vested from then on. The reason is stored as the buyer's
+    /// compliance note. Only the admin can do this, and only after the presale is finalized.
+    pub fn revoke_unvested(
+        ctx: Context<RevokeUnvested>,
+        reason: [u8; COMPLIANCE_NOTE_LEN],
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let buyer_account = &mut ctx.accounts.buyer_account;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(presale.pool_created, PresaleError::PresaleNotFinalized);
+        require!(!buyer_account.revoked, PresaleError::AllocationRevoked);
+        require!(!buyer_account.vesting_revoked, PresaleError::VestingAlreadyRevoked);
+
+        let vested_raw = presale.buyer_vested_amount(
+            buyer_account,
+            ctx.accounts.vesting_schedule.as_deref(),
+            Clock::get()?.unix_timestamp,
+        )?;
+        let revoked_raw = buyer_account
+            .total_allocation()?
+            .saturating_sub(vested_raw.max(buyer_account.settled_tokens()?));
+
+        if revoked_raw > 0 {
+            let seeds: &[&[u8]] = &[PRESALE_SEED, presale.admin.as_ref(), &[presale.bump]];
+            let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TokenTransfer {
+                        from: ctx.accounts.presale_wallet.to_account_info(),
+                        to: ctx.accounts.revocation_pool.to_account_info(),
+                        authority: presale.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                revoked_raw,
+            )?;
+        }
+
+        buyer_account.debit_allocation(revoked_raw)?;
+        buyer_account.tokens_revoked = buyer_account.tokens_revoked.checked_add(revoked_raw).ok_or(PresaleError::MathOverflow)?;
+        buyer_account.vesting_revoked = true;
+        buyer_account.compliance_note = reason;
+        presale.total_revoked = presale.total_revoked.checked_add(revoked_raw).ok_or(PresaleError::MathOverflow)?;
+        sync_buyer_registry(presale, buyer_account, &ctx.accounts.registry_page, false)?;
+
+        emit!(AllocationRevokedEvent {
+            admin: ctx.accounts.admin.key(),
+            buyer: buyer_account.buyer,
+            tokens_revoked: revoked_raw,
+            revocation_pool: ctx.accounts.revocation_pool.key(),
+            reason,
+        });
+
+        msg!(
+            "Revoked {} unvested tokens (raw) from buyer {}",
+            revoked_raw,
+            buyer_account.buyer
+        );
+
+        Ok(())
+    }
+
+    /// Sets how long after a `set_stage` transition the admin may still roll it back, at most
+    /// `MAX_STAGE_ROLLBACK_WINDOW`. Only the admin can perform this action.
+    pub fn set_stage_rollback_window(ctx: Context<SetStage>, window_seconds: i64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(
+            (0..=MAX_STAGE_ROLLBACK_WINDOW).contains(&window_seconds),
+            PresaleError::InvalidRollbackWindow
+        );
+
+        presale.stage_rollback_window = window_seconds;
+
+        emit!(StageRollbackWindowUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            window_seconds,
+        });
+
+        msg!("Stage rollback window set to {} seconds", window_seconds);
+
+        Ok(())
+    }
+
+    /// Reverts an accidental `set_stage` transition, provided it happened within the rollback
+    /// window and no purchases occurred under the new stage. Only the admin can perform this action.
+    pub fn rollback_stage(ctx: Context<SetStage>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(
+            presale.stage_changed_at > 0 && !presale.pool_created,
+            PresaleError::NoStageToRollBack
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now <= presale.stage_changed_at.saturating_add(presale.stage_rollback_window),
+            PresaleError::RollbackWindowElapsed
+        );
+        require!(
+            presale.purchases_in_stage == 0,
+            PresaleError::PurchasesInStage
+        );
+
+        let from_stage = presale.sale_stage;
+        presale.sale_stage = presale.previous_stage;
+        presale.stage_changed_at = 0; // Only the latest transition can be rolled back
+        presale.apply_stage_price(now);
+
+        emit!(StageRolledBackEvent {
+            admin: ctx.accounts.admin.key(),
+            from_stage,
+            to_stage: presale.sale_stage,
+            timestamp: now,
+        });
+
+        msg!("Stage rolled back from {} to {}", from_stage, presale.sale_stage);
+
+        Ok(())
+    }
+
+    /// Configures the identity/KYC provider: a verifier program ID and how eligibility is checked.
+    /// `IDENTITY_MODE_ACCOUNT` inspects an attestation account owned by the verifier whose data names the
+    /// buyer at `subject_offset`; `IDENTITY_MODE_CPI` asks the verifier program to approve the buyer;
+    /// `IDENTITY_MODE_GATEWAY` requires an active Civic gateway token issued in the `verifier` gatekeeper
+    /// network (e.g. a uniqueness pass, so one human maps to one wallet). Switching providers is a config update. Only the admin can perform this action.
+    pub fn set_identity_verifier(
+        ctx: Context<SetIdentityVerifier>,
+        verifier: Pubkey,
+        mode: u8,
+        subject_offset: u16,
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(mode <= IDENTITY_MODE_GATEWAY, PresaleError::InvalidIdentityMode);
+
+        presale.identity_verifier = verifier;
+        presale.identity_mode = mode;
+        presale.identity_subject_offset = subject_offset;
+
+        emit!(IdentityVerifierUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            verifier,
+            mode,
+            subject_offset,
+        });
+
+        msg!(
+            "Identity verifier set to {} (mode {}, subject offset {})",
+            verifier,
+            mode,
+            subject_offset
+        );
+
+        Ok(())
+    }
+
+    /// Sets (or rotates) the gatekeeper key that must co-sign public-round purchases, after the backend's
+    /// captcha and rate limiting (default pubkey = no co-signature). Only the admin can perform this action.
+    pub fn set_gatekeeper(ctx: Context<SetGatekeeper>, gatekeeper: Pubkey) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        presale.gatekeeper = gatekeeper;
+
+        emit!(GatekeeperUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            gatekeeper,
+        });
+
+        msg!("Gatekeeper set to {}", gatekeeper);
+
+        Ok(())
+    }
+
+    /// Sets (or rotates) the backend key allowed to record Web2 (exchange) purchases through
+    /// `record_web2_purchase` (default pubkey = Web2 purchases disabled). Only the admin can perform this action.
+    pub fn set_web2_recorder(ctx: Context<SetWeb2Recorder>, web2_recorder: Pubkey) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        let previous_recorder = presale.web2_recorder;
+        presale.web2_recorder = web2_recorder;
+
+        emit!(Web2RecorderUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            previous_recorder,
+            web2_recorder,
+        });
+
+        msg!("Web2 recorder set to {}", web2_recorder);
+
+        Ok(())
+    }
+
+    /// Sets the KYC authority whose Ed25519 attestations buyers must present with each purchase
+    /// (default pubkey = KYC mode off). Only the admin can perform this action.
+    pub fn set_kyc_authority(ctx: Context<SetKycAuthority>, kyc_authority: Pubkey) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        let previous_authority = presale.kyc_authority;
+        presale.kyc_authority = kyc_authority;
+
+        emit!(KycAuthorityUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            previous_authority,
+            kyc_authority,
+        });
+
+        msg!("KYC authority set to {}", kyc_authority);
+
+        Ok(())
+    }
+
+    /// Sets the region bitmask whose bits, when present in a buyer's KYC attestation, reject the
+    /// purchase (0 = no restrictions). Only the admin can perform this action.
+    pub fn set_blocked_regions(ctx: Context<SetBlockedRegions>, blocked_regions: u64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        presale.blocked_regions = blocked_regions;
+
+        emit!(BlockedRegionsUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            blocked_regions,
+        });
+
+        msg!("Blocked regions set to {:#x}", blocked_regions);
+
+        Ok(())
+    }
+
+    /// Sets the merkle root of the private-round whitelist. While the sale is in the private stage,
+    /// buyers must present a proof of inclusion with each purchase; a zero root lets anyone buy.
+    /// Only the admin can perform this action.
+    pub fn set_whitelist_root(ctx: Context<SetWhitelistRoot>, whitelist_root: [u8; 32]) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        presale.whitelist_root = whitelist_root;
+
+        emit!(WhitelistRootUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            whitelist_root,
+        });
+
+        msg!("Whitelist root updated (whitelist enabled: {})", whitelist_root != [0u8; 32]);
+
+        Ok(())
+    }
+
+    /// Stores a whitelist root for one phase of a stage in `slot`, active from `activates_at` until a later
+    /// phase of the same stage activates, so the private-round list and a public-round priority list can
+    /// differ without clobbering a live root. While a phase is active, purchases in its stage need a proof
+    /// against its root. A zero root clears the slot. Only the admin can perform this action.
+    pub fn set_phase_whitelist_root(
+        ctx: Context<SetWhitelistRoot>,
+        slot: u8,
+        stage: u8,
+        activates_at: i64,
+        root: [u8; 32],
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(
+            (slot as usize) < MAX_WHITELIST_PHASES && (stage == 1 || stage == 2),
+            PresaleError::InvalidWhitelistPhase
+        );
+
+        presale.phase_whitelist_roots[slot as usize] = if root == [0u8; 32] {
+            PhaseWhitelistRoot::default()
+        } else {
+            PhaseWhitelistRoot { stage, activates_at, root }
+        };
+
+        emit!(PhaseWhitelistRootUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            slot,
+            stage,
+            activates_at,
+            root,
+        });
+
+        msg!(
+            "Whitelist phase {} set for stage {} from {} (cleared: {})",
+            slot,
+            stage,
+            activates_at,
+            root == [0u8; 32]
+        );
+
+        Ok(())
+    }
+
+    /// Sets the maximum private-round purchase per wallet for each whitelist tier (e.g. Gold, Silver,
+    /// Bronze), in raw units of cumulative private-round tokens; 0 leaves a tier uncapped.
+    /// Only the admin can perform this action.
+    pub fn set_whitelist_tier_caps(
+        ctx: Context<SetWhitelistTierCaps>,
+        tier_caps: [u64; WHITELIST_TIER_COUNT],
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        presale.whitelist_tier_caps = tier_caps;
+
+        emit!(WhitelistTierCapsUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            tier_caps,
+        });
+
+        msg!("Whitelist tier caps set to {:?} (raw)", tier_caps);
+
+        Ok(())
+    }
+
+    /// Whitelists one wallet for the private round in `tier` by creating its `WhitelistEntry`, as an
+    /// alternative to merkle proofs. The first entry turns on the whitelist. Only the admin can perform this action.
+    pub fn add_to_whitelist(ctx: Context<AddToWhitelist>, wallet: Pubkey, tier: u8) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!((tier as usize) < WHITELIST_TIER_COUNT, PresaleError::InvalidWhitelistTier);
+
+        let whitelist_entry = &mut ctx.accounts.whitelist_entry;
+        whitelist_entry.presale = presale.key();
+        whitelist_entry.wallet = wallet;
+        whitelist_entry.tier = tier;
+        whitelist_entry.bump = ctx.bumps.whitelist_entry;
+
+        presale.entry_whitelist = true;
+
+        emit!(WalletWhitelistedEvent {
+            admin: ctx.accounts.admin.key(),
+            wallet,
+            tier,
+        });
+
+        msg!("Wallet {} added to the whitelist in tier {}", wallet, tier);
+
+        Ok(())
+    }
+
+    /// Removes a wallet from the private-round whitelist, closing its `WhitelistEntry` and refunding the
+    /// rent to the admin. The whitelist stays on even once empty. Only the admin can perform this action.
+    pub fn remove_from_whitelist(ctx: Context<RemoveFromWhitelist>) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        emit!(WalletRemovedFromWhitelistEvent {
+            admin: ctx.accounts.admin.key(),
+            wallet: ctx.accounts.whitelist_entry.wallet,
+        });
+
+        msg!("Wallet {} removed from the whitelist", ctx.accounts.whitelist_entry.wallet);
+
+        Ok(())
+    }
+
+    /// Sets the refundable SOL deposit locked by `register_interest` (0 = registration closed).
+    /// Only the admin can perform this action.
+    pub fn set_registration_deposit(ctx: Context<SetRegistrationDeposit>, deposit_lamports: u64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        presale.registration_deposit = deposit_lamports;
+
+        emit!(RegistrationDepositUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            deposit_lamports,
+        });
+
+        msg!("Registration deposit set to {} lamports", deposit_lamports);
+
+        Ok(())
+    }
+
+    /// Registers the calling wallet's interest in the private round before the sale starts, locking
+    /// `registration_deposit` lamports in its `Registration` PDA until the admin approves it or the
+    /// sale starts.
+    pub fn register_interest(ctx: Context<RegisterInterest>) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.sale_stage == 0 && presale.registration_deposit > 0,
+            PresaleError::RegistrationClosed
+        );
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                SystemTransfer {
+                    from: ctx.accounts.wallet.to_account_info(),
+                    to: ctx.accounts.registration.to_account_info(),
+                },
+            ),
+            presale.registration_deposit,
+        )?;
+
+        let registration = &mut ctx.accounts.registration;
+        registration.presale = presale.key();
+        registration.wallet = ctx.accounts.wallet.key();
+        registration.deposit_lamports = presale.registration_deposit;
+        registration.registered_at = Clock::get()?.unix_timestamp;
+        registration.approved = false;
+        registration.bump = ctx.bumps.registration;
+
+        msg!(
+            "Wallet {} registered with a deposit of {} lamports",
+            registration.wallet,
+            registration.deposit_lamports
+        );
+
+        Ok(())
+    }
+
+    /// Approves a registration, whitelisting its wallet for the private round in `tier` as
+    /// `add_to_whitelist` does. The deposit becomes refundable. Only the admin can perform this action.
+    pub fn approve_registration(ctx: Context<ApproveRegistration>, tier: u8) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!((tier as usize) < WHITELIST_TIER_COUNT, PresaleError::InvalidWhitelistTier);
+
+        let registration = &mut ctx.accounts.registration;
+        registration.approved = true;
+
+        let whitelist_entry = &mut ctx.accounts.whitelist_entry;
+        whitelist_entry.presale = presale.key();
+        whitelist_entry.wallet = registration.wallet;
+        whitelist_entry.tier = tier;
+        whitelist_entry.bump = ctx.bumps.whitelist_entry;
+
+        presale.entry_whitelist = true;
+
+        emit!(RegistrationApprovedEvent {
+            admin: ctx.accounts.admin.key(),
+            wallet: registration.wallet,
+            tier,
+        });
+
+        msg!("Registration of wallet {} approved in tier {}", registration.wallet, tier);
+
+        Ok(())
+    }
+
+    /// Closes the caller's `Registration`, returning the deposit and rent. Deposits stay locked until
+    /// the registration is approved or the sale has started.
+    pub fn withdraw_registration_deposit(ctx: Context<WithdrawRegistrationDeposit>) -> Result<()> {
+        let registration = &ctx.accounts.registration;
+
+        ctx.accounts.presale.ensure_not_locked_down()?;
+
+        require!(
+            registration.approved || ctx.accounts.presale.sale_stage != 0,
+            PresaleError::RegistrationDepositLocked
+        );
+
+        msg!(
+            "Wallet {} withdrew its registration deposit of {} lamports",
+            registration.wallet,
+            registration.deposit_lamports
+        );
+
+        Ok(())
+    }
+
+    /// Blocks a wallet from buying and claiming by creating its `Blacklist` PDA (admin pays rent).
+    /// The first entry turns the blacklist on, after which buy and claim instructions must pass the
+    /// buyer's `Blacklist` PDA, whether or not it exists. Only the admin can perform this action.
+    pub fn blacklist_wallet(ctx: Context<BlacklistWallet>, wallet: Pubkey) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        let blacklist = &mut ctx.accounts.blacklist;
+        blacklist.presale = presale.key();
+        blacklist.wallet = wallet;
+        blacklist.blacklisted_at = Clock::get()?.unix_timestamp;
+        blacklist.bump = ctx.bumps.blacklist;
+
+        presale.blacklist_enabled = true;
+
+        emit!(WalletBlacklistedEvent {
+            admin: ctx.accounts.admin.key(),
+            wallet,
+            timestamp: blacklist.blacklisted_at,
+        });
+
+        msg!("Wallet {} blacklisted", wallet);
+
+        Ok(())
+    }
+
+    /// Unblocks a wallet by closing its `Blacklist` PDA, refunding the rent to the admin.
+    /// Only the admin can perform this action.
+    pub fn unblacklist_wallet(ctx: Context<UnblacklistWallet>) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        emit!(WalletUnblacklistedEvent {
+            admin: ctx.accounts.admin.key(),
+            wallet: ctx.accounts.blacklist.wallet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Wallet {} removed from the blacklist", ctx.accounts.blacklist.wallet);
+
+        Ok(())
+    }
+
+    /// Checks whether `buyer` passes the configured identity verification, without purchasing.
+    /// Fails with the verifier's error if not.
+    pub fn verify_eligibility(ctx: Context<VerifyEligibility>) -> Result<()> {
+        ctx.accounts.presale.ensure_not_locked_down()?;
+
+        check_identity(
+            &ctx.accounts.presale,
+            &ctx.accounts.buyer.to_account_info(),
+            ctx.accounts.attestation.as_ref(),
+            ctx.accounts.identity_verifier_program.as_ref(),
+        )?;
+        check_kyc_attestation(
+            &ctx.accounts.presale,
+            &ctx.accounts.buyer.key(),
+            ctx.accounts.instructions_sysvar.as_ref(),
+        )?;
+
+        msg!("Buyer {} is eligible", ctx.accounts.buyer.key());
+
+        Ok(())
+    }
+
+    /// Grants a promotional bonus (e.g. +10% for the first 48 hours) to a buyer. Bonus tokens come out of
+    /// unallocated presale wallet supply and are claimed and vested together with the purchased allocation.
+    /// Only the admin can perform this action.
+    pub fn grant_bonus(ctx: Context<GrantBonus>, bonus_tokens_raw: u64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let buyer_account = &mut ctx.accounts.buyer_account;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(!presale.cancelled, PresaleError::SaleCancelled);
+        require!(!buyer_account.revoked, PresaleError::AllocationRevoked);
+        require!(bonus_tokens_raw > 0, PresaleError::InvalidBonus);
+
+        // The bonus must be backed by tokens no one else is owed
+        require!(
+            ctx.accounts
+                .presale_wallet
+                .amount
+                .saturating_sub(presale.outstanding_allocations())
+                >= bonus_tokens_raw,
+            PresaleError::InsufficientTokens
+        );
+
+        buyer_account.bonus_tokens = buyer_account.bonus_tokens.checked_add(bonus_tokens_raw).ok_or(PresaleError::MathOverflow)?;
+        presale.total_bonus = presale.total_bonus.checked_add(bonus_tokens_raw).ok_or(PresaleError::MathOverflow)?;
+
+        emit!(BonusGrantedEvent {
+            buyer: buyer_account.buyer,
+            bonus_tokens: bonus_tokens_raw,
+            total_bonus_tokens: buyer_account.bonus_tokens,
+        });
+
+        msg!(
+            "Granted {} bonus tokens (raw) to buyer {}",
+            bonus_tokens_raw,
+            buyer_account.buyer
+        );
+
+        Ok(())
+    }
+
+    /// Unwinds a single fat-fingered purchase, identified by its index in the buyer's purchase history:
+    /// the tokens are de-allocated, the escrowed payment is returned, and sale totals are decremented.
+    /// Only within `PURCHASE_REVERSAL_WINDOW` of the purchase and before finalization (so before any claim).
+    /// Only the operator or admin can perform this action.
+    pub fn reverse_purchase_in_kind(ctx: Context<ReversePurchaseInKind>, purchase_index: u16) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let presale_key = presale.key();
+        let buyer_account = &mut ctx.accounts.buyer_account;
+        let signer = ctx.accounts.operator.key();
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            signer == presale.operator || signer == presale.admin,
+            PresaleError::Unauthorized
+        );
+        require!(!presale.pool_created, PresaleError::LiquidityPoolAlreadyCreated);
+        require!(!presale.refunds_enabled(), PresaleError::SaleCancelled);
+        require!(!buyer_account.revoked, PresaleError::AllocationRevoked);
+
+        let record = buyer_account
+            .purchases
+            .get(purchase_index as usize)
+            .cloned()
+            .ok_or(PresaleError::PurchaseNotFound)?;
+        require!(!record.reversed, PresaleError::PurchaseAlreadyReversed);
+        // Payment token purchases are only refundable through `claim_token_refund`
+        require!(record.currency != CURRENCY_TOKEN, PresaleError::InvalidCurrency);
+        require!(
+            !paid_into_escrow(record.payment_type) || record.timestamp > presale.last_settlement_at,
+            PresaleError::ProceedsAlreadySettled
+        );
+        require!(
+            Clock::get()?.unix_timestamp <= record.timestamp.saturating_add(PURCHASE_REVERSAL_WINDOW),
+            PresaleError::ReversalWindowElapsed
+        );
+        require!(
+            record.tokens <= buyer_account.tokens_purchased
+                && record.tokens <= buyer_account.unclaimed_tokens()?,
+            PresaleError::InsufficientAllocation
+        );
+
+        // Return what was actually paid under the purchase's config version, not a re-pricing at current config.
+        // Web2 payments were never escrowed, so only Web3 (and unwrapped wSOL) payments are returned
+        let amount_refunded = if paid_into_escrow(record.payment_type) { record.amount_paid } else { 0 };
+
+        if amount_refunded > 0 {
+            let escrow_seeds: &[&[u8]] = &[ESCROW_SEED, presale_key.as_ref(), &[presale.escrow_bump]];
+            let signer_seeds: &[&[&[u8]]] = &[escrow_seeds];
+
+            if record.currency == CURRENCY_SOL {
+                system_program::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.system_program.to_account_info(),
+                        SystemTransfer {
+                            from: ctx.accounts.escrow.to_account_info(),
+                            to: ctx.accounts.buyer.to_account_info(),
+                        },
+                        signer_seeds,
+                    ),
+                    amount_refunded,
+                )?;
+                presale.escrowed_lamports = presale.escrowed_lamports.checked_sub(amount_refunded).ok_or(PresaleError::MathOverflow)?;
+                presale.unsettled_reversed_lamports =
+                    presale.unsettled_reversed_lamports.checked_add(amount_refunded).ok_or(PresaleError::MathOverflow)?;
+            } else {
+                let expected_mint = presale.stable_coin_mint(record.currency);
+                let mint_account = ctx
+                    .accounts
+                    .stable_coin_mint
+                    .as_ref()
+                    .ok_or(PresaleError::InvalidStableToken)?;
+                let escrow_vault = ctx
+                    .accounts
+                    .escrow_stable_coin_account
+                    .as_ref()
+                    .ok_or(PresaleError::InvalidTokenAccount)?;
+                let buyer_vault = ctx
+                    .accounts
+                    .buyer_stable_coin_account
+                    .as_ref()
+                    .ok_or(PresaleError::InvalidTokenAccount)?;
+                require!(
+                    expected_mint != Pubkey::default()
+                        && mint_account.key() == expected_mint
+                        && escrow_vault.mint == expected_mint
+                        && buyer_vault.mint == expected_mint,
+                    PresaleError::InvalidStableToken
+                );
+
+                transfer_stable_coin(
+                    &ctx.accounts.token_program,
+                    mint_account,
+                    escrow_vault.to_account_info(),
+                    buyer_vault.to_account_info(),
+                    ctx.accounts.escrow.to_account_info(),
+                    signer_seeds,
+                    amount_refunded,
+                )?;
+                let escrowed = presale.escrowed_stable_coin(record.currency);
+                *escrowed = escrowed.checked_sub(amount_refunded).ok_or(PresaleError::MathOverflow)?;
+                let reversed = presale.unsettled_reversed_stable_coin(record.currency);
+                *reversed = reversed.checked_add(amount_refunded).ok_or(PresaleError::MathOverflow)?;
+            }
+
+            let contributed = buyer_account.contribution(record.currency);
+            *contributed = contributed.checked_sub(amount_refunded).ok_or(PresaleError::MathOverflow)?;
+        }
+
+        // De-allocate the tokens from the round they were bought in
+        buyer_account.tokens_purchased = buyer_account.tokens_purchased.checked_sub(record.tokens).ok_or(PresaleError::MathOverflow)?;
+        let round_tokens = if record.stage == 1 {
+            &mut buyer_account.private_round_tokens
+        } else {
+            &mut buyer_account.public_round_tokens
+        };
+        *round_tokens = round_tokens.saturating_sub(record.tokens);
+        buyer_account.purchases[purchase_index as usize].reversed = true;
+
+        // Take back the commission the purchase earned its referrer
+        if record.referral_commission > 0 {
+            let referrer_account = ctx
+                .accounts
+                .referrer_account
+                .as_mut()
+                .ok_or(PresaleError::ReferrerAccountRequired)?;
+            require!(referrer_account.buyer == record.referrer, PresaleError::InvalidReferrer);
+            reverse_referral_commission(presale, referrer_account, buyer_account.buyer, record.referral_commission)?;
+        }
+        buyer_account.config_version = presale.config_version;
+
+        presale.total_sold = presale.total_sold.checked_sub(record.tokens).ok_or(PresaleError::MathOverflow)?;
+        if record.stage == presale.sale_stage {
+            presale.purchases_in_stage = presale.purchases_in_stage.saturating_sub(1);
+        }
+        sync_buyer_registry(presale, buyer_account, &ctx.accounts.registry_page, false)?;
+
+        emit!(PurchaseReversedEvent {
+            buyer: buyer_account.buyer,
+            operator: signer,
+            purchase_index,
+            currency: record.currency,
+            amount_refunded,
+            tokens: record.tokens,
+        });
+
+        msg!(
+            "Reversed purchase {} of buyer {}: {} tokens (raw) de-allocated, {} returned",
+            purchase_index,
+            buyer_account.buyer,
+            record.tokens,
+            amount_refunded
+        );
+
+        Ok(())
+    }
+
+    /// Creates the purchase journal, a ring buffer of the latest purchases read by `get_recent_purchases`.
+    /// Once it exists, every purchase must be recorded in it. With `anonymize`, the view truncates buyer pubkeys.
+    /// Only the admin can perform this action.
+    pub fn create_purchase_journal(ctx: Context<CreatePurchaseJournal>, anonymize: bool) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        let mut journal = ctx.accounts.purchase_journal.load_init()?;
+        journal.presale = presale.key();
+
+        presale.purchase_journal_enabled = true;
+        presale.journal_anonymized = anonymize;
+
+        msg!("Purchase journal created (anonymized: {})", anonymize);
+
+        Ok(())
+    }
+
+    /// Returns the last `limit` purchases (newest first, at most `RECENT_PURCHASES_LIMIT`) from the
+    /// purchase journal, for the "recent buys" ticker. Buyer pubkeys are truncated to their first
+    /// 4 bytes when the journal is anonymized.
+    pub fn get_recent_purchases(ctx: Context<GetRecentPurchases>, limit: u8) -> Result<Vec<RecentPurchase>> {
+        let presale = &ctx.accounts.presale;
+        let journal = ctx.accounts.purchase_journal.load()?;
+
+        let recent: Vec<RecentPurchase> = journal
+            .latest((limit as usize).min(RECENT_PURCHASES_LIMIT))
+            .map(|entry| {
+                let mut buyer = entry.buyer.to_bytes();
+                if presale.journal_anonymized {
+                    buyer[4..].fill(0);
+                }
+                RecentPurchase {
+                    buyer: Pubkey::new_from_array(buyer),
+                    timestamp: entry.timestamp,
+                    currency: entry.currency,
+                    amount_paid: entry.amount_paid,
+                    tokens: entry.tokens,
+                }
+            })
+            .collect();
+
+        msg!("Returning {} recent purchases", recent.len());
+
+        Ok(recent)
+    }
+
+    /// Creates the vesting statistics account, which tracks total allocated, vested and claimed tokens
+    /// across all buyers for circulating-supply dashboards. Once it exists, every claim must update it.
+    /// Only the admin can perform this action, before `finalize_presale`.
+    pub fn create_vesting_stats(ctx: Context<CreateVestingStats>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(!presale.pool_created, PresaleError::VestingScheduleLocked);
+
+        let vesting_stats = &mut ctx.accounts.vesting_stats;
+        vesting_stats.presale = presale.key();
+        vesting_stats.total_allocated = presale.total_allocated();
+        vesting_stats.last_updated = Clock::get()?.unix_timestamp;
+        vesting_stats.bump = ctx.bumps.vesting_stats;
+
+        presale.vesting_stats_enabled = true;
+
+        msg!("Vesting statistics created");
+
+        Ok(())
+    }
+
+    /// Schedules the token generation event: claims are rejected before `tge_timestamp`
+    /// (0 = claims open at finalization). Cannot be changed once the TGE has passed.
+    /// Only the admin can perform this action.
+    pub fn set_tge_timestamp(ctx: Context<SetTgeTimestamp>, tge_timestamp: i64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let now = Clock::get()?.unix_timestamp;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(tge_timestamp >= 0, PresaleError::InvalidTgeTimestamp);
+        require!(
+            presale.tge_timestamp == 0 || now < presale.tge_timestamp,
+            PresaleError::TgeAlreadyPassed
+        );
+
+        presale.tge_timestamp = tge_timestamp;
+
+        emit!(TgeTimestampUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            tge_timestamp,
+        });
+
+        msg!("TGE timestamp set to {}", tge_timestamp);
+
+        Ok(())
+    }
+
+    /// Schedules the vesting start independently of sale stage transitions, so the sale can end
+    /// weeks before listing without starting the unlock clock (0 = vesting starts at finalization).
+    /// The start cannot be backdated, nor changed once the clock is running.
+    /// Only the admin can perform this action.
+    pub fn set_vesting_start(ctx: Context<SetVestingStart>, vesting_start: i64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let now = Clock::get()?.unix_timestamp;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(
+            vesting_start == 0 || vesting_start >= now,
+            PresaleError::InvalidVestingStart
+        );
+        require!(
+            !presale.pool_created || now < presale.vesting_start,
+            PresaleError::VestingAlreadyStarted
+        );
+        require!(
+            !presale.pool_created || vesting_start != 0,
+            PresaleError::InvalidVestingStart
+        );
+
+        presale.vesting_start = vesting_start;
+        presale.bump_config_version()?;
+
+        emit!(VestingStartUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            vesting_start,
+        });
+
+        msg!("Vesting start set to {}", vesting_start);
+
+        Ok(())
+    }
+
+    /// Permissionless batch claim: pushes every buyer's currently unlocked tokens to their claim recipient.
+    /// `remaining_accounts` holds (writable `BuyerAccount`, recipient's NLOV associated token account) pairs,
+    /// plus the buyer's `Blacklist` PDA in each entry once the blacklist is on; buyers with nothing unlocked,
+    /// with their own vesting override, or blacklisted, are skipped, so a keeper can sweep many users in one
+    /// transaction.
+    pub fn claim_for<'info>(ctx: Context<'_, '_, 'info, 'info, ClaimFor<'info>>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(presale.pool_created, PresaleError::PresaleNotFinalized);
+        presale.ensure_tge_reached()?;
+        presale.ensure_claims_not_paused()?;
+        // Once the blacklist is on, each entry also carries the buyer's `Blacklist` PDA
+        let entry_len = if presale.blacklist_enabled { 3 } else { 2 };
+        require!(
+            !ctx.remaining_accounts.is_empty() && ctx.remaining_accounts.len().is_multiple_of(entry_len),
+            PresaleError::InvalidClaimBatch
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let presale_key = presale.key();
+        let seeds: &[&[u8]] = &[PRESALE_SEED, presale.admin.as_ref(), &[presale.bump]];
+        let signer_seeds: &[&[&[u8]]] = &[seeds];
+        let mut buyers_claimed: u32 = 0;
+        let mut batch_claimed_raw: u64 = 0;
+        let mut batch_vested_delta_raw: u64 = 0;
+
+        for pair in ctx.remaining_accounts.chunks(entry_len) {
+            let mut buyer_account = Account::<BuyerAccount>::try_from(&pair[0])?;
+            require!(buyer_account.presale == presale_key, PresaleError::InvalidClaimBatch);
+            require!(
+                pair[1].key() == get_associated_token_address(&buyer_account.claim_recipient, &ctx.accounts.token_mint.key()),
+                PresaleError::InvalidClaimRecipient
+            );
+
+            if let Some(blacklist) = pair.get(2) {
+                let blacklist_address = Pubkey::find_program_address(
+                    &[BLACKLIST_SEED, presale_key.as_ref(), buyer_account.buyer.as_ref()],
+                    &crate::ID,
+                )
+                .0;
+                require!(blacklist.key() == blacklist_address, PresaleError::InvalidClaimBatch);
+                if is_blacklisted(blacklist) {
+                    continue; // Blocked wallets can't be claimed for either
+                }
+            }
+
+            if buyer_account.vesting_override {
+                continue; // Vests under their own schedule, claimed individually
+            }
+
+            let vested_raw = presale.buyer_vested_amount(&buyer_account, ctx.accounts.vesting_schedule.as_deref(), now)?;
+            let claimable_raw = vested_raw.saturating_sub(buyer_account.settled_tokens()?);
+            if claimable_raw == 0 {
+                continue;
+            }
+
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TokenTransfer {
+                        from: ctx.accounts.presale_wallet.to_account_info(),
+                        to: pair[1].clone(),
+                        authority: presale.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                claimable_raw,
+            )?;
+
+            buyer_account.tokens_claimed = buyer_account.tokens_claimed.checked_add(claimable_raw).ok_or(PresaleError::MathOverflow)?;
+            buyer_account.config_version = presale.config_version; // Settled under the final vesting config
+            batch_claimed_raw = batch_claimed_raw.checked_add(claimable_raw).ok_or(PresaleError::MathOverflow)?;
+            batch_vested_delta_raw = batch_vested_delta_raw
+                .checked_add(buyer_account.record_vested(vested_raw)?)
+                .ok_or(PresaleError::MathOverflow)?;
+            buyer_account.exit(&crate::ID)?;
+            buyers_claimed += 1;
+
+            // Everything unlocked is pushed, so what remains is the unclaimed rest of the allocation
+            let total_allocation = buyer_account.total_allocation()?;
+            emit!(TokensClaimedEvent {
+                buyer: buyer_account.buyer,
+                tokens_claimed: claimable_raw,
+                tokens_remaining: total_allocation.saturating_sub(buyer_account.tokens_claimed),
+                total_allocation,
+                total_claimed: buyer_account.tokens_claimed,
+                tokens_locked: total_allocation.saturating_sub(vested_raw.max(buyer_account.settled_tokens()?)),
+            });
+        }
+
+        presale.total_claimed = presale.total_claimed.checked_add(batch_claimed_raw).ok_or(PresaleError::MathOverflow)?;
+        record_vesting_stats(presale, ctx.accounts.vesting_stats.as_mut(), batch_vested_delta_raw)?;
+
+        msg!(
+            "Batch claim pushed {} tokens (raw) to {} buyers",
+            batch_claimed_raw,
+            buyers_claimed
+        );
+
+        Ok(())
+    }
+
+    /// QA faucet: credits `tokens_raw` to a buyer's allocation without payment, so test environments can
+    /// exercise claims, vesting and refunds at scale. Recorded as an unescrowed (Web2) purchase of 0.
+    /// Only compiled with the `devnet-only` feature. Only the admin can perform this action.
+    #[cfg(feature = "devnet-only")]
+    pub fn faucet_buy(ctx: Context<FaucetBuy>, tokens_raw: u64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(
+            presale.sale_stage == 1 || presale.sale_stage == 2,
+            PresaleError::PresaleNotActive
+        );
+        require!(tokens_raw > 0, PresaleError::InvalidPrice);
+
+        enforce_hardcap(presale, None, tokens_raw)?;
+        require!(
+            ctx.accounts
+                .presale_wallet
+                .amount
+                .saturating_sub(presale.outstanding_allocations())
+                >= tokens_raw,
+            PresaleError::InsufficientTokens
+        );
+
+        presale.total_sold = presale.total_sold.checked_add(tokens_raw).ok_or(PresaleError::MathOverflow)?;
+        presale.purchases_in_stage = presale.purchases_in_stage.checked_add(1).ok_or(PresaleError::MathOverflow)?;
+
+        let first_purchase = ctx.accounts.buyer_account.record_purchase(
+            presale.key(),
+            ctx.accounts.buyer.key(),
+            ctx.bumps.buyer_account,
+            PurchaseRecord {
+                timestamp: Clock::get()?.unix_timestamp,
+                currency: CURRENCY_SOL,
+                amount_paid: 0,
+                tokens: tokens_raw,
+                stage: presale.sale_stage,
+                payment_type: PAYMENT_TYPE_WEB2,
+                reversed: false,
+                config_version: presale.config_version,
+                referrer: Pubkey::default(),
+                referral_commission: 0,
+            },
+        )?;
+        sync_buyer_registry(
+            presale,
+            &mut ctx.accounts.buyer_account,
+            &ctx.accounts.registry_page,
+            first_purchase,
+        )?;
+
+        msg!(
+            "Faucet credited {} tokens (raw) to {}",
+            tokens_raw,
+            ctx.accounts.buyer.key()
+        );
+
+        Ok(())
+    }
+
+    /// Records a purchase paid off-chain (Web2, e.g. through an exchange) of `tokens` whole NLOV for a
+    /// buyer. Nothing is escrowed: `amount_paid` is declared by the backend for the record. The purchase
+    /// stays provisional, holding its tokens in `provisional_tokens`, until `confirm_web2_purchase` once
+    /// the chargeback window has passed. Only the `web2_recorder` can perform this action; buyers can't
+    /// self-report Web2 payments.
+    pub fn record_web2_purchase(
+        ctx: Context<RecordWeb2Purchase>,
+        currency: u8, // CURRENCY_SOL, CURRENCY_USDC, CURRENCY_USDT or CURRENCY_PYUSD
+        amount_paid: u64, // Lamports or stablecoin raw units paid off-chain
+        tokens: u64, // Whole NLOV bought
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let buyer = ctx.accounts.buyer.key();
+
+        presale.ensure_not_locked_down()?;
+        presale.ensure_web2_recorder(&ctx.accounts.web2_recorder.key())?;
+
+        require!(
+            presale.sale_stage == 1 || presale.sale_stage == 2,
+            PresaleError::PresaleNotActive
+        );
+        require!(currency <= CURRENCY_PYUSD, PresaleError::InvalidCurrency);
+        require!(tokens >= 1 && amount_paid > 0, PresaleError::InvalidPrice);
+
+        let tokens_raw = tokens
+            .checked_mul(10u64.pow(ctx.accounts.token_mint.decimals as u32))
+            .ok_or(PresaleError::MathOverflow)?;
+
+        check_not_blacklisted(presale, ctx.accounts.blacklist.as_ref())?;
+        enforce_hardcap(presale, None, tokens_raw)?;
+        require!(
+            ctx.accounts
+                .presale_wallet
+                .amount
+                .saturating_sub(presale.outstanding_allocations())
+                >= tokens_raw,
+            PresaleError::InsufficientTokens
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let provisional_purchase = &mut ctx.accounts.provisional_purchase;
+        provisional_purchase.presale = presale.key();
+        provisional_purchase.buyer = buyer;
+        provisional_purchase.sequence = presale.next_web2_purchase_sequence;
+        provisional_purchase.currency = currency;
+        provisional_purchase.amount_paid = amount_paid;
+        provisional_purchase.tokens = tokens_raw;
+        provisional_purchase.stage = presale.sale_stage;
+        provisional_purchase.recorded_at = now;
+        provisional_purchase.confirmable_at = now.checked_add(presale.web2_chargeback_window).ok_or(PresaleError::MathOverflow)?;
+        provisional_purchase.bump = ctx.bumps.provisional_purchase;
+
+        presale.next_web2_purchase_sequence = presale.next_web2_purchase_sequence.checked_add(1).ok_or(PresaleError::MathOverflow)?;
+        presale.provisional_tokens = presale.provisional_tokens.checked_add(tokens_raw).ok_or(PresaleError::MathOverflow)?;
+
+        emit!(Web2PurchaseRecordedEvent {
+            buyer,
+            sequence: provisional_purchase.sequence,
+            currency,
+            amount_paid,
+            tokens_purchased: tokens,
+            confirmable_at: provisional_purchase.confirmable_at,
+        });
+
+        msg!(
+            "Recorded provisional Web2 purchase {}: {} tokens for buyer {}, paid {} (raw) in currency {}",
+            provisional_purchase.sequence,
+            tokens,
+            buyer,
+            amount_paid,
+            currency
+        );
+
+        Ok(())
+    }
+
+    /// Confirms a provisional Web2 purchase once its chargeback window has passed, rolling its tokens
+    /// into `total_sold` and the buyer's allocation. Private-round purchases stay within the buyer's
+    /// whitelist tier cap, proven by their `WhitelistEntry` or `whitelist_proof`. The provisional record is
+    /// closed to the recorder. Only the `web2_recorder` can perform this action, and not once the sale is
+    /// finalized.
+    pub fn confirm_web2_purchase(
+        ctx: Context<ConfirmWeb2Purchase>,
+        whitelist_proof: Option<WhitelistProof>, // Buyer's tier and merkle proof, for private-round purchases without a `whitelist_entry`
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let provisional_purchase = &ctx.accounts.provisional_purchase;
+        let tokens_raw = provisional_purchase.tokens;
+
+        presale.ensure_not_locked_down()?;
+        presale.ensure_web2_recorder(&ctx.accounts.web2_recorder.key())?;
+
+        require!(!presale.cancelled, PresaleError::SaleCancelled);
+        require!(
+            !presale.pool_created,
+            PresaleError::LiquidityPoolAlreadyCreated
+        );
+        // Not once the stage the purchase was recorded in has been rolled back
+        require!(
+            presale.sale_stage >= provisional_purchase.stage,
+            PresaleError::PresaleNotActive
+        );
+        require!(
+            Clock::get()?.unix_timestamp >= provisional_purchase.confirmable_at,
+            PresaleError::ChargebackWindowOpen
+        );
+        check_not_blacklisted(presale, ctx.accounts.blacklist.as_ref())?;
+        require!(
+            presale.wallet_headroom(ctx.accounts.buyer_account.tokens_purchased) >= tokens_raw,
+            PresaleError::WalletCapExceeded
+        );
+
+        presale.provisional_tokens = presale.provisional_tokens.saturating_sub(tokens_raw);
+        presale.total_sold = presale.total_sold.checked_add(tokens_raw).ok_or(PresaleError::MathOverflow)?;
+        presale.purchases_in_stage = presale.purchases_in_stage.checked_add(1).ok_or(PresaleError::MathOverflow)?;
+
+        let first_purchase = ctx.accounts.buyer_account.record_purchase(
+            presale.key(),
+            provisional_purchase.buyer,
+            ctx.bumps.buyer_account,
+            PurchaseRecord {
+                timestamp: provisional_purchase.recorded_at,
+                currency: provisional_purchase.currency,
+                amount_paid: provisional_purchase.amount_paid,
+                tokens: tokens_raw,
+                stage: provisional_purchase.stage,
+                payment_type: PAYMENT_TYPE_WEB2,
+                reversed: false,
+                config_version: presale.config_version,
+                referrer: Pubkey::default(),
+                referral_commission: 0,
+            },
+        )?;
+        if provisional_purchase.stage == 1 && presale.whitelist_enabled() {
+            let tier = private_round_tier(
+                presale,
+                &provisional_purchase.buyer,
+                ctx.accounts.whitelist_entry.as_ref(),
+                whitelist_proof.as_ref(),
+            )?;
+            check_tier_cap(presale, tier, ctx.accounts.buyer_account.private_round_tokens)?;
+        }
+        sync_buyer_registry(
+            presale,
+            &mut ctx.accounts.buyer_account,
+            &ctx.accounts.registry_page,
+            first_purchase,
+        )?;
+
+        emit!(Web2PurchaseConfirmedEvent {
+            buyer: provisional_purchase.buyer,
+            sequence: provisional_purchase.sequence,
+            tokens: tokens_raw,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Confirmed Web2 purchase {} of {} tokens (raw) for {}",
+            provisional_purchase.sequence,
+            tokens_raw,
+            provisional_purchase.buyer
+        );
+
+        Ok(())
+    }
+
+    /// Drops a provisional Web2 purchase that was charged back (or otherwise failed) before confirmation,
+    /// releasing its tokens. The provisional record is closed to the recorder.
+    /// Only the `web2_recorder` can perform this action.
+    pub fn cancel_web2_purchase(ctx: Context<CancelWeb2Purchase>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let provisional_purchase = &ctx.accounts.provisional_purchase;
+
+        presale.ensure_web2_recorder(&ctx.accounts.web2_recorder.key())?;
+
+        presale.provisional_tokens = presale.provisional_tokens.saturating_sub(provisional_purchase.tokens);
+
+        emit!(Web2PurchaseCancelledEvent {
+            buyer: provisional_purchase.buyer,
+            sequence: provisional_purchase.sequence,
+            tokens: provisional_purchase.tokens,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Cancelled Web2 purchase {} of {} tokens (raw) for {}",
+            provisional_purchase.sequence,
+            provisional_purchase.tokens,
+            provisional_purchase.buyer
+        );
+
+        Ok(())
+    }
+
+    /// Sets how long recorded Web2 purchases stay provisional before they can be confirmed, so payments
+    /// charged back at the exchange can be cancelled first. Only the admin can perform this action.
+    pub fn set_web2_chargeback_window(ctx: Context<SetWeb2ChargebackWindow>, window_seconds: i64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(
+            (0..=MAX_WEB2_CHARGEBACK_WINDOW).contains(&window_seconds),
+            PresaleError::InvalidChargebackWindow
+        );
+
+        presale.web2_chargeback_window = window_seconds;
+
+        emit!(Web2ChargebackWindowUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            window_seconds,
+        });
+
+        msg!("Web2 chargeback window set to {} seconds", window_seconds);
+
+        Ok(())
+    }
+
+    /// Caps how many tokens (raw units) a single buyer can accumulate through purchases,
+    /// so whales can't absorb the entire round. 0 removes the cap. Only the admin can perform this action.
+    pub fn set_max_allocation_per_buyer(ctx: Context<SetMaxAllocationPerBuyer>, max_tokens: u64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        presale.max_allocation_per_buyer = max_tokens;
+
+        emit!(MaxAllocationPerBuyerUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            max_tokens,
+        });
+
+        msg!("Max allocation per buyer set to {} tokens (raw)", max_tokens);
+
+        Ok(())
+    }
+
+    /// Sets the minimum time between two purchases from the same wallet (0 = no cooldown), to blunt
+    /// sniping bots at round open. Only the admin can perform this action.
+    pub fn set_purchase_cooldown(ctx: Context<SetPurchaseCooldown>, cooldown_seconds: i64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(cooldown_seconds >= 0, PresaleError::InvalidPurchaseCooldown);
+
+        presale.purchase_cooldown = cooldown_seconds;
+
+        emit!(PurchaseCooldownUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            cooldown_seconds,
+        });
+
+        msg!("Purchase cooldown set to {} seconds", cooldown_seconds);
+
+        Ok(())
+    }
+
+    /// Sets the minimum delay between `commit_purchase` and `reveal_purchase`. While non-zero,
+    /// public-round purchases must consume a revealed commitment, so bots can't snipe the round open.
+    /// Only the admin can perform this action.
+    pub fn set_commit_reveal_delay(ctx: Context<SetCommitRevealDelay>, delay_seconds: i64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(delay_seconds >= 0, PresaleError::InvalidCommitRevealDelay);
+
+        presale.commit_reveal_delay = delay_seconds;
+
+        emit!(CommitRevealDelayUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            delay_seconds,
+        });
+
+        msg!("Commit-reveal delay set to {} seconds", delay_seconds);
+
+        Ok(())
+    }
+
+    /// Limits how many purchases a single wallet can make in each round (0 = unlimited), separately
+    /// from amount caps, so bots can't split one buy into many. Only the admin can perform this action.
+    pub fn set_purchase_limits(
+        ctx: Context<SetPurchaseLimits>,
+        private_round_limit: u16,
+        public_round_limit: u16,
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        presale.private_round_purchase_limit = private_round_limit;
+        presale.public_round_purchase_limit = public_round_limit;
+
+        emit!(PurchaseLimitsUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            private_round_limit,
+            public_round_limit,
+        });
+
+        msg!(
+            "Purchase limits set to {} (private round) and {} (public round) per wallet",
+            private_round_limit,
+            public_round_limit
+        );
+
+        Ok(())
+    }
+
+    /// Sets the referral commission, paid in NLOV to the referrer's buyer account as a share of each
+    /// referred purchase. 0 disables referral rewards. Only the admin can perform this action.
+    pub fn set_referral_commission(ctx: Context<SetReferralCommission>, commission_bps: u16) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(commission_bps <= MAX_REFERRAL_COMMISSION_BPS, PresaleError::InvalidReferralCommission);
+
+        presale.referral_commission_bps = commission_bps;
+
+        emit!(ReferralCommissionUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            commission_bps,
+        });
+
+        msg!("Referral commission set to {} bps", commission_bps);
+
+        Ok(())
+    }
+
+    /// Configures `claim_and_stake`: the staking program claimed tokens are deposited into
+    /// (default = disabled), its vault receiving them, and the bonus, in bps of the claimed amount,
+    /// for staking instead of claiming. Only the admin can perform this action.
+    pub fn configure_staking(
+        ctx: Context<ConfigureStaking>,
+        staking_program: Pubkey,
+        staking_vault: Pubkey,
+        stake_bonus_bps: u16,
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(stake_bonus_bps <= BPS_DENOMINATOR, PresaleError::InvalidStakeBonus);
+        require!(
+            staking_program == Pubkey::default() || staking_vault != Pubkey::default(),
+            PresaleError::InvalidStakingVault
+        );
+
+        presale.staking_program = staking_program;
+        presale.staking_vault = staking_vault;
+        presale.stake_bonus_bps = stake_bonus_bps;
+
+        emit!(StakingConfiguredEvent {
+            admin: ctx.accounts.admin.key(),
+            staking_program,
+            staking_vault,
+            stake_bonus_bps,
+        });
+
+        msg!(
+            "Staking program set to {} (vault {}) with a {} bps bonus",
+            staking_program,
+            staking_vault,
+            stake_bonus_bps
+        );
+
+        Ok(())
+    }
+
+    /// Opens the private sale (like `set_stage` from stage 0) and anchors the terms in force:
+    /// `terms_hash` (hash of the off-chain sale terms document) and a snapshot of the on-chain
+    /// config are written once to the `TermsAnchor` PDA, so later disputes can be settled against them.
+    /// Only the admin can perform this action.
+    pub fn activate_sale(ctx: Context<ActivateSale>, terms_hash: [u8; 32]) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(presale.sale_stage == 0, PresaleError::SaleAlreadyActivated);
+        require!(terms_hash != [0u8; 32], PresaleError::InvalidTermsHash);
+
+        let clock = Clock::get()?;
+
+        presale.ensure_within_max_end(
+            presale.private_sale_duration,
+            presale.public_sale_duration,
+            clock.unix_timestamp,
+        )?;
+
+        presale.presale_start = clock.unix_timestamp;
+        presale.sale_stage = 1;
+        presale.previous_stage = 0;
+        presale.stage_changed_at = clock.unix_timestamp;
+        presale.purchases_in_stage = 0;
+        presale.apply_stage_price(clock.unix_timestamp);
+
+        let terms_anchor = &mut ctx.accounts.terms_anchor;
+        terms_anchor.presale = presale.key();
+        terms_anchor.terms_hash = terms_hash;
+        terms_anchor.activated_at = clock.unix_timestamp;
+        terms_anchor.config = SaleTermsSnapshot::capture(presale);
+        terms_anchor.bump = ctx.bumps.terms_anchor;
+
+        emit!(SaleActivatedEvent {
+            admin: ctx.accounts.admin.key(),
+            terms_hash,
+            config_version: presale.config_version,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Private sale started at {} with anchored terms", presale.presale_start);
+
+        Ok(())
+    }
+
+    /// Sets the claim deadline, after which the admin may close fully settled buyer accounts
+    /// on buyers' behalf (0 = no deadline). Only the admin can perform this action.
+    pub fn set_claim_deadline(ctx: Context<SetClaimDeadline>, claim_deadline: i64) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(claim_deadline >= 0, PresaleError::InvalidClaimDeadline);
+
+        presale.claim_deadline = claim_deadline;
+
+        emit!(ClaimDeadlineUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            claim_deadline,
+        });
+
+        msg!("Claim deadline set to {}", claim_deadline);
+
+        Ok(())
+    }
+
+    /// Pauses or resumes token releases. While paused, every claim path fails with `ClaimsPaused`;
+    /// purchases, refunds and accounting are unaffected. Only the admin can perform this action.
+    pub fn pause_claims(ctx: Context<PauseClaims>, paused: bool) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+
+        presale.claims_paused = paused;
+
+        emit!(ClaimsPausedEvent {
+            admin: ctx.accounts.admin.key(),
+            paused,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Claims {}", if paused { "paused" } else { "resumed" });
+
+        Ok(())
+    }
+
+    /// Closes a `BuyerAccount` whose allocation is fully claimed once the sale is over,
+    /// refunding its rent to the buyer. The buyer can close their own account at any time;
+    /// the admin can close it on their behalf after the claim deadline.
+    pub fn close_buyer_account(ctx: Context<CloseBuyerAccount>) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+        let buyer_account = &ctx.accounts.buyer_account;
+        let authority = ctx.accounts.authority.key();
+
+        presale.ensure_not_locked_down()?;
+
+        if authority != buyer_account.buyer {
+            require!(authority == presale.admin, PresaleError::Unauthorized);
+            require!(
+                presale.claim_deadline > 0
+                    && Clock::get()?.unix_timestamp >= presale.claim_deadline,
+                PresaleError::ClaimDeadlineNotReached
+            );
+        }
+        require!(
+            presale.sale_stage == 3 || presale.cancelled,
+            PresaleError::PresaleActive
+        );
+        require!(
+            buyer_account.unclaimed_tokens()? == 0
+                && (presale.pool_created || !buyer_account.has_refundable_contribution()),
+            PresaleError::AllocationNotSettled
+        );
+
+        // A re-created account registers again, so the closed one must leave the registry
+        remove_from_buyer_registry(presale, buyer_account, &ctx.accounts.registry_page)?;
+
+        msg!("Buyer account for {} closed", buyer_account.buyer);
+
+        Ok(())
+    }
+
+    /// Hands claim execution for the buyer's allocation to a separate authority (e.g. a custodian's
+    /// signing infrastructure) while tokens keep going to the claim recipient. Can be set once,
+    /// by the buyer with the operator (or admin) co-signing.
+    pub fn set_claim_authority(ctx: Context<SetClaimAuthority>, claim_authority: Pubkey) -> Result<()> {
+        let presale = &ctx.accounts.presale;
+        let buyer_account = &mut ctx.accounts.buyer_account;
+        let operator = ctx.accounts.operator.key();
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            operator == presale.operator || operator == presale.admin,
+            PresaleError::Unauthorized
+        );
+        require!(
+            claim_authority != Pubkey::default(),
+            PresaleError::InvalidClaimAuthority
+        );
+        require!(
+            buyer_account.claim_authority == Pubkey::default(),
+            PresaleError::ClaimAuthorityAlreadySet
+        );
+
+        buyer_account.claim_authority = claim_authority;
+
+        emit!(ClaimAuthoritySetEvent {
+            buyer: buyer_account.buyer,
+            claim_authority,
+            operator,
+        });
+
+        msg!(
+            "Buyer {} delegated claims to {}",
+            buyer_account.buyer,
+            claim_authority
+        );
+
+        Ok(())
+    }
+
+    /// Returns a buyer's allocation breakdown, unlocked and claimed amounts, and what could be
+    /// refunded right now, so frontends don't need to re-implement the vesting and refund math.
+    pub fn get_buyer_allocation(ctx: Context<GetBuyerAllocation>) -> Result<BuyerAllocation> {
+        let presale = &ctx.accounts.presale;
+        let buyer_account = &ctx.accounts.buyer_account;
+        let now = Clock::get()?.unix_timestamp;
+
+        let milestones = ctx.accounts.vesting_schedule.as_deref();
+        let unlocked = presale.buyer_unlocked_amount(buyer_account, milestones, now)?;
+        let refunds_open = presale.refunds_enabled();
+
+        let allocation = BuyerAllocation {
+            buyer: buyer_account.buyer,
+            purchased: buyer_account.tokens_purchased,
+            bonus: buyer_account.bonus_tokens,
+            referral: buyer_account.referral_tokens,
+            tge_unlock: presale.buyer_tge_amount(buyer_account, milestones)?,
+            unlocked,
+            claimable: unlocked.saturating_sub(buyer_account.settled_tokens()?),
+            claimed: buyer_account.tokens_claimed,
+            forfeited: buyer_account.tokens_forfeited,
+            refundable_lamports: if refunds_open { buyer_account.contributed_lamports } else { 0 },
+            refundable_usdc: if refunds_open { buyer_account.contributed_usdc } else { 0 },
+            refundable_usdt: if refunds_open { buyer_account.contributed_usdt } else { 0 },
+            refundable_pyusd: if refunds_open { buyer_account.contributed_pyusd } else { 0 },
+        };
+
+        msg!(
+            "Buyer {}: {} purchased, {} unlocked, {} claimed (raw)",
+            allocation.buyer,
+            allocation.purchased,
+            allocation.unlocked,
+            allocation.claimed
+        );
+
+        Ok(allocation)
+    }
+
+    /// Returns how many tokens `buyer` could claim right now (raw units): unlocked and not yet claimed
+    /// or forfeited, or 0 while claims are paused or if the buyer has no position.
+    pub fn get_claimable_amount(ctx: Context<GetClaimableAmount>, buyer: Pubkey) -> Result<u64> {
+        let presale = &ctx.accounts.presale;
+
+        let claimable = match &ctx.accounts.buyer_account {
+            Some(buyer_account) if !presale.claims_paused => presale
+                .buyer_unlocked_amount(
+                    buyer_account,
+                    ctx.accounts.vesting_schedule.as_deref(),
+                    Clock::get()?.unix_timestamp,
+                )?
+                .saturating_sub(buyer_account.settled_tokens()?),
+            _ => 0,
+        };
+
+        msg!("Buyer {} can claim {} tokens (raw)", buyer, claimable);
+
+        Ok(claimable)
+    }
+
+    /// Sums a wallet's position across several sales of the same token, e.g. successive rounds run
+    /// by different admins. `remaining_accounts` holds (`Presale`, presale wallet, `BuyerAccount` PDA)
+    /// triples, one per sale, followed by the buyer's own `VestingSchedule` if they have an override, or
+    /// else the sale's for sales with milestone vesting.
+    /// Sales the wallet never bought in can be included; their (empty) buyer accounts are skipped.
+    pub fn get_aggregate_position<'info>(
+        ctx: Context<'_, '_, 'info, 'info, GetAggregatePosition<'info>>,
+        buyer: Pubkey,
+    ) -> Result<AggregatePosition> {
+        require!(!ctx.remaining_accounts.is_empty(), PresaleError::InvalidAggregateBatch);
+
+        let token_mint = ctx.accounts.token_mint.key();
+        let now = Clock::get()?.unix_timestamp;
+        let mut seen_presales: Vec<Pubkey> = Vec::new();
+        let mut position = AggregatePosition {
+            buyer,
+            sales: 0,
+            purchased: 0,
+            bonus: 0,
+            referral: 0,
+            unlocked: 0,
+            claimable: 0,
+            claimed: 0,
+            forfeited: 0,
+        };
+
+        let mut accounts = ctx.remaining_accounts.iter();
+        while let Some(presale_info) = accounts.next() {
+            let presale_wallet_info = accounts.next().ok_or(PresaleError::InvalidAggregateBatch)?;
+            let buyer_account_info = accounts.next().ok_or(PresaleError::InvalidAggregateBatch)?;
+            let presale = Account::<Presale>::try_from(presale_info)?;
+            let presale_wallet = Account::<TokenAccount>::try_from(presale_wallet_info)?;
+            require!(
+                presale_wallet.key() == presale.presale_wallet
+                    && presale_wallet.mint == token_mint
+                    && !seen_presales.contains(&presale.key()),
+                PresaleError::InvalidAggregateBatch
+            );
+            seen_presales.push(presale.key());
+
+            let (buyer_account_pda, _) = Pubkey::find_program_address(
+                &[BUYER_SEED, presale.key().as_ref(), buyer.as_ref()],
+                ctx.program_id,
+            );
+            require!(buyer_account_info.key() == buyer_account_pda, PresaleError::InvalidAggregateBatch);
+
+            let buyer_account = if buyer_account_info.data_is_empty() {
+                None // Never bought in this sale
+            } else {
+                Some(Account::<BuyerAccount>::try_from(buyer_account_info)?)
+            };
+            let vesting_override = buyer_account.as_ref().is_some_and(|account| account.vesting_override);
+
+            let milestones = if vesting_override || presale.milestone_vesting {
+                let schedule_info = accounts.next().ok_or(PresaleError::VestingScheduleRequired)?;
+                let presale_key = presale.key();
+                let mut schedule_seeds: Vec<&[u8]> = vec![VESTING_SCHEDULE_SEED, presale_key.as_ref()];
+                if vesting_override {
+                    schedule_seeds.push(buyer.as_ref());
+                }
+                let (schedule_pda, _) = Pubkey::find_program_address(&schedule_seeds, ctx.program_id);
+                require!(schedule_info.key() == schedule_pda, PresaleError::VestingScheduleRequired);
+                Some(Account::<VestingSchedule>::try_from(schedule_info)?)
+            } else {
+                None
+            };
+
+            let Some(buyer_account) = buyer_account else {
+                continue;
+            };
+
+            let unlocked = presale.buyer_unlocked_amount(&buyer_account, milestones.as_deref(), now)?;
+            position.sales += 1;
+            position.purchased = position.purchased.checked_add(buyer_account.tokens_purchased).ok_or(PresaleError::MathOverflow)?;
+            position.bonus = position.bonus.checked_add(buyer_account.bonus_tokens).ok_or(PresaleError::MathOverflow)?;
+            position.referral = position.referral.checked_add(buyer_account.referral_tokens).ok_or(PresaleError::MathOverflow)?;
+            position.unlocked = position.unlocked.checked_add(unlocked).ok_or(PresaleError::MathOverflow)?;
+            position.claimable = position
+                .claimable
+                .checked_add(unlocked.saturating_sub(buyer_account.settled_tokens()?))
+                .ok_or(PresaleError::MathOverflow)?;
+            position.claimed = position.claimed.checked_add(buyer_account.tokens_claimed).ok_or(PresaleError::MathOverflow)?;
+            position.forfeited = position.forfeited.checked_add(buyer_account.tokens_forfeited).ok_or(PresaleError::MathOverflow)?;
+        }
+
+        msg!(
+            "Buyer {} across {} sales: {} purchased, {} unlocked, {} claimed (raw)",
+            buyer,
+            position.sales,
+            position.purchased,
+            position.unlocked,
+            position.claimed
+        );
+
+        Ok(position)
+    }
+
+    /// Backfills buyer accounts for purchases made while the sale only tracked `total_sold`, from an
+    /// off-chain snapshot. `remaining_accounts` holds the writable `BuyerAccount` PDA of each entry,
+    /// in order; missing accounts are created, paid for by the admin. Idempotent: buyers already
+    /// imported are skipped, so a failed or repeated batch can simply be resubmitted.
+    /// Only the admin can perform this action.
+    pub fn import_buyers<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ImportBuyers<'info>>,
+        entries: Vec<ImportedPurchase>,
+    ) -> Result<()> {
+        let presale = &mut ctx.accounts.presale;
+
+        presale.ensure_not_locked_down()?;
+
+        require!(
+            presale.admin == ctx.accounts.admin.key(),
+            PresaleError::Unauthorized
+        );
+        require!(
+            !entries.is_empty() && entries.len() == ctx.remaining_accounts.len(),
+            PresaleError::InvalidImportBatch
+        );
+
+        let presale_key = presale.key();
+        let rent_lamports = Rent::get()?.minimum_balance(BuyerAccount::LEN);
+        let mut buyers_imported: u32 = 0;
+        let mut batch_imported_raw: u64 = 0;
+
+        for (entry, account_info) in entries.iter().zip(ctx.remaining_accounts.iter()) {
+            require!(
+                entry.tokens > 0 && (entry.stage == 1 || entry.stage == 2),
+                PresaleError::InvalidImportBatch
+            );
+            // Only the canonical PDA is the buyer account every other instruction derives
+            let (expected_key, bump) =
+                Pubkey::find_program_address(&[BUYER_SEED, presale_key.as_ref(), entry.buyer.as_ref()], &crate::ID);
+            require_keys_eq!(account_info.key(), expected_key, PresaleError::InvalidImportBatch);
+            let buyer_seeds: &[&[u8]] = &[BUYER_SEED, presale_key.as_ref(), entry.buyer.as_ref(), &[bump]];
+
+            if account_info.data_is_empty() {
+                // Lamports sent to the address beforehand can't block creation: top it up to rent
+                // exemption, then allocate and assign it instead of creating it
+                let shortfall = rent_lamports.saturating_sub(account_info.lamports());
+                if shortfall > 0 {
+                    system_program::transfer(
+                        CpiContext::new(
+                            ctx.accounts.system_program.to_account_info(),
+                            SystemTransfer {
+                                from: ctx.accounts.admin.to_account_info(),
+                                to: account_info.clone(),
+                            },
+                        ),
+                        shortfall,
+                    )?;
+                }
+                system_program::allocate(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.system_program.to_account_info(),
+                        Allocate {
+                            account_to_allocate: account_info.clone(),
+                        },
+                        &[buyer_seeds],
+                    ),
+                    BuyerAccount::LEN as u64,
+                )?;
+                system_program::assign(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.system_program.to_account_info(),
+                        Assign {
+                            account_to_assign: account_info.clone(),
+                        },
+                        &[buyer_seeds],
+                    ),
+                    &crate::ID,
+                )?;
+                // A zeroed body is an empty buyer account; credit_allocation fills it in below
+                account_info.try_borrow_mut_data()?[..8].copy_from_slice(BuyerAccount::DISCRIMINATOR);
+            }
+
+            let mut buyer_account = Account::<BuyerAccount>::try_from(account_info)?;
+            if buyer_account.imported_tokens > 0 {
+                continue; // Already imported
+            }
+            require!(!buyer_account.revoked, PresaleError::AllocationRevoked);
+
+            let first_purchase = buyer_account.credit_allocation(presale_key, entry.buyer, bump, entry.tokens)?;
+            if entry.stage == 1 {
+                buyer_account.credit_round_tokens(entry.tokens, 0)?;
+            } else {
+                buyer_account.credit_round_tokens(0, entry.tokens)?;
+            }
+            buyer_account.imported_tokens = entry.tokens;
+            sync_buyer_registry(presale, &mut buyer_account, &ctx.accounts.registry_page, first_purchase)?;
+            buyer_account.exit(&crate::ID)?;
+
+            batch_imported_raw = batch_imported_raw.checked_add(entry.tokens).ok_or(PresaleError::MathOverflow)?;
+            buyers_imported += 1;
+        }
+
+        // Imports attribute tokens already counted in total_sold; they never add to it
+        presale.total_imported = presale.total_imported.checked_add(batch_imported_raw).ok_or(PresaleError::MathOverflow)?;
+        require!(
+            presale.total_imported <= presale.total_sold,
+            PresaleError::ImportExceedsTotalSold
+        );
+
+        emit!(BuyersImportedEvent {
+            buyers_imported,
+            tokens_imported: batch_imported_raw,
+            total_imported: presale.total_imported,
+        });
+
+        msg!(
+            "Imported {} tokens (raw) for {} buyers",
+            batch_imported_raw,
+            buyers_imported
+        );
+
+        Ok(())
+    }
+
+    /// Read-only overview of the sale terms and progress, so buyers can verify them before purchasing.
+    pub fn get_presale_summary(ctx: Context<GetPresaleSummary>) -> Result<PresaleSummary> {
+        let presale = &ctx.accounts.presale;
+        let (usd_price_micros_per_nlov, sol_price_lamports_per_nlov) =
+            presale.active_prices(Clock::get()?.unix_timestamp);
+
+        let summary = PresaleSummary {
+            sale_stage: presale.sale_stage,
+            usd_price_micros_per_nlov,
+            sol_price_lamports_per_nlov,
+            immutable_pricing: presale.immutable_pricing,
+            hardcap_tokens: presale.hardcap_tokens,
+            softcap_tokens: presale.softcap_tokens,
+            total_sold: presale.total_sold,
+            unique_buyers: presale.unique_buyers,
+            cancelled: presale.cancelled,
+            finalized: presale.pool_created,
+            config_version: presale.config_version,
+            max_end_timestamp: presale.max_end_timestamp,
+        };
+
+        msg!(
+            "Presale summary: stage {}, {} micro-USD/NLOV, {} lamports/NLOV (immutable: {}), {} of {} tokens (raw) sold",
+            summary.sale_stage,
+            summary.usd_price_micros_per_nlov,
+            summary.sol_price_lamports_per_nlov,
+            summary.immutable_pricing,
+            summary.total_sold,
+            summary.hardcap_tokens
+        );
+
+        Ok(summary)
+    }
+}
+}
+
+pub use handlers::*;
+
+/// The accounts every purchase is checked against, whatever currency it is paid in.
+struct PurchaseAccounts<'a, 'info> {
+    buyer: &'a Signer<'info>,
+    buyer_account: &'a BuyerAccount,
+    presale_wallet: &'a TokenAccount,
+    ticket: Option<&'a Account<'info, PurchaseTicket>>,
+    attestation: Option<&'a UncheckedAccount<'info>>,
+    identity_verifier_program: Option<&'a UncheckedAccount<'info>>,
+    instructions_sysvar: Option<&'a UncheckedAccount<'info>>,
+    referrer_account: Option<&'a Account<'info, BuyerAccount>>,
+    whitelist_entry: Option<&'a Account<'info, WhitelistEntry>>,
+    blacklist: Option<&'a UncheckedAccount<'info>>,
+    gatekeeper_signed: bool,
+    purchase_commitment: Option<&'a Account<'info, PurchaseCommitment>>,
+}
+
+/// Runs the checks shared by the buy instructions on a purchase of `tokens_raw` paid with
+/// `payment_amount` (raw units of the payment currency): blacklist, cooldown and purchase count,
+/// gatekeeper, commitment, whitelist and tier cap, identity/KYC, hardcap and volume step, the wallet
+/// cap, and the presale wallet's supply including any referral commission. Returns the commission and
+/// the referrer it is credited to (default pubkey = not referred).
+fn check_purchase<'info>(
+    presale: &mut Account<'info, Presale>,
+    accounts: &PurchaseAccounts<'_, 'info>,
+    payment_amount: u64,
+    whitelist_proof: Option<&WhitelistProof>,
+    tokens_raw: u64,
+    now: i64,
+) -> Result<(u64, Pubkey)> {
+    let buyer = accounts.buyer;
+
+    check_not_blacklisted(presale, accounts.blacklist)?;
+    presale.ensure_purchase_cooldown_elapsed(accounts.buyer_account, now)?;
+    presale.ensure_purchase_count_within_limit(accounts.buyer_account)?;
+    presale.ensure_gatekeeper_signed(accounts.gatekeeper_signed)?;
+    check_purchase_commitment(presale, accounts.purchase_commitment, payment_amount)?;
+
+    // Buyers must be on the active whitelist, if any, and private-round buyers within their tier's cap
+    check_whitelist(
+        presale,
+        &buyer.key(),
+        whitelist_proof,
+        accounts.whitelist_entry,
+        accounts
+            .buyer_account
+            .private_round_tokens
+            .checked_add(tokens_raw)
+            .ok_or(PresaleError::MathOverflow)?,
+    )?;
+
+    // Identity/KYC check against the configured verifier, if any
+    check_identity(
+        presale,
+        &buyer.to_account_info(),
+        accounts.attestation,
+        accounts.identity_verifier_program,
+    )?;
+    check_kyc_attestation(presale, &buyer.key(), accounts.instructions_sysvar)?;
+
+    // Enforce hardcap: Check if this purchase would exceed the hardcap, honoring reservations
+    enforce_hardcap(presale, accounts.ticket, tokens_raw)?;
+    presale.ensure_within_volume_step(tokens_raw)?;
+
+    // Enforce the per-buyer cap on the buyer's cumulative allocation
+    require!(
+        presale.wallet_headroom(accounts.buyer_account.tokens_purchased) >= tokens_raw,
+        PresaleError::WalletCapExceeded
+    );
+
+    // Ensure enough tokens exist in the presale wallet for this purchase
+    let tokens_currently_sold_raw = presale
+        .total_sold
+        .checked_add(presale.provisional_tokens)
+        .ok_or(PresaleError::MathOverflow)?
+        .checked_add(presale.total_bonus)
+        .ok_or(PresaleError::MathOverflow)?
+        .checked_add(presale.total_referral)
+        .ok_or(PresaleError::MathOverflow)?;
+
+    // A referral commission is paid on top of the purchase, out of the same supply
+    let referral_commission_raw = presale.referral_commission(tokens_raw, accounts.referrer_account.is_some());
+    let referrer = accounts
+        .referrer_account
+        .map_or(Pubkey::default(), |referrer_account| referrer_account.buyer);
+
+    require!(
+        accounts.presale_wallet.amount.saturating_sub(tokens_currently_sold_raw)
+            >= tokens_raw.checked_add(referral_commission_raw).ok_or(PresaleError::MathOverflow)?,
+        PresaleError::InsufficientTokens
+    );
+
+    Ok((referral_commission_raw, referrer))
+}
+
+/// Verifies that a private-stage buyer is on the whitelist, either through their `WhitelistEntry` or a
+/// merkle proof, and that `private_round_tokens` (raw units, including this purchase) stays within their
+/// tier's cap. While a whitelist phase of the current stage is active, a proof against the phase's root
+/// is required instead (entries still count in the private round). Leaves are `keccak(buyer || tier)`,
+/// and each proof step hashes the sorted pair of nodes, so proofs carry no left/right flags. Without a
+/// root, phase or any entries, anyone can buy.
+fn check_whitelist(
+    presale: &Presale,
+    buyer: &Pubkey,
+    proof: Option<&WhitelistProof>,
+    whitelist_entry: Option<&Account<WhitelistEntry>>,
+    private_round_tokens: u64,
+) -> Result<()> {
+    let phase_root = presale.phase_whitelist_root(Clock::get()?.unix_timestamp);
+    let private_round = presale.sale_stage == 1;
+    if phase_root.is_none() && !(private_round && presale.whitelist_enabled()) {
+        return Ok(());
+    }
+
+    // Seeds already tie an entry to this presale and buyer
+    let tier = match whitelist_entry {
+        Some(entry) if private_round => entry.tier,
+        _ => {
+            let proof = proof.ok_or(PresaleError::NotWhitelisted)?;
+            let root = phase_root.unwrap_or(presale.whitelist_root);
+            require!(root != [0u8; 32], PresaleError::NotWhitelisted);
+            verify_whitelist_proof(&root, buyer, proof)?;
+            proof.tier
+        }
+    };
+    if !private_round {
+        return Ok(()); // Tier caps only apply to the private round
+    }
+
+    check_tier_cap(presale, tier, private_round_tokens)
+}
+
+/// The private-round whitelist tier of `wallet`, from its `WhitelistEntry` or a merkle proof against the
+/// private-round root, for allocations credited outside the buy instructions.
+fn private_round_tier(
+    presale: &Presale,
+    wallet: &Pubkey,
+    whitelist_entry: Option<&Account<WhitelistEntry>>,
+    proof: Option<&WhitelistProof>,
+) -> Result<u8> {
+    if let Some(entry) = whitelist_entry {
+        return Ok(entry.tier);
+    }
+    let proof = proof.ok_or(PresaleError::NotWhitelisted)?;
+    require!(presale.whitelist_root != [0u8; 32], PresaleError::NotWhitelisted);
+    verify_whitelist_proof(&presale.whitelist_root, wallet, proof)?;
+    Ok(proof.tier)
+}
+
+/// Requires `private_round_tokens` (raw units) to stay within the cap of whitelist `tier` (0 = no cap).
+fn check_tier_cap(presale: &Presale, tier: u8, private_round_tokens: u64) -> Result<()> {
+    let tier_cap = presale.whitelist_tier_cap(tier)?;
+    require!(
+        tier_cap == 0 || private_round_tokens <= tier_cap,
+        PresaleError::WhitelistTierCapExceeded
+    );
+
+    Ok(())
+}
+
+/// Records the accounts a new sale is created with (token wallet, merchant wallet, escrow, fee
+/// authority and its own bump) and opens the first page of its buyer registry.
+#[allow(clippy::too_many_arguments)]
+fn open_sale<'info>(
+    presale: &mut Account<'info, Presale>,
+    presale_bump: u8,
+    registry_page: &AccountLoader<'info, BuyerRegistryPage>,
+    presale_wallet: &Account<'info, TokenAccount>,
+    merchant_wallet: &AccountInfo<'info>,
+    escrow: &SystemAccount<'info>,
+    escrow_bump: u8,
+    fee_authority: Option<&Signer<'info>>,
+    admin: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    presale.presale_wallet = presale_wallet.key();
+
+    // Open the first page of the buyer registry
+    let mut registry_page = registry_page.load_init()?;
+    registry_page.presale = presale.key();
+    registry_page.page_index = 0;
+    presale.registry_page_count = 1;
+    presale.merchant_wallet = merchant_wallet.key();
+    presale.record_escrow(escrow.key(), escrow_bump);
+    fund_escrow_rent(admin, escrow, system_program)?;
+    presale.fee_authority = fee_authority.map_or(Pubkey::default(), |authority| authority.key());
+
+    presale.bump = presale_bump;
+
+    Ok(())
+}
+
+/// Tops the escrow PDA up to the rent-exempt minimum of an empty account, so payments below that
+/// minimum can land in it. The escrow may already hold lamports sent to its address.
+fn fund_escrow_rent<'info>(
+    payer: &Signer<'info>,
+    escrow: &SystemAccount<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    let shortfall = Rent::get()?.minimum_balance(0).saturating_sub(escrow.lamports());
+    if shortfall > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                SystemTransfer {
+                    from: payer.to_account_info(),
+                    to: escrow.to_account_info(),
+                },
+            ),
+            shortfall,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Whether a purchase's payment was taken on-chain into the escrow: Web3 payments and wrapped SOL,
+/// which is unwrapped into it. Web2 payments are only declared.
+fn paid_into_escrow(payment_type: u8) -> bool {
+    payment_type == 0 || payment_type == PAYMENT_TYPE_WRAPPED_SOL
+}
+
+/// The public-round discount for a whitelisted buyer: one presenting their `WhitelistEntry` or a valid
+/// proof against the private-round root. Anyone else, and every private-round buyer, gets none.
+fn whitelist_discount_bps(presale: &Presale, buyer: &Pubkey, proof: Option<&WhitelistProof>, has_entry: bool) -> u16 {
+    if presale.sale_stage != 2 || presale.whitelist_discount_bps == 0 {
+        return 0;
+    }
+
+    let whitelisted = has_entry
+        || proof.is_some_and(|proof| {
+            presale.whitelist_root != [0u8; 32] && verify_whitelist_proof(&presale.whitelist_root, buyer, proof).is_ok()
+        });
+    if whitelisted {
+        presale.whitelist_discount_bps
+    } else {
+        0
+    }
+}
+
+/// The discount a purchase gets at `price`: the larger of the bulk-purchase discount for `payment` and the
+/// buyer's public-round whitelist discount.
+fn purchase_discount_bps(
+    presale: &Presale,
+    currency: u8,
+    payment: u64,
+    price: u64,
+    buyer: &Pubkey,
+    proof: Option<&WhitelistProof>,
+    has_entry: bool,
+) -> Result<u16> {
+    Ok(bulk_discount_bps(presale, currency, payment, price)?.max(whitelist_discount_bps(presale, buyer, proof, has_entry)))
+}
+
+/// Checks a merkle proof that `buyer` is whitelisted at `proof.tier` under `root`.
+fn verify_whitelist_proof(root: &[u8; 32], buyer: &Pubkey, proof: &WhitelistProof) -> Result<()> {
+    let mut node = keccak::hashv(&[buyer.as_ref(), &[proof.tier]]).to_bytes();
+    for sibling in &proof.proof {
+        node = if node <= *sibling {
+            keccak::hashv(&[&node, sibling]).to_bytes()
+        } else {
+            keccak::hashv(&[sibling, &node]).to_bytes()
+        };
+    }
+    require!(node == *root, PresaleError::NotWhitelisted);
+
+    Ok(())
+}
+
+/// In commit-reveal mode, requires a public-round purchase to consume a revealed commitment for
+/// exactly `amount`; Anchor closes the commitment once the purchase succeeds.
+fn check_purchase_commitment(
+    presale: &Presale,
+    purchase_commitment: Option<&Account<PurchaseCommitment>>,
+    amount: u64,
+) -> Result<()> {
+    if presale.sale_stage != 2 || presale.commit_reveal_delay == 0 {
+        return Ok(());
+    }
+
+    let purchase_commitment = purchase_commitment.ok_or(PresaleError::PurchaseCommitmentRequired)?;
+    require!(
+        purchase_commitment.revealed && purchase_commitment.amount == amount,
+        PresaleError::PurchaseCommitmentRequired
+    );
+
+    Ok(())
+}
+
+/// Rejects blacklisted wallets. Once the blacklist is on, the wallet's `Blacklist` PDA (seeds checked
+/// by the accounts struct) must be passed, and must not hold an entry.
+fn check_not_blacklisted(presale: &Presale, blacklist: Option<&UncheckedAccount>) -> Result<()> {
+    if !presale.blacklist_enabled {
+        return Ok(());
+    }
+
+    let blacklist = blacklist.ok_or(PresaleError::BlacklistRequired)?;
+    require!(!is_blacklisted(blacklist), PresaleError::WalletBlacklisted);
+
+    Ok(())
+}
+
+/// Whether a `Blacklist` PDA holds a live entry; unblacklisting closes it.
+fn is_blacklisted(blacklist: &AccountInfo) -> bool {
+    blacklist.owner == &crate::ID && !blacklist.data_is_empty()
+}
+
+/// Verifies a buyer against the configured identity provider.
+/// In account mode, the attestation must be owned by the verifier and name the buyer at the subject offset.
+/// In CPI mode, the verifier program is invoked with `[buyer, attestation]` and the buyer pubkey as data,
+/// and must fail for ineligible buyers.
+/// In gateway mode, the attestation must be the buyer's active, unexpired gateway token in the
+/// configured gatekeeper network.
+fn check_identity<'info>(
+    presale: &Presale,
+    buyer: &AccountInfo<'info>,
+    attestation: Option<&UncheckedAccount<'info>>,
+    verifier_program: Option<&UncheckedAccount<'info>>,
+) -> Result<()> {
+    if presale.identity_mode == IDENTITY_MODE_NONE {
+        return Ok(());
+    }
+
+    let attestation = attestation.ok_or(PresaleError::IdentityNotVerified)?;
+
+    if presale.identity_mode == IDENTITY_MODE_ACCOUNT {
+        require!(
+            *attestation.owner == presale.identity_verifier,
+            PresaleError::IdentityNotVerified
+        );
+        let data = attestation.try_borrow_data()?;
+        let offset = presale.identity_subject_offset as usize;
+        require!(
+            data.len() >= offset + 32 && data[offset..offset + 32] == buyer.key().to_bytes(),
+            PresaleError::IdentityNotVerified
+        );
+    } else if presale.identity_mode == IDENTITY_MODE_GATEWAY {
+        require!(
+            *attestation.owner == GATEWAY_PROGRAM_ID,
+            PresaleError::IdentityNotVerified
+        );
+        let data = attestation.try_borrow_data()?;
+        let token = parse_gateway_token(&data).ok_or(PresaleError::IdentityNotVerified)?;
+        require!(
+            token.owner_wallet == buyer.key()
+                && token.gatekeeper_network == presale.identity_verifier
+                && token.state == GATEWAY_TOKEN_STATE_ACTIVE,
+            PresaleError::IdentityNotVerified
+        );
+        if let Some(expire_time) = token.expire_time {
+            require!(
+                Clock::get()?.unix_timestamp < expire_time,
+                PresaleError::IdentityNotVerified
+            );
+        }
+    } else {
+        let verifier_program = verifier_program.ok_or(PresaleError::IdentityNotVerified)?;
+        invoke(
+            &Instruction {
+                program_id: presale.identity_verifier,
+                accounts: vec![
+                    AccountMeta::new_readonly(buyer.key(), false),
+                    AccountMeta::new_readonly(attestation.key(), false),
+                ],
+                data: buyer.key().to_bytes().to_vec(),
+            },
+            &[
+                buyer.clone(),
+                attestation.to_account_info(),
+                verifier_program.to_account_info(),
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The fields of a Civic gateway token checked in gateway identity mode.
+struct GatewayToken {
+    owner_wallet: Pubkey,
+    gatekeeper_network: Pubkey,
+    state: u8,
+    expire_time: Option<i64>,
+}
+
+/// Reads a Borsh-serialized gateway token: features, optional parent token, owner wallet, optional
+/// owner identity, gatekeeper network, issuing gatekeeper, state and optional expiry.
+fn parse_gateway_token(data: &[u8]) -> Option<GatewayToken> {
+    let mut rest = data.get(1..)?; // Features
+    let mut take = |len: usize| -> Option<&[u8]> {
+        let (head, tail) = (rest.get(..len)?, rest.get(len..)?);
+        rest = tail;
+        Some(head)
+    };
+    if take(1)?[0] == 1 {
+        take(32)?; // Parent gateway token
+    }
+    let owner_wallet = Pubkey::try_from(take(32)?).ok()?;
+    if take(1)?[0] == 1 {
+        take(32)?; // Owner identity
+    }
+    let gatekeeper_network = Pubkey::try_from(take(32)?).ok()?;
+    take(32)?; // Issuing gatekeeper
+    let state = take(1)?[0];
+    let expire_time = match take(1)?[0] {
+        0 => None,
+        _ => Some(i64::from_le_bytes(take(8)?.try_into().ok()?)),
+    };
+
+    Some(GatewayToken {
+        owner_wallet,
+        gatekeeper_network,
+        state,
+        expire_time,
+    })
+}
+
+/// Verifies the buyer's KYC attestation while a KYC authority is set: an Ed25519 program instruction
+/// earlier in the transaction must carry the authority's signature over `buyer || presale || expiry`
+/// (expiry as little-endian i64 Unix time), and the expiry must not have passed.
+fn check_kyc_attestation(
+    presale: &Account<Presale>,
+    buyer: &Pubkey,
+    instructions_sysvar: Option<&UncheckedAccount>,
+) -> Result<()> {
+    if presale.kyc_authority == Pubkey::default() {
+        return Ok(());
+    }
+
+    let instructions_sysvar = instructions_sysvar.ok_or(PresaleError::KycAttestationRequired)?;
+    let current_index = load_current_index_checked(instructions_sysvar)?;
+    for index in 0..current_index {
+        let instruction = load_instruction_at_checked(index as usize, instructions_sysvar)?;
+        if instruction.program_id != ed25519_program::ID {
+            continue;
+        }
+        let attestation = parse_kyc_attestation(&instruction.data, &presale.kyc_authority, buyer, &presale.key());
+        if let Some((expiry, regions)) = attestation {
+            require!(
+                Clock::get()?.unix_timestamp <= expiry,
+                PresaleError::KycAttestationExpired
+            );
+            require!(
+                regions & presale.blocked_regions == 0,
+                PresaleError::RegionRestricted
+            );
+            return Ok(());
+        }
+    }
+
+    err!(PresaleError::KycAttestationRequired)
+}
+
+/// Reads an Ed25519 program instruction holding a single signature by `kyc_authority` over a KYC
+/// attestation for `buyer` and `presale`, all stored in the instruction itself, and returns the expiry
+/// and the buyer's region bitmask.
+fn parse_kyc_attestation(
+    data: &[u8],
+    kyc_authority: &Pubkey,
+    buyer: &Pubkey,
+    presale: &Pubkey,
+) -> Option<(i64, u64)> {
+    // Header: signature count and padding, then one offsets record of seven u16 fields
+    if data.len() < 16 || data[0] != 1 {
+        return None;
+    }
+    let field = |index: usize| u16::from_le_bytes([data[2 + 2 * index], data[3 + 2 * index]]);
+    let public_key_offset = field(2) as usize;
+    let message_offset = field(4) as usize;
+    if [field(1), field(3), field(6)].iter().any(|&instruction_index| instruction_index != u16::MAX)
+        || field(5) as usize != KYC_ATTESTATION_LEN
+    {
+        return None; // Data living in other instructions is not supported
+    }
+
+    let public_key = data.get(public_key_offset..public_key_offset + 32)?;
+    let message = data.get(message_offset..message_offset + KYC_ATTESTATION_LEN)?;
+    if public_key != kyc_authority.as_ref()
+        || &message[..32] != buyer.as_ref()
+        || &message[32..64] != presale.as_ref()
+    {
+        return None;
+    }
+
+    let expiry = i64::from_le_bytes(message[64..72].try_into().ok()?);
+    let regions = u64::from_le_bytes(message[72..80].try_into().ok()?);
+    Some((expiry, regions))
+}
+
+/// Appends a purchase to the purchase journal, which is mandatory once it has been created.
+fn record_in_journal(
+    presale: &Presale,
+    purchase_journal: Option<&AccountLoader<PurchaseJournal>>,
+    entry: JournalEntry,
+) -> Result<()> {
+    if !presale.purchase_journal_enabled {
+        return Ok(());
+    }
+
+    let journal = purchase_journal.ok_or(PresaleError::PurchaseJournalRequired)?;
+    journal.load_mut()?.push(entry);
+
+    Ok(())
+}
+
+/// Credits a referral commission to the referrer's buyer account, where it is claimed with their allocation.
+fn credit_referrer(
+    presale: &mut Presale,
+    referrer_account: Option<&mut Account<BuyerAccount>>,
+    buyer: Pubkey,
+    commission_raw: u64,
+) -> Result<()> {
+    let Some(referrer_account) = referrer_account else {
+        return Ok(());
+    };
+
+    require!(referrer_account.buyer != buyer, PresaleError::InvalidReferrer);
+    require!(!referrer_account.revoked, PresaleError::AllocationRevoked);
+
+    if commission_raw > 0 {
+        referrer_account.referral_tokens = referrer_account.referral_tokens.checked_add(commission_raw).ok_or(PresaleError::MathOverflow)?;
+        presale.total_referral = presale.total_referral.checked_add(commission_raw).ok_or(PresaleError::MathOverflow)?;
+
+        emit!(ReferralCreditedEvent {
+            referrer: referrer_account.buyer,
+            buyer,
+            commission_tokens: commission_raw,
+        });
+    }
+
+    Ok(())
+}
+
+/// Takes back a referral commission credited on a purchase that was refunded or reversed. Commission the
+/// referrer already claimed stays with them. Returns the tokens taken back.
+fn reverse_referral_commission(
+    presale: &mut Presale,
+    referrer_account: &mut BuyerAccount,
+    buyer: Pubkey,
+    commission_raw: u64,
+) -> Result<u64> {
+    let reversed_raw = commission_raw
+        .min(referrer_account.referral_tokens)
+        .min(referrer_account.unclaimed_tokens()?);
+    referrer_account.referral_tokens = referrer_account.referral_tokens.checked_sub(reversed_raw).ok_or(PresaleError::MathOverflow)?;
+    presale.total_referral = presale.total_referral.saturating_sub(reversed_raw);
+
+    emit!(ReferralReversedEvent {
+        referrer: referrer_account.buyer,
+        buyer,
+        commission_tokens: commission_raw,
+        reversed_tokens: reversed_raw,
+    });
+
+    Ok(reversed_raw)
+}
+
+/// Pays `amount` (raw units) of a buyer's unlocked, not yet claimed tokens to their claim recipient.
+#[allow(clippy::too_many_arguments)]
+fn claim_unlocked<'info>(
+    presale: &mut Account<'info, Presale>,
+    buyer_account: &mut Account<'info, BuyerAccount>,
+    presale_wallet: &Account<'info, TokenAccount>,
+    recipient_token_account: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    vesting_schedule: Option<&VestingSchedule>,
+    vesting_stats: Option<&mut Account<'info, VestingStats>>,
+    amount: u64,
+) -> Result<()> {
+    require!(presale.pool_created, PresaleError::PresaleNotFinalized);
+    presale.ensure_tge_reached()?;
+    presale.ensure_claims_not_paused()?;
+
+    let vested_raw = presale.buyer_vested_amount(buyer_account, vesting_schedule, Clock::get()?.unix_timestamp)?;
+    let claimable_raw = vested_raw.saturating_sub(buyer_account.settled_tokens()?);
+    require!(claimable_raw > 0, PresaleError::NothingToClaim);
+    require!(amount > 0, PresaleError::InvalidClaimAmount);
+    require!(amount <= claimable_raw, PresaleError::ClaimExceedsUnlocked);
+
+    let seeds: &[&[u8]] = &[PRESALE_SEED, presale.admin.as_ref(), &[presale.bump]];
+    let signer_seeds: &[&[&[u8]]] = &[seeds];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TokenTransfer {
+                from: presale_wallet.to_account_info(),
+                to: recipient_token_account.to_account_info(),
+                authority: presale.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        amount,
+    )?;
+
+    buyer_account.tokens_claimed = buyer_account.tokens_claimed.checked_add(amount).ok_or(PresaleError::MathOverflow)?;
+    buyer_account.config_version = presale.config_version; // Settled under the final vesting config
+    presale.total_claimed = presale.total_claimed.checked_add(amount).ok_or(PresaleError::MathOverflow)?;
+    let vested_delta_raw = buyer_account.record_vested(vested_raw)?;
+    record_vesting_stats(presale, vesting_stats, vested_delta_raw)?;
+
+    let total_allocation = buyer_account.total_allocation()?;
+    emit!(TokensClaimedEvent {
+        buyer: buyer_account.buyer,
+        tokens_claimed: amount,
+        tokens_remaining: claimable_raw - amount,
+        total_allocation,
+        total_claimed: buyer_account.tokens_claimed,
+        tokens_locked: total_allocation.saturating_sub(vested_raw.max(buyer_account.settled_tokens()?)),
+    });
+
+    msg!(
+        "Buyer {} claimed {} tokens (raw), {} still unlocked",
+        buyer_account.buyer,
+        amount,
+        claimable_raw - amount
+    );
+
+    Ok(())
+}
+
+/// Brings the vesting statistics up to date after a claim, adding `vested_delta` (raw units) newly
+/// observed as vested. A no-op until `create_vesting_stats` has been called.
+fn record_vesting_stats(
+    presale: &Presale,
+    vesting_stats: Option<&mut Account<VestingStats>>,
+    vested_delta: u64,
+) -> Result<()> {
+    if !presale.vesting_stats_enabled {
+        return Ok(());
+    }
+
+    let vesting_stats = vesting_stats.ok_or(PresaleError::VestingStatsRequired)?;
+    vesting_stats.total_allocated = presale.total_allocated();
+    vesting_stats.total_vested = vesting_stats.total_vested.checked_add(vested_delta).ok_or(PresaleError::MathOverflow)?;
+    vesting_stats.total_claimed = presale.total_claimed;
+    vesting_stats.last_updated = Clock::get()?.unix_timestamp;
+
+    Ok(())
+}
+
+/// Rejects a purchase that would buy fewer tokens than the buyer expected, e.g. after a price update
+/// or stage transition landed first, instead of filling it at a worse rate.
+fn ensure_min_tokens_out(tokens_raw: u64, min_tokens_out: Option<u64>) -> Result<()> {
+    if let Some(min_tokens_out) = min_tokens_out {
+        require!(tokens_raw >= min_tokens_out, PresaleError::SlippageExceeded);
+    }
+
+    Ok(())
+}
+
+/// Rejects a purchase submitted with a deadline that the chain clock has already passed, so a
+/// transaction stuck during congestion isn't filled later under prices or a stage the buyer didn't see.
+fn ensure_purchase_not_expired(valid_until_timestamp: Option<i64>) -> Result<()> {
+    if let Some(valid_until) = valid_until_timestamp {
+        require!(
+            Clock::get()?.unix_timestamp <= valid_until,
+            PresaleError::PurchaseExpired
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks a milestone list: at most `MAX_VESTING_MILESTONES`, and unless empty, strictly increasing
+/// timestamps with non-zero shares adding up to 100%.
+fn validate_vesting_milestones(milestones: &[VestingMilestone]) -> Result<()> {
+    require!(
+        milestones.len() <= MAX_VESTING_MILESTONES,
+        PresaleError::InvalidVestingMilestones
+    );
+    if !milestones.is_empty() {
+        require!(
+            milestones.iter().all(|milestone| milestone.unlock_bps > 0)
+                && milestones.windows(2).all(|pair| pair[0].timestamp < pair[1].timestamp)
+                && milestones.iter().map(|milestone| milestone.unlock_bps as u64).sum::<u64>()
+                    == BPS_DENOMINATOR as u64,
+            PresaleError::InvalidVestingMilestones
+        );
+    }
+    Ok(())
+}
+
+/// Transfers `amount` of a stablecoin with a checked transfer, which Token-2022 mints such as PYUSD
+/// require and SPL Token mints accept too. `signer_seeds` is empty when `authority` signed the transaction.
+fn transfer_stable_coin<'info>(
+    token_program: &impl ToAccountInfo<'info>,
+    mint: &InterfaceAccount<'info, InterfaceMint>,
+    from: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from,
+                mint: mint.to_account_info(),
+                to,
+                authority,
+            },
+            signer_seeds,
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+/// Converts a whole number of days into seconds, rejecting values that overflow an `i64`.
+fn days_to_seconds(days: i64) -> Result<i64> {
+    days.checked_mul(86400).ok_or(PresaleError::MathOverflow.into())
+}
+
+/// `value * numerator / denominator`, computed in u128 so the product cannot overflow, with the
+/// result checked back into u64.
+fn mul_div(value: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    let result = (value as u128)
+        .checked_mul(numerator as u128)
+        .and_then(|product| product.checked_div(denominator as u128))
+        .ok_or(PresaleError::MathOverflow)?;
+    u64::try_from(result).map_err(|_| PresaleError::MathOverflow.into())
+}
+
+/// The transfers paying out `amount` of proceeds: all of it to `merchant` while no treasury split is
+/// configured, otherwise each treasury wallet's share, paid to the next account of `recipients`: the
+/// wallet itself for SOL (`mint` = None), or a token account it owns for `mint`. Rounding dust goes to
+/// the last wallet. A SOL share that would leave its wallet below the rent-exempt minimum is rejected
+/// up front rather than failing the transfer.
+fn treasury_payouts<'info>(
+    presale: &Presale,
+    amount: u64,
+    merchant: Option<AccountInfo<'info>>,
+    recipients: &mut std::slice::Iter<'_, AccountInfo<'info>>,
+    mint: Option<Pubkey>,
+) -> Result<Vec<(AccountInfo<'info>, u64)>> {
+    let splits = presale.treasury_splits();
+    if splits.is_empty() {
+        let merchant = merchant.ok_or(PresaleError::InvalidTokenAccount)?;
+        return Ok(vec![(merchant, amount)]);
+    }
+
+    let mut unpaid = amount;
+    let mut payouts = Vec::with_capacity(splits.len());
+    for (index, split) in splits.iter().enumerate() {
+        let recipient = recipients.next().ok_or(PresaleError::TreasuryAccountMismatch)?;
+        let recipient_wallet = match mint {
+            None => recipient.key(),
+            Some(mint) => {
+                require!(
+                    *recipient.owner == token::ID || *recipient.owner == token_2022::ID,
+                    PresaleError::TreasuryAccountMismatch
+                );
+                let data = recipient.try_borrow_data()?;
+                let token_account = InterfaceTokenAccount::try_deserialize(&mut &data[..])
+                    .map_err(|_| PresaleError::TreasuryAccountMismatch)?;
+                require!(token_account.mint == mint, PresaleError::TreasuryAccountMismatch);
+                token_account.owner
+            }
+        };
+        require!(recipient_wallet == split.wallet, PresaleError::TreasuryAccountMismatch);
+
+        let share = if index + 1 == splits.len() {
+            unpaid
+        } else {
+            mul_div(amount, split.share_bps as u64, BPS_DENOMINATOR as u64)?
+        };
+        if mint.is_none() && share > 0 {
+            require!(
+                recipient.lamports().saturating_add(share) >= Rent::get()?.minimum_balance(0),
+                PresaleError::TreasuryShareBelowRent
+            );
+        }
+        unpaid -= share;
+        payouts.push((recipient.clone(), share));
+    }
+
+    Ok(payouts)
+}
+
+/// Converts `raw` units of a stablecoin with `decimals` decimals to USD micro-dollars, rounding down.
+/// Stablecoins are treated 1:1 with USD.
+fn stable_coin_raw_to_micros(raw: u64, decimals: u8) -> Result<u64> {
+    let unit = 10u64.checked_pow(decimals as u32).ok_or(PresaleError::MathOverflow)?;
+    mul_div(raw, USD_MICROS_PER_DOLLAR, unit)
+}
+
+/// The raw units of a stablecoin with `decimals` decimals worth at least `micros` USD micro-dollars.
+fn micros_to_stable_coin_raw(micros: u64, decimals: u8) -> Result<u64> {
+    let unit = 10u64.checked_pow(decimals as u32).ok_or(PresaleError::MathOverflow)?;
+    let result = (micros as u128)
+        .checked_mul(unit as u128)
+        .map(|product| product.div_ceil(USD_MICROS_PER_DOLLAR as u128))
+        .ok_or(PresaleError::MathOverflow)?;
+    u64::try_from(result).map_err(|_| PresaleError::MathOverflow.into())
+}
+
+/// Decimals of the stablecoin a quote is for: the passed `AcceptedCurrency` entry's, which must be for
+/// `currency`, or `STABLE_COIN_DECIMALS` when none is passed.
+fn quoted_stable_coin_decimals(accepted_currency: Option<&Account<AcceptedCurrency>>, currency: u8) -> Result<u8> {
+    match accepted_currency {
+        Some(accepted_currency) => {
+            require!(accepted_currency.currency == currency, PresaleError::InvalidStableToken);
+            Ok(accepted_currency.decimals)
+        }
+        None => Ok(STABLE_COIN_DECIMALS),
+    }
+}
+
+/// The wallet a quote is for, or the default pubkey when none is passed.
+fn quoted_buyer(accounts: &QuotePurchase) -> Pubkey {
+    accounts.buyer.as_ref().map_or(Pubkey::default(), |buyer| buyer.key())
+}
+
+/// Whether a quote's buyer presented their `WhitelistEntry`. An entry passed for another wallet, or
+/// without the buyer, is rejected.
+fn quoted_whitelist_entry(accounts: &QuotePurchase) -> Result<bool> {
+    match (&accounts.whitelist_entry, &accounts.buyer) {
+        (None, _) => Ok(false),
+        (Some(whitelist_entry), Some(buyer)) if whitelist_entry.wallet == buyer.key() => Ok(true),
+        _ => err!(PresaleError::NotWhitelisted),
+    }
+}
+
+/// The `PaymentToken` entry and price update account a `CURRENCY_TOKEN` quote is priced with.
+fn quoted_payment_token<'a, 'info>(
+    accounts: &'a QuotePurchase<'info>,
+) -> Result<(&'a Account<'info, PaymentToken>, &'a UncheckedAccount<'info>)> {
+    let payment_token = accounts.payment_token.as_ref().ok_or(PresaleError::PaymentTokenAccountsRequired)?;
+    let price_update = accounts
+        .payment_token_price_update
+        .as_ref()
+        .ok_or(PresaleError::PaymentTokenAccountsRequired)?;
+    require_keys_eq!(price_update.key(), payment_token.price_feed, PresaleError::InvalidOraclePrice);
+    Ok((payment_token, price_update))
+}
+
+/// The price a purchase in `currency` pays for one NLOV right now: lamports for SOL, USD micro-dollars
+/// for stablecoins, after the public round ramp and volume pricing.
+fn purchase_price(
+    presale: &Presale,
+    currency: u8,
+    sol_usd_price_update: Option<&UncheckedAccount>,
+    sol_usd_fallback_feed: Option<&UncheckedAccount>,
+    sol_usd_twap_observation: Option<&UncheckedAccount>,
+) -> Result<u64> {
+    presale.ensure_usd_pricing_current()?;
+
+    let now = Clock::get()?.unix_timestamp;
+    let base_price = if currency == CURRENCY_SOL {
+        current_sol_price(presale, sol_usd_price_update, sol_usd_fallback_feed, sol_usd_twap_observation)?
+    } else {
+        presale.active_prices(now).0
+    };
+
+    presale.effective_price(base_price, now)
+}
+
+/// The bulk-purchase discount for a payment (lamports for SOL, USD micro-dollars for stablecoins) at `price`.
+fn bulk_discount_bps(presale: &Presale, currency: u8, payment: u64, price: u64) -> Result<u16> {
+    if presale.discount_tiers[0].discount_bps == 0 {
+        return Ok(0);
+    }
+
+    Ok(presale.bulk_discount_bps(payment_usd_cents(presale, currency, payment, price)?))
+}
+
+/// The USD value of a payment at `price`, in cents: SOL is valued at the NLOV USD price over its SOL price.
+fn payment_usd_cents(presale: &Presale, currency: u8, payment: u64, price: u64) -> Result<u64> {
+    if currency != CURRENCY_SOL {
+        return Ok(payment / USD_MICROS_PER_CENT);
+    }
+
+    let usd_price_micros_per_nlov = purchase_price(presale, CURRENCY_USDC, None, None, None)?;
+    require!(price > 0, PresaleError::InvalidPrice);
+    mul_div(payment, usd_price_micros_per_nlov, price.checked_mul(USD_MICROS_PER_CENT).ok_or(PresaleError::MathOverflow)?)
+}
+
+/// Whole tokens a payment buys at `price` less `discount_bps`, rounded per `rounding_mode`. Every token
+/// credited must be paid in full, so payments the mode rounds up to a token they don't cover are
+/// rejected, and payments below the price of one token buy nothing whatever the mode.
+fn tokens_for_payment(payment: u64, price: u64, discount_bps: u16, rounding_mode: u8) -> Result<u64> {
+    let discounted_price = (price as u128) * ((BPS_DENOMINATOR - discount_bps) as u128);
+    require!(discounted_price > 0, PresaleError::InvalidPrice);
+
+    let scaled_payment = (payment as u128) * (BPS_DENOMINATOR as u128);
+    if scaled_payment < discounted_price {
+        return Ok(0);
+    }
+    let tokens = match rounding_mode {
+        TOKEN_ROUNDING_NEAREST => (scaled_payment + discounted_price / 2) / discounted_price,
+        TOKEN_ROUNDING_CEIL => scaled_payment.div_ceil(discounted_price),
+        _ => scaled_payment / discounted_price,
+    };
+    require!(
+        tokens.checked_mul(discounted_price).ok_or(PresaleError::MathOverflow)? <= scaled_payment,
+        PresaleError::InvalidPrice
+    );
+    u64::try_from(tokens).map_err(|_| PresaleError::MathOverflow.into())
+}
+
+/// The full price of `tokens` at `price` less `discount_bps`: the smallest payment that buys them in any
+/// rounding mode.
+fn payment_for_tokens(tokens: u64, price: u64, discount_bps: u16) -> Result<u64> {
+    let discounted_price = (price as u128) * ((BPS_DENOMINATOR - discount_bps) as u128);
+    let cost = (tokens as u128)
+        .checked_mul(discounted_price)
+        .ok_or(PresaleError::MathOverflow)?;
+    let payment = cost.div_ceil(BPS_DENOMINATOR as u128);
+    u64::try_from(payment).map_err(|_| PresaleError::MathOverflow.into())
+}
+
+/// The smallest payment (lamports for SOL, USD micro-dollars for stablecoins, rounded up by `round_up` to
+/// what can actually be paid) buying `desired_tokens` at `price`, under the larger of `base_discount_bps`
+/// and the largest bulk discount whose threshold the discounted payment still reaches.
+fn payment_for_desired_tokens(
+    presale: &Presale,
+    currency: u8,
+    desired_tokens: u64,
+    price: u64,
+    base_discount_bps: u16,
+    round_up: impl Fn(u64) -> Result<u64>,
+) -> Result<u64> {
+    let mut payment = round_up(payment_for_tokens(desired_tokens, price, base_discount_bps)?)?;
+    for tier in presale
+        .discount_tiers
+        .iter()
+        .rev()
+        .filter(|tier| tier.discount_bps > base_discount_bps)
+    {
+        let discounted = round_up(payment_for_tokens(desired_tokens, price, tier.discount_bps)?)?;
+        if payment_usd_cents(presale, currency, discounted, price)? >= tier.min_usd_cents {
+            payment = discounted;
+            break;
+        }
+    }
+
+    Ok(payment)
+}
+
+/// The lamport price of one NLOV: the active `sol_price_lamports_per_nlov`, or, once a SOL/USD price feed
+/// is configured, the active `usd_price_micros_per_nlov` converted at the Pyth price, falling back to the
+/// Switchboard feed when the Pyth price is missing, invalid or stale. In the public round, a configured
+/// pool TWAP replaces the oracle price, provided it stays within `max_twap_deviation_bps` of it.
+fn current_sol_price(
+    presale: &Presale,
+    sol_usd_price_update: Option<&UncheckedAccount>,
+    sol_usd_fallback_feed: Option<&UncheckedAccount>,
+    sol_usd_twap_observation: Option<&UncheckedAccount>,
+) -> Result<u64> {
+    let now = Clock::get()?.unix_timestamp;
+    let (usd_price_micros_per_nlov, sol_price_lamports_per_nlov) = presale.active_prices(now);
+    if presale.sol_usd_price_feed == Pubkey::default() {
+        return Ok(sol_price_lamports_per_nlov);
+    }
+
+    let primary = match sol_usd_price_update {
+        Some(price_update) => read_pyth_price(presale, price_update, now),
+        None => err!(PresaleError::OraclePriceRequired),
+    };
+    let (price, exponent) = match (primary, sol_usd_fallback_feed) {
+        (Ok(price), _) => price,
+        (Err(_), Some(feed)) if presale.sol_usd_fallback_feed != Pubkey::default() => {
+            read_switchboard_price(presale, feed, now)?
+        }
+        (Err(err), _) => return Err(err),
+    };
+    let oracle_lamports = usd_micros_to_lamports(usd_price_micros_per_nlov, price, exponent)?;
+
+    if presale.sale_stage != 2 || presale.sol_usd_twap_observation == Pubkey::default() {
+        return Ok(oracle_lamports);
+    }
+
+    let observation = sol_usd_twap_observation.ok_or(PresaleError::TwapPriceRequired)?;
+    let (twap_price, twap_exponent) = read_raydium_twap_price(presale, observation, now)?;
+    let twap_lamports = usd_micros_to_lamports(usd_price_micros_per_nlov, twap_price, twap_exponent)?;
+    require!(
+        (twap_lamports.abs_diff(oracle_lamports) as u128) * (BPS_DENOMINATOR as u128)
+            <= (oracle_lamports as u128) * (presale.max_twap_deviation_bps as u128),
+        PresaleError::TwapDeviationTooLarge
+    );
+
+    Ok(twap_lamports)
+}
+
+/// Reads the SOL/USD price over the last `twap_window` seconds from a Raydium CLMM SOL/stablecoin pool's
+/// observations (SOL as token 0), as a price and exponent. The latest observation must be no older
+/// than `max_price_age`.
+fn read_raydium_twap_price(presale: &Presale, observation: &UncheckedAccount, now: i64) -> Result<(u128, i32)> {
+    require!(
+        *observation.owner == RAYDIUM_CLMM_PROGRAM_ID,
+        PresaleError::InvalidOraclePrice
+    );
+    let data = observation.try_borrow_data()?;
+    let (average_tick, last_observed_at) =
+        parse_raydium_twap_tick(&data, presale.twap_window).ok_or(PresaleError::InvalidOraclePrice)?;
+
+    // A tick's price is 1.0001^tick raw stablecoin units per lamport, i.e. 10^(9 - 6) times that in USD per SOL
+    let price = tick_to_price(average_tick)?;
+    presale.check_oracle_price_quality(price, 0, last_observed_at, now)?;
+    Ok((price, 9 - (STABLE_COIN_DECIMALS as i32) - TWAP_PRICE_DECIMALS))
+}
+
+/// Reads a Raydium CLMM `ObservationState` account, returning the average tick between the latest
+/// observation and the newest one at least `window` seconds older, and the latest observation's time.
+/// Layout: discriminator, initialized, recent epoch, observation index, pool ID, then a ring buffer of
+/// (block timestamp, tick cumulative, padding) observations.
+fn parse_raydium_twap_tick(data: &[u8], window: i64) -> Option<(i64, i64)> {
+    let observations_end = RAYDIUM_OBSERVATIONS_OFFSET + RAYDIUM_OBSERVATION_NUM * RAYDIUM_OBSERVATION_LEN;
+    if data.len() < observations_end || data[..8] != RAYDIUM_OBSERVATION_DISCRIMINATOR {
+        return None;
+    }
+
+    let observation_at = |index: usize| -> Option<(i64, i64)> {
+        let offset = RAYDIUM_OBSERVATIONS_OFFSET + index * RAYDIUM_OBSERVATION_LEN;
+        let timestamp = u32::from_le_bytes(data[offset..offset + 4].try_into().ok()?);
+        let tick_cumulative = i64::from_le_bytes(data[offset + 4..offset + 12].try_into().ok()?);
+        Some((timestamp as i64, tick_cumulative))
+    };
+    let latest_index = u16::from_le_bytes(
+        data[RAYDIUM_OBSERVATION_INDEX_OFFSET..RAYDIUM_OBSERVATION_INDEX_OFFSET + 2].try_into().ok()?,
+    ) as usize;
+    if latest_index >= RAYDIUM_OBSERVATION_NUM {
+        return None;
+    }
+    let (latest_at, latest_cumulative) = observation_at(latest_index)?;
+
+    let (earlier_at, earlier_cumulative) = (1..RAYDIUM_OBSERVATION_NUM)
+        .map(|back| (latest_index + RAYDIUM_OBSERVATION_NUM - back) % RAYDIUM_OBSERVATION_NUM)
+        .filter_map(observation_at)
+        .find(|&(timestamp, _)| timestamp > 0 && timestamp <= latest_at - window)?;
+
+    let average_tick = (latest_cumulative.checked_sub(earlier_cumulative)?).div_euclid(latest_at - earlier_at);
+    Some((average_tick, latest_at))
+}
+
+/// 1.0001^`tick` with `TWAP_PRICE_DECIMALS` decimals.
+fn tick_to_price(tick: i64) -> Result<u128> {
+    let one = 10u128.pow(TWAP_PRICE_DECIMALS as u32);
+    let multiply = |a: u128, b: u128| a.checked_mul(b).map(|product| product / one).ok_or(PresaleError::MathOverflow);
+
+    let mut base = one + one / 10_000;
+    let mut remaining = tick.unsigned_abs();
+    let mut result = one;
+    while remaining > 0 {
+        if remaining & 1 == 1 {
+            result = multiply(result, base)?;
+        }
+        remaining >>= 1;
+        if remaining > 0 {
+            base = multiply(base, base)?;
+        }
+    }
+
+    let price = if tick < 0 { one * one / result } else { result };
+    require!(price > 0, PresaleError::InvalidOraclePrice);
+    Ok(price)
+}
+
+/// Reads a positive, fresh and confident SOL/USD price and its exponent from a Pyth price update account.
+fn read_pyth_price(presale: &Presale, price_update: &UncheckedAccount, now: i64) -> Result<(u128, i32)> {
+    require!(
+        *price_update.owner == PYTH_RECEIVER_PROGRAM_ID,
+        PresaleError::InvalidOraclePrice
+    );
+    let data = price_update.try_borrow_data()?;
+    let (price, confidence, exponent, publish_time) =
+        parse_pyth_price(&data, &SOL_USD_FEED_ID).ok_or(PresaleError::InvalidOraclePrice)?;
+    require!(price > 0, PresaleError::InvalidOraclePrice);
+    presale.check_oracle_price_quality(price as u128, confidence as u128, publish_time, now)?;
+
+    Ok((price as u128, exponent))
+}
+
+/// Reads a payment token's positive USD price and its exponent from its Pyth price update account,
+/// subject to the token's own staleness and confidence limits.
+fn read_payment_token_price(payment_token: &PaymentToken, price_update: &UncheckedAccount, now: i64) -> Result<(u128, i32)> {
+    require!(
+        *price_update.owner == PYTH_RECEIVER_PROGRAM_ID,
+        PresaleError::InvalidOraclePrice
+    );
+    let data = price_update.try_borrow_data()?;
+    let (price, confidence, exponent, publish_time) =
+        parse_pyth_price(&data, &payment_token.feed_id).ok_or(PresaleError::InvalidOraclePrice)?;
+    require!(price > 0, PresaleError::InvalidOraclePrice);
+    check_price_quality(
+        price as u128,
+        confidence as u128,
+        publish_time,
+        now,
+        payment_token.max_price_age,
+        payment_token.max_confidence_bps,
+    )?;
+
+    Ok((price as u128, exponent))
+}
+
+/// Reads a fully verified Pyth `PriceUpdateV2` account for feed `feed_id`, returning the price, its
+/// confidence interval, exponent and publish time. Layout: discriminator, write authority, verification level, then the
+/// price message (feed ID, price, confidence, exponent, publish time, ...).
+fn parse_pyth_price(data: &[u8], feed_id: &[u8; 32]) -> Option<(i64, u64, i32, i64)> {
+    if data.len() < 101 || data[..8] != PYTH_PRICE_UPDATE_DISCRIMINATOR || data[40] != PYTH_VERIFICATION_FULL {
+        return None;
+    }
+    if data[41..73] != feed_id[..] {
+        return None;
+    }
+
+    let price = i64::from_le_bytes(data[73..81].try_into().ok()?);
+    let confidence = u64::from_le_bytes(data[81..89].try_into().ok()?);
+    let exponent = i32::from_le_bytes(data[89..93].try_into().ok()?);
+    let publish_time = i64::from_le_bytes(data[93..101].try_into().ok()?);
+    Some((price, confidence, exponent, publish_time))
+}
+
+/// Reads a positive, fresh and confident SOL/USD price and its exponent from a Switchboard pull feed,
+/// taking the result's standard deviation as its confidence interval.
+fn read_switchboard_price(presale: &Presale, feed: &UncheckedAccount, now: i64) -> Result<(u128, i32)> {
+    require!(
+        *feed.owner == SWITCHBOARD_ON_DEMAND_PROGRAM_ID,
+        PresaleError::InvalidOraclePrice
+    );
+    let data = feed.try_borrow_data()?;
+    let (value, std_dev, last_update) = parse_switchboard_price(&data).ok_or(PresaleError::InvalidOraclePrice)?;
+    require!(value > 0 && std_dev >= 0, PresaleError::InvalidOraclePrice);
+    presale.check_oracle_price_quality(value as u128, std_dev as u128, last_update, now)?;
+
+    Ok((value as u128, -SWITCHBOARD_DECIMALS))
+}
+
+/// Reads a Switchboard `PullFeedAccountData` account, returning the current result and its standard
+/// deviation (both scaled by 10^`SWITCHBOARD_DECIMALS`) and the time of the last update. Layout:
+/// discriminator, 32 oracle submissions, feed configuration up to the last update timestamp, then the
+/// current result.
+fn parse_switchboard_price(data: &[u8]) -> Option<(i128, i128, i64)> {
+    let std_dev_offset = SWITCHBOARD_RESULT_OFFSET + 16;
+    if data.len() < std_dev_offset + 16 || data[..8] != SWITCHBOARD_PULL_FEED_DISCRIMINATOR {
+        return None;
+    }
+
+    let last_update = i64::from_le_bytes(
+        data[SWITCHBOARD_LAST_UPDATE_OFFSET..SWITCHBOARD_LAST_UPDATE_OFFSET + 8].try_into().ok()?,
+    );
+    let value = i128::from_le_bytes(data[SWITCHBOARD_RESULT_OFFSET..std_dev_offset].try_into().ok()?);
+    let std_dev = i128::from_le_bytes(data[std_dev_offset..std_dev_offset + 16].try_into().ok()?);
+    Some((value, std_dev, last_update))
+}
+
+/// Reads the escrow vault balance and refund obligations of each registered payment token passed as a
+/// (`PaymentToken`, escrow vault) pair. A vault that was never created holds nothing.
+fn escrowed_payment_token_balances<'info>(
+    presale: &Account<Presale>,
+    escrow: &Pubkey,
+    pairs: &'info [AccountInfo<'info>],
+) -> Result<Vec<PaymentTokenBalance>> {
+    require!(pairs.len().is_multiple_of(2), PresaleError::InvalidTokenAccount);
+
+    let mut balances = Vec::with_capacity(pairs.len() / 2);
+    for pair in pairs.chunks(2) {
+        let payment_token = Account::<PaymentToken>::try_from(&pair[0])?;
+        require!(
+            payment_token.presale == presale.key()
+                && pair[1].key() == get_associated_token_address(escrow, &payment_token.mint),
+            PresaleError::InvalidTokenAccount
+        );
+        let balance = if pair[1].data_is_empty() {
+            0
+        } else {
+            InterfaceAccount::<InterfaceTokenAccount>::try_from(&pair[1])?.amount
+        };
+
+        balances.push(PaymentTokenBalance {
+            mint: payment_token.mint,
+            balance,
+            // Escrowed funds stay refundable until the sale is finalized
+            obligations: if presale.pool_created { 0 } else { payment_token.escrowed },
+        });
+    }
+    Ok(balances)
+}
+
+/// The most recent update time among the SOL/USD oracle feeds passed in: the Pyth price update and, when
+/// configured, the Switchboard fallback. `None` if neither is readable.
+fn sol_oracle_last_update(
+    presale: &Presale,
+    sol_usd_price_update: Option<&UncheckedAccount>,
+    sol_usd_fallback_feed: Option<&UncheckedAccount>,
+) -> Option<i64> {
+    let primary = sol_usd_price_update
+        .filter(|price_update| *price_update.owner == PYTH_RECEIVER_PROGRAM_ID)
+        .and_then(|price_update| {
+            let data = price_update.try_borrow_data().ok()?;
+            parse_pyth_price(&data, &SOL_USD_FEED_ID).map(|(_, _, _, publish_time)| publish_time)
+        });
+    let fallback = sol_usd_fallback_feed
+        .filter(|feed| presale.sol_usd_fallback_feed != Pubkey::default() && *feed.owner == SWITCHBOARD_ON_DEMAND_PROGRAM_ID)
+        .and_then(|feed| {
+            let data = feed.try_borrow_data().ok()?;
+            parse_switchboard_price(&data).map(|(_, _, last_update)| last_update)
+        });
+    primary.max(fallback)
+}
+
+/// The raw units of a token with `decimals` decimals worth at least `micros` USD micro-dollars at a
+/// positive USD price of `price * 10^exponent` per whole token; the inverse of `token_amount_to_usd_micros`.
+fn usd_micros_to_token_amount(micros: u64, decimals: u8, price: u128, exponent: i32) -> Result<u64> {
+    // amount = micros / (price * 10^(6 + exponent - decimals)), rounded up
+    let scale = 6i32
+        .checked_add(exponent)
+        .and_then(|scale| scale.checked_sub(decimals as i32))
+        .ok_or(PresaleError::MathOverflow)?;
+    let ten = 10u128;
+    let scale_factor = ten.checked_pow(scale.unsigned_abs()).ok_or(PresaleError::MathOverflow)?;
+    let amount = if scale >= 0 {
+        (micros as u128).div_ceil(price.checked_mul(scale_factor).ok_or(PresaleError::MathOverflow)?)
+    } else {
+        (micros as u128)
+            .checked_mul(scale_factor)
+            .ok_or(PresaleError::MathOverflow)?
+            .div_ceil(price)
+    };
+
+    u64::try_from(amount).map_err(|_| PresaleError::MathOverflow.into())
+}
+
+/// Values `amount` raw units of a token with `decimals` decimals at a positive USD price of
+/// `price * 10^exponent` per whole token, in micro-dollars, rounding down.
+fn token_amount_to_usd_micros(amount: u64, decimals: u8, price: u128, exponent: i32) -> Result<u64> {
+    // micros = amount / 10^decimals * price * 10^exponent * 10^6 = amount * price * 10^(6 + exponent - decimals)
+    let scale = 6i32
+        .checked_add(exponent)
+        .and_then(|scale| scale.checked_sub(decimals as i32))
+        .ok_or(PresaleError::MathOverflow)?;
+    let ten = 10u128;
+    let value = (amount as u128).checked_mul(price).ok_or(PresaleError::MathOverflow)?;
+    let micros = if scale >= 0 {
+        value
+            .checked_mul(ten.checked_pow(scale as u32).ok_or(PresaleError::MathOverflow)?)
+            .ok_or(PresaleError::MathOverflow)?
+    } else {
+        match ten.checked_pow(scale.unsigned_abs()) {
+            Some(divisor) => value / divisor,
+            None => 0,
+        }
+    };
+
+    u64::try_from(micros).map_err(|_| PresaleError::MathOverflow.into())
+}
+
+/// Fails if an oracle price published at `published_at` is older than `max_price_age` seconds, or if its
+/// confidence interval is wider than `max_confidence_bps` of the price (0 = no limit).
+fn check_price_quality(
+    price: u128,
+    confidence: u128,
+    published_at: i64,
+    now: i64,
+    max_price_age: i64,
+    max_confidence_bps: u16,
+) -> Result<()> {
+    require!(
+        now <= published_at.saturating_add(max_price_age),
+        PresaleError::StaleOraclePrice
+    );
+    require!(
+        max_confidence_bps == 0
+            || confidence.saturating_mul(BPS_DENOMINATOR as u128)
+                <= price.saturating_mul(max_confidence_bps as u128),
+        PresaleError::OracleConfidenceTooWide
+    );
+    Ok(())
+}
+
+/// Converts a USD price in micro-dollars per NLOV into lamports per NLOV at a positive SOL/USD price of
+/// `price * 10^exponent`.
+fn usd_micros_to_lamports(usd_micros: u64, price: u128, exponent: i32) -> Result<u64> {
+    // lamports = micros / 10^6 * 10^9 / (price * 10^exponent) = micros * 10^(3 - exponent) / price
+    let scale = 3i32.checked_sub(exponent).ok_or(PresaleError::MathOverflow)?;
+    let ten = 10u128;
+    let lamports = if scale >= 0 {
+        (usd_micros as u128)
+            .checked_mul(ten.checked_pow(scale as u32).ok_or(PresaleError::MathOverflow)?)
+            .ok_or(PresaleError::MathOverflow)?
+            / price
+    } else {
+        (usd_micros as u128)
+            / price
+                .checked_mul(ten.checked_pow(scale.unsigned_abs()).ok_or(PresaleError::MathOverflow)?)
+                .ok_or(PresaleError::MathOverflow)?
+    };
+    require!(lamports > 0, PresaleError::InvalidOraclePrice);
+
+    u64::try_from(lamports).map_err(|_| PresaleError::MathOverflow.into())
+}
+
+/// Checks a purchase against the hardcap. Without a ticket, only unreserved headroom can be bought;
+/// with a ticket, the purchase consumes that ticket's reservation (the ticket is closed by the caller).
+fn enforce_hardcap(
+    presale: &mut Presale,
+    ticket: Option<&Account<PurchaseTicket>>,
+    tokens_raw: u64,
+) -> Result<()> {
+    let reserved_for_others = match ticket {
+        Some(ticket) => {
+            require!(
+                Clock::get()?.unix_timestamp < ticket.expires_at,
+                PresaleError::TicketExpired
+            );
+            require!(tokens_raw <= ticket.tokens, PresaleError::ExceedsReservation);
+            presale.reserved_tokens.saturating_sub(ticket.tokens)
+        }
+        None => presale.reserved_tokens,
+    };
+
+    require!(
+        presale
+            .total_sold
+            .saturating_add(presale.provisional_tokens)
+            .saturating_add(reserved_for_others)
+            .saturating_add(tokens_raw)
+            <= presale.hardcap_tokens,
+        PresaleError::HardcapReached
+    );
+
+    presale.reserved_tokens = reserved_for_others;
+
+    Ok(())
+}
+
+/// Releases a refunded buyer's whole allocation (purchased, bonus and referral tokens) back to the sale,
+/// along with the commissions their purchases earned referrers, and returns the purchased tokens released.
+/// `referrer_accounts` holds the `BuyerAccount` of each referrer of the buyer's purchases, in any order.
+/// The caller returns the escrowed payments.
+fn release_refunded_allocation<'info>(
+    presale: &mut Presale,
+    buyer_account: &mut BuyerAccount,
+    registry_page: &AccountLoader<BuyerRegistryPage>,
+    referrer_accounts: &'info [AccountInfo<'info>],
+) -> Result<u64> {
+    let mut referrers = Vec::with_capacity(referrer_accounts.len());
+    for referrer_info in referrer_accounts {
+        let referrer_account = Account::<BuyerAccount>::try_from(referrer_info)?;
+        require!(
+            referrer_account.presale == buyer_account.presale
+                && referrers.iter().all(|other: &Account<BuyerAccount>| other.key() != referrer_info.key()),
+            PresaleError::InvalidReferrer
+        );
+        referrers.push(referrer_account);
+    }
+    let buyer = buyer_account.buyer;
+    for record in buyer_account
+        .purchases
+        .iter_mut()
+        .filter(|record| !record.reversed && record.referral_commission > 0)
+    {
+        let referrer_account = referrers
+            .iter_mut()
+            .find(|referrer_account| referrer_account.buyer == record.referrer)
+            .ok_or(PresaleError::ReferrerAccountRequired)?;
+        reverse_referral_commission(presale, referrer_account, buyer, record.referral_commission)?;
+        record.referral_commission = 0; // Taken back once, whichever refund releases the allocation
+    }
+    for referrer_account in &referrers {
+        referrer_account.exit(&crate::ID)?;
+    }
+
+    let tokens_released = buyer_account.tokens_purchased;
+    presale.total_sold = presale.total_sold.saturating_sub(tokens_released);
+    presale.total_bonus = presale.total_bonus.saturating_sub(buyer_account.bonus_tokens);
+    presale.total_referral = presale.total_referral.saturating_sub(buyer_account.referral_tokens);
+    buyer_account.tokens_purchased = 0;
+    buyer_account.bonus_tokens = 0;
+    buyer_account.referral_tokens = 0;
+    buyer_account.private_round_tokens = 0;
+    buyer_account.public_round_tokens = 0;
+    buyer_account.config_version = presale.config_version;
+    sync_buyer_registry(presale, buyer_account, registry_page, false)?;
+    Ok(tokens_released)
+}
+
+/// Keeps the buyer registry in sync with a buyer's allocation after a purchase.
+/// New buyers are appended to the current (last) page and counted; returning buyers have their entry updated.
+fn sync_buyer_registry(
+    presale: &mut Presale,
+    buyer_account: &mut BuyerAccount,
+    registry_page: &AccountLoader<BuyerRegistryPage>,
+    first_purchase: bool,
+) -> Result<()> {
+    let mut page = registry_page.load_mut()?;
+
+    if first_purchase {
+        require!(
+            page.page_index + 1 == presale.registry_page_count,
+            PresaleError::InvalidRegistryPage
+        );
+        buyer_account.registry_page = page.page_index;
+        buyer_account.registry_index = page.append(buyer_account.buyer, buyer_account.tokens_purchased)?;
+        presale.unique_buyers = presale.unique_buyers.checked_add(1).ok_or(PresaleError::MathOverflow)?;
+    } else {
+        require!(
+            page.page_index == buyer_account.registry_page,
+            PresaleError::InvalidRegistryPage
+        );
+        page.entries[buyer_account.registry_index as usize].amount = buyer_account.tokens_purchased;
+    }
+
+    Ok(())
+}
+
+/// Clears a closing buyer's registry entry and stops counting them. The entry is zeroed in place, so
+/// other buyers keep their indices; accounts that never made it into the registry are left alone.
+fn remove_from_buyer_registry(
+    presale: &mut Presale,
+    buyer_account: &BuyerAccount,
+    registry_page: &AccountLoader<BuyerRegistryPage>,
+) -> Result<()> {
+    let mut page = registry_page.load_mut()?;
+    require!(
+        page.page_index == buyer_account.registry_page,
+        PresaleError::InvalidRegistryPage
+    );
+
+    let entry = &mut page.entries[buyer_account.registry_index as usize];
+    if entry.buyer == buyer_account.buyer {
+        *entry = BuyerRegistryEntry { buyer: Pubkey::default(), amount: 0 };
+        presale.unique_buyers = presale.unique_buyers.saturating_sub(1);
+    }
+
+    Ok(())
+}
+
+/// Accounts for the `initialize` instruction.
+#[derive(Accounts)]
+#[instruction(usd_price_micros_per_nlov: u64, sol_price_lamports_per_nlov: u64, private_sale_duration_days: i64, public_sale_duration_days: i64, hardcap_tokens: u64, immutable_pricing: bool, max_end_timestamp: i64)]
+pub struct Initialize<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>, // Admin who deploys the contract
+
+    #[account(
+        init,
+        payer = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()], // Derive Presale PDA
+        bump,
+        space = Presale::LEN,
+    )]
+    pub presale: Account<'info, Presale>, 
+
+    #[account(
+        init,
+        payer = admin,
+        space = BuyerRegistryPage::LEN,
+        seeds = [REGISTRY_SEED, presale.key().as_ref(), &0u32.to_le_bytes()],
+        bump
+    )]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // First page of the buyer registry
+
+    pub token_mint: Account<'info, Mint>, 
+
+    #[account(init, payer = admin, token::mint = token_mint, token::authority = presale)]
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    /// CHECK: This is a system account for receiving SOL. No specific constraints needed beyond its address.
+    #[account(mut)]
+    pub merchant_wallet: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [ESCROW_SEED, presale.key().as_ref()],
+        bump
+    )]
+    pub escrow: SystemAccount<'info>, // Funded with its rent-exempt minimum so payments of any size can land in it
+
+    pub fee_authority: Option<Signer<'info>>, // Launchpad hosting the sale, co-signing to be able to set a protocol fee
+
+    pub system_program: Program<'info, System>, 
+    pub token_program: Program<'info, Token>,   
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+/// Accounts for the `clone_sale` instruction.
+#[derive(Accounts)]
+pub struct CloneSale<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>, // Admin of the new sale
+
+    #[account(
+        seeds = [PRESALE_SEED, template_sale.admin.as_ref()],
+        bump = template_sale.bump,
+    )]
+    pub template_sale: Account<'info, Presale>,
+
+    #[account(
+        init,
+        payer = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump,
+        space = Presale::LEN,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = BuyerRegistryPage::LEN,
+        seeds = [REGISTRY_SEED, presale.key().as_ref(), &0u32.to_le_bytes()],
+        bump
+    )]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // First page of the buyer registry
+
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(init, payer = admin, token::mint = token_mint, token::authority = presale)]
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    /// CHECK: This is a system account for receiving SOL. No specific constraints needed beyond its address.
+    #[account(mut)]
+    pub merchant_wallet: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [ESCROW_SEED, presale.key().as_ref()],
+        bump
+    )]
+    pub escrow: SystemAccount<'info>, // Funded with its rent-exempt minimum so payments of any size can land in it
+
+    pub fee_authority: Option<Signer<'info>>, // Launchpad hosting the sale, co-signing to be able to set a protocol fee
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+/// Accounts for the `set_stage` instruction.
+#[derive(Accounts)]
+pub struct SetStage<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>, // Only admin can change sale stage
+
+    #[account(
+        mut,
+        has_one = admin, // Ensures the stored presale.admin matches the Signer
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `update_sale_period` instruction.
+#[derive(Accounts)]
+pub struct UpdateSalePeriod<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>, // Only the admin can update the sale period
+
+    #[account(
+        mut,
+        has_one = admin, // Ensures only the admin can update the sale period
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `buy_tokens` instruction (SOL payment).
+#[derive(Accounts)]
+pub struct BuyTokens<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>, // The user buying tokens
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump,
+    )]
+    pub presale: Account<'info, Presale>, 
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = BuyerAccount::LEN,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>, // Buyer's allocation record
+
+    #[account(mut, has_one = presale)]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // Current page for new buyers, own page otherwise
+
+    #[account(mut, close = buyer, has_one = presale, has_one = buyer)]
+    pub ticket: Option<Account<'info, PurchaseTicket>>, // Optional reservation, consumed by this purchase
+
+    /// CHECK: Validated by `check_identity` against the configured verifier
+    pub attestation: Option<UncheckedAccount<'info>>, // Required when an identity verifier is configured
+
+    #[account(address = presale.identity_verifier)]
+    /// CHECK: Checked by presale.identity_verifier
+    pub identity_verifier_program: Option<UncheckedAccount<'info>>, // Required in CPI identity mode
+
+    #[account(address = sysvar::instructions::ID)]
+    /// CHECK: The instructions sysvar
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>, // Required while a KYC authority is set
+
+    #[account(mut, seeds = [JOURNAL_SEED, presale.key().as_ref()], bump)]
+    pub purchase_journal: Option<AccountLoader<'info, PurchaseJournal>>, // Required once the journal exists
+
+    #[account(
+        mut,
+        has_one = presale,
+        seeds = [BUYER_SEED, presale.key().as_ref(), referrer_account.buyer.as_ref()],
+        bump = referrer_account.bump,
+    )]
+    pub referrer_account: Option<Account<'info, BuyerAccount>>, // Referrer's buyer account, if the purchase was referred
+
+    #[account(
+        seeds = [WHITELIST_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = whitelist_entry.bump,
+    )]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>, // Buyer's whitelist entry, instead of a merkle proof
+
+    #[account(seeds = [BLACKLIST_SEED, presale.key().as_ref(), buyer.key().as_ref()], bump)]
+    /// CHECK: Only read by `check_not_blacklisted`; may not exist
+    pub blacklist: Option<UncheckedAccount<'info>>, // Required once any wallet is blacklisted
+
+    #[account(address = presale.gatekeeper)]
+    pub gatekeeper: Option<Signer<'info>>, // Co-signs public-round purchases while a gatekeeper is set
+
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [COMMITMENT_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = purchase_commitment.bump,
+    )]
+    pub purchase_commitment: Option<Account<'info, PurchaseCommitment>>, // Revealed commitment, consumed in commit-reveal mode
+
+    #[account(address = presale.sol_usd_price_feed)]
+    /// CHECK: Parsed by `current_sol_price` after checking its owner
+    pub sol_usd_price_update: Option<UncheckedAccount<'info>>, // Required once a SOL/USD price feed is configured
+
+    #[account(address = presale.sol_usd_fallback_feed)]
+    /// CHECK: Parsed by `current_sol_price` after checking its owner
+    pub sol_usd_fallback_feed: Option<UncheckedAccount<'info>>, // Switchboard price used while the Pyth price is unavailable
+
+    #[account(address = presale.sol_usd_twap_observation)]
+    /// CHECK: Parsed by `current_sol_price` after checking its owner
+    pub sol_usd_twap_observation: Option<UncheckedAccount<'info>>, // Pool observations pricing public-round SOL buys once a TWAP is configured
+
+    #[account(mut, token::mint = WSOL_MINT, token::authority = buyer)]
+    pub buyer_wsol_account: Option<Account<'info, TokenAccount>>, // Required for wrapped SOL payments
+
+    #[account(mut, address = get_associated_token_address(&escrow.key(), &WSOL_MINT))]
+    /// CHECK: The escrow's wSOL account, created and closed within the purchase to unwrap the payment
+    pub escrow_wsol_account: Option<UncheckedAccount<'info>>, // Required for wrapped SOL payments
+
+    #[account(address = WSOL_MINT)]
+    pub wsol_mint: Option<Account<'info, Mint>>, // Required for wrapped SOL payments
+
+    #[account(mut, address = presale.fee_wallet)]
+    /// CHECK: Checked by presale.fee_wallet
+    pub fee_wallet: Option<UncheckedAccount<'info>>, // Required for on-chain payments while a protocol fee is set
+
+    #[account(mut, address = presale.presale_wallet)]
+    pub presale_wallet: Account<'info, TokenAccount>, 
+
+    #[account(
+        mut,
+        seeds = [ESCROW_SEED, presale.key().as_ref()],
+        bump = presale.escrow_bump,
+    )]
+    pub escrow: SystemAccount<'info>, // Holds SOL proceeds until they are withdrawn
+
+    #[account(address = presale_wallet.mint)]
+    pub token_mint: Account<'info, Mint>, // To get token decimals for calculations
+
+    pub system_program: Program<'info, System>, 
+    pub token_program: Program<'info, Token>,   
+    pub associated_token_program: Program<'info, AssociatedToken>, 
+}
+
+/// Accounts for the `check_presale_token_balance` instruction.
+#[derive(Accounts)]
+pub struct CheckPresaleTokenBalance<'info> {
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump,
+    )]
+    pub presale: Account<'info, Presale>, // Presale storage PDA
+
+    #[account()]
+    pub presale_wallet: Account<'info, TokenAccount>, // Store presale tokens
+
+    pub token_mint: Account<'info, Mint>, // To get token decimals for calculations
+}
+
+/// Accounts for the `update_sale_price` instruction.
+#[derive(Accounts)]
+pub struct UpdateSalePrice<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>, // Only the admin can update the price
+
+    #[account(
+        mut,
+        has_one = admin, // Ensures only the admin can update
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `schedule_price_update` instruction.
+#[derive(Accounts)]
+pub struct SchedulePriceUpdate<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_whitelist_discount` instruction.
+#[derive(Accounts)]
+pub struct SetWhitelistDiscount<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_token_rounding_mode` instruction.
+#[derive(Accounts)]
+pub struct SetTokenRoundingMode<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_price_change_delay` instruction.
+#[derive(Accounts)]
+pub struct SetPriceChangeDelay<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_price_guardrails` instruction.
+#[derive(Accounts)]
+pub struct SetPriceGuardrails<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `configure_sol_oracle` instruction.
+#[derive(Accounts)]
+pub struct ConfigureSolOracle<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `configure_sol_twap` instruction.
+#[derive(Accounts)]
+pub struct ConfigureSolTwap<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_volume_pricing` instruction.
+#[derive(Accounts)]
+pub struct SetVolumePricing<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_public_price_ramp` instruction.
+#[derive(Accounts)]
+pub struct SetPublicPriceRamp<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_discount_tiers` instruction.
+#[derive(Accounts)]
+pub struct SetDiscountTiers<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `buy_tokens_by_stable_coin` instruction.
+#[derive(Accounts)]
+pub struct BuyTokensByStableCoin<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>, // The user buying tokens
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump,
+    )]
+    pub presale: Account<'info, Presale>, // Presale storage PDA
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = BuyerAccount::LEN,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>, // Buyer's allocation record
+
+    #[account(mut, has_one = presale)]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // Current page for new buyers, own page otherwise
+
+    #[account(mut, close = buyer, has_one = presale, has_one = buyer)]
+    pub ticket: Option<Account<'info, PurchaseTicket>>, // Optional reservation, consumed by this purchase
+
+    /// CHECK: Validated by `check_identity` against the configured verifier
+    pub attestation: Option<UncheckedAccount<'info>>, // Required when an identity verifier is configured
+
+    #[account(address = presale.identity_verifier)]
+    /// CHECK: Checked by presale.identity_verifier
+    pub identity_verifier_program: Option<UncheckedAccount<'info>>, // Required in CPI identity mode
+
+    #[account(address = sysvar::instructions::ID)]
+    /// CHECK: The instructions sysvar
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>, // Required while a KYC authority is set
+
+    #[account(mut, seeds = [JOURNAL_SEED, presale.key().as_ref()], bump)]
+    pub purchase_journal: Option<AccountLoader<'info, PurchaseJournal>>, // Required once the journal exists
+
+    #[account(
+        mut,
+        has_one = presale,
+        seeds = [BUYER_SEED, presale.key().as_ref(), referrer_account.buyer.as_ref()],
+        bump = referrer_account.bump,
+    )]
+    pub referrer_account: Option<Account<'info, BuyerAccount>>, // Referrer's buyer account, if the purchase was referred
+
+    #[account(
+        seeds = [WHITELIST_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = whitelist_entry.bump,
+    )]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>, // Buyer's whitelist entry, instead of a merkle proof
+
+    #[account(seeds = [BLACKLIST_SEED, presale.key().as_ref(), buyer.key().as_ref()], bump)]
+    /// CHECK: Only read by `check_not_blacklisted`; may not exist
+    pub blacklist: Option<UncheckedAccount<'info>>, // Required once any wallet is blacklisted
+
+    #[account(address = presale.gatekeeper)]
+    pub gatekeeper: Option<Signer<'info>>, // Co-signs public-round purchases while a gatekeeper is set
+
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [COMMITMENT_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = purchase_commitment.bump,
+    )]
+    pub purchase_commitment: Option<Account<'info, PurchaseCommitment>>, // Revealed commitment, consumed in commit-reveal mode
+
+    #[account(mut, address = presale.presale_wallet)]
+    pub presale_wallet: Account<'info, TokenAccount>, // Presale token storage
+
+    #[account(mut)]
+    pub buyer_stable_coin_account: InterfaceAccount<'info, InterfaceTokenAccount>, // Buyer’s stablecoin account
+
+    /// CHECK: Escrow PDA, only used as the authority of the stablecoin vault
+    #[account(
+        seeds = [ESCROW_SEED, presale.key().as_ref()],
+        bump = presale.escrow_bump,
+    )]
+    pub escrow: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = stable_coin_mint,
+        associated_token::authority = escrow,
+        associated_token::token_program = token_program,
+    )]
+    pub escrow_stable_coin_account: InterfaceAccount<'info, InterfaceTokenAccount>, // Escrow vault for this stablecoin
+
+    #[account(mint::token_program = token_program)]
+    pub stable_coin_mint: InterfaceAccount<'info, InterfaceMint>, // Stablecoin mint (USDC, USDT or PYUSD)
+
+    #[account(
+        mut,
+        token::mint = stable_coin_mint,
+        token::authority = presale.fee_wallet,
+        token::token_program = token_program,
+    )]
+    pub fee_stable_coin_account: Option<InterfaceAccount<'info, InterfaceTokenAccount>>, // Required for Web3 payments while a protocol fee is set
+
+    #[account(
+        has_one = presale,
+        constraint = accepted_currency.mint == stable_coin_mint.key() @ PresaleError::InvalidStableToken,
+        seeds = [ACCEPTED_CURRENCY_SEED, presale.key().as_ref(), &[accepted_currency.currency]],
+        bump = accepted_currency.bump,
+    )]
+    pub accepted_currency: Account<'info, AcceptedCurrency>, // Registry entry for the stablecoin mint
+
+    #[account(address = presale_wallet.mint)]
+    pub token_mint: Account<'info, Mint>, 
+
+    pub token_program: Interface<'info, TokenInterface>, // SPL Token, or Token-2022 for PYUSD
+    pub associated_token_program: Program<'info, AssociatedToken>, 
+    pub system_program: Program<'info, System>, 
+}
+
+/// Accounts for the `buy_tokens_with_token` instruction.
+#[derive(Accounts)]
+pub struct BuyTokensWithToken<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = BuyerAccount::LEN,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(mut, has_one = presale)]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // Current page for new buyers, own page otherwise
+
+    #[account(mut, close = buyer, has_one = presale, has_one = buyer)]
+    pub ticket: Option<Account<'info, PurchaseTicket>>, // Optional reservation, consumed by this purchase
+
+    /// CHECK: Validated by `check_identity` against the configured verifier
+    pub attestation: Option<UncheckedAccount<'info>>, // Required when an identity verifier is configured
+
+    #[account(address = presale.identity_verifier)]
+    /// CHECK: Checked by presale.identity_verifier
+    pub identity_verifier_program: Option<UncheckedAccount<'info>>, // Required in CPI identity mode
+
+    #[account(address = sysvar::instructions::ID)]
+    /// CHECK: The instructions sysvar
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>, // Required while a KYC authority is set
+
+    #[account(mut, seeds = [JOURNAL_SEED, presale.key().as_ref()], bump)]
+    pub purchase_journal: Option<AccountLoader<'info, PurchaseJournal>>, // Required once the journal exists
+
+    #[account(
+        mut,
+        has_one = presale,
+        seeds = [BUYER_SEED, presale.key().as_ref(), referrer_account.buyer.as_ref()],
+        bump = referrer_account.bump,
+    )]
+    pub referrer_account: Option<Account<'info, BuyerAccount>>, // Referrer's buyer account, if the purchase was referred
+
+    #[account(
+        seeds = [WHITELIST_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = whitelist_entry.bump,
+    )]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>, // Buyer's whitelist entry, instead of a merkle proof
+
+    #[account(seeds = [BLACKLIST_SEED, presale.key().as_ref(), buyer.key().as_ref()], bump)]
+    /// CHECK: Only read by `check_not_blacklisted`; may not exist
+    pub blacklist: Option<UncheckedAccount<'info>>, // Required once any wallet is blacklisted
+
+    #[account(address = presale.gatekeeper)]
+    pub gatekeeper: Option<Signer<'info>>, // Co-signs public-round purchases while a gatekeeper is set
+
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [COMMITMENT_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = purchase_commitment.bump,
+    )]
+    pub purchase_commitment: Option<Account<'info, PurchaseCommitment>>, // Revealed commitment, consumed in commit-reveal mode
+
+    #[account(address = presale.presale_wallet)]
+    pub presale_wallet: Account<'info, TokenAccount>, // Presale token storage
+
+    #[account(
+        mut,
+        has_one = presale,
+        seeds = [PAYMENT_TOKEN_SEED, presale.key().as_ref(), payment_mint.key().as_ref()],
+        bump = payment_token.bump,
+    )]
+    pub payment_token: Account<'info, PaymentToken>, // Registry entry for the payment mint
+
+    pub payment_mint: Account<'info, Mint>,
+
+    #[account(address = payment_token.price_feed)]
+    /// CHECK: Parsed by `read_payment_token_price` after checking its owner
+    pub price_update: UncheckedAccount<'info>, // The payment token's Pyth price update account
+
+    #[account(mut, token::mint = payment_mint, token::authority = buyer)]
+    pub buyer_payment_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Escrow PDA, only used as the authority of the payment token vault
+    #[account(
+        seeds = [ESCROW_SEED, presale.key().as_ref()],
+        bump = presale.escrow_bump,
+    )]
+    pub escrow: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = payment_mint,
+        associated_token::authority = escrow,
+    )]
+    pub escrow_payment_account: Account<'info, TokenAccount>, // Escrow vault for this payment token
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = TokenContribution::LEN,
+        seeds = [TOKEN_CONTRIBUTION_SEED, presale.key().as_ref(), payment_mint.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub token_contribution: Account<'info, TokenContribution>, // Buyer's refundable payments in this token
+
+    #[account(mut, token::mint = payment_mint, token::authority = presale.fee_wallet)]
+    pub fee_payment_account: Option<Account<'info, TokenAccount>>, // Required while a protocol fee is set
+
+    #[account(address = presale_wallet.mint)]
+    pub token_mint: Account<'info, Mint>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `finalize_presale` instruction.
+#[derive(Accounts)]
+pub struct FinalizePresale<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(mut, address = presale.presale_wallet)]
+    pub presale_wallet: Account<'info, TokenAccount>, 
+
+    #[account(mut)]
+    pub liquidity_wallet: Account<'info, TokenAccount>, 
+
+    #[account(address = presale_wallet.mint)]
+    pub token_mint: Account<'info, Mint>, 
+
+    pub token_program: Program<'info, Token>, 
+}
+
+/// Accounts for the `mint_purchase_receipt` instruction.
+#[derive(Accounts)]
+pub struct MintPurchaseReceipt<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>, // Buyer receiving the receipt, pays for its accounts
+
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = buyer,
+        has_one = presale,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = buyer_account.bump
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(
+        init,
+        payer = buyer,
+        seeds = [RECEIPT_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = presale,
+        mint::freeze_authority = presale,
+    )]
+    pub receipt_mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = buyer,
+        associated_token::mint = receipt_mint,
+        associated_token::authority = buyer,
+    )]
+    pub receipt_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Created and validated by the token metadata program
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), receipt_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: Created and validated by the token metadata program
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), receipt_mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    pub master_edition: UncheckedAccount<'info>,
+
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+/// Accounts for the `settle_period` instruction.
+#[derive(Accounts)]
+pub struct SettlePeriod<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        seeds = [ESCROW_SEED, presale.key().as_ref()],
+        bump = presale.escrow_bump,
+    )]
+    pub escrow: SystemAccount<'info>,
+
+    #[account(mut, address = presale.merchant_wallet)]
+    /// CHECK: Checked by presale.merchant_wallet
+    pub merchant_wallet: AccountInfo<'info>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = Settlement::LEN,
+        seeds = [SETTLEMENT_SEED, presale.key().as_ref(), &presale.settlement_count.to_le_bytes()],
+        bump
+    )]
+    pub settlement: Account<'info, Settlement>,
+
+    #[account(address = presale.usdc_mint)]
+    pub usdc_mint: Option<InterfaceAccount<'info, InterfaceMint>>, // Required if USDC is escrowed
+
+    #[account(mut, associated_token::mint = presale.usdc_mint, associated_token::authority = escrow)]
+    pub escrow_usdc_account: Option<Account<'info, TokenAccount>>, // Required if USDC is escrowed
+
+    #[account(mut, token::mint = presale.usdc_mint, token::authority = presale.merchant_wallet)]
+    pub merchant_usdc_account: Option<Account<'info, TokenAccount>>, // Required if USDC is escrowed
+
+    #[account(address = presale.usdt_mint)]
+    pub usdt_mint: Option<InterfaceAccount<'info, InterfaceMint>>, // Required if USDT is escrowed
+
+    #[account(mut, associated_token::mint = presale.usdt_mint, associated_token::authority = escrow)]
+    pub escrow_usdt_account: Option<Account<'info, TokenAccount>>, // Required if USDT is escrowed
+
+    #[account(mut, token::mint = presale.usdt_mint, token::authority = presale.merchant_wallet)]
+    pub merchant_usdt_account: Option<Account<'info, TokenAccount>>, // Required if USDT is escrowed
+
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(&escrow.key(), &presale.pyusd_mint, &token_2022::ID),
+    )]
+    pub escrow_pyusd_account: Option<InterfaceAccount<'info, InterfaceTokenAccount>>, // Required if PYUSD is escrowed
+
+    #[account(mut, token::mint = presale.pyusd_mint, token::authority = presale.merchant_wallet)]
+    pub merchant_pyusd_account: Option<InterfaceAccount<'info, InterfaceTokenAccount>>, // Required if PYUSD is escrowed
+
+    #[account(address = presale.pyusd_mint)]
+    pub pyusd_mint: Option<InterfaceAccount<'info, InterfaceMint>>, // Required if PYUSD is escrowed
+
+    pub token_2022_program: Option<Program<'info, Token2022>>, // Required if PYUSD is escrowed
+    pub token_program: Program<'info, Token>, // Also settles registered payment tokens
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `create_registry_page` instruction.
+#[derive(Accounts)]
+pub struct CreateRegistryPage<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        has_one = presale,
+        seeds = [REGISTRY_SEED, presale.key().as_ref(), &(presale.registry_page_count - 1).to_le_bytes()],
+        bump
+    )]
+    pub current_page: AccountLoader<'info, BuyerRegistryPage>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = BuyerRegistryPage::LEN,
+        seeds = [REGISTRY_SEED, presale.key().as_ref(), &presale.registry_page_count.to_le_bytes()],
+        bump
+    )]
+    pub new_page: AccountLoader<'info, BuyerRegistryPage>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `get_escrow_balances` instruction.
+#[derive(Accounts)]
+pub struct GetEscrowBalances<'info> {
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        seeds = [ESCROW_SEED, presale.key().as_ref()],
+        bump = presale.escrow_bump,
+    )]
+    pub escrow: SystemAccount<'info>,
+
+    #[account(associated_token::mint = presale.usdc_mint, associated_token::authority = escrow)]
+    pub escrow_usdc_account: Option<Account<'info, TokenAccount>>, // Omit if no USDC was ever escrowed
+
+    #[account(associated_token::mint = presale.usdt_mint, associated_token::authority = escrow)]
+    pub escrow_usdt_account: Option<Account<'info, TokenAccount>>, // Omit if no USDT was ever escrowed
+
+    #[account(address = get_associated_token_address_with_program_id(&escrow.key(), &presale.pyusd_mint, &token_2022::ID))]
+    pub escrow_pyusd_account: Option<InterfaceAccount<'info, InterfaceTokenAccount>>, // Omit if no PYUSD was ever escrowed
+}
+
+/// Accounts for the `withdraw_proceeds` instruction.
+#[derive(Accounts)]
+pub struct WithdrawProceeds<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        seeds = [ESCROW_SEED, presale.key().as_ref()],
+        bump = presale.escrow_bump,
+    )]
+    pub escrow: SystemAccount<'info>,
+
+    #[account(mut, address = presale.merchant_wallet)]
+    /// CHECK: Checked by presale.merchant_wallet
     pub merchant_wallet: AccountInfo<'info>,
 
-    pub system_program: Program<'info, System>, 
-    pub token_program: Program<'info, Token>,   
-    pub associated_token_program: Program<'info, AssociatedToken>,
+    #[account(mut, token::authority = escrow)]
+    pub escrow_stable_coin_account: Option<InterfaceAccount<'info, InterfaceTokenAccount>>, // Required for stablecoin withdrawals
+
+    #[account(mut, token::authority = presale.merchant_wallet)]
+    pub merchant_stable_coin_account: Option<InterfaceAccount<'info, InterfaceTokenAccount>>, // Required for stablecoin withdrawals
+
+    pub stable_coin_mint: Option<InterfaceAccount<'info, InterfaceMint>>, // Required for stablecoin withdrawals
+
+    #[account(mut, has_one = presale)]
+    pub payment_token: Option<Account<'info, PaymentToken>>, // Required to withdraw a payment token (CURRENCY_TOKEN)
+
+    pub token_program: Interface<'info, TokenInterface>, // SPL Token, or Token-2022 for PYUSD
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `configure_vesting` instruction.
+#[derive(Accounts)]
+pub struct ConfigureVesting<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        has_one = presale_wallet,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    #[account(token::mint = presale_wallet.mint)]
+    pub penalty_wallet: Option<Account<'info, TokenAccount>>, // Treasury for forfeited tokens; omit to burn them
+}
+
+/// Accounts for the `configure_round_vesting` instruction.
+#[derive(Accounts)]
+pub struct ConfigureRoundVesting<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_vesting_milestones` instruction.
+#[derive(Accounts)]
+pub struct SetVestingMilestones<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = VestingSchedule::LEN,
+        seeds = [VESTING_SCHEDULE_SEED, presale.key().as_ref()],
+        bump
+    )]
+    pub vesting_schedule: Account<'info, VestingSchedule>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `set_buyer_vesting_schedule` instruction.
+#[derive(Accounts)]
+pub struct SetBuyerVestingSchedule<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer_account.buyer.as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = VestingSchedule::LEN,
+        seeds = [VESTING_SCHEDULE_SEED, presale.key().as_ref(), buyer_account.buyer.as_ref()],
+        bump
+    )]
+    pub vesting_schedule: Account<'info, VestingSchedule>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `migrate_vesting_schedule` instruction.
+#[derive(Accounts)]
+pub struct MigrateVestingSchedule<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>, // Pays any additional rent
+
+    #[account(
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(mut, owner = crate::ID)]
+    /// CHECK: Deserialized by the handler after the realloc, since older layouts may not deserialize as is
+    pub vesting_schedule: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `migrate_usd_pricing` instruction.
+#[derive(Accounts)]
+pub struct MigrateUsdPricing<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>, // Pays any additional rent
+
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    /// CHECK: Deserialized by the handler after the realloc, since older layouts may not deserialize as is
+    pub presale: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [ESCROW_SEED, presale.key().as_ref()],
+        bump
+    )]
+    pub escrow: SystemAccount<'info>, // Recorded, and funded with its rent-exempt minimum, for a baseline sale
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = BuyerRegistryPage::LEN,
+        seeds = [REGISTRY_SEED, presale.key().as_ref(), &0u32.to_le_bytes()],
+        bump
+    )]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // First page of the buyer registry, opened for a baseline sale
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `claim_tokens` instruction.
+#[derive(Accounts)]
+pub struct ClaimTokens<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = presale_wallet,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        has_one = buyer,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(mut)]
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    #[account(address = presale_wallet.mint)]
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(address = buyer_account.claim_recipient)]
+    /// CHECK: Checked against buyer_account.claim_recipient
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = token_mint,
+        associated_token::authority = recipient,
+    )]
+    pub recipient_token_account: Account<'info, TokenAccount>, // Receives the claimed tokens
+
+    #[account(has_one = presale)]
+    pub vesting_schedule: Option<Account<'info, VestingSchedule>>, // The buyer's override, or the sale's milestones; required with either
+
+    #[account(mut, seeds = [VESTING_STATS_SEED, presale.key().as_ref()], bump = vesting_stats.bump)]
+    pub vesting_stats: Option<Account<'info, VestingStats>>, // Required once vesting stats exist
+
+    #[account(seeds = [BLACKLIST_SEED, presale.key().as_ref(), buyer.key().as_ref()], bump)]
+    /// CHECK: Only read by `check_not_blacklisted`; may not exist
+    pub blacklist: Option<UncheckedAccount<'info>>, // Required once any wallet is blacklisted
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `claim_and_stake` instruction.
+#[derive(Accounts)]
+pub struct ClaimAndStake<'info> {
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = presale_wallet,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        has_one = buyer,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(mut)]
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        address = presale.staking_vault @ PresaleError::InvalidStakingVault,
+        token::mint = presale_wallet.mint,
+    )]
+    pub staking_vault: Account<'info, TokenAccount>, // Receives the staked tokens; pinned by configure_staking
+
+    #[account(mut)]
+    /// CHECK: The buyer's stake account, validated by the staking program
+    pub stake_account: UncheckedAccount<'info>,
+
+    #[account(address = presale.staking_program)]
+    /// CHECK: Checked against presale.staking_program
+    pub staking_program: UncheckedAccount<'info>,
+
+    #[account(has_one = presale)]
+    pub vesting_schedule: Option<Account<'info, VestingSchedule>>, // The buyer's override, or the sale's milestones; required with either
+
+    #[account(mut, seeds = [VESTING_STATS_SEED, presale.key().as_ref()], bump = vesting_stats.bump)]
+    pub vesting_stats: Option<Account<'info, VestingStats>>, // Required once vesting stats exist
+
+    #[account(seeds = [BLACKLIST_SEED, presale.key().as_ref(), buyer.key().as_ref()], bump)]
+    /// CHECK: Only read by `check_not_blacklisted`; may not exist
+    pub blacklist: Option<UncheckedAccount<'info>>, // Required once any wallet is blacklisted
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for the `claim_early` instruction.
+#[derive(Accounts)]
+pub struct ClaimEarly<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = presale_wallet,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        has_one = buyer,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(mut)]
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    #[account(mut, address = presale_wallet.mint)]
+    pub token_mint: Account<'info, Mint>, // Mutable so forfeited tokens can be burned
+
+    #[account(address = buyer_account.claim_recipient)]
+    /// CHECK: Checked against buyer_account.claim_recipient
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = token_mint,
+        associated_token::authority = recipient,
+    )]
+    pub recipient_token_account: Account<'info, TokenAccount>, // Receives the claimed tokens
+
+    #[account(mut, address = presale.penalty_wallet)]
+    pub penalty_wallet: Option<Account<'info, TokenAccount>>, // Required unless forfeited tokens are burned
+
+    #[account(has_one = presale)]
+    pub vesting_schedule: Option<Account<'info, VestingSchedule>>, // The buyer's override, or the sale's milestones; required with either
+
+    #[account(mut, seeds = [VESTING_STATS_SEED, presale.key().as_ref()], bump = vesting_stats.bump)]
+    pub vesting_stats: Option<Account<'info, VestingStats>>, // Required once vesting stats exist
+
+    #[account(seeds = [BLACKLIST_SEED, presale.key().as_ref(), buyer.key().as_ref()], bump)]
+    /// CHECK: Only read by `check_not_blacklisted`; may not exist
+    pub blacklist: Option<UncheckedAccount<'info>>, // Required once any wallet is blacklisted
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `set_claim_recipient` instruction.
+#[derive(Accounts)]
+pub struct SetClaimRecipient<'info> {
+    pub buyer: Signer<'info>,
+
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        has_one = buyer,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+}
+
+/// Accounts for the `set_operator` instruction.
+#[derive(Accounts)]
+pub struct SetOperator<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_compliance_note` instruction.
+#[derive(Accounts)]
+pub struct SetComplianceNote<'info> {
+    pub operator: Signer<'info>, // Operator or admin
+
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer_account.buyer.as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+}
+
+/// Accounts for the `set_guardian` instruction.
+#[derive(Accounts)]
+pub struct SetGuardian<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `lockdown` instruction.
+#[derive(Accounts)]
+pub struct Lockdown<'info> {
+    pub admin: Signer<'info>,
+
+    pub guardian: Signer<'info>, // Must co-sign with the admin
+
+    #[account(
+        mut,
+        has_one = admin,
+        has_one = guardian,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `request_lockdown_lift` and `lift_lockdown` instructions.
+#[derive(Accounts)]
+pub struct UpdateLockdown<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `reserve_purchase_ticket` instruction.
+#[derive(Accounts)]
+pub struct ReservePurchaseTicket<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = PurchaseTicket::LEN,
+        seeds = [TICKET_SEED, presale.key().as_ref(), &presale.next_ticket_sequence.to_le_bytes()],
+        bump
+    )]
+    pub ticket: Account<'info, PurchaseTicket>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `release_expired_ticket` instruction.
+#[derive(Accounts)]
+pub struct ReleaseExpiredTicket<'info> {
+    pub payer: Signer<'info>, // Anyone can release an expired ticket
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(mut, close = buyer, has_one = presale, has_one = buyer)]
+    pub ticket: Account<'info, PurchaseTicket>,
+
+    #[account(mut)]
+    pub buyer: SystemAccount<'info>, // Receives the ticket rent
+}
+
+/// Accounts for the `commit_purchase` instruction.
+#[derive(Accounts)]
+pub struct CommitPurchase<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = PurchaseCommitment::LEN,
+        seeds = [COMMITMENT_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub purchase_commitment: Account<'info, PurchaseCommitment>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `reveal_purchase` instruction.
+#[derive(Accounts)]
+pub struct RevealPurchase<'info> {
+    pub buyer: Signer<'info>,
+
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(mut, has_one = presale, has_one = buyer)]
+    pub purchase_commitment: Account<'info, PurchaseCommitment>,
+}
+
+/// Accounts for the `transfer_allocation` instruction.
+#[derive(Accounts)]
+pub struct TransferAllocation<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>, // Current holder of the allocation, pays for the new account if needed
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        constraint = from_account.buyer == owner.key() @ PresaleError::Unauthorized,
+        seeds = [BUYER_SEED, presale.key().as_ref(), owner.key().as_ref()],
+        bump = from_account.bump,
+    )]
+    pub from_account: Account<'info, BuyerAccount>,
+
+    /// CHECK: Any wallet can receive an allocation
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = BuyerAccount::LEN,
+        seeds = [BUYER_SEED, presale.key().as_ref(), recipient.key().as_ref()],
+        bump
+    )]
+    pub to_account: Account<'info, BuyerAccount>,
+
+    #[account(mut, has_one = presale)]
+    pub from_registry_page: AccountLoader<'info, BuyerRegistryPage>, // Page holding the sender's entry
+
+    #[account(mut, has_one = presale)]
+    pub to_registry_page: AccountLoader<'info, BuyerRegistryPage>, // Current page if the recipient is new, its own page otherwise
+
+    #[account(seeds = [BLACKLIST_SEED, presale.key().as_ref(), owner.key().as_ref()], bump)]
+    /// CHECK: Only read by `check_not_blacklisted`; may not exist
+    pub from_blacklist: Option<UncheckedAccount<'info>>, // Sender's blacklist PDA, required once any wallet is blacklisted
+
+    #[account(seeds = [BLACKLIST_SEED, presale.key().as_ref(), recipient.key().as_ref()], bump)]
+    /// CHECK: Only read by `check_not_blacklisted`; may not exist
+    pub to_blacklist: Option<UncheckedAccount<'info>>, // Recipient's blacklist PDA, required once any wallet is blacklisted
+
+    #[account(
+        seeds = [WHITELIST_SEED, presale.key().as_ref(), recipient.key().as_ref()],
+        bump = to_whitelist_entry.bump,
+    )]
+    pub to_whitelist_entry: Option<Account<'info, WhitelistEntry>>, // Recipient's whitelist entry, instead of a merkle proof
+
+    pub claim_authority: Option<Signer<'info>>, // Required when the sender's allocation has a claim authority
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `get_cap_status` instruction.
+#[derive(Accounts)]
+#[instruction(buyer: Pubkey)]
+pub struct GetCapStatus<'info> {
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        has_one = presale,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>, // Omit if the buyer has not purchased yet
+
+    #[account(address = presale.presale_wallet)]
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    #[account(address = presale_wallet.mint)]
+    pub token_mint: Account<'info, Mint>, // To get token decimals for calculations
+
+    #[account(
+        seeds = [WHITELIST_SEED, presale.key().as_ref(), buyer.as_ref()],
+        bump = whitelist_entry.bump,
+    )]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>, // Buyer's whitelist entry, for their tier cap
+}
+
+/// Accounts for the `quote_purchase` instruction.
+#[derive(Accounts)]
+pub struct QuotePurchase<'info> {
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(address = presale.presale_wallet)]
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    #[account(address = presale_wallet.mint)]
+    pub token_mint: Account<'info, Mint>, // To get token decimals for calculations
+
+    #[account(address = presale.sol_usd_price_feed)]
+    /// CHECK: Parsed by `current_sol_price` after checking its owner
+    pub sol_usd_price_update: Option<UncheckedAccount<'info>>, // Needed to quote SOL once a price feed is configured
+
+    #[account(address = presale.sol_usd_fallback_feed)]
+    /// CHECK: Parsed by `current_sol_price` after checking its owner
+    pub sol_usd_fallback_feed: Option<UncheckedAccount<'info>>,
+
+    #[account(address = presale.sol_usd_twap_observation)]
+    /// CHECK: Parsed by `current_sol_price` after checking its owner
+    pub sol_usd_twap_observation: Option<UncheckedAccount<'info>>,
+
+    #[account(has_one = presale)]
+    pub accepted_currency: Option<Account<'info, AcceptedCurrency>>, // Quoted stablecoin's decimals; 6 when omitted
+
+    /// CHECK: Only its key is read
+    pub buyer: Option<UncheckedAccount<'info>>, // Wallet the quote is for, to include its whitelist discount
+
+    #[account(has_one = presale)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>, // The buyer's whitelist entry, instead of a merkle proof
+
+    #[account(has_one = presale)]
+    pub payment_token: Option<Account<'info, PaymentToken>>, // Required to quote CURRENCY_TOKEN
+
+    /// CHECK: Must be `payment_token.price_feed`; parsed by `read_payment_token_price` after checking its owner
+    pub payment_token_price_update: Option<UncheckedAccount<'info>>, // Required to quote CURRENCY_TOKEN
+}
+
+/// Accounts for the `health_check` instruction.
+#[derive(Accounts)]
+pub struct HealthCheck<'info> {
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(address = presale.presale_wallet)]
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [ESCROW_SEED, presale.key().as_ref()],
+        bump = presale.escrow_bump,
+    )]
+    pub escrow: SystemAccount<'info>,
+
+    #[account(associated_token::mint = presale.usdc_mint, associated_token::authority = escrow)]
+    pub escrow_usdc_account: Option<Account<'info, TokenAccount>>, // Omit to skip the USDC escrow check
+
+    #[account(associated_token::mint = presale.usdt_mint, associated_token::authority = escrow)]
+    pub escrow_usdt_account: Option<Account<'info, TokenAccount>>, // Omit to skip the USDT escrow check
+
+    #[account(address = get_associated_token_address_with_program_id(&escrow.key(), &presale.pyusd_mint, &token_2022::ID))]
+    pub escrow_pyusd_account: Option<InterfaceAccount<'info, InterfaceTokenAccount>>, // Omit to skip the PYUSD escrow check
+
+    #[account(address = presale.sol_usd_price_feed)]
+    /// CHECK: Parsed by `sol_oracle_last_update` after checking its owner
+    pub sol_usd_price_update: Option<UncheckedAccount<'info>>, // Omit to skip the oracle staleness check
+
+    #[account(address = presale.sol_usd_fallback_feed)]
+    /// CHECK: Parsed by `sol_oracle_last_update` after checking its owner
+    pub sol_usd_fallback_feed: Option<UncheckedAccount<'info>>,
+}
+
+/// Accounts for the `set_softcap` instruction.
+#[derive(Accounts)]
+pub struct SetSoftcap<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `cancel_presale` instruction.
+#[derive(Accounts)]
+pub struct CancelPresale<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `claim_refund` instruction.
+#[derive(Accounts)]
+pub struct ClaimRefund<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        has_one = buyer,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(mut, has_one = presale)]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // Page holding the buyer's entry
+
+    #[account(
+        mut,
+        seeds = [ESCROW_SEED, presale.key().as_ref()],
+        bump = presale.escrow_bump,
+    )]
+    pub escrow: SystemAccount<'info>,
+
+    #[account(mut, associated_token::mint = presale.usdc_mint, associated_token::authority = escrow)]
+    pub escrow_usdc_account: Option<Account<'info, TokenAccount>>, // Required if the buyer paid in USDC
+
+    #[account(mut, token::mint = presale.usdc_mint, token::authority = buyer)]
+    pub buyer_usdc_account: Option<Account<'info, TokenAccount>>, // Required if the buyer paid in USDC
+
+    #[account(mut, associated_token::mint = presale.usdt_mint, associated_token::authority = escrow)]
+    pub escrow_usdt_account: Option<Account<'info, TokenAccount>>, // Required if the buyer paid in USDT
+
+    #[account(mut, token::mint = presale.usdt_mint, token::authority = buyer)]
+    pub buyer_usdt_account: Option<Account<'info, TokenAccount>>, // Required if the buyer paid in USDT
+
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(&escrow.key(), &presale.pyusd_mint, &token_2022::ID),
+    )]
+    pub escrow_pyusd_account: Option<InterfaceAccount<'info, InterfaceTokenAccount>>, // Required if the buyer paid in PYUSD
+
+    #[account(mut, token::mint = presale.pyusd_mint, token::authority = buyer)]
+    pub buyer_pyusd_account: Option<InterfaceAccount<'info, InterfaceTokenAccount>>, // Required if the buyer paid in PYUSD
+
+    #[account(address = presale.pyusd_mint)]
+    pub pyusd_mint: Option<InterfaceAccount<'info, InterfaceMint>>, // Required if the buyer paid in PYUSD
+
+    pub token_2022_program: Option<Program<'info, Token2022>>, // Required if the buyer paid in PYUSD
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `claim_token_refund` instruction.
+#[derive(Accounts)]
+pub struct ClaimTokenRefund<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        has_one = buyer,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>, // Allocation released with the first refund
+
+    #[account(mut, has_one = presale)]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // Page holding the buyer's entry
+
+    #[account(
+        mut,
+        has_one = presale,
+        seeds = [PAYMENT_TOKEN_SEED, presale.key().as_ref(), payment_token.mint.as_ref()],
+        bump = payment_token.bump,
+    )]
+    pub payment_token: Account<'info, PaymentToken>,
+
+    #[account(
+        mut,
+        close = buyer,
+        has_one = presale,
+        has_one = buyer,
+        seeds = [TOKEN_CONTRIBUTION_SEED, presale.key().as_ref(), payment_token.mint.as_ref(), buyer.key().as_ref()],
+        bump = token_contribution.bump,
+    )]
+    pub token_contribution: Account<'info, TokenContribution>,
+
+    #[account(
+        seeds = [ESCROW_SEED, presale.key().as_ref()],
+        bump = presale.escrow_bump,
+    )]
+    pub escrow: SystemAccount<'info>,
+
+    #[account(mut, associated_token::mint = payment_token.mint, associated_token::authority = escrow)]
+    pub escrow_payment_account: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = payment_token.mint, token::authority = buyer)]
+    pub buyer_payment_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for the `configure_unsold_stream` instruction.
+#[derive(Accounts)]
+pub struct ConfigureUnsoldStream<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        has_one = presale_wallet,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    #[account(token::mint = presale_wallet.mint)]
+    pub community_rewards_wallet: Account<'info, TokenAccount>, // Receives the unsold-token stream
+}
+
+/// Accounts for the `release_next_tranche` instruction.
+#[derive(Accounts)]
+pub struct ReleaseNextTranche<'info> {
+    #[account(
+        mut,
+        has_one = presale_wallet,
+        has_one = community_rewards_wallet,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(mut)]
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub community_rewards_wallet: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for the `revoke_allocation` instruction.
+#[derive(Accounts)]
+pub struct RevokeAllocation<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        has_one = presale_wallet,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer_account.buyer.as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(mut, has_one = presale)]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // Page holding the buyer's entry
+
+    #[account(mut)]
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = presale_wallet.mint, token::authority = admin)]
+    pub revocation_pool: Account<'info, TokenAccount>, // Admin-controlled pool receiving revoked tokens
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for the `revoke_unvested` instruction.
+#[derive(Accounts)]
+pub struct RevokeUnvested<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        has_one = presale_wallet,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer_account.buyer.as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(mut, has_one = presale)]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // Page holding the buyer's entry
+
+    #[account(mut)]
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = presale_wallet.mint, token::authority = admin)]
+    pub revocation_pool: Account<'info, TokenAccount>, // Admin-controlled pool receiving revoked tokens
+
+    #[account(has_one = presale)]
+    pub vesting_schedule: Option<Account<'info, VestingSchedule>>, // The buyer's override, or the sale's milestones; required with either
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for the `set_whitelist_root` instruction.
+#[derive(Accounts)]
+pub struct SetWhitelistRoot<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_whitelist_tier_caps` instruction.
+#[derive(Accounts)]
+pub struct SetWhitelistTierCaps<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `add_to_whitelist` instruction.
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct AddToWhitelist<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = WhitelistEntry::LEN,
+        seeds = [WHITELIST_SEED, presale.key().as_ref(), wallet.as_ref()],
+        bump
+    )]
+    pub whitelist_entry: Account<'info, WhitelistEntry>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `add_accepted_currency` instruction.
+#[derive(Accounts)]
+#[instruction(currency: u8)]
+pub struct AddAcceptedCurrency<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = AcceptedCurrency::LEN,
+        seeds = [ACCEPTED_CURRENCY_SEED, presale.key().as_ref(), &[currency]],
+        bump
+    )]
+    pub accepted_currency: Account<'info, AcceptedCurrency>,
+
+    pub stable_coin_mint: InterfaceAccount<'info, InterfaceMint>, // SPL Token mint, or Token-2022 for PYUSD
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `set_accepted_currency_enabled` instruction.
+#[derive(Accounts)]
+pub struct UpdateAcceptedCurrency<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        seeds = [ACCEPTED_CURRENCY_SEED, presale.key().as_ref(), &[accepted_currency.currency]],
+        bump = accepted_currency.bump,
+    )]
+    pub accepted_currency: Account<'info, AcceptedCurrency>,
+}
+
+/// Accounts for the `remove_accepted_currency` instruction.
+#[derive(Accounts)]
+pub struct RemoveAcceptedCurrency<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        close = admin,
+        has_one = presale,
+        seeds = [ACCEPTED_CURRENCY_SEED, presale.key().as_ref(), &[accepted_currency.currency]],
+        bump = accepted_currency.bump,
+    )]
+    pub accepted_currency: Account<'info, AcceptedCurrency>,
+}
+
+/// Accounts for the `add_payment_token` instruction.
+#[derive(Accounts)]
+pub struct AddPaymentToken<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = PaymentToken::LEN,
+        seeds = [PAYMENT_TOKEN_SEED, presale.key().as_ref(), payment_mint.key().as_ref()],
+        bump
+    )]
+    pub payment_token: Account<'info, PaymentToken>,
+
+    pub payment_mint: Account<'info, Mint>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `set_payment_token_enabled` instruction.
+#[derive(Accounts)]
+pub struct UpdatePaymentToken<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        seeds = [PAYMENT_TOKEN_SEED, presale.key().as_ref(), payment_token.mint.as_ref()],
+        bump = payment_token.bump,
+    )]
+    pub payment_token: Account<'info, PaymentToken>,
+}
+
+/// Accounts for the `remove_from_whitelist` instruction.
+#[derive(Accounts)]
+pub struct RemoveFromWhitelist<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(mut, close = admin, has_one = presale)]
+    pub whitelist_entry: Account<'info, WhitelistEntry>,
+}
+
+/// Accounts for the `set_registration_deposit` instruction.
+#[derive(Accounts)]
+pub struct SetRegistrationDeposit<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `register_interest` instruction.
+#[derive(Accounts)]
+pub struct RegisterInterest<'info> {
+    #[account(mut)]
+    pub wallet: Signer<'info>,
+
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init,
+        payer = wallet,
+        space = Registration::LEN,
+        seeds = [REGISTRATION_SEED, presale.key().as_ref(), wallet.key().as_ref()],
+        bump
+    )]
+    pub registration: Account<'info, Registration>, // Holds the deposit on top of its rent
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `approve_registration` instruction.
+#[derive(Accounts)]
+pub struct ApproveRegistration<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(mut, has_one = presale)]
+    pub registration: Account<'info, Registration>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = WhitelistEntry::LEN,
+        seeds = [WHITELIST_SEED, presale.key().as_ref(), registration.wallet.as_ref()],
+        bump
+    )]
+    pub whitelist_entry: Account<'info, WhitelistEntry>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `withdraw_registration_deposit` instruction.
+#[derive(Accounts)]
+pub struct WithdrawRegistrationDeposit<'info> {
+    #[account(mut)]
+    pub wallet: Signer<'info>,
+
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(mut, close = wallet, has_one = presale, has_one = wallet)]
+    pub registration: Account<'info, Registration>,
+}
+
+/// Accounts for the `blacklist_wallet` instruction.
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct BlacklistWallet<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = Blacklist::LEN,
+        seeds = [BLACKLIST_SEED, presale.key().as_ref(), wallet.as_ref()],
+        bump
+    )]
+    pub blacklist: Account<'info, Blacklist>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `unblacklist_wallet` instruction.
+#[derive(Accounts)]
+pub struct UnblacklistWallet<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(mut, close = admin, has_one = presale)]
+    pub blacklist: Account<'info, Blacklist>,
+}
+
+/// Accounts for the `set_identity_verifier` instruction.
+#[derive(Accounts)]
+pub struct SetIdentityVerifier<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_gatekeeper` instruction.
+#[derive(Accounts)]
+pub struct SetGatekeeper<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_web2_recorder` instruction.
+#[derive(Accounts)]
+pub struct SetWeb2Recorder<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_kyc_authority` instruction.
+#[derive(Accounts)]
+pub struct SetKycAuthority<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_blocked_regions` instruction.
+#[derive(Accounts)]
+pub struct SetBlockedRegions<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `verify_eligibility` instruction.
+#[derive(Accounts)]
+pub struct VerifyEligibility<'info> {
+    /// CHECK: Any wallet can be checked
+    pub buyer: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    /// CHECK: Validated by `check_identity` against the configured verifier
+    pub attestation: Option<UncheckedAccount<'info>>,
+
+    #[account(address = presale.identity_verifier)]
+    /// CHECK: Checked by presale.identity_verifier
+    pub identity_verifier_program: Option<UncheckedAccount<'info>>,
+
+    #[account(address = sysvar::instructions::ID)]
+    /// CHECK: The instructions sysvar
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>, // Required while a KYC authority is set
+}
+
+/// Accounts for the `grant_bonus` instruction.
+#[derive(Accounts)]
+pub struct GrantBonus<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        has_one = presale_wallet,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer_account.buyer.as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    pub presale_wallet: Account<'info, TokenAccount>,
+}
+
+/// Accounts for the `reverse_purchase_in_kind` instruction.
+#[derive(Accounts)]
+pub struct ReversePurchaseInKind<'info> {
+    pub operator: Signer<'info>, // Operator or admin
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        has_one = buyer,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(mut)]
+    pub buyer: SystemAccount<'info>, // Receives the returned payment
+
+    #[account(
+        mut,
+        has_one = presale,
+        seeds = [BUYER_SEED, presale.key().as_ref(), referrer_account.buyer.as_ref()],
+        bump = referrer_account.bump,
+    )]
+    pub referrer_account: Option<Account<'info, BuyerAccount>>, // Required if the purchase was referred
+
+    #[account(mut, has_one = presale)]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // Page holding the buyer's entry
+
+    #[account(
+        mut,
+        seeds = [ESCROW_SEED, presale.key().as_ref()],
+        bump = presale.escrow_bump,
+    )]
+    pub escrow: SystemAccount<'info>,
+
+    #[account(mut, token::authority = escrow)]
+    pub escrow_stable_coin_account: Option<InterfaceAccount<'info, InterfaceTokenAccount>>, // Required for stablecoin purchases
+
+    #[account(mut, token::authority = buyer)]
+    pub buyer_stable_coin_account: Option<InterfaceAccount<'info, InterfaceTokenAccount>>, // Required for stablecoin purchases
+
+    pub stable_coin_mint: Option<InterfaceAccount<'info, InterfaceMint>>, // Required for stablecoin purchases
+
+    pub token_program: Interface<'info, TokenInterface>, // SPL Token, or Token-2022 for PYUSD
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `create_purchase_journal` instruction.
+#[derive(Accounts)]
+pub struct CreatePurchaseJournal<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = PurchaseJournal::LEN,
+        seeds = [JOURNAL_SEED, presale.key().as_ref()],
+        bump
+    )]
+    pub purchase_journal: AccountLoader<'info, PurchaseJournal>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `get_recent_purchases` instruction.
+#[derive(Accounts)]
+pub struct GetRecentPurchases<'info> {
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(seeds = [JOURNAL_SEED, presale.key().as_ref()], bump)]
+    pub purchase_journal: AccountLoader<'info, PurchaseJournal>,
+}
+
+/// Accounts for the `create_vesting_stats` instruction.
+#[derive(Accounts)]
+pub struct CreateVestingStats<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = VestingStats::LEN,
+        seeds = [VESTING_STATS_SEED, presale.key().as_ref()],
+        bump
+    )]
+    pub vesting_stats: Account<'info, VestingStats>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `set_tge_timestamp` instruction.
+#[derive(Accounts)]
+pub struct SetTgeTimestamp<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_vesting_start` instruction.
+#[derive(Accounts)]
+pub struct SetVestingStart<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `claim_for` instruction.
+/// (`BuyerAccount`, recipient token account) pairs are passed as writable remaining accounts, each
+/// followed by the buyer's `Blacklist` PDA once the blacklist is on.
+#[derive(Accounts)]
+pub struct ClaimFor<'info> {
+    #[account(
+        mut,
+        has_one = presale_wallet,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(mut)]
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    #[account(address = presale_wallet.mint)]
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(has_one = presale)]
+    pub vesting_schedule: Option<Account<'info, VestingSchedule>>, // The buyer's override, or the sale's milestones; required with either
+
+    #[account(mut, seeds = [VESTING_STATS_SEED, presale.key().as_ref()], bump = vesting_stats.bump)]
+    pub vesting_stats: Option<Account<'info, VestingStats>>, // Required once vesting stats exist
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for the `faucet_buy` instruction (`devnet-only` builds).
+#[cfg(feature = "devnet-only")]
+#[derive(Accounts)]
+pub struct FaucetBuy<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>, // Pays for new buyer accounts
+
+    #[account(
+        mut,
+        has_one = admin,
+        has_one = presale_wallet,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    /// CHECK: Any wallet can be credited
+    pub buyer: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = BuyerAccount::LEN,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(mut, has_one = presale)]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // Current page for new buyers, own page otherwise
+
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `record_web2_purchase` instruction.
+#[derive(Accounts)]
+pub struct RecordWeb2Purchase<'info> {
+    #[account(mut)]
+    pub web2_recorder: Signer<'info>, // Backend key; pays for the provisional record
+
+    #[account(
+        mut,
+        has_one = presale_wallet,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    /// CHECK: The wallet credited with the purchase
+    pub buyer: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = web2_recorder,
+        space = ProvisionalPurchase::LEN,
+        seeds = [WEB2_PURCHASE_SEED, presale.key().as_ref(), &presale.next_web2_purchase_sequence.to_le_bytes()],
+        bump
+    )]
+    pub provisional_purchase: Account<'info, ProvisionalPurchase>,
+
+    #[account(seeds = [BLACKLIST_SEED, presale.key().as_ref(), buyer.key().as_ref()], bump)]
+    /// CHECK: Only read by `check_not_blacklisted`; may not exist
+    pub blacklist: Option<UncheckedAccount<'info>>, // Required once any wallet is blacklisted
+
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    #[account(address = presale_wallet.mint)]
+    pub token_mint: Account<'info, Mint>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `confirm_web2_purchase` instruction.
+#[derive(Accounts)]
+pub struct ConfirmWeb2Purchase<'info> {
+    #[account(mut)]
+    pub web2_recorder: Signer<'info>, // Backend key; pays for new buyer accounts
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(mut, close = web2_recorder, has_one = presale)]
+    pub provisional_purchase: Account<'info, ProvisionalPurchase>,
+
+    #[account(
+        init_if_needed,
+        payer = web2_recorder,
+        space = BuyerAccount::LEN,
+        seeds = [BUYER_SEED, presale.key().as_ref(), provisional_purchase.buyer.as_ref()],
+        bump
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(mut, has_one = presale)]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // Current page for new buyers, own page otherwise
+
+    #[account(seeds = [BLACKLIST_SEED, presale.key().as_ref(), provisional_purchase.buyer.as_ref()], bump)]
+    /// CHECK: Only read by `check_not_blacklisted`; may not exist
+    pub blacklist: Option<UncheckedAccount<'info>>, // Required once any wallet is blacklisted
+
+    #[account(
+        seeds = [WHITELIST_SEED, presale.key().as_ref(), provisional_purchase.buyer.as_ref()],
+        bump = whitelist_entry.bump,
+    )]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>, // Buyer's whitelist entry, instead of a merkle proof
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `cancel_web2_purchase` instruction.
+#[derive(Accounts)]
+pub struct CancelWeb2Purchase<'info> {
+    #[account(mut)]
+    pub web2_recorder: Signer<'info>, // Receives the provisional record's rent
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(mut, close = web2_recorder, has_one = presale)]
+    pub provisional_purchase: Account<'info, ProvisionalPurchase>,
+}
+
+/// Accounts for the `set_max_allocation_per_buyer` instruction.
+#[derive(Accounts)]
+pub struct SetMaxAllocationPerBuyer<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_web2_chargeback_window` instruction.
+#[derive(Accounts)]
+pub struct SetWeb2ChargebackWindow<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_purchase_cooldown` instruction.
+#[derive(Accounts)]
+pub struct SetPurchaseCooldown<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_commit_reveal_delay` instruction.
+#[derive(Accounts)]
+pub struct SetCommitRevealDelay<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_purchase_limits` instruction.
+#[derive(Accounts)]
+pub struct SetPurchaseLimits<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_protocol_fee` instruction.
+#[derive(Accounts)]
+pub struct SetProtocolFee<'info> {
+    pub fee_authority: Signer<'info>, // Launchpad authority recorded at the sale's creation
+
+    #[account(
+        mut,
+        has_one = fee_authority @ PresaleError::Unauthorized,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_treasury_splits` instruction.
+#[derive(Accounts)]
+pub struct SetTreasurySplits<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `set_referral_commission` instruction.
+#[derive(Accounts)]
+pub struct SetReferralCommission<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `configure_staking` instruction.
+#[derive(Accounts)]
+pub struct ConfigureStaking<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `activate_sale` instruction.
+#[derive(Accounts)]
+pub struct ActivateSale<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = TermsAnchor::LEN,
+        seeds = [TERMS_SEED, presale.key().as_ref()],
+        bump
+    )]
+    pub terms_anchor: Account<'info, TermsAnchor>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `set_claim_deadline` instruction.
+#[derive(Accounts)]
+pub struct SetClaimDeadline<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `pause_claims` instruction.
+#[derive(Accounts)]
+pub struct PauseClaims<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Accounts for the `close_buyer_account` instruction.
+#[derive(Accounts)]
+pub struct CloseBuyerAccount<'info> {
+    pub authority: Signer<'info>, // The buyer, or the admin after the claim deadline
+
+    #[account(
+        mut,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(mut, close = buyer, has_one = presale, has_one = buyer)]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(mut, has_one = presale)]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // Page holding the buyer's registry entry
+
+    #[account(mut)]
+    pub buyer: SystemAccount<'info>, // Receives the account rent
+}
+
+/// Accounts for the `set_claim_authority` instruction.
+#[derive(Accounts)]
+pub struct SetClaimAuthority<'info> {
+    pub buyer: Signer<'info>,
+
+    pub operator: Signer<'info>, // Operator or admin co-signing the delegation
+
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        has_one = buyer,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+}
+
+/// Accounts for the `claim_tokens_as_authority` instruction.
+#[derive(Accounts)]
+pub struct ClaimTokensAsAuthority<'info> {
+    #[account(mut)]
+    pub claim_authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = presale_wallet,
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        has_one = claim_authority @ PresaleError::Unauthorized,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer_account.buyer.as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(mut)]
+    pub presale_wallet: Account<'info, TokenAccount>,
+
+    #[account(address = presale_wallet.mint)]
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(address = buyer_account.claim_recipient)]
+    /// CHECK: Checked against buyer_account.claim_recipient
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = claim_authority,
+        associated_token::mint = token_mint,
+        associated_token::authority = recipient,
+    )]
+    pub recipient_token_account: Account<'info, TokenAccount>, // Receives the claimed tokens
+
+    #[account(has_one = presale)]
+    pub vesting_schedule: Option<Account<'info, VestingSchedule>>, // The buyer's override, or the sale's milestones; required with either
+
+    #[account(mut, seeds = [VESTING_STATS_SEED, presale.key().as_ref()], bump = vesting_stats.bump)]
+    pub vesting_stats: Option<Account<'info, VestingStats>>, // Required once vesting stats exist
+
+    #[account(seeds = [BLACKLIST_SEED, presale.key().as_ref(), buyer_account.buyer.as_ref()], bump)]
+    /// CHECK: Only read by `check_not_blacklisted`; may not exist
+    pub blacklist: Option<UncheckedAccount<'info>>, // Required once any wallet is blacklisted
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `get_buyer_allocation` instruction.
+#[derive(Accounts)]
+pub struct GetBuyerAllocation<'info> {
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        has_one = presale,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer_account.buyer.as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    #[account(has_one = presale)]
+    pub vesting_schedule: Option<Account<'info, VestingSchedule>>, // The buyer's override, or the sale's milestones; required with either
+}
+
+/// Accounts for the `get_claimable_amount` instruction.
+#[derive(Accounts)]
+#[instruction(buyer: Pubkey)]
+pub struct GetClaimableAmount<'info> {
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        has_one = presale,
+        seeds = [BUYER_SEED, presale.key().as_ref(), buyer.as_ref()],
+        bump = buyer_account.bump,
+    )]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>, // Omit if the buyer has not purchased
+
+    #[account(has_one = presale)]
+    pub vesting_schedule: Option<Account<'info, VestingSchedule>>, // The buyer's override, or the sale's milestones; required with either
+}
+
+/// Accounts for the `get_aggregate_position` instruction.
+/// (`Presale`, presale wallet, `BuyerAccount`) triples are passed as read-only remaining accounts.
+#[derive(Accounts)]
+pub struct GetAggregatePosition<'info> {
+    pub token_mint: Account<'info, Mint>, // Token all the aggregated sales distribute
+}
+
+/// Accounts for the `import_buyers` instruction.
+/// The `BuyerAccount` PDA of each snapshot entry is passed as a writable remaining account.
+#[derive(Accounts)]
+pub struct ImportBuyers<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>, // Pays for created buyer accounts
+
+    #[account(
+        mut,
+        has_one = admin,
+        seeds = [PRESALE_SEED, admin.key().as_ref()],
+        bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(mut, has_one = presale)]
+    pub registry_page: AccountLoader<'info, BuyerRegistryPage>, // Current page for new buyers, own page otherwise
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for the `get_presale_summary` instruction.
+#[derive(Accounts)]
+pub struct GetPresaleSummary<'info> {
+    #[account(
+        seeds = [PRESALE_SEED, presale.admin.as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+/// Defines the state of the presale contract.
+#[account]
+pub struct Presale {
+    pub admin: Pubkey,              // Admin wallet address
+    pub presale_start: i64,         // Presale start timestamp (Unix time)
+    pub usd_price_micros_per_nlov: u64, // Price in USD micro-dollars per NLOV (e.g., 30_000 for $0.03, 5_000 for $0.005)
+    pub sol_price_lamports_per_nlov: u64, // Price in SOL lamports per NLOV (e.g., 182_000_000 for 0.182 SOL)
+    pub private_sale_duration: i64, // Private sale duration (in seconds)
+    pub public_sale_duration: i64,  // Public sale duration (in seconds)
+    pub sale_stage: u8,             // Sale stage (0: Not started, 1: Private, 2: Public, 3: Ended)
+    pub total_sold: u64,            // Total tokens sold (in raw token units with decimals)
+    pub hardcap_tokens: u64,        // New: Maximum tokens to be sold in presale
+    pub pool_created: bool,         // Liquidity pool created flag
+    pub presale_wallet: Pubkey,     // Token account for presale
+    pub merchant_wallet: Pubkey,    // Wallet for receiving SOL/Stablecoin payments
+    pub bump: u8,                   // Store bump here
+    pub escrowed_lamports: u64,     // SOL proceeds held in escrow
+    pub escrowed_usdc: u64,         // USDC proceeds held in escrow (raw units)
+    pub escrowed_usdt: u64,         // USDT proceeds held in escrow (raw units)
+    pub registry_page_count: u32,   // Number of buyer registry pages opened
+    pub vesting_start: i64,         // Vesting start timestamp: scheduled by the admin, or set at finalization
+    pub vesting_duration: i64,      // Linear vesting duration (in seconds), 0 = fully unlocked
+    pub early_claim_penalty_bps: u16, // Share of unvested tokens forfeited on early claim, 0 = disabled
+    pub penalty_wallet: Pubkey,     // Receives forfeited tokens; default pubkey = burn
+    pub total_claimed: u64,         // Total tokens claimed by buyers (raw units)
+    pub total_forfeited: u64,       // Total tokens forfeited through early claims (raw units)
+    pub operator: Pubkey,           // Operator wallet for day-to-day tasks (compliance notes)
+    pub guardian: Pubkey,           // Co-signer required for a lockdown
+    pub locked_down: bool,          // Kill switch: everything except refunds is frozen
+    pub lockdown_lift_at: i64,      // Earliest time a requested lockdown lift can execute (0 = not requested)
+    pub next_ticket_sequence: u64,  // Sequence number of the next purchase ticket
+    pub reserved_tokens: u64,       // Hardcap headroom held by outstanding tickets (raw units)
+    pub softcap_tokens: u64,        // Minimum tokens sold for the sale to succeed (raw units)
+    pub cancelled: bool,            // Sale cancelled by the admin; refunds open
+    pub community_rewards_wallet: Pubkey, // Receives the unsold-token stream
+    pub unsold_stream_months: u8,   // Months to stream unsold tokens over, 0 = lump sum to liquidity wallet
+    pub unsold_stream_total: u64,   // Unsold tokens to stream, fixed at finalization (raw units)
+    pub unsold_stream_start: i64,   // Stream start timestamp, set at finalization
+    pub unsold_stream_released: u64, // Unsold tokens released so far (raw units)
+    pub total_revoked: u64,         // Total tokens revoked from buyers for compliance (raw units)
+    pub previous_stage: u8,         // Stage before the last set_stage transition
+    pub stage_changed_at: i64,      // Time of the last set_stage transition (0 = nothing to roll back)
+    pub stage_rollback_window: i64, // Seconds after a transition during which it can be rolled back
+    pub purchases_in_stage: u64,    // Purchases since the last stage transition
+    pub identity_verifier: Pubkey,  // Identity/KYC verifier program (gatekeeper network in gateway mode)
+    pub identity_mode: u8,          // IDENTITY_MODE_* (0 = no identity check)
+    pub identity_subject_offset: u16, // Offset of the buyer pubkey in attestation data (account mode)
+    pub unique_buyers: u64,         // Distinct wallets holding a buyer account
+    pub immutable_pricing: bool,    // Prices can never be updated (set at initialize)
+    pub total_bonus: u64,           // Bonus tokens granted to buyers (raw units)
+    pub purchase_journal_enabled: bool, // Purchases must be recorded in the purchase journal
+    pub journal_anonymized: bool,   // Recent purchases view truncates buyer pubkeys
+    pub tge_timestamp: i64,         // Claims open at the token generation event (0 = at finalization)
+    pub config_version: u32,        // Bumped whenever pricing or vesting config changes
+    pub max_allocation_per_buyer: u64, // Cumulative purchase cap per buyer, raw units (0 = no cap)
+    pub referral_commission_bps: u16, // Referrer commission in NLOV, as a share of the referred purchase
+    pub total_referral: u64,        // Referral commissions credited (raw units)
+    pub claim_deadline: i64,        // Admin may close settled buyer accounts after this (0 = never)
+    pub max_end_timestamp: i64,     // Latest time the sale may be scheduled to end, fixed at initialize (0 = no limit)
+    pub total_imported: u64,        // Tokens attributed to buyers by import_buyers, part of total_sold (raw units)
+    pub vesting_cliff: i64,         // Seconds after the vesting start before linear vesting unlocks anything
+    pub tge_unlock_bps: u16,        // Share of each allocation unlocked at the vesting start
+    pub private_round_vesting: LinearVesting, // Override for private-round tokens
+    pub public_round_vesting: LinearVesting,  // Override for public-round tokens
+    pub milestone_vesting: bool,    // Allocations unlock per the VestingSchedule milestones instead
+    pub settlement_count: u32,      // Periods settled to the merchant; index of the next Settlement
+    pub last_settlement_at: i64,    // End of the last settled period (0 = never settled)
+    pub unsettled_reversed_lamports: u64, // Lamports reversed in kind since the last settlement
+    pub unsettled_reversed_usdc: u64, // USDC (raw) reversed in kind since the last settlement
+    pub unsettled_reversed_usdt: u64, // USDT (raw) reversed in kind since the last settlement
+    pub claims_paused: bool,        // Token releases frozen by the admin
+    pub vesting_stats_enabled: bool, // Claims must update the VestingStats account
+    pub staking_program: Pubkey,    // Program claim_and_stake deposits into (default = disabled)
+    pub stake_bonus_bps: u16,       // Bonus for claim_and_stake, in bps of the claimed amount
+    pub total_stake_bonus: u64,     // Staking bonuses paid out of unsold tokens (raw units)
+    pub whitelist_root: [u8; 32],   // Merkle root of private-round buyers (zero = no whitelist)
+    pub entry_whitelist: bool,      // Private-round buyers may also qualify through a WhitelistEntry
+    pub whitelist_tier_caps: [u64; WHITELIST_TIER_COUNT], // Max private-round tokens per wallet by tier (raw units, 0 = uncapped)
+    pub kyc_authority: Pubkey,      // Signs buyers' KYC attestations (default = KYC mode off)
+    pub blocked_regions: u64,       // Region bits whose attested buyers may not purchase
+    pub blacklist_enabled: bool,    // Buys and claims must pass the buyer's Blacklist PDA
+    pub purchase_cooldown: i64,     // Minimum seconds between purchases from one wallet (0 = none)
+    pub private_round_purchase_limit: u16, // Max purchases per wallet in the private round (0 = unlimited)
+    pub public_round_purchase_limit: u16, // Max purchases per wallet in the public round (0 = unlimited)
+    pub registration_deposit: u64,  // Lamports locked by register_interest (0 = registration closed)
+    pub gatekeeper: Pubkey,         // Co-signs public-round purchases (default = not required)
+    pub commit_reveal_delay: i64,   // Min seconds from commit to reveal; public buys need a commitment (0 = off)
+    pub phase_whitelist_roots: [PhaseWhitelistRoot; MAX_WHITELIST_PHASES], // Per-stage whitelist phases, latest active one wins
+    pub sol_usd_price_feed: Pubkey, // Pyth SOL/USD price update account pricing SOL buys (default = manual pricing)
+    pub sol_usd_fallback_feed: Pubkey, // Switchboard SOL/USD feed used while the Pyth price is unavailable
+    pub max_price_age: i64,         // Max age of an oracle price, in seconds
+    pub max_confidence_bps: u16,    // Max oracle confidence interval, in basis points of the price (0 = no limit)
+    pub stage_prices: [StagePrice; MAX_STAGE_PRICES], // Prices applied on entering each stage, set at initialization
+    pub volume_price_step_tokens: u64, // Tokens (raw) sold per volume price step (0 = off)
+    pub volume_price_step_bps: u16,    // Price increase per volume step, in basis points
+    pub public_price_ramp_bps: u16,    // Price increase reached by the end of the public round, in basis points (0 = off)
+    pub discount_tiers: [DiscountTier; MAX_DISCOUNT_TIERS], // Bulk-purchase discounts by payment size, ascending
+    pub price_guardrails: PriceGuardrails, // Bounds on manual price updates
+    pub scheduled_price: ScheduledPrice, // Queued price update and when it takes effect
+    pub sol_usd_twap_observation: Pubkey, // Raydium CLMM observations pricing public-round SOL buys (default = off)
+    pub twap_window: i64,           // TWAP averaging window, in seconds
+    pub max_twap_deviation_bps: u16, // Max TWAP deviation from the oracle price, in basis points
+    pub usd_pricing_version: u8,    // USD_PRICING_VERSION once USD prices are in micro-dollars (0 = legacy cents)
+    pub token_rounding_mode: u8,    // TOKEN_ROUNDING_* mode for tokens bought (default floor)
+    pub price_change_delay: i64,    // Seconds a price change stays pending before taking effect (0 = immediate)
+    pub whitelist_discount_bps: u16, // Public-round discount for whitelisted wallets, in basis points (0 = off)
+    pub usdc_mint: Pubkey,          // Mint accepted as CURRENCY_USDC, mirroring its AcceptedCurrency (default = none)
+    pub usdt_mint: Pubkey,          // Mint accepted as CURRENCY_USDT, mirroring its AcceptedCurrency (default = none)
+    pub pyusd_mint: Pubkey,         // Token-2022 mint accepted as CURRENCY_PYUSD, mirroring its AcceptedCurrency (default = none)
+    pub escrowed_pyusd: u64,        // PYUSD proceeds held in escrow (raw units)
+    pub unsettled_reversed_pyusd: u64, // PYUSD (raw) reversed in kind since the last settlement
+    pub escrow: Pubkey,             // Escrow PDA holding SOL proceeds and owning the token vaults
+    pub escrow_bump: u8,            // Escrow PDA bump
+    pub treasury_splits: [TreasurySplit; MAX_TREASURY_WALLETS], // Payout shares of proceeds (none = merchant wallet)
+    pub protocol_fee_bps: u16,      // Fee taken out of on-chain payments, in basis points (0 = off)
+    pub fee_wallet: Pubkey,         // Receives the protocol fee (SOL, and owns the token accounts for token fees)
+    pub web2_recorder: Pubkey,      // Backend key recording Web2 purchases (default = Web2 disabled)
+    pub web2_chargeback_window: i64, // Seconds a recorded Web2 purchase stays provisional
+    pub provisional_tokens: u64,    // Tokens held by unconfirmed Web2 purchases (raw units)
+    pub next_web2_purchase_sequence: u64, // Sequence number of the next recorded Web2 purchase
+    pub staking_vault: Pubkey,      // Staking program vault claim_and_stake deposits into
+    pub fee_authority: Pubkey,      // Launchpad authority setting the protocol fee (default = no fee can be set)
+    pub escrowed_payment_tokens: u32, // Registered payment tokens with proceeds held in escrow
+}
+
+impl Presale {
+    /// Size of the original layout, which ended at `bump`.
+    pub const BASELINE_LEN: usize = 8 + 32 + 8 + 8 + 8 + 8 + 8 + 1 + 8 + 1 + 32 + 32 + 8 + 1;
+
+    pub const LEN: usize = 8 + // Discriminator
+        32 +  // Admin pubkey
+        8 +   // Presale start
+        8 +   // USD price (micro-dollars)
+        8 +   // SOL price (lamports)
+        8 +   // Private sale duration (in seconds)
+        8 +   // Public sale duration (in seconds)
+        1 +   // Sale stage
+        8 +   // Total sold (raw units with decimals)
+        1 +   // Pool created flag
+        32 +  // Presale wallet Pubkey
+        32 +  // Merchant wallet Pubkey
+        8 +   // Hardcap tokens
+        1 +   // Bump
+        8 +   // Escrowed lamports
+        8 +   // Escrowed USDC (raw)
+        8 +   // Escrowed USDT (raw)
+        4 +   // Registry page count
+        8 +   // Vesting start
+        8 +   // Vesting duration (in seconds)
+        2 +   // Early claim penalty (bps)
+        32 +  // Penalty wallet Pubkey
+        8 +   // Total claimed (raw)
+        8 +   // Total forfeited (raw)
+        32 +  // Operator Pubkey
+        32 +  // Guardian Pubkey
+        1 +   // Locked down flag
+        8 +   // Lockdown lift timestamp
+        8 +   // Next ticket sequence
+        8 +   // Reserved tokens (raw)
+        8 +   // Softcap tokens (raw)
+        1 +   // Cancelled flag
+        32 +  // Community rewards wallet Pubkey
+        1 +   // Unsold stream months
+        8 +   // Unsold stream total (raw)
+        8 +   // Unsold stream start
+        8 +   // Unsold stream released (raw)
+        8 +   // Total revoked (raw)
+        1 +   // Previous stage
+        8 +   // Stage changed at
+        8 +   // Stage rollback window (in seconds)
+        8 +   // Purchases in stage
+        32 +  // Identity verifier Pubkey
+        1 +   // Identity mode
+        2 +   // Identity subject offset
+        8 +   // Unique buyers
+        1 +   // Immutable pricing flag
+        8 +   // Total bonus
+        1 +   // Purchase journal enabled
+        1 +   // Journal anonymized
+        8 +   // TGE timestamp
+        4 +   // Config version
+        8 +   // Max allocation per buyer
+        2 +   // Referral commission bps
+        8 +   // Total referral
+        8 +   // Claim deadline
+        8 +   // Max end timestamp
+        8 +   // Total imported
+        8 +   // Vesting cliff
+        2 +   // TGE unlock bps
+        LinearVesting::LEN + // Private round vesting
+        LinearVesting::LEN + // Public round vesting
+        1 +   // Milestone vesting flag
+        4 +   // Settlement count
+        8 +   // Last settlement timestamp
+        8 +   // Unsettled reversed lamports
+        8 +   // Unsettled reversed USDC
+        8 +   // Unsettled reversed USDT
+        1 +   // Claims paused flag
+        1 +   // Vesting stats flag
+        32 +  // Staking program
+        2 +   // Stake bonus bps
+        8 +   // Total stake bonus
+        32 +  // Whitelist root
+        1 +   // Entry whitelist flag
+        8 * WHITELIST_TIER_COUNT + // Whitelist tier caps
+        32 +  // KYC authority
+        8 +   // Blocked regions
+        1 +   // Blacklist flag
+        8 +   // Purchase cooldown
+        2 +   // Private round purchase limit
+        2 +   // Public round purchase limit
+        8 +   // Registration deposit
+        32 +  // Gatekeeper
+        8 +   // Commit-reveal delay
+        PhaseWhitelistRoot::LEN * MAX_WHITELIST_PHASES + // Whitelist phases
+        32 +  // SOL/USD price feed
+        32 +  // SOL/USD fallback feed
+        8 +   // Max price age
+        2 +   // Max confidence
+        StagePrice::LEN * MAX_STAGE_PRICES + // Stage prices
+        8 +   // Volume price step tokens
+        2 +   // Volume price step bps
+        2 +   // Public price ramp bps
+        DiscountTier::LEN * MAX_DISCOUNT_TIERS + // Discount tiers
+        PriceGuardrails::LEN + // Price guardrails
+        ScheduledPrice::LEN + // Scheduled price update
+        32 + // SOL/USD TWAP observations
+        8 +  // TWAP window
+        2 +  // Max TWAP deviation
+        1 +  // USD pricing version
+        1 +  // Token rounding mode
+        8 +  // Price change delay
+        2 +  // Whitelist discount (bps)
+        32 + // USDC mint
+        32 + // USDT mint
+        32 + // PYUSD mint
+        8 +  // Escrowed PYUSD
+        8 +   // Unsettled reversed PYUSD
+        32 +  // Escrow PDA
+        1 +   // Escrow bump
+        TreasurySplit::LEN * MAX_TREASURY_WALLETS + // Treasury splits
+        2 +   // Protocol fee (bps)
+        32 +  // Fee wallet
+        32 +  // Web2 recorder
+        8 +   // Web2 chargeback window
+        8 +   // Provisional tokens
+        8 +   // Next Web2 purchase sequence
+        32 +  // Staking vault
+        32 +  // Fee authority
+        4;    // Payment tokens with escrowed proceeds
+
+    /// Gives a sale migrated from the baseline layout the non-zero defaults of the fields appended after
+    /// `bump`, which read as zero after the realloc, and records its escrow.
+    pub fn init_appended_fields(&mut self, escrow: Pubkey, escrow_bump: u8) {
+        self.operator = self.admin; // The admin operates the sale until an operator is set
+        self.stage_rollback_window = DEFAULT_STAGE_ROLLBACK_WINDOW;
+        self.config_version = 1;
+        self.identity_mode = IDENTITY_MODE_NONE;
+        self.token_rounding_mode = TOKEN_ROUNDING_FLOOR;
+        self.record_escrow(escrow, escrow_bump);
+    }
+
+    /// Puts a freshly created presale into its initial state: not started, nothing sold, no roles
+    /// beyond the admin, and no vesting schedule. Pricing, durations and caps are set by the caller.
+    pub fn reset_for_new_sale(&mut self, admin: Pubkey, now: i64) {
+        self.admin = admin;
+        self.stage_rollback_window = DEFAULT_STAGE_ROLLBACK_WINDOW;
+        self.stage_changed_at = 0;
+        self.identity_mode = IDENTITY_MODE_NONE;
+        self.unique_buyers = 0;
+        self.total_bonus = 0;
+        self.tge_timestamp = 0;
+        self.config_version = 1;
+        self.max_allocation_per_buyer = 0;
+        self.referral_commission_bps = 0;
+        self.total_referral = 0;
+        self.claim_deadline = 0;
+        self.total_imported = 0;
+        self.softcap_tokens = 0;
+        self.cancelled = false;
+        self.next_ticket_sequence = 0;
+        self.reserved_tokens = 0;
+        self.operator = admin; // The admin operates the sale until an operator is set
+        self.presale_start = now;
+        self.sale_stage = 0;
+        self.total_sold = 0;
+        self.pool_created = false;
+        self.escrowed_lamports = 0;
+        self.escrowed_usdc = 0;
+        self.escrowed_usdt = 0;
+        self.escrowed_pyusd = 0;
+        self.total_claimed = 0;
+        self.total_forfeited = 0;
+        self.vesting_duration = 0; // Fully unlocked at finalization until a schedule is configured
+        self.vesting_cliff = 0;
+        self.tge_unlock_bps = 0;
+        self.private_round_vesting = LinearVesting::default(); // Rounds follow the sale-wide schedule
+        self.public_round_vesting = LinearVesting::default();
+        self.milestone_vesting = false;
+        self.settlement_count = 0;
+        self.last_settlement_at = 0;
+        self.unsettled_reversed_lamports = 0;
+        self.unsettled_reversed_usdc = 0;
+        self.unsettled_reversed_usdt = 0;
+        self.unsettled_reversed_pyusd = 0;
+        self.claims_paused = false;
+        self.escrowed_payment_tokens = 0;
+        self.vesting_stats_enabled = false;
+        self.staking_program = Pubkey::default();
+        self.staking_vault = Pubkey::default();
+        self.stake_bonus_bps = 0;
+        self.total_stake_bonus = 0;
+        self.whitelist_root = [0u8; 32];
+        self.entry_whitelist = false;
+        self.whitelist_tier_caps = [0; WHITELIST_TIER_COUNT];
+        self.kyc_authority = Pubkey::default();
+        self.blocked_regions = 0;
+        self.blacklist_enabled = false;
+        self.purchase_cooldown = 0;
+        self.private_round_purchase_limit = 0;
+        self.public_round_purchase_limit = 0;
+        self.registration_deposit = 0;
+        self.gatekeeper = Pubkey::default();
+        self.commit_reveal_delay = 0;
+        self.phase_whitelist_roots = [PhaseWhitelistRoot::default(); MAX_WHITELIST_PHASES];
+        self.sol_usd_price_feed = Pubkey::default();
+        self.sol_usd_fallback_feed = Pubkey::default();
+        self.max_price_age = 0;
+        self.max_confidence_bps = 0;
+        self.stage_prices = [StagePrice::default(); MAX_STAGE_PRICES];
+        self.volume_price_step_tokens = 0;
+        self.volume_price_step_bps = 0;
+        self.public_price_ramp_bps = 0;
+        self.discount_tiers = [DiscountTier::default(); MAX_DISCOUNT_TIERS];
+        self.treasury_splits = [TreasurySplit::default(); MAX_TREASURY_WALLETS];
+        self.protocol_fee_bps = 0;
+        self.fee_wallet = Pubkey::default();
+        self.fee_authority = Pubkey::default();
+        self.web2_recorder = Pubkey::default();
+        self.web2_chargeback_window = 0;
+        self.provisional_tokens = 0;
+        self.next_web2_purchase_sequence = 0;
+        self.price_guardrails = PriceGuardrails::default();
+        self.scheduled_price = ScheduledPrice::default();
+        self.sol_usd_twap_observation = Pubkey::default();
+        self.twap_window = 0;
+        self.max_twap_deviation_bps = 0;
+        self.usd_pricing_version = USD_PRICING_VERSION;
+        self.token_rounding_mode = TOKEN_ROUNDING_FLOOR;
+        self.price_change_delay = 0;
+        self.whitelist_discount_bps = 0;
+        self.early_claim_penalty_bps = 0;
+    }
+
+    /// The configured treasury splits; empty when proceeds go to the merchant wallet.
+    pub fn treasury_splits(&self) -> &[TreasurySplit] {
+        let count = self
+            .treasury_splits
+            .iter()
+            .take_while(|split| split.share_bps > 0)
+            .count();
+        &self.treasury_splits[..count]
+    }
+
+    /// Records the escrow PDA that purchases pay into, so clients can find the SOL vault and the
+    /// associated token accounts it owns for each stablecoin and payment token.
+    pub fn record_escrow(&mut self, escrow: Pubkey, escrow_bump: u8) {
+        self.escrow = escrow;
+        self.escrow_bump = escrow_bump;
+    }
+
+    /// Refunds open when the sale is cancelled, or when it ended below the softcap without being finalized
+    /// or settled.
+    pub fn refunds_enabled(&self) -> bool {
+        self.cancelled
+            || (self.sale_stage == 3
+                && !self.pool_created
+                && self.settlement_count == 0
+                && self.total_sold < self.softcap_tokens)
+    }
+
+    /// Fails unless `signer` is the configured Web2 recorder (none is configured by default).
+    pub fn ensure_web2_recorder(&self, signer: &Pubkey) -> Result<()> {
+        require!(
+            self.web2_recorder != Pubkey::default() && self.web2_recorder == *signer,
+            PresaleError::Unauthorized
+        );
+
+        Ok(())
+    }
+
+    /// The protocol fee taken out of an on-chain `payment`, rounded down.
+    pub fn protocol_fee(&self, payment: u64) -> Result<u64> {
+        mul_div(payment, self.protocol_fee_bps as u64, BPS_DENOMINATOR as u64)
+    }
+
+    /// Commission owed to the referrer of a purchase of `tokens_raw` (0 if the purchase was not referred).
+    pub fn referral_commission(&self, tokens_raw: u64, referred: bool) -> u64 {
+        if !referred {
+            return 0;
+        }
+        ((tokens_raw as u128) * (self.referral_commission_bps as u128) / (BPS_DENOMINATOR as u128)) as u64
+    }
+
+    /// The private-round cap of whitelist `tier` (raw units, 0 = uncapped).
+    pub fn whitelist_tier_cap(&self, tier: u8) -> Result<u64> {
+        Ok(*self
+            .whitelist_tier_caps
+            .get(tier as usize)
+            .ok_or(PresaleError::InvalidWhitelistTier)?)
+    }
+
+    /// Tokens a whitelist `tier` buyer holding `private_round_tokens` can still buy in the private round
+    /// (raw units, u64::MAX if the tier is uncapped).
+    pub fn tier_cap_headroom(&self, tier: u8, private_round_tokens: u64) -> Result<u64> {
+        let tier_cap = self.whitelist_tier_cap(tier)?;
+        if tier_cap == 0 {
+            return Ok(u64::MAX);
+        }
+        Ok(tier_cap.saturating_sub(private_round_tokens))
+    }
+
+    /// Bitmask (`1 << CURRENCY_*`) of the currencies purchases can be paid in during the current stage:
+    /// SOL, the registered stablecoins, and payment tokens in the public round. Empty while the sale is
+    /// not active.
+    pub fn currencies_open(&self) -> u8 {
+        if self.sale_stage != 1 && self.sale_stage != 2 {
+            return 0;
+        }
+
+        let mut currencies = 1 << CURRENCY_SOL;
+        for currency in [CURRENCY_USDC, CURRENCY_USDT, CURRENCY_PYUSD] {
+            if self.stable_coin_mint(currency) != Pubkey::default() {
+                currencies |= 1 << currency;
+            }
+        }
+        if self.sale_stage == 2 {
+            currencies |= 1 << CURRENCY_TOKEN;
+        }
+        currencies
+    }
+
+    /// Tokens a buyer holding `tokens_purchased` can still buy under the per-buyer cap (raw units).
+    pub fn wallet_headroom(&self, tokens_purchased: u64) -> u64 {
+        if self.max_allocation_per_buyer == 0 {
+            return u64::MAX;
+        }
+        self.max_allocation_per_buyer.saturating_sub(tokens_purchased)
+    }
+
+    /// Records a change to config that derived accounts are computed under (pricing, vesting).
+    pub fn bump_config_version(&mut self) -> Result<()> {
+        self.config_version = self.config_version.checked_add(1).ok_or(PresaleError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// The mint accepted for a stablecoin currency, or the default pubkey if none is registered.
+    pub fn stable_coin_mint(&self, currency: u8) -> Pubkey {
+        match currency {
+            CURRENCY_USDC => self.usdc_mint,
+            CURRENCY_USDT => self.usdt_mint,
+            CURRENCY_PYUSD => self.pyusd_mint,
+            _ => Pubkey::default(),
+        }
+    }
+
+    /// Mirrors an `AcceptedCurrency` registration, so account constraints can check mints against it.
+    pub fn set_stable_coin_mint(&mut self, currency: u8, mint: Pubkey) {
+        match currency {
+            CURRENCY_USDC => self.usdc_mint = mint,
+            CURRENCY_USDT => self.usdt_mint = mint,
+            _ => self.pyusd_mint = mint,
+        }
+    }
+
+    /// Keeps `escrowed_payment_tokens` in step with a payment token's escrowed proceeds moving from
+    /// `before` to `after` (raw units).
+    pub fn track_payment_token_escrow(&mut self, before: u64, after: u64) -> Result<()> {
+        if before == 0 && after > 0 {
+            self.escrowed_payment_tokens = self.escrowed_payment_tokens.checked_add(1).ok_or(PresaleError::MathOverflow)?;
+        } else if before > 0 && after == 0 {
+            self.escrowed_payment_tokens = self.escrowed_payment_tokens.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    /// The escrowed proceeds of a stablecoin currency (raw units).
+    pub fn escrowed_stable_coin(&mut self, currency: u8) -> &mut u64 {
+        match currency {
+            CURRENCY_USDC => &mut self.escrowed_usdc,
+            CURRENCY_USDT => &mut self.escrowed_usdt,
+            _ => &mut self.escrowed_pyusd,
+        }
+    }
+
+    /// A stablecoin currency's in-kind reversals since the last settlement (raw units).
+    pub fn unsettled_reversed_stable_coin(&mut self, currency: u8) -> &mut u64 {
+        match currency {
+            CURRENCY_USDC => &mut self.unsettled_reversed_usdc,
+            CURRENCY_USDT => &mut self.unsettled_reversed_usdt,
+            _ => &mut self.unsettled_reversed_pyusd,
+        }
+    }
+
+    /// Fails if a schedule with these durations would end after `max_end_timestamp`.
+    /// A sale that has not started yet is assumed to start at `now`.
+    pub fn ensure_within_max_end(
+        &self,
+        private_sale_duration: i64,
+        public_sale_duration: i64,
+        now: i64,
+    ) -> Result<()> {
+        if self.max_end_timestamp == 0 {
+            return Ok(());
+        }
+        let start = if self.sale_stage == 0 { now } else { self.presale_start };
+        let end = start
+            .checked_add(private_sale_duration)
+            .and_then(|t| t.checked_add(public_sale_duration))
+            .ok_or(PresaleError::SaleEndExceedsMax)?;
+        require!(end <= self.max_end_timestamp, PresaleError::SaleEndExceedsMax);
+        Ok(())
+    }
+
+    /// Fails before the token generation event, if one is scheduled.
+    pub fn ensure_tge_reached(&self) -> Result<()> {
+        require!(
+            Clock::get()?.unix_timestamp >= self.tge_timestamp,
+            PresaleError::TgeNotReached
+        );
+        Ok(())
+    }
+
+    /// Fails if the buyer's previous purchase was less than `purchase_cooldown` ago.
+    pub fn ensure_purchase_cooldown_elapsed(&self, buyer_account: &BuyerAccount, now: i64) -> Result<()> {
+        if self.purchase_cooldown == 0 || buyer_account.last_purchase_ts == 0 {
+            return Ok(());
+        }
+
+        require!(
+            now >= buyer_account.last_purchase_ts.saturating_add(self.purchase_cooldown),
+            PresaleError::PurchaseCooldownActive
+        );
+        Ok(())
+    }
+
+    /// Fails if the buyer has already made the maximum number of purchases in the current round.
+    pub fn ensure_purchase_count_within_limit(&self, buyer_account: &BuyerAccount) -> Result<()> {
+        require!(
+            self.purchases_remaining(Some(buyer_account)) > 0,
+            PresaleError::PurchaseLimitReached
+        );
+        Ok(())
+    }
+
+    /// Purchases the buyer can still make in the current round (u16::MAX if unlimited); pass `None` for
+    /// a buyer without a `BuyerAccount` yet.
+    pub fn purchases_remaining(&self, buyer_account: Option<&BuyerAccount>) -> u16 {
+        let (limit, purchases) = if self.sale_stage == 1 {
+            (self.private_round_purchase_limit, buyer_account.map_or(0, |account| account.private_round_purchases))
+        } else {
+            (self.public_round_purchase_limit, buyer_account.map_or(0, |account| account.public_round_purchases))
+        };
+
+        if limit == 0 {
+            return u16::MAX;
+        }
+        limit.saturating_sub(purchases)
+    }
+
+    /// Stores a price schedule, at most one entry for each of the private and public stages.
+    pub fn set_stage_prices(&mut self, stage_prices: &[StagePrice]) -> Result<()> {
+        require!(stage_prices.len() <= MAX_STAGE_PRICES, PresaleError::InvalidStagePrice);
+
+        let mut schedule = [StagePrice::default(); MAX_STAGE_PRICES];
+        for entry in stage_prices {
+            require!(
+                (entry.stage == 1 || entry.stage == 2)
+                    && entry.usd_price_micros_per_nlov > 0
+                    && entry.sol_price_lamports_per_nlov > 0,
+                PresaleError::InvalidStagePrice
+            );
+            let slot = &mut schedule[entry.stage as usize - 1];
+            require!(slot.stage == 0, PresaleError::InvalidStagePrice);
+            *slot = *entry;
+        }
+
+        self.stage_prices = schedule;
+        Ok(())
+    }
+
+    /// The prices in effect at `now`: the scheduled update's once it is due, the stored prices otherwise.
+    pub fn active_prices(&self, now: i64) -> (u64, u64) {
+        let scheduled = &self.scheduled_price;
+        if scheduled.effective_at != 0 && now >= scheduled.effective_at {
+            (scheduled.usd_price_micros_per_nlov, scheduled.sol_price_lamports_per_nlov)
+        } else {
+            (self.usd_price_micros_per_nlov, self.sol_price_lamports_per_nlov)
+        }
+    }
+
+    /// Stores a due scheduled price update as the current prices and clears it.
+    pub fn apply_scheduled_price(&mut self, now: i64) {
+        let scheduled = self.scheduled_price;
+        if scheduled.effective_at == 0 || now < scheduled.effective_at {
+            return;
+        }
+
+        self.usd_price_micros_per_nlov = scheduled.usd_price_micros_per_nlov;
+        self.sol_price_lamports_per_nlov = scheduled.sol_price_lamports_per_nlov;
+        self.scheduled_price = ScheduledPrice::default();
+        msg!(
+            "Scheduled prices applied: {} micro-USD/NLOV, {} lamports/NLOV",
+            scheduled.usd_price_micros_per_nlov,
+            scheduled.sol_price_lamports_per_nlov
+        );
+    }
+
+    /// Fails until a presale created with USD prices in cents has been migrated to micro-dollars.
+    pub fn ensure_usd_pricing_current(&self) -> Result<()> {
+        require!(
+            self.usd_pricing_version == USD_PRICING_VERSION,
+            PresaleError::UsdPricingMigrationRequired
+        );
+        Ok(())
+    }
+
+    /// Converts every USD price stored in cents (current, per-stage, scheduled and guardrail prices)
+    /// to micro-dollars.
+    pub fn convert_usd_prices_from_cents(&mut self) -> Result<()> {
+        let to_micros = |cents: u64| cents.checked_mul(USD_MICROS_PER_CENT).ok_or(PresaleError::MathOverflow);
+
+        self.usd_price_micros_per_nlov = to_micros(self.usd_price_micros_per_nlov)?;
+        for entry in self.stage_prices.iter_mut() {
+            entry.usd_price_micros_per_nlov = to_micros(entry.usd_price_micros_per_nlov)?;
+        }
+        self.scheduled_price.usd_price_micros_per_nlov = to_micros(self.scheduled_price.usd_price_micros_per_nlov)?;
+        self.price_guardrails.min_usd_price_micros = to_micros(self.price_guardrails.min_usd_price_micros)?;
+        self.price_guardrails.max_usd_price_micros = to_micros(self.price_guardrails.max_usd_price_micros)?;
+        Ok(())
+    }
+
+    /// Fails if new prices fall outside the guardrails' bounds or move too far from the current prices.
+    pub fn ensure_price_within_guardrails(&self, usd_price_micros: u64, sol_price_lamports: u64) -> Result<()> {
+        let guardrails = &self.price_guardrails;
+        let within = |price: u64, min: u64, max: u64| price >= min && (max == 0 || price <= max);
+        require!(
+            within(usd_price_micros, guardrails.min_usd_price_micros, guardrails.max_usd_price_micros)
+                && within(sol_price_lamports, guardrails.min_sol_price_lamports, guardrails.max_sol_price_lamports),
+            PresaleError::PriceOutOfBounds
+        );
+
+        if guardrails.max_change_bps > 0 {
+            let within_change = |new_price: u64, current_price: u64| {
+                (new_price.abs_diff(current_price) as u128) * (BPS_DENOMINATOR as u128)
+                    <= (current_price as u128) * (guardrails.max_change_bps as u128)
+            };
+            require!(
+                within_change(usd_price_micros, self.usd_price_micros_per_nlov)
+                    && within_change(sol_price_lamports, self.sol_price_lamports_per_nlov),
+                PresaleError::PriceChangeTooLarge
+            );
+        }
+        Ok(())
+    }
+
+    /// The discount of the largest tier whose threshold a purchase of `usd_cents` reaches.
+    pub fn bulk_discount_bps(&self, usd_cents: u64) -> u16 {
+        self.discount_tiers
+            .iter()
+            .filter(|tier| tier.discount_bps > 0 && usd_cents >= tier.min_usd_cents)
+            .map(|tier| tier.discount_bps)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The price buyers pay at `now` for a base price: ramped over the public round, then marked up for
+    /// volume sold.
+    pub fn effective_price(&self, base_price: u64, now: i64) -> Result<u64> {
+        self.volume_adjusted_price(self.ramped_price(base_price, now)?)
+    }
+
+    /// Marks `base_price` up linearly over the public round's schedule, reaching `public_price_ramp_bps`
+    /// at its end.
+    pub fn ramped_price(&self, base_price: u64, now: i64) -> Result<u64> {
+        if self.sale_stage != 2 || self.public_price_ramp_bps == 0 {
+            return Ok(base_price);
+        }
+
+        let round_start = self.presale_start.saturating_add(self.private_sale_duration);
+        let markup_bps = if self.public_sale_duration > 0 {
+            let elapsed = now.saturating_sub(round_start).clamp(0, self.public_sale_duration);
+            mul_div(
+                self.public_price_ramp_bps as u64,
+                elapsed as u64,
+                self.public_sale_duration as u64,
+            )?
+        } else {
+            self.public_price_ramp_bps as u64
+        };
+        mul_div(base_price, BPS_DENOMINATOR as u64 + markup_bps, BPS_DENOMINATOR as u64)
+    }
+
+    /// Tokens (raw units) left to sell at the current volume price step (u64::MAX without volume pricing).
+    pub fn volume_step_headroom(&self) -> u64 {
+        if self.volume_price_step_tokens == 0 {
+            return u64::MAX;
+        }
+        self.volume_price_step_tokens - self.total_sold % self.volume_price_step_tokens
+    }
+
+    /// Requires a purchase of `tokens_raw` to fit within the current volume price step, so no part of it
+    /// is bought at this step's price once the next step's is due.
+    pub fn ensure_within_volume_step(&self, tokens_raw: u64) -> Result<()> {
+        require!(
+            tokens_raw <= self.volume_step_headroom(),
+            PresaleError::VolumePriceStepCrossed
+        );
+        Ok(())
+    }
+
+    /// Marks `base_price` up by `volume_price_step_bps` for each full `volume_price_step_tokens` sold so far.
+    pub fn volume_adjusted_price(&self, base_price: u64) -> Result<u64> {
+        if self.volume_price_step_tokens == 0 {
+            return Ok(base_price);
+        }
+
+        let steps = self.total_sold / self.volume_price_step_tokens;
+        let markup_bps = steps
+            .checked_mul(self.volume_price_step_bps as u64)
+            .and_then(|markup| markup.checked_add(BPS_DENOMINATOR as u64))
+            .ok_or(PresaleError::MathOverflow)?;
+        mul_div(base_price, markup_bps, BPS_DENOMINATOR as u64)
+    }
+
+    /// Switches to the scheduled prices of the current stage, if it has any, after settling a due
+    /// scheduled price update so the stage's prices take precedence.
+    pub fn apply_stage_price(&mut self, now: i64) {
+        self.apply_scheduled_price(now);
+
+        let scheduled = self
+            .stage_prices
+            .iter()
+            .find(|entry| entry.stage != 0 && entry.stage == self.sale_stage)
+            .copied();
+
+        if let Some(entry) = scheduled {
+            self.usd_price_micros_per_nlov = entry.usd_price_micros_per_nlov;
+            self.sol_price_lamports_per_nlov = entry.sol_price_lamports_per_nlov;
+            msg!(
+                "Stage {} prices applied: {} micro-USD/NLOV, {} lamports/NLOV",
+                entry.stage,
+                entry.usd_price_micros_per_nlov,
+                entry.sol_price_lamports_per_nlov
+            );
+        }
+    }
+
+    /// Fails if an oracle price was published more than `max_price_age` ago, or if its confidence
+    /// interval is wider than `max_confidence_bps` of the price.
+    pub fn check_oracle_price_quality(&self, price: u128, confidence: u128, published_at: i64, now: i64) -> Result<()> {
+        check_price_quality(price, confidence, published_at, now, self.max_price_age, self.max_confidence_bps)
+    }
+
+    /// Fails if a public-round purchase lacks the gatekeeper's co-signature while one is required.
+    pub fn ensure_gatekeeper_signed(&self, gatekeeper_signed: bool) -> Result<()> {
+        require!(
+            self.sale_stage != 2 || self.gatekeeper == Pubkey::default() || gatekeeper_signed,
+            PresaleError::GatekeeperSignatureRequired
+        );
+        Ok(())
+    }
+
+    /// Fails while token releases are paused.
+    pub fn ensure_claims_not_paused(&self) -> Result<()> {
+        require!(!self.claims_paused, PresaleError::ClaimsPaused);
+        Ok(())
+    }
+
+    /// Fails while the presale is under lockdown.
+    pub fn ensure_not_locked_down(&self) -> Result<()> {
+        require!(!self.locked_down, PresaleError::LockedDown);
+        Ok(())
+    }
+
+    /// Tokens sold or granted as bonuses or referral commissions that are still owed to buyers
+    /// and held in the presale wallet (raw units).
+    pub fn outstanding_allocations(&self) -> u64 {
+        self.total_sold
+            .saturating_add(self.provisional_tokens)
+            .saturating_add(self.total_bonus)
+            .saturating_add(self.total_referral)
+            .saturating_sub(self.total_claimed)
+            .saturating_sub(self.total_forfeited)
+            .saturating_sub(self.total_revoked)
+    }
+
+    /// The root of the current stage's latest whitelist phase that has activated by `now`, if any.
+    pub fn phase_whitelist_root(&self, now: i64) -> Option<[u8; 32]> {
+        self.phase_whitelist_roots
+            .iter()
+            .filter(|phase| {
+                phase.root != [0u8; 32] && phase.stage == self.sale_stage && phase.activates_at <= now
+            })
+            .max_by_key(|phase| phase.activates_at)
+            .map(|phase| phase.root)
+    }
+
+    /// Whether private-round purchases are restricted to whitelisted wallets.
+    pub fn whitelist_enabled(&self) -> bool {
+        self.whitelist_root != [0u8; 32] || self.entry_whitelist
+    }
+
+    /// Tokens sold or granted as bonuses or referral commissions, less revocations (raw units).
+    pub fn total_allocated(&self) -> u64 {
+        self.total_sold
+            .saturating_add(self.total_bonus)
+            .saturating_add(self.total_referral)
+            .saturating_sub(self.total_revoked)
+    }
+
+    /// The sale-wide schedule set by `configure_vesting`.
+    pub fn default_vesting(&self) -> LinearVesting {
+        LinearVesting {
+            configured: true,
+            duration: self.vesting_duration,
+            cliff: self.vesting_cliff,
+            tge_unlock_bps: self.tge_unlock_bps,
+            mode: VESTING_MODE_LINEAR,
+        }
+    }
+
+    /// Portion of `allocation` unlocked at `now` under the sale-wide schedule.
+    pub fn vested_amount(&self, allocation: u64, now: i64) -> Result<u64> {
+        self.default_vesting()
+            .vested_amount(allocation, now.saturating_sub(self.vesting_start))
+    }
+
+    /// Portion of a buyer's allocation claimable in principle at `now`: nothing before finalization
+    /// and the TGE, the vested amount afterwards.
+    pub fn buyer_unlocked_amount(
+        &self,
+        buyer_account: &BuyerAccount,
+        milestones: Option<&VestingSchedule>,
+        now: i64,
+    ) -> Result<u64> {
+        if self.pool_created && now >= self.tge_timestamp {
+            self.buyer_vested_amount(buyer_account, milestones, now)
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Portion of a buyer's allocation unlocked at `now`. With a per-buyer override or milestone vesting
+    /// the whole allocation follows those milestones, which must then be passed in. Otherwise private-
+    /// and public-round tokens follow their round's schedule when one is configured, and everything else
+    /// the sale-wide schedule. Once the unvested remainder was revoked, the rest of the allocation is vested.
+    pub fn buyer_vested_amount(
+        &self,
+        buyer_account: &BuyerAccount,
+        milestones: Option<&VestingSchedule>,
+        now: i64,
+    ) -> Result<u64> {
+        if buyer_account.vesting_revoked {
+            return buyer_account.total_allocation();
+        }
+        if let Some(milestones) = self.milestone_schedule(buyer_account, milestones)? {
+            return milestones.vested_amount(buyer_account.total_allocation()?, now);
+        }
+        let elapsed = now.saturating_sub(self.vesting_start);
+        self.sum_over_schedules(buyer_account, |schedule, tokens| schedule.vested_amount(tokens, elapsed))
+    }
+
+    /// Portion of a buyer's allocation unlocked at the vesting start (TGE), across their schedules.
+    /// With milestones (the buyer's or the sale's), that is the milestones due by the TGE.
+    pub fn buyer_tge_amount(&self, buyer_account: &BuyerAccount, milestones: Option<&VestingSchedule>) -> Result<u64> {
+        if let Some(milestones) = self.milestone_schedule(buyer_account, milestones)? {
+            return milestones.vested_amount(buyer_account.total_allocation()?, self.tge_timestamp);
+        }
+        self.sum_over_schedules(buyer_account, |schedule, tokens| schedule.tge_amount(tokens))
+    }
+
+    /// The milestone schedule a buyer's allocation follows, if any: their own override, else the sale's
+    /// when milestone vesting is enabled. Fails unless `milestones` is that schedule.
+    fn milestone_schedule<'a>(
+        &self,
+        buyer_account: &BuyerAccount,
+        milestones: Option<&'a VestingSchedule>,
+    ) -> Result<Option<&'a VestingSchedule>> {
+        let schedule_buyer = if buyer_account.vesting_override {
+            buyer_account.buyer
+        } else if self.milestone_vesting {
+            Pubkey::default()
+        } else {
+            return Ok(None);
+        };
+        let schedule = milestones
+            .filter(|schedule| schedule.buyer == schedule_buyer)
+            .ok_or(PresaleError::VestingScheduleRequired)?;
+        Ok(Some(schedule))
+    }
+
+    /// Applies `f` to each part of a buyer's allocation with the schedule it vests under
+    /// (private round, public round, everything else) and sums the results.
+    fn sum_over_schedules(
+        &self,
+        buyer_account: &BuyerAccount,
+        f: impl Fn(&LinearVesting, u64) -> Result<u64>,
+    ) -> Result<u64> {
+        let default_vesting = self.default_vesting();
+        let round_schedule = |schedule: &LinearVesting| {
+            if schedule.configured {
+                *schedule
+            } else {
+                default_vesting
+            }
+        };
+        let other_raw = buyer_account
+            .total_allocation()?
+            .saturating_sub(buyer_account.private_round_tokens)
+            .saturating_sub(buyer_account.public_round_tokens);
+
+        let private_raw = f(&round_schedule(&self.private_round_vesting), buyer_account.private_round_tokens)?;
+        let public_raw = f(&round_schedule(&self.public_round_vesting), buyer_account.public_round_tokens)?;
+        let other_vested_raw = f(&default_vesting, other_raw)?;
+        private_raw
+            .checked_add(public_raw)
+            .and_then(|sum| sum.checked_add(other_vested_raw))
+            .ok_or(PresaleError::MathOverflow.into())
+    }
+}
+
+/// A vesting schedule: a TGE unlock at the vesting start, then linear vesting with a cliff, either
+/// continuous or in equal monthly tranches.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinearVesting {
+    pub configured: bool,    // Unset round overrides fall back to the sale-wide schedule
+    pub duration: i64,       // Linear vesting duration (in seconds), 0 = fully unlocked
+    pub cliff: i64,          // Seconds after the vesting start before linear vesting unlocks anything
+    pub tge_unlock_bps: u16, // Share unlocked at the vesting start
+    pub mode: u8,            // VESTING_MODE_LINEAR or VESTING_MODE_MONTHLY
+}
+
+impl LinearVesting {
+    pub const LEN: usize = 1 + 8 + 8 + 2 + 1;
+
+    /// Portion of `allocation` unlocked `elapsed` seconds after the vesting start: `tge_unlock_bps` of it
+    /// at the start, then the remainder linearly over `duration`, with nothing more unlocking before the cliff.
+    /// In monthly mode the remainder unlocks in equal tranches at the end of each 30-day month instead.
+    pub fn vested_amount(&self, allocation: u64, elapsed: i64) -> Result<u64> {
+        if self.duration <= 0 || elapsed >= self.duration {
+            return Ok(allocation);
+        }
+        if elapsed < 0 {
+            return Ok(0);
+        }
+        let tge_unlocked = self.tge_amount(allocation)?;
+        if elapsed < self.cliff {
+            return Ok(tge_unlocked);
+        }
+        let linear = allocation.checked_sub(tge_unlocked).ok_or(PresaleError::MathOverflow)?;
+        let linear_unlocked = if self.mode == VESTING_MODE_MONTHLY {
+            let tranches = (self.duration / VESTING_TRANCHE_SECONDS).max(1);
+            mul_div(linear, (elapsed / VESTING_TRANCHE_SECONDS) as u64, tranches as u64)?
+        } else {
+            mul_div(linear, elapsed as u64, self.duration as u64)?
+        };
+        tge_unlocked.checked_add(linear_unlocked).ok_or(PresaleError::MathOverflow.into())
+    }
+
+    /// Portion of `allocation` unlocked at the vesting start (all of it without linear vesting).
+    pub fn tge_amount(&self, allocation: u64) -> Result<u64> {
+        if self.duration <= 0 {
+            return Ok(allocation);
+        }
+        mul_div(allocation, self.tge_unlock_bps as u64, BPS_DENOMINATOR as u64)
+    }
+}
+
+/// Escrow balances and refund obligations returned by `get_escrow_balances`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct EscrowBalances {
+    pub sol_balance: u64,      // Lamports held by the escrow PDA
+    pub usdc_balance: u64,     // USDC vault balance (raw units)
+    pub usdt_balance: u64,     // USDT vault balance (raw units)
+    pub sol_obligations: u64,  // Lamports buyers could still be refunded
+    pub usdc_obligations: u64, // USDC buyers could still be refunded (raw units)
+    pub usdt_obligations: u64, // USDT buyers could still be refunded (raw units)
+    pub pyusd_balance: u64,    // PYUSD vault balance (raw units)
+    pub pyusd_obligations: u64, // PYUSD buyers could still be refunded (raw units)
+    pub payment_tokens: Vec<PaymentTokenBalance>, // One per (`PaymentToken`, escrow vault) pair passed in
+}
+
+/// A registered payment token's escrow balance and refund obligations, as returned by `get_escrow_balances`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PaymentTokenBalance {
+    pub mint: Pubkey,
+    pub balance: u64,     // Escrow vault balance (raw units)
+    pub obligations: u64, // Payments buyers could still be refunded (raw units)
+}
+
+/// Purchase headroom for a buyer returned by `get_cap_status`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct CapStatus {
+    pub buyer: Pubkey,
+    pub buyer_tokens_purchased: u64, // Buyer's current allocation (raw units)
+    pub hardcap_headroom: u64,       // Tokens left under the hardcap, net of reservations (raw units)
+    pub supply_headroom: u64,        // Unallocated tokens in the presale wallet (raw units)
+    pub wallet_headroom: u64,        // Tokens left under the per-buyer cap (u64::MAX if uncapped)
+    pub volume_step_headroom: u64,   // Tokens left at the current volume price step (u64::MAX without volume pricing)
+    pub tier_cap_headroom: u64,      // Tokens left under the buyer's whitelist tier cap in the private round (u64::MAX if uncapped or unknown)
+    pub purchases_remaining: u16,    // Purchases left under the current round's purchase limit (u16::MAX if unlimited)
+    pub currencies_open: u8,         // Bitmask (1 << CURRENCY_*) of the currencies accepted in the current stage
+    pub binding_constraint: u8,      // CAP_* code of the tightest constraint
+    pub max_tokens: u64,             // Most the buyer can purchase now (raw units)
+    pub max_usd_cents: u64,          // max_tokens at the current USD price
+    pub max_lamports: u64,           // max_tokens at the current SOL price
+}
+
+/// A purchase quote returned by `quote_purchase`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PurchaseQuote {
+    pub currency: u8,         // CURRENCY_* code of the payment
+    pub amount: u64,          // Payment quoted, as passed to the buy instruction
+    pub tokens_out: u64,      // Tokens the payment buys (raw units)
+    pub effective_price: u64, // Price per NLOV: lamports for SOL, USD micro-dollars for stablecoins and payment tokens
+    pub unconverted: u64,     // Part of the payment not converted into tokens, in lamports or USD micro-dollars
+    pub protocol_fee: u64,    // Protocol fee taken out of the payment, in the units of `amount`
+    pub discount_bps: u16,    // Bulk-purchase or whitelist discount applied
+}
+
+/// A payment quote returned by `quote_payment`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PaymentQuote {
+    pub currency: u8,         // CURRENCY_* code of the payment
+    pub amount: u64,          // Payment to pass to the buy instruction: lamports, or raw stablecoin or payment token units
+    pub tokens_out: u64,      // Tokens the payment buys (raw units), at least the desired amount
+    pub effective_price: u64, // Price per NLOV: lamports for SOL, USD micro-dollars for stablecoins and payment tokens
+    pub discount_bps: u16,    // Bulk-purchase or whitelist discount applied
+}
+
+/// Warning conditions returned by `health_check`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct SaleHealth {
+    pub warnings: u32,          // Bitmask of HEALTH_* flags, 0 = healthy
+    pub sale_stage: u8,
+    pub token_shortfall: u64,   // NLOV missing from the presale wallet (raw units)
+    pub lamport_shortfall: u64, // Escrowed lamports missing from the escrow
+    pub usdc_shortfall: u64,    // Escrowed USDC missing from the vault (raw units, 0 if not checked)
+    pub usdt_shortfall: u64,    // Escrowed USDT missing from the vault (raw units, 0 if not checked)
+    pub timestamp: i64,         // Time of the check
+    pub pyusd_shortfall: u64,   // Escrowed PYUSD missing from the vault (raw units, 0 if not checked)
+    pub oracle_last_update: i64, // Latest SOL/USD oracle update (0 if not checked or unreadable)
+}
+
+/// A journal entry returned by `get_recent_purchases`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct RecentPurchase {
+    pub buyer: Pubkey,    // Truncated to its first 4 bytes when the journal is anonymized
+    pub timestamp: i64,
+    pub currency: u8,
+    pub amount_paid: u64, // Lamports or stablecoin raw units
+    pub tokens: u64,      // Tokens bought (raw units)
+}
+
+/// Sale terms and progress returned by `get_presale_summary`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PresaleSummary {
+    pub sale_stage: u8,
+    pub usd_price_micros_per_nlov: u64,
+    pub sol_price_lamports_per_nlov: u64,
+    pub immutable_pricing: bool, // Prices are fixed for the life of the sale
+    pub hardcap_tokens: u64,
+    pub softcap_tokens: u64,
+    pub total_sold: u64,         // Tokens sold (raw units)
+    pub unique_buyers: u64,
+    pub cancelled: bool,
+    pub finalized: bool,         // finalize_presale has run
+    pub config_version: u32,     // Current pricing/vesting config version
+    pub max_end_timestamp: i64,  // Latest scheduled end allowed (0 = no limit)
+}
+
+/// A historical purchase from the off-chain snapshot passed to `import_buyers`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ImportedPurchase {
+    pub buyer: Pubkey,
+    pub tokens: u64, // Tokens bought (raw units)
+    pub stage: u8,   // 1 = private sale, 2 = public sale
+}
+
+/// A buyer's allocation returned by `get_buyer_allocation`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct BuyerAllocation {
+    pub buyer: Pubkey,
+    pub purchased: u64,           // Tokens purchased (raw units)
+    pub bonus: u64,               // Bonus tokens granted (raw units)
+    pub referral: u64,            // Referral commissions earned (raw units)
+    pub tge_unlock: u64,          // Part of the allocation that unlocks at TGE (raw units)
+    pub unlocked: u64,            // Vested to date, including tokens already claimed (raw units)
+    pub claimable: u64,           // Unlocked and not yet claimed or forfeited (raw units)
+    pub claimed: u64,             // Tokens claimed so far (raw units)
+    pub forfeited: u64,           // Tokens forfeited through an early claim (raw units)
+    pub refundable_lamports: u64, // SOL claim_refund would return now
+    pub refundable_usdc: u64,     // USDC claim_refund would return now (raw units)
+    pub refundable_usdt: u64,     // USDT claim_refund would return now (raw units)
+    pub refundable_pyusd: u64,    // PYUSD claim_refund would return now (raw units)
+}
+
+/// A wallet's position summed across sales, returned by `get_aggregate_position`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct AggregatePosition {
+    pub buyer: Pubkey,
+    pub sales: u32,     // Sales the wallet has a buyer account in
+    pub purchased: u64, // Tokens purchased (raw units)
+    pub bonus: u64,     // Bonus tokens granted (raw units)
+    pub referral: u64,  // Referral commissions earned (raw units)
+    pub unlocked: u64,  // Vested to date, including tokens already claimed (raw units)
+    pub claimable: u64, // Unlocked and not yet claimed or forfeited (raw units)
+    pub claimed: u64,   // Tokens claimed so far (raw units)
+    pub forfeited: u64, // Tokens forfeited through early claims (raw units)
+}
+
+/// A settled period of merchant proceeds. Gross purchases for the period are the net amounts
+/// plus the reversed amounts.
+#[account]
+pub struct Settlement {
+    pub presale: Pubkey,         // Presale this settlement belongs to
+    pub index: u32,              // Settlement sequence number
+    pub period_start: i64,       // Previous settlement time (sale start for the first period)
+    pub period_end: i64,         // Settlement time
+    pub net_lamports: u64,       // Lamports paid to the merchant
+    pub net_usdc: u64,           // USDC (raw) paid to the merchant
+    pub net_usdt: u64,           // USDT (raw) paid to the merchant
+    pub reversed_lamports: u64,  // Lamports reversed in kind during the period
+    pub reversed_usdc: u64,      // USDC (raw) reversed in kind during the period
+    pub reversed_usdt: u64,      // USDT (raw) reversed in kind during the period
+    pub bump: u8,                // Store bump here
+    pub net_pyusd: u64,          // PYUSD (raw) paid to the merchant
+    pub reversed_pyusd: u64,     // PYUSD (raw) reversed in kind during the period
+    pub payment_tokens: u32,     // Registered payment tokens settled, each reported by a PaymentTokenSettledEvent
+}
+
+impl Settlement {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        4 +  // Index
+        8 +  // Period start
+        8 +  // Period end
+        8 +  // Net lamports
+        8 +  // Net USDC
+        8 +  // Net USDT
+        8 +  // Reversed lamports
+        8 +  // Reversed USDC
+        8 +  // Reversed USDT
+        1 +  // Bump
+        8 +  // Net PYUSD
+        8 +  // Reversed PYUSD
+        4;   // Payment tokens settled
+}
+
+/// Admin-managed whitelisting of one wallet for the private round.
+#[account]
+pub struct WhitelistEntry {
+    pub presale: Pubkey, // Presale this entry belongs to
+    pub wallet: Pubkey,  // Whitelisted wallet
+    pub tier: u8,        // Index into Presale::whitelist_tier_caps
+    pub bump: u8,        // Store bump here
+}
+
+impl WhitelistEntry {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        32 + // Wallet pubkey
+        1 +  // Tier
+        1;   // Bump
+}
+
+/// A stablecoin mint accepted for one of the stablecoin currencies, managed by the admin.
+#[account]
+pub struct AcceptedCurrency {
+    pub presale: Pubkey, // Presale this entry belongs to
+    pub currency: u8,    // CURRENCY_USDC, CURRENCY_USDT or CURRENCY_PYUSD: the currency purchases in this mint count as
+    pub mint: Pubkey,    // Accepted stablecoin mint
+    pub decimals: u8,    // The mint's decimals
+    pub enabled: bool,   // Whether purchases in this mint are open
+    pub bump: u8,        // Store bump here
+}
+
+impl AcceptedCurrency {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        1 +  // Currency
+        32 + // Mint
+        1 +  // Decimals
+        1 +  // Enabled
+        1;   // Bump
+}
+
+/// An SPL token `buy_tokens_with_token` accepts, priced by its Pyth feed, managed by the admin.
+#[account]
+pub struct PaymentToken {
+    pub presale: Pubkey,         // Presale this entry belongs to
+    pub mint: Pubkey,            // Accepted token mint
+    pub decimals: u8,            // The mint's decimals
+    pub price_feed: Pubkey,      // Pyth PriceUpdateV2 account pricing the token in USD
+    pub feed_id: [u8; 32],       // Pyth feed ID the price update must carry
+    pub max_price_age: i64,      // Oldest accepted price, in seconds
+    pub max_confidence_bps: u16, // Widest accepted confidence interval, in basis points of the price (0 = no limit)
+    pub enabled: bool,           // Whether purchases in this token are open
+    pub escrowed: u64,           // Payments held in escrow (raw units)
+    pub bump: u8,                // Store bump here
+}
+
+impl PaymentToken {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        32 + // Mint
+        1 +  // Decimals
+        32 + // Price feed
+        32 + // Feed ID
+        8 +  // Max price age
+        2 +  // Max confidence
+        1 +  // Enabled
+        8 +  // Escrowed
+        1;   // Bump
+}
+
+/// A buyer's refundable payments in one payment token.
+#[account]
+pub struct TokenContribution {
+    pub presale: Pubkey, // Presale this record belongs to
+    pub buyer: Pubkey,   // Paying wallet
+    pub mint: Pubkey,    // Payment token mint
+    pub amount: u64,     // Paid into escrow (raw units)
+    pub bump: u8,        // Store bump here
+}
+
+impl TokenContribution {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        32 + // Buyer pubkey
+        32 + // Mint
+        8 +  // Amount
+        1;   // Bump
+}
+
+/// A wallet's registration of interest in the private round, holding its refundable deposit.
+#[account]
+pub struct Registration {
+    pub presale: Pubkey,        // Presale this registration belongs to
+    pub wallet: Pubkey,         // Registered wallet
+    pub deposit_lamports: u64,  // Deposit held on top of the account rent
+    pub registered_at: i64,     // When the wallet registered
+    pub approved: bool,         // Approved and whitelisted by the admin
+    pub bump: u8,               // Store bump here
+}
+
+impl Registration {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        32 + // Wallet pubkey
+        8 +  // Deposit
+        8 +  // Registered at
+        1 +  // Approved flag
+        1;   // Bump
+}
+
+/// A wallet blocked from buying and claiming.
+#[account]
+pub struct Blacklist {
+    pub presale: Pubkey,     // Presale this entry belongs to
+    pub wallet: Pubkey,      // Blocked wallet
+    pub blacklisted_at: i64, // When the wallet was blocked
+    pub bump: u8,            // Store bump here
+}
+
+impl Blacklist {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        32 + // Wallet pubkey
+        8 +  // Blacklisted at
+        1;   // Bump
+}
+
+/// Sale-wide vesting totals, updated on each claim so circulating-supply dashboards can read one account.
+#[account]
+pub struct VestingStats {
+    pub presale: Pubkey,      // Presale these statistics belong to
+    pub total_allocated: u64, // Tokens sold or granted, less revocations (raw units)
+    pub total_vested: u64,    // Tokens vested as of each buyer's latest claim (raw units)
+    pub total_claimed: u64,   // Tokens claimed by all buyers (raw units)
+    pub last_updated: i64,    // Time of the last claim (creation time before any)
+    pub bump: u8,             // Store bump here
+}
+
+impl VestingStats {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        8 +  // Total allocated
+        8 +  // Total vested
+        8 +  // Total claimed
+        8 +  // Last updated
+        1;   // Bump
+}
+
+/// Milestone vesting schedule of a presale, or of a single buyer: each milestone unlocks a fixed share
+/// of every allocation it covers at a fixed time, e.g. 25% per quarter.
+#[account]
+pub struct VestingSchedule {
+    pub presale: Pubkey,                    // Presale this schedule belongs to
+    pub buyer: Pubkey,                      // Buyer this schedule overrides (default pubkey = the whole sale)
+    pub milestones: Vec<VestingMilestone>,  // Strictly increasing timestamps, shares summing to 100%
+    pub bump: u8,                           // Store bump here
+    pub version: u8,                        // Layout version, VESTING_SCHEDULE_VERSION once migrated
+}
+
+impl VestingSchedule {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        32 + // Buyer pubkey
+        4 + MAX_VESTING_MILESTONES * VestingMilestone::LEN + // Milestones
+        1 +  // Bump
+        1;   // Version
+
+    /// Portion of `allocation` unlocked at `now`: the shares of all milestones that are due.
+    pub fn vested_amount(&self, allocation: u64, now: i64) -> Result<u64> {
+        let unlocked_bps: u64 = self
+            .milestones
+            .iter()
+            .filter(|milestone| milestone.timestamp <= now)
+            .map(|milestone| milestone.unlock_bps as u64)
+            .sum();
+        if unlocked_bps >= BPS_DENOMINATOR as u64 {
+            return Ok(allocation);
+        }
+        mul_div(allocation, unlocked_bps, BPS_DENOMINATOR as u64)
+    }
+}
+
+/// A point in time at which a share of each allocation unlocks.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VestingMilestone {
+    pub timestamp: i64,  // Unlock time (Unix time)
+    pub unlock_bps: u16, // Share of each allocation unlocked at this milestone
+}
+
+impl VestingMilestone {
+    pub const LEN: usize = 8 + 2;
+}
+
+/// A buyer's claim to a private-round whitelist tier, proven against `Presale::whitelist_root`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct WhitelistProof {
+    pub tier: u8,             // Tier in the buyer's leaf, `keccak(buyer || tier)`
+    pub proof: Vec<[u8; 32]>, // Sibling hashes from the leaf up to the root
+}
+
+/// A whitelist root for one phase of a sale stage.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PhaseWhitelistRoot {
+    pub stage: u8,         // Sale stage the phase belongs to (0 = unused slot)
+    pub activates_at: i64, // When the phase starts (Unix time)
+    pub root: [u8; 32],    // Merkle root of the phase's list (zero = unused slot)
+}
+
+impl PhaseWhitelistRoot {
+    pub const LEN: usize = 1 + 8 + 32;
+}
+
+/// The prices of one sale stage, applied when the sale enters it.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StagePrice {
+    pub stage: u8,                        // Sale stage the prices apply to (0 = unused slot)
+    pub usd_price_micros_per_nlov: u64,    // USD price in micro-dollars per NLOV
+    pub sol_price_lamports_per_nlov: u64, // SOL price in lamports per NLOV
+}
+
+impl StagePrice {
+    pub const LEN: usize = 1 + 8 + 8;
+}
+
+/// A bulk-purchase discount for payments of at least `min_usd_cents`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiscountTier {
+    pub min_usd_cents: u64, // Smallest payment the tier applies to, in USD cents
+    pub discount_bps: u16,  // Discount off the price, in basis points (0 = unused slot)
+}
+
+impl DiscountTier {
+    pub const LEN: usize = 8 + 2;
+}
+
+/// A treasury wallet's share of paid-out proceeds.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TreasurySplit {
+    pub wallet: Pubkey,  // Receives SOL, and owns the token accounts receiving token proceeds
+    pub share_bps: u16,  // Share of each payout, in basis points (0 = unused slot)
+}
+
+impl TreasurySplit {
+    pub const LEN: usize = 32 + 2;
+}
+
+/// Bounds on manual price updates, guarding against fat-fingered prices.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PriceGuardrails {
+    pub min_usd_price_micros: u64,    // Lowest USD price in micro-dollars per NLOV
+    pub max_usd_price_micros: u64,    // Highest USD price in micro-dollars per NLOV (0 = unbounded)
+    pub min_sol_price_lamports: u64, // Lowest SOL price in lamports per NLOV
+    pub max_sol_price_lamports: u64, // Highest SOL price in lamports per NLOV (0 = unbounded)
+    pub max_change_bps: u16,         // Largest change per update, in bps of the current price (0 = unlimited)
+}
+
+impl PriceGuardrails {
+    pub const LEN: usize = 8 * 4 + 2;
+}
+
+/// A price update queued to take effect at `effective_at` (0 = none pending).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScheduledPrice {
+    pub usd_price_micros_per_nlov: u64,    // New USD price in micro-dollars per NLOV
+    pub sol_price_lamports_per_nlov: u64, // New SOL price in lamports per NLOV
+    pub effective_at: i64,                // Unix time the new prices take effect
+}
+
+impl ScheduledPrice {
+    pub const LEN: usize = 8 + 8 + 8;
+}
+
+/// Parameters to change when cloning a sale; `None` keeps the template's value.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default)]
+pub struct SaleOverrides {
+    pub usd_price_micros_per_nlov: Option<u64>,
+    pub sol_price_lamports_per_nlov: Option<u64>,
+    pub private_sale_duration_days: Option<i64>,
+    pub public_sale_duration_days: Option<i64>,
+    pub hardcap_tokens: Option<u64>,        // Raw units
+    pub softcap_tokens: Option<u64>,        // Raw units
+    pub max_allocation_per_buyer: Option<u64>, // Raw units, 0 = no cap
+    pub max_end_timestamp: Option<i64>,     // Not copied from the template; defaults to no limit
+}
+
+/// Tracks a single buyer's purchases in a presale.
+#[account]
+pub struct BuyerAccount {
+    pub presale: Pubkey,        // Presale this allocation belongs to
+    pub buyer: Pubkey,          // Wallet that made the purchases
+    pub tokens_purchased: u64,  // Total tokens purchased (in raw token units with decimals)
+    pub receipt_minted: bool,   // Whether the receipt NFT has been minted
+    pub bump: u8,               // Store bump here
+    pub registry_page: u32,     // Buyer registry page holding this buyer's entry
+    pub registry_index: u32,    // Entry index within that page
+    pub tokens_claimed: u64,    // Tokens claimed so far, including early claims (raw units)
+    pub tokens_forfeited: u64,  // Tokens forfeited through an early claim (raw units)
+    pub claim_recipient: Pubkey, // Wallet receiving claimed tokens (the buyer by default)
+    pub contributed_lamports: u64, // Refundable SOL paid into escrow
+    pub contributed_usdc: u64,  // Refundable USDC paid into escrow (raw units)
+    pub contributed_usdt: u64,  // Refundable USDT paid into escrow (raw units)
+    pub compliance_note: [u8; COMPLIANCE_NOTE_LEN], // Operator annotation (KYC case ID, screening reference)
+    pub tokens_revoked: u64,    // Tokens revoked for compliance (raw units)
+    pub revoked: bool,          // Revoked wallets cannot purchase again
+    pub private_round_tokens: u64, // Part of the allocation bought in the private sale (raw units)
+    pub public_round_tokens: u64, // Part of the allocation bought in the public sale (raw units)
+    pub bonus_tokens: u64,      // Promotional bonus granted on top of purchases, claimable with them (raw units)
+    pub config_version: u32,    // Presale config version of the last claim or refund settled against this account
+    pub referral_tokens: u64,   // Referral commissions earned, claimable with the allocation (raw units)
+    pub claim_authority: Pubkey, // Signs claims instead of the buyer when set (default = the buyer claims)
+    pub imported_tokens: u64,   // Tokens backfilled by import_buyers (raw units); nonzero once imported
+    pub vesting_revoked: bool,  // Unvested remainder revoked; what is left counts as fully vested
+    pub vesting_override: bool, // Allocation vests per the buyer's own VestingSchedule
+    pub vested_recorded: u64,   // Vested tokens counted in VestingStats as of the last claim (raw units)
+    pub last_purchase_ts: i64,  // Time of the latest purchase, for the purchase cooldown (0 = none yet)
+    pub private_round_purchases: u16, // Purchases made in the private round
+    pub public_round_purchases: u16, // Purchases made in the public round
+    pub contributed_pyusd: u64, // Refundable PYUSD paid into escrow (raw units)
+    pub protocol_fee_lamports: u64, // Protocol fees paid on SOL payments, kept by the fee wallet on refund
+    pub protocol_fee_usdc: u64, // Protocol fees paid on USDC payments (raw units), kept on refund
+    pub protocol_fee_usdt: u64, // Protocol fees paid on USDT payments (raw units), kept on refund
+    pub protocol_fee_pyusd: u64, // Protocol fees paid on PYUSD payments (raw units), kept on refund
+    pub purchases: Vec<PurchaseRecord>, // Individual purchases, oldest first
+}
+
+/// A single purchase recorded in a buyer's history.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PurchaseRecord {
+    pub timestamp: i64,   // Purchase time (Unix time)
+    pub currency: u8,     // CURRENCY_SOL, CURRENCY_USDC, CURRENCY_USDT, CURRENCY_PYUSD or CURRENCY_TOKEN
+    pub amount_paid: u64, // Lamports or stablecoin raw units paid (or declared, for Web2)
+    pub tokens: u64,      // Tokens bought (raw units)
+    pub stage: u8,        // Sale stage at purchase time
+    pub payment_type: u8, // 0 = Web3 (paid into escrow), 1 = Web2, 2 = wrapped SOL (unwrapped into escrow)
+    pub reversed: bool,   // Unwound by `reverse_purchase_in_kind`
+    pub config_version: u32, // Presale config version the price was taken from
+    pub referrer: Pubkey, // Buyer credited a referral commission on the purchase (default = not referred)
+    pub referral_commission: u64, // Commission credited to the referrer, taken back on refund or reversal (raw units)
+}
+
+impl PurchaseRecord {
+    pub const LEN: usize = 8 + // Timestamp
+        1 +  // Currency
+        8 +  // Amount paid
+        8 +  // Tokens
+        1 +  // Stage
+        1 +  // Payment type
+        1 +  // Reversed flag
+        4 +  // Config version
+        32 + // Referrer
+        8;   // Referral commission
+}
+
+impl BuyerAccount {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        32 + // Buyer pubkey
+        8 +  // Tokens purchased
+        1 +  // Receipt minted flag
+        1 +  // Bump
+        4 +  // Registry page
+        4 +  // Registry index
+        8 +  // Tokens claimed
+        8 +  // Tokens forfeited
+        32 + // Claim recipient pubkey
+        8 +  // Contributed lamports
+        8 +  // Contributed USDC
+        8 +  // Contributed USDT
+        COMPLIANCE_NOTE_LEN + // Compliance note
+        8 +  // Tokens revoked
+        1 +  // Revoked flag
+        8 +  // Private round tokens
+        8 +  // Public round tokens
+        8 +  // Bonus tokens
+        4 +  // Config version
+        8 +  // Referral tokens
+        32 + // Claim authority
+        8 +  // Imported tokens
+        1 +  // Vesting revoked flag
+        1 +  // Vesting override flag
+        8 +  // Vested recorded
+        8 +  // Last purchase timestamp
+        2 +  // Private round purchases
+        2 +  // Public round purchases
+        8 +  // Contributed PYUSD
+        8 +  // Protocol fee lamports
+        8 +  // Protocol fee USDC
+        8 +  // Protocol fee USDT
+        8 +  // Protocol fee PYUSD
+        4 + PURCHASE_HISTORY_CAPACITY * PurchaseRecord::LEN; // Purchase history
+
+    /// Adds a purchase to the allocation and the purchase history, filling in the account on the
+    /// buyer's first purchase. Returns true if this was the buyer's first purchase.
+    /// Once the history holds `PURCHASE_HISTORY_CAPACITY` entries, further purchases are refused, so
+    /// every credited purchase can be reversed in kind.
+    pub fn record_purchase(&mut self, presale: Pubkey, buyer: Pubkey, bump: u8, record: PurchaseRecord) -> Result<bool> {
+        require!(!self.revoked, PresaleError::AllocationRevoked);
+        require!(self.purchases.len() < PURCHASE_HISTORY_CAPACITY, PresaleError::PurchaseHistoryFull);
+
+        let first_purchase = self.credit_allocation(presale, buyer, bump, record.tokens)?;
+        if record.stage == 1 {
+            self.credit_round_tokens(record.tokens, 0)?;
+            self.private_round_purchases = self.private_round_purchases.saturating_add(1);
+        } else {
+            self.credit_round_tokens(0, record.tokens)?;
+            self.public_round_purchases = self.public_round_purchases.saturating_add(1);
+        }
+        self.last_purchase_ts = record.timestamp;
+        self.purchases.push(record);
+        Ok(first_purchase)
+    }
+
+    /// Adds tokens to the allocation, filling in the account if it is new.
+    /// Returns true if the account was new.
+    pub fn credit_allocation(&mut self, presale: Pubkey, buyer: Pubkey, bump: u8, tokens_raw: u64) -> Result<bool> {
+        let is_new = self.buyer == Pubkey::default();
+        if is_new {
+            self.presale = presale;
+            self.buyer = buyer;
+            self.claim_recipient = buyer; // Claims go to the purchasing wallet until changed
+            self.bump = bump;
+        }
+        self.tokens_purchased = self.tokens_purchased.checked_add(tokens_raw).ok_or(PresaleError::MathOverflow)?;
+        Ok(is_new)
+    }
+
+    /// Adds tokens to the per-round breakdown of the allocation.
+    pub fn credit_round_tokens(&mut self, private_raw: u64, public_raw: u64) -> Result<()> {
+        self.private_round_tokens = self.private_round_tokens.checked_add(private_raw).ok_or(PresaleError::MathOverflow)?;
+        self.public_round_tokens = self.public_round_tokens.checked_add(public_raw).ok_or(PresaleError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Removes tokens from the allocation, taking purchased tokens before bonus tokens before referral
+    /// commissions, and public-round tokens before private-round ones.
+    /// Returns the (private, public, bonus, referral) split that was removed.
+    pub fn debit_allocation(&mut self, tokens_raw: u64) -> Result<(u64, u64, u64, u64)> {
+        let purchased_raw = tokens_raw.min(self.tokens_purchased);
+        let bonus_raw = (tokens_raw - purchased_raw).min(self.bonus_tokens);
+        let referral_raw = tokens_raw - purchased_raw - bonus_raw;
+        self.tokens_purchased -= purchased_raw;
+        self.bonus_tokens -= bonus_raw;
+        self.referral_tokens = self.referral_tokens.checked_sub(referral_raw).ok_or(PresaleError::MathOverflow)?;
+
+        let public_raw = purchased_raw.min(self.public_round_tokens);
+        let private_raw = (purchased_raw - public_raw).min(self.private_round_tokens);
+        self.public_round_tokens -= public_raw;
+        self.private_round_tokens -= private_raw;
+        Ok((private_raw, public_raw, bonus_raw, referral_raw))
+    }
+
+    /// Purchased, bonus and referral tokens: everything the buyer can eventually claim (raw units).
+    pub fn total_allocation(&self) -> Result<u64> {
+        Ok(self
+            .tokens_purchased
+            .checked_add(self.bonus_tokens)
+            .ok_or(PresaleError::MathOverflow)?
+            .checked_add(self.referral_tokens)
+            .ok_or(PresaleError::MathOverflow)?)
+    }
+
+    /// Records an on-chain payment into escrow so it can be refunded.
+    pub fn record_contribution(&mut self, currency: u8, amount: u64) -> Result<()> {
+        let contributed = self.contribution(currency);
+        *contributed = contributed.checked_add(amount).ok_or(PresaleError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Records the protocol fee taken out of an on-chain payment, which refunds don't return.
+    pub fn record_protocol_fee(&mut self, currency: u8, fee: u64) -> Result<()> {
+        let paid = match currency {
+            CURRENCY_SOL => &mut self.protocol_fee_lamports,
+            CURRENCY_USDC => &mut self.protocol_fee_usdc,
+            CURRENCY_USDT => &mut self.protocol_fee_usdt,
+            _ => &mut self.protocol_fee_pyusd,
+        };
+        *paid = paid.checked_add(fee).ok_or(PresaleError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// The refundable escrowed payments recorded in `currency` (lamports or raw units).
+    pub fn contribution(&mut self, currency: u8) -> &mut u64 {
+        match currency {
+            CURRENCY_SOL => &mut self.contributed_lamports,
+            CURRENCY_USDC => &mut self.contributed_usdc,
+            CURRENCY_USDT => &mut self.contributed_usdt,
+            _ => &mut self.contributed_pyusd,
+        }
+    }
+
+    /// Tokens allocated (including bonuses) but not yet claimed or forfeited (raw units).
+    pub fn unclaimed_tokens(&self) -> Result<u64> {
+        Ok(self.total_allocation()?.saturating_sub(self.settled_tokens()?))
+    }
+
+    /// Whether escrowed payments are still recorded against this account.
+    pub fn has_refundable_contribution(&self) -> bool {
+        self.contributed_lamports > 0
+            || self.contributed_usdc > 0
+            || self.contributed_usdt > 0
+            || self.contributed_pyusd > 0
+    }
+
+    /// Tokens already paid out or forfeited (raw units).
+    pub fn settled_tokens(&self) -> Result<u64> {
+        self.tokens_claimed.checked_add(self.tokens_forfeited).ok_or(PresaleError::MathOverflow.into())
+    }
+
+    /// Records `vested` (raw units) as the buyer's vested amount at a claim and returns how much it grew
+    /// since the last one. Settled tokens always count as vested.
+    pub fn record_vested(&mut self, vested: u64) -> Result<u64> {
+        let vested = vested.max(self.settled_tokens()?);
+        let delta = vested.saturating_sub(self.vested_recorded);
+        self.vested_recorded = self.vested_recorded.max(vested);
+        Ok(delta)
+    }
+}
+
+/// A Web2 purchase recorded by the backend, held until its chargeback window has passed.
+#[account]
+pub struct ProvisionalPurchase {
+    pub presale: Pubkey,      // Presale this purchase belongs to
+    pub buyer: Pubkey,        // Wallet credited on confirmation
+    pub sequence: u64,        // Recording order, part of the PDA seeds
+    pub currency: u8,         // Currency paid off-chain
+    pub amount_paid: u64,     // Lamports or stablecoin raw units paid off-chain
+    pub tokens: u64,          // Tokens bought (raw units)
+    pub stage: u8,            // Sale stage the purchase was made in
+    pub recorded_at: i64,     // When the backend recorded it (Unix time)
+    pub confirmable_at: i64,  // End of the chargeback window (Unix time)
+    pub bump: u8,             // Store bump here
+}
+
+impl ProvisionalPurchase {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        32 + // Buyer pubkey
+        8 +  // Sequence
+        1 +  // Currency
+        8 +  // Amount paid
+        8 +  // Tokens
+        1 +  // Stage
+        8 +  // Recorded at
+        8 +  // Confirmable at
+        1;   // Bump
+}
+
+/// A reservation of hardcap headroom, granted in sequence order.
+#[account]
+pub struct PurchaseTicket {
+    pub presale: Pubkey,  // Presale this ticket belongs to
+    pub buyer: Pubkey,    // Wallet that reserved the headroom
+    pub sequence: u64,    // Position in the reservation queue
+    pub tokens: u64,      // Reserved tokens (raw units)
+    pub expires_at: i64,  // Reservation expiry (Unix time)
+    pub bump: u8,         // Store bump here
+}
+
+impl PurchaseTicket {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        32 + // Buyer pubkey
+        8 +  // Sequence
+        8 +  // Reserved tokens
+        8 +  // Expiry
+        1;   // Bump
+}
+
+/// A buyer's hidden commitment to an upcoming purchase, for the commit-reveal purchase flow.
+#[account]
+pub struct PurchaseCommitment {
+    pub presale: Pubkey,         // Presale this commitment belongs to
+    pub buyer: Pubkey,           // Committing wallet
+    pub commitment: [u8; 32],    // keccak(buyer || amount || salt)
+    pub committed_at: i64,       // When the commitment was made
+    pub revealed: bool,          // Revealed by reveal_purchase
+    pub amount: u64,             // Revealed payment amount
+    pub bump: u8,                // Store bump here
+}
+
+impl PurchaseCommitment {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        32 + // Buyer pubkey
+        32 + // Commitment hash
+        8 +  // Committed at
+        1 +  // Revealed flag
+        8 +  // Amount
+        1;   // Bump
+}
+
+/// A fixed-size page of the on-chain buyer registry, so the full buyer set can be walked.
+#[account(zero_copy)]
+#[repr(C)]
+pub struct BuyerRegistryPage {
+    pub presale: Pubkey,                                         // Presale this page belongs to
+    pub page_index: u32,                                         // Position of this page in the registry
+    pub count: u32,                                              // Number of entries in use
+    pub entries: [BuyerRegistryEntry; REGISTRY_PAGE_CAPACITY],   // Buyers in order of first purchase
+}
+
+/// A single buyer entry in the registry.
+#[zero_copy]
+#[repr(C)]
+pub struct BuyerRegistryEntry {
+    pub buyer: Pubkey, // Buyer wallet
+    pub amount: u64,   // Tokens purchased (raw units)
+}
+
+impl BuyerRegistryPage {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        4 +  // Page index
+        4 +  // Entry count
+        REGISTRY_PAGE_CAPACITY * (32 + 8); // Entries
+
+    /// Appends a buyer to the page, returning their entry index.
+    pub fn append(&mut self, buyer: Pubkey, amount: u64) -> Result<u32> {
+        require!(
+            (self.count as usize) < REGISTRY_PAGE_CAPACITY,
+            PresaleError::RegistryPageFull
+        );
+
+        let index = self.count;
+        self.entries[index as usize] = BuyerRegistryEntry { buyer, amount };
+        self.count += 1;
+
+        Ok(index)
+    }
+}
+
+/// Ring buffer of the latest purchases, powering the recent buys ticker without an indexer.
+#[account(zero_copy)]
+#[repr(C)]
+pub struct PurchaseJournal {
+    pub presale: Pubkey,                                    // Presale this journal belongs to
+    pub head: u32,                                          // Slot the next purchase is written to
+    pub count: u32,                                         // Number of slots in use
+    pub entries: [JournalEntry; PURCHASE_JOURNAL_CAPACITY], // Latest purchases, overwritten oldest first
+}
+
+/// A single purchase in the journal.
+#[zero_copy]
+#[repr(C)]
+pub struct JournalEntry {
+    pub buyer: Pubkey,
+    pub timestamp: i64,
+    pub tokens: u64,      // Tokens bought (raw units)
+    pub amount_paid: u64, // Lamports or stablecoin raw units
+    pub currency: u8,     // CURRENCY_* code
+    pub _padding: [u8; 7],
+}
+
+impl PurchaseJournal {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        4 +  // Head
+        4 +  // Entry count
+        PURCHASE_JOURNAL_CAPACITY * (32 + 8 + 8 + 8 + 8); // Entries
+
+    /// Records a purchase, overwriting the oldest one once the buffer is full.
+    pub fn push(&mut self, entry: JournalEntry) {
+        self.entries[self.head as usize] = entry;
+        self.head = (self.head + 1) % PURCHASE_JOURNAL_CAPACITY as u32;
+        self.count = (self.count + 1).min(PURCHASE_JOURNAL_CAPACITY as u32);
+    }
+
+    /// The latest `limit` purchases, newest first.
+    pub fn latest(&self, limit: usize) -> impl Iterator<Item = &JournalEntry> {
+        let head = self.head as usize;
+        (1..=limit.min(self.count as usize)).map(move |back| {
+            &self.entries[(head + PURCHASE_JOURNAL_CAPACITY - back) % PURCHASE_JOURNAL_CAPACITY]
+        })
+    }
+}
+
+/// Sale terms in force when the sale opened, written once by `activate_sale` and never modified.
+#[account]
+pub struct TermsAnchor {
+    pub presale: Pubkey,           // Presale these terms belong to
+    pub terms_hash: [u8; 32],      // Hash of the off-chain sale terms document
+    pub activated_at: i64,         // Time the sale opened
+    pub config: SaleTermsSnapshot, // On-chain config at activation
+    pub bump: u8,
+}
+
+/// Snapshot of the presale config captured at activation.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default)]
+pub struct SaleTermsSnapshot {
+    pub usd_price_micros_per_nlov: u64,
+    pub sol_price_lamports_per_nlov: u64,
+    pub immutable_pricing: bool,
+    pub private_sale_duration: i64,   // Seconds
+    pub public_sale_duration: i64,    // Seconds
+    pub hardcap_tokens: u64,          // Raw units
+    pub softcap_tokens: u64,          // Raw units
+    pub max_allocation_per_buyer: u64, // Raw units, 0 = no cap
+    pub referral_commission_bps: u16,
+    pub vesting_duration: i64,        // Seconds, 0 = fully unlocked
+    pub vesting_cliff: i64,           // Seconds
+    pub tge_unlock_bps: u16,
+    pub early_claim_penalty_bps: u16,
+    pub tge_timestamp: i64,
+    pub identity_mode: u8,
+    pub identity_verifier: Pubkey,
+    pub merchant_wallet: Pubkey,
+    pub presale_wallet: Pubkey,
+    pub config_version: u32,
+    pub max_end_timestamp: i64,
+    pub private_round_vesting: LinearVesting,
+    pub public_round_vesting: LinearVesting,
+    pub milestone_vesting: bool,
+}
+
+impl SaleTermsSnapshot {
+    pub const LEN: usize = 8 + // USD price
+        8 +  // SOL price
+        1 +  // Immutable pricing flag
+        8 +  // Private sale duration
+        8 +  // Public sale duration
+        8 +  // Hardcap
+        8 +  // Softcap
+        8 +  // Max allocation per buyer
+        2 +  // Referral commission
+        8 +  // Vesting duration
+        8 +  // Vesting cliff
+        2 +  // TGE unlock bps
+        2 +  // Early claim penalty
+        8 +  // TGE timestamp
+        1 +  // Identity mode
+        32 + // Identity verifier
+        32 + // Merchant wallet
+        32 + // Presale wallet
+        4 +  // Config version
+        8 +  // Max end timestamp
+        LinearVesting::LEN + // Private round vesting
+        LinearVesting::LEN + // Public round vesting
+        1;   // Milestone vesting flag
+
+    pub fn capture(presale: &Presale) -> Self {
+        Self {
+            usd_price_micros_per_nlov: presale.usd_price_micros_per_nlov,
+            sol_price_lamports_per_nlov: presale.sol_price_lamports_per_nlov,
+            immutable_pricing: presale.immutable_pricing,
+            private_sale_duration: presale.private_sale_duration,
+            public_sale_duration: presale.public_sale_duration,
+            hardcap_tokens: presale.hardcap_tokens,
+            softcap_tokens: presale.softcap_tokens,
+            max_allocation_per_buyer: presale.max_allocation_per_buyer,
+            referral_commission_bps: presale.referral_commission_bps,
+            vesting_duration: presale.vesting_duration,
+            vesting_cliff: presale.vesting_cliff,
+            tge_unlock_bps: presale.tge_unlock_bps,
+            early_claim_penalty_bps: presale.early_claim_penalty_bps,
+            tge_timestamp: presale.tge_timestamp,
+            identity_mode: presale.identity_mode,
+            identity_verifier: presale.identity_verifier,
+            merchant_wallet: presale.merchant_wallet,
+            presale_wallet: presale.presale_wallet,
+            config_version: presale.config_version,
+            max_end_timestamp: presale.max_end_timestamp,
+            private_round_vesting: presale.private_round_vesting,
+            public_round_vesting: presale.public_round_vesting,
+            milestone_vesting: presale.milestone_vesting,
+        }
+    }
+}
+
+impl TermsAnchor {
+    pub const LEN: usize = 8 + // Discriminator
+        32 + // Presale pubkey
+        32 + // Terms hash
+        8 +  // Activation timestamp
+        SaleTermsSnapshot::LEN + // Config snapshot
+        1;   // Bump
+}
+
+/// Event emitted when tokens are purchased with SOL.
+#[event]
+pub struct BuyTokensEvent {
+    pub buyer: Pubkey,
+    pub tokens_purchased: u64, 
+    pub sol_spent: u64,
+    pub sol_price_lamports_per_nlov: u64, 
+    pub payment_type: u8,
+    pub discount_bps: u16, // Bulk-purchase or whitelist discount applied
+    pub rounding_mode: u8, // TOKEN_ROUNDING_* mode tokens_purchased was rounded with
+    pub protocol_fee: u64, // Part of the payment routed to the fee wallet instead of escrow
+}
+
+/// Event emitted when the sale price is updated.
+#[event]
+pub struct UpdateSalePriceEvent {
+    pub admin: Pubkey,
+    pub new_usd_price_micros: u64, 
+    pub new_sol_price_lamports: u64, 
+    pub sale_stage: u8,
+}
+
+/// Event emitted when a price update is scheduled.
+#[event]
+pub struct SchedulePriceUpdateEvent {
+    pub admin: Pubkey,
+    pub new_usd_price_micros: u64,
+    pub new_sol_price_lamports: u64,
+    pub effective_at: i64, // 0 = the pending update was cancelled
+}
+
+/// Event emitted when the public-round whitelist discount changes.
+#[event]
+pub struct WhitelistDiscountUpdatedEvent {
+    pub admin: Pubkey,
+    pub discount_bps: u16,
+}
+
+/// Event emitted when the token rounding mode changes.
+#[event]
+pub struct TokenRoundingModeUpdatedEvent {
+    pub admin: Pubkey,
+    pub rounding_mode: u8, // TOKEN_ROUNDING_* mode
+}
+
+/// Event emitted when the price change delay is raised.
+#[event]
+pub struct PriceChangeDelayUpdatedEvent {
+    pub admin: Pubkey,
+    pub delay_seconds: i64,
+}
+
+/// Event emitted when the price guardrails change.
+#[event]
+pub struct PriceGuardrailsUpdatedEvent {
+    pub admin: Pubkey,
+    pub guardrails: PriceGuardrails,
+}
+
+/// Event emitted when the SOL/USD oracle configuration changes.
+#[event]
+pub struct SolOracleConfiguredEvent {
+    pub admin: Pubkey,
+    pub price_feed: Pubkey,    // Default pubkey = manual SOL pricing
+    pub fallback_feed: Pubkey, // Default pubkey = no fallback
+    pub max_price_age: i64,
+    pub max_confidence_bps: u16,
+}
+
+/// Event emitted when the SOL/USD TWAP configuration changes.
+#[event]
+pub struct SolTwapConfiguredEvent {
+    pub admin: Pubkey,
+    pub observation: Pubkey, // Default pubkey = TWAP pricing off
+    pub twap_window: i64,
+    pub max_deviation_bps: u16,
+}
+
+/// Event emitted when volume-based pricing changes.
+#[event]
+pub struct VolumePricingUpdatedEvent {
+    pub admin: Pubkey,
+    pub step_tokens: u64, // Raw units; 0 = off
+    pub step_bps: u16,
+}
+
+/// Event emitted when the public round price ramp changes.
+#[event]
+pub struct PublicPriceRampUpdatedEvent {
+    pub admin: Pubkey,
+    pub end_markup_bps: u16, // 0 = off
 }
 
-/// Accounts for the `set_stage` instruction.
-#[derive(Accounts)]
-pub struct SetStage<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>, // Only admin can change sale stage
+/// Event emitted when the bulk-purchase discount tiers change.
+#[event]
+pub struct DiscountTiersUpdatedEvent {
+    pub admin: Pubkey,
+    pub tiers: Vec<DiscountTier>, // Empty = bulk discounts off
+}
 
-    #[account(
-        mut,
-        has_one = admin, // Ensures the stored presale.admin matches the Signer
-        seeds = [PRESALE_SEED, admin.key().as_ref()],
-        bump
-    )]
-    pub presale: Account<'info, Presale>,
+/// Event emitted when tokens are purchased with a stablecoin.
+#[event]
+pub struct BuyTokensByStableCoinEvent {
+    pub buyer: Pubkey,
+    pub tokens_purchased: u64,   
+    pub stable_coin_amount: u64, // Raw units of the stablecoin mint charged for the tokens
+    pub payment_type: u8,
+    pub discount_bps: u16, // Bulk-purchase or whitelist discount applied
+    pub rounding_mode: u8, // TOKEN_ROUNDING_* mode tokens_purchased was rounded with
+    pub protocol_fee: u64, // Part of the payment routed to the fee wallet instead of escrow
 }
 
-/// Accounts for the `update_sale_period` instruction.
-#[derive(Accounts)]
-pub struct UpdateSalePeriod<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>, // Only the admin can update the sale period
+/// Event emitted when tokens are purchased with a registered payment token.
+#[event]
+pub struct BuyTokensWithTokenEvent {
+    pub buyer: Pubkey,
+    pub mint: Pubkey,          // Payment token mint
+    pub tokens_purchased: u64,
+    pub amount: u64,           // Raw units of the payment token charged for the tokens
+    pub usd_micros: u64,       // The payment's USD value at the oracle price
+    pub discount_bps: u16,     // Bulk-purchase or whitelist discount applied
+    pub rounding_mode: u8,     // TOKEN_ROUNDING_* mode tokens_purchased was rounded with
+    pub protocol_fee: u64,     // Part of the payment routed to the fee wallet instead of escrow
+}
 
-    #[account(
-        mut,
-        has_one = admin, // Ensures only the admin can update the sale period
-        seeds = [PRESALE_SEED, admin.key().as_ref()],
-        bump
-    )]
-    pub presale: Account<'info, Presale>,
+/// Event emitted when the backend records a Web2 (off-chain) purchase.
+#[event]
+pub struct Web2PurchaseRecordedEvent {
+    pub buyer: Pubkey,
+    pub sequence: u64,         // Provisional purchase to confirm or cancel
+    pub currency: u8,
+    pub amount_paid: u64,      // Lamports or stablecoin raw units paid off-chain
+    pub tokens_purchased: u64, // Whole NLOV
+    pub confirmable_at: i64,   // End of the chargeback window
 }
 
-/// Accounts for the `buy_tokens` instruction (SOL payment).
-#[derive(Accounts)]
-pub struct BuyTokens<'info> {
-    #[account(mut)]
-    pub buyer: Signer<'info>, // The user buying tokens
+/// Event emitted when a provisional Web2 purchase is confirmed into the buyer's allocation.
+#[event]
+pub struct Web2PurchaseConfirmedEvent {
+    pub buyer: Pubkey,
+    pub sequence: u64,
+    pub tokens: u64, // Raw units
+    pub timestamp: i64,
+}
 
-    #[account(
-        mut,
-        seeds = [PRESALE_SEED, presale.admin.as_ref()],
-        bump,
-    )]
-    pub presale: Account<'info, Presale>, 
+/// Event emitted when a provisional Web2 purchase is cancelled before confirmation.
+#[event]
+pub struct Web2PurchaseCancelledEvent {
+    pub buyer: Pubkey,
+    pub sequence: u64,
+    pub tokens: u64, // Raw units released from `provisional_tokens`
+    pub timestamp: i64,
+}
 
-    #[account(mut)]
-    pub presale_wallet: Account<'info, TokenAccount>, 
+/// Event emitted when the presale is finalized.
+#[event]
+pub struct FinalizePresaleEvent {
+    pub admin: Pubkey,
+    pub unsold_presale_tokens: u64, 
+}
 
-    #[account(mut, address = presale.merchant_wallet)]
-    /// CHECK: Checked by presale.merchant_wallet
-    pub merchant_wallet: AccountInfo<'info>,
+/// Event emitted when a buyer mints their purchase receipt NFT.
+#[event]
+pub struct ReceiptMintedEvent {
+    pub buyer: Pubkey,
+    pub receipt_mint: Pubkey,
+    pub tokens_purchased: u64, // Raw units at the time the receipt was minted
+}
 
-    pub token_mint: Account<'info, Mint>, // To get token decimals for calculations
+/// Event emitted when a buyer stakes claimed tokens through `claim_and_stake`.
+#[event]
+pub struct TokensStakedEvent {
+    pub buyer: Pubkey,
+    pub stake_account: Pubkey,
+    pub tokens_claimed: u64, // Raw units
+    pub stake_bonus: u64,    // Raw units, paid on top of the claim
+}
 
-    pub system_program: Program<'info, System>, 
-    pub token_program: Program<'info, Token>,   
-    pub associated_token_program: Program<'info, AssociatedToken>, 
+/// Event emitted when a buyer claims vested tokens.
+#[event]
+pub struct TokensClaimedEvent {
+    pub buyer: Pubkey,
+    pub tokens_claimed: u64,   // Raw units
+    pub tokens_remaining: u64, // Unlocked tokens still unclaimed after this claim; the unclaimed allocation for `claim_for` (raw units)
+    pub total_allocation: u64, // Buyer's full allocation: purchased, bonus and referral (raw units)
+    pub total_claimed: u64,    // Cumulative tokens claimed by the buyer, including this claim (raw units)
+    pub tokens_locked: u64,    // Tokens still waiting to vest (raw units)
 }
 
-/// Accounts for the `check_presale_token_balance` instruction.
-#[derive(Accounts)]
-pub struct CheckPresaleTokenBalance<'info> {
-    #[account(
-        seeds = [PRESALE_SEED, presale.admin.as_ref()],
-        bump,
-    )]
-    pub presale: Account<'info, Presale>, // Presale storage PDA
+/// Event emitted when a buyer exits vesting early and forfeits part of their allocation.
+#[event]
+pub struct TokensForfeitedEvent {
+    pub buyer: Pubkey,
+    pub tokens_released: u64,  // Raw units sent to the buyer
+    pub tokens_forfeited: u64, // Raw units sent to the penalty wallet or burned
+    pub penalty_bps: u16,
+    pub burned: bool,
+}
 
-    #[account()]
-    pub presale_wallet: Account<'info, TokenAccount>, // Store presale tokens
+/// Event emitted when a buyer changes the wallet receiving their claims.
+#[event]
+pub struct ClaimRecipientUpdatedEvent {
+    pub buyer: Pubkey,
+    pub previous_recipient: Pubkey,
+    pub new_recipient: Pubkey,
+}
 
-    pub token_mint: Account<'info, Mint>, // To get token decimals for calculations
+/// Event emitted when the operator annotates a buyer's account.
+#[event]
+pub struct ComplianceNoteUpdatedEvent {
+    pub buyer: Pubkey,
+    pub operator: Pubkey,
+    pub previous_note: [u8; COMPLIANCE_NOTE_LEN],
+    pub note: [u8; COMPLIANCE_NOTE_LEN],
 }
 
-/// Accounts for the `update_sale_price` instruction.
-#[derive(Accounts)]
-pub struct UpdateSalePrice<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>, // Only the admin can update the price
+/// Event emitted when the admin sets or rotates the guardian that co-signs a lockdown.
+#[event]
+pub struct GuardianUpdatedEvent {
+    pub admin: Pubkey,
+    pub guardian: Pubkey,
+}
 
-    #[account(
-        mut,
-        has_one = admin, // Ensures only the admin can update
-        seeds = [PRESALE_SEED, admin.key().as_ref()],
-        bump
-    )]
-    pub presale: Account<'info, Presale>,
+/// Event emitted when the admin and guardian lock the presale down.
+#[event]
+pub struct LockdownEvent {
+    pub admin: Pubkey,
+    pub guardian: Pubkey,
+    pub timestamp: i64,
+}
+
+/// Event emitted when the admin starts the timelock to lift a lockdown.
+#[event]
+pub struct LockdownLiftRequestedEvent {
+    pub admin: Pubkey,
+    pub lift_at: i64,
+}
+
+/// Event emitted when a lockdown is lifted.
+#[event]
+pub struct LockdownLiftedEvent {
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
+
+/// Event emitted when a buyer reserves hardcap headroom with a ticket.
+#[event]
+pub struct TicketReservedEvent {
+    pub buyer: Pubkey,
+    pub sequence: u64,
+    pub tokens: u64, // Raw units
+    pub expires_at: i64,
+}
+
+/// Event emitted when a buyer moves allocation to another wallet.
+#[event]
+pub struct AllocationTransferredEvent {
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub amount: u64, // Raw units
+}
+
+/// Event emitted when the admin cancels the presale.
+#[event]
+pub struct PresaleCancelledEvent {
+    pub admin: Pubkey,
+    pub total_sold: u64, // Raw units
+}
+
+/// Event emitted when a buyer reclaims their escrowed payments.
+#[event]
+pub struct RefundClaimedEvent {
+    pub buyer: Pubkey,
+    pub lamports: u64,
+    pub usdc: u64,            // Raw units
+    pub usdt: u64,            // Raw units
+    pub tokens_released: u64, // Allocation given up (raw units)
+    pub pyusd: u64,           // Raw units
+    pub protocol_fee_lamports: u64, // Protocol fees kept by the fee wallet, not refunded
+    pub protocol_fee_usdc: u64,     // Raw units, not refunded
+    pub protocol_fee_usdt: u64,     // Raw units, not refunded
+    pub protocol_fee_pyusd: u64,    // Raw units, not refunded
+}
+
+/// Event emitted when a buyer reclaims escrowed payments in a registered payment token.
+#[event]
+pub struct TokenRefundClaimedEvent {
+    pub buyer: Pubkey,
+    pub mint: Pubkey,         // Payment token refunded
+    pub amount: u64,          // Raw units of `mint`
+    pub tokens_released: u64, // Allocation given up (raw units), 0 if an earlier refund released it
+}
+
+/// Event emitted at finalization when unsold tokens are scheduled to stream to the community rewards wallet.
+#[event]
+pub struct UnsoldStreamScheduledEvent {
+    pub community_rewards_wallet: Pubkey,
+    pub total: u64, // Raw units
+    pub months: u8,
+    pub start: i64,
+}
+
+/// Event emitted when unsold-token tranches are released.
+#[event]
+pub struct TrancheReleasedEvent {
+    pub tranches_released: u64, // Tranches due so far
+    pub amount: u64,            // Raw units released by this crank
+    pub total_released: u64,    // Raw units released to date
+}
+
+/// Event emitted when the admin revokes a buyer's allocation.
+#[event]
+pub struct AllocationRevokedEvent {
+    pub admin: Pubkey,
+    pub buyer: Pubkey,
+    pub tokens_revoked: u64, // Raw units
+    pub revocation_pool: Pubkey,
+    pub reason: [u8; COMPLIANCE_NOTE_LEN],
+}
+
+/// Event emitted when the admin rolls back a stage transition.
+#[event]
+pub struct StageRolledBackEvent {
+    pub admin: Pubkey,
+    pub from_stage: u8,
+    pub to_stage: u8,
+    pub timestamp: i64,
+}
+
+/// Event emitted when the admin sets the stage rollback window.
+#[event]
+pub struct StageRollbackWindowUpdatedEvent {
+    pub admin: Pubkey,
+    pub window_seconds: i64,
+}
+
+/// Event emitted when a buyer is granted bonus tokens.
+#[event]
+pub struct BonusGrantedEvent {
+    pub buyer: Pubkey,
+    pub bonus_tokens: u64,       // Bonus granted (raw units)
+    pub total_bonus_tokens: u64, // Buyer's total bonus after this grant (raw units)
+}
+
+/// Event emitted when a purchase is reversed in kind.
+#[event]
+pub struct PurchaseReversedEvent {
+    pub buyer: Pubkey,
+    pub operator: Pubkey,
+    pub purchase_index: u16, // Index of the purchase in the buyer's history
+    pub currency: u8,
+    pub amount_refunded: u64, // Lamports or stablecoin raw units returned (0 for Web2)
+    pub tokens: u64,          // Tokens de-allocated (raw units)
+}
+
+/// Event emitted when a referrer is credited a commission.
+#[event]
+pub struct ReferralCreditedEvent {
+    pub referrer: Pubkey,
+    pub buyer: Pubkey,            // Referred buyer
+    pub commission_tokens: u64,   // Raw units
+}
+
+/// Event emitted when the sale opens with its terms anchored on-chain.
+#[event]
+pub struct SaleActivatedEvent {
+    pub admin: Pubkey,
+    pub terms_hash: [u8; 32],
+    pub config_version: u32,
+    pub timestamp: i64,
+}
+
+/// Event emitted when a buyer delegates claims to a separate claim authority.
+#[event]
+pub struct ClaimAuthoritySetEvent {
+    pub buyer: Pubkey,
+    pub claim_authority: Pubkey,
+    pub operator: Pubkey, // Operator or admin who co-signed
+}
+
+/// Event emitted when a batch of historical buyers is imported.
+#[event]
+pub struct BuyersImportedEvent {
+    pub buyers_imported: u32,
+    pub tokens_imported: u64, // Raw units, this batch
+    pub total_imported: u64,  // Raw units, all batches
+}
+
+/// Event emitted for every withdrawal of escrowed proceeds to the merchant.
+#[event]
+pub struct ProceedsWithdrawnEvent {
+    pub admin: Pubkey,
+    pub currency: u8,
+    pub mint: Pubkey,   // Token mint withdrawn (default for SOL)
+    pub amount: u64,    // Lamports, or raw token units
+    pub remaining: u64, // Left in the escrow (vault) afterwards
+    pub timestamp: i64,
+}
+
+/// Event emitted when a period of proceeds is settled to the merchant.
+#[event]
+pub struct PeriodSettledEvent {
+    pub index: u32,
+    pub period_start: i64,
+    pub period_end: i64,
+    pub net_lamports: u64,
+    pub net_usdc: u64, // Raw units
+    pub net_usdt: u64, // Raw units
+    pub net_pyusd: u64, // Raw units
+    pub payment_tokens: u32, // Payment tokens settled, each reported by a PaymentTokenSettledEvent
+}
+
+/// Event emitted by `settle_period` for each registered payment token it pays out.
+#[event]
+pub struct PaymentTokenSettledEvent {
+    pub index: u32, // Settlement the payout belongs to
+    pub mint: Pubkey,
+    pub amount: u64, // Raw units
+}
+
+/// Event emitted when `claim_and_stake` is configured.
+#[event]
+pub struct StakingConfiguredEvent {
+    pub admin: Pubkey,
+    pub staking_program: Pubkey,
+    pub staking_vault: Pubkey,
+    pub stake_bonus_bps: u16,
+}
+
+/// Event emitted when the fee authority changes the protocol fee.
+#[event]
+pub struct ProtocolFeeUpdatedEvent {
+    pub fee_authority: Pubkey,
+    pub fee_bps: u16,
+    pub fee_wallet: Pubkey,
+}
+
+/// Event emitted when the admin changes the treasury splits.
+#[event]
+pub struct TreasurySplitsUpdatedEvent {
+    pub admin: Pubkey,
+    pub splits: Vec<TreasurySplit>, // Empty = proceeds go to the merchant wallet
+}
+
+/// Event emitted when the admin sets or rotates the gatekeeper key.
+#[event]
+pub struct GatekeeperUpdatedEvent {
+    pub admin: Pubkey,
+    pub gatekeeper: Pubkey, // Default pubkey = no co-signature required
+}
+
+/// Event emitted when the admin sets or rotates the operator.
+#[event]
+pub struct OperatorUpdatedEvent {
+    pub admin: Pubkey,
+    pub previous_operator: Pubkey,
+    pub operator: Pubkey,
+}
+
+/// Event emitted when the admin sets or rotates the Web2 recorder key.
+#[event]
+pub struct Web2RecorderUpdatedEvent {
+    pub admin: Pubkey,
+    pub previous_recorder: Pubkey,
+    pub web2_recorder: Pubkey, // Default pubkey = Web2 purchases disabled
+}
+
+/// Event emitted when the admin sets or rotates the KYC authority.
+#[event]
+pub struct KycAuthorityUpdatedEvent {
+    pub admin: Pubkey,
+    pub previous_authority: Pubkey,
+    pub kyc_authority: Pubkey, // Default pubkey = KYC mode off
+}
+
+/// Event emitted when the admin configures the identity/KYC provider.
+#[event]
+pub struct IdentityVerifierUpdatedEvent {
+    pub admin: Pubkey,
+    pub verifier: Pubkey,
+    pub mode: u8, // IDENTITY_MODE_NONE = identity checks off
+    pub subject_offset: u16,
+}
+
+/// Event emitted when the blocked region bitmask changes.
+#[event]
+pub struct BlockedRegionsUpdatedEvent {
+    pub admin: Pubkey,
+    pub blocked_regions: u64,
+}
+
+/// Event emitted when the Web2 chargeback window changes.
+#[event]
+pub struct Web2ChargebackWindowUpdatedEvent {
+    pub admin: Pubkey,
+    pub window_seconds: i64,
+}
+
+/// Event emitted when the per-wallet purchase cooldown changes.
+#[event]
+pub struct PurchaseCooldownUpdatedEvent {
+    pub admin: Pubkey,
+    pub cooldown_seconds: i64,
+}
+
+/// Event emitted when the commit-reveal delay changes.
+#[event]
+pub struct CommitRevealDelayUpdatedEvent {
+    pub admin: Pubkey,
+    pub delay_seconds: i64, // 0 = commit-reveal off
+}
+
+/// Event emitted when the per-round purchase limits change.
+#[event]
+pub struct PurchaseLimitsUpdatedEvent {
+    pub admin: Pubkey,
+    pub private_round_limit: u16, // 0 = unlimited
+    pub public_round_limit: u16,  // 0 = unlimited
+}
+
+/// Event emitted when the per-buyer allocation cap changes.
+#[event]
+pub struct MaxAllocationPerBuyerUpdatedEvent {
+    pub admin: Pubkey,
+    pub max_tokens: u64, // Raw units, 0 = uncapped
+}
+
+/// Event emitted when token releases are paused or resumed.
+#[event]
+pub struct ClaimsPausedEvent {
+    pub admin: Pubkey,
+    pub paused: bool,
+    pub timestamp: i64,
 }
 
-/// Accounts for the `buy_tokens_by_stable_coin` instruction.
-#[derive(Accounts)]
-pub struct BuyTokensByStableCoin<'info> {
-    #[account(mut)]
-    pub buyer: Signer<'info>, // The user buying tokens
+/// Event emitted when the TGE is scheduled.
+#[event]
+pub struct TgeTimestampUpdatedEvent {
+    pub admin: Pubkey,
+    pub tge_timestamp: i64, // 0 = claims open at finalization
+}
 
-    #[account(
-        mut,
-        seeds = [PRESALE_SEED, presale.admin.as_ref()],
-        bump,
-    )]
-    pub presale: Account<'info, Presale>, // Presale storage PDA
+/// Event emitted when the claim deadline changes.
+#[event]
+pub struct ClaimDeadlineUpdatedEvent {
+    pub admin: Pubkey,
+    pub claim_deadline: i64, // 0 = no deadline
+}
 
-    #[account(mut)]
-    pub presale_wallet: Account<'info, TokenAccount>, // Presale token storage
+/// Event emitted when the vesting start is scheduled.
+#[event]
+pub struct VestingStartUpdatedEvent {
+    pub admin: Pubkey,
+    pub vesting_start: i64, // 0 = vesting starts at finalization
+}
 
-    #[account(mut)]
-    pub buyer_stable_coin_account: Account<'info, TokenAccount>, // Buyer’s stablecoin account
+/// Event emitted when the private-round whitelist root changes.
+#[event]
+pub struct WhitelistRootUpdatedEvent {
+    pub admin: Pubkey,
+    pub whitelist_root: [u8; 32], // Zero = whitelist off
+}
 
-    #[account(mut)]
-    pub merchant_stable_coin_account: Account<'info, TokenAccount>, // Merchant’s stablecoin account
+/// Event emitted when a phase whitelist root is set or cleared.
+#[event]
+pub struct PhaseWhitelistRootUpdatedEvent {
+    pub admin: Pubkey,
+    pub slot: u8,
+    pub stage: u8,
+    pub activates_at: i64,
+    pub root: [u8; 32], // Zero = slot cleared
+}
 
-    #[account()]
-    pub stable_coin_mint: Account<'info, Mint>, // Stablecoin mint (USDC or USDT)
+/// Event emitted when the per-tier private-round caps change.
+#[event]
+pub struct WhitelistTierCapsUpdatedEvent {
+    pub admin: Pubkey,
+    pub tier_caps: [u64; WHITELIST_TIER_COUNT], // Raw units, 0 = uncapped
+}
 
-    pub token_mint: Account<'info, Mint>, 
+/// Event emitted when a wallet is added to the private-round whitelist.
+#[event]
+pub struct WalletWhitelistedEvent {
+    pub admin: Pubkey,
+    pub wallet: Pubkey,
+    pub tier: u8,
+}
 
-    pub token_program: Program<'info, Token>, 
-    pub associated_token_program: Program<'info, AssociatedToken>, 
-    pub system_program: Program<'info, System>, 
+/// Event emitted when a wallet is removed from the private-round whitelist.
+#[event]
+pub struct WalletRemovedFromWhitelistEvent {
+    pub admin: Pubkey,
+    pub wallet: Pubkey,
 }
 
-/// Accounts for the `finalize_presale` instruction.
-#[derive(Accounts)]
-pub struct FinalizePresale<'info> {
-    #[account(mut)]
-    pub admin: Signer<'info>,
+/// Event emitted when the registration deposit changes.
+#[event]
+pub struct RegistrationDepositUpdatedEvent {
+    pub admin: Pubkey,
+    pub deposit_lamports: u64, // 0 = registration closed
+}
 
-    #[account(
-        mut,
-        has_one = admin,
-        seeds = [PRESALE_SEED, admin.key().as_ref()],
-        bump
-    )]
-    pub presale: Account<'info, Presale>,
+/// Event emitted when a registration is approved, whitelisting its wallet.
+#[event]
+pub struct RegistrationApprovedEvent {
+    pub admin: Pubkey,
+    pub wallet: Pubkey,
+    pub tier: u8,
+}
 
-    #[account(mut)]
-    pub presale_wallet: Account<'info, TokenAccount>, 
+/// Event emitted when a wallet is blacklisted.
+#[event]
+pub struct WalletBlacklistedEvent {
+    pub admin: Pubkey,
+    pub wallet: Pubkey,
+    pub timestamp: i64,
+}
 
-    #[account(mut)]
-    pub liquidity_wallet: Account<'info, TokenAccount>, 
+/// Event emitted when a wallet is removed from the blacklist.
+#[event]
+pub struct WalletUnblacklistedEvent {
+    pub admin: Pubkey,
+    pub wallet: Pubkey,
+    pub timestamp: i64,
+}
 
-    pub token_mint: Account<'info, Mint>, 
+/// Event emitted when a new sale is cloned from a template sale.
+#[event]
+pub struct SaleClonedEvent {
+    pub admin: Pubkey,
+    pub template_sale: Pubkey,
+    pub presale: Pubkey,
+    pub usd_price_micros_per_nlov: u64,
+    pub sol_price_lamports_per_nlov: u64,
+    pub hardcap_tokens: u64,
+}
 
-    pub token_program: Program<'info, Token>, 
+/// Event emitted when a stablecoin mint is registered as an accepted currency.
+#[event]
+pub struct AcceptedCurrencyAddedEvent {
+    pub admin: Pubkey,
+    pub currency: u8,
+    pub mint: Pubkey,
+    pub decimals: u8,
 }
 
-/// Defines the state of the presale contract.
-#[account]
-pub struct Presale {
-    pub admin: Pubkey,              // Admin wallet address
-    pub presale_start: i64,         // Presale start timestamp (Unix time)
-    pub usd_price_cents_per_nlov: u64, // Price in USD cents per NLOV (e.g., 3 for $0.03)
-    pub sol_price_lamports_per_nlov: u64, // Price in SOL lamports per NLOV (e.g., 182_000_000 for 0.182 SOL)
-    pub private_sale_duration: i64, // Private sale duration (in seconds)
-    pub public_sale_duration: i64,  // Public sale duration (in seconds)
-    pub sale_stage: u8,             // Sale stage (0: Not started, 1: Private, 2: Public, 3: Ended)
-    pub total_sold: u64,            // Total tokens sold (in raw token units with decimals)
-    pub hardcap_tokens: u64,        // New: Maximum tokens to be sold in presale
-    pub pool_created: bool,         // Liquidity pool created flag
-    pub presale_wallet: Pubkey,     // Token account for presale
-    pub merchant_wallet: Pubkey,    // Wallet for receiving SOL/Stablecoin payments
-    pub bump: u8,                   // Store bump here
+/// Event emitted when purchases in an accepted currency are paused or resumed.
+#[event]
+pub struct AcceptedCurrencyEnabledEvent {
+    pub admin: Pubkey,
+    pub currency: u8,
+    pub mint: Pubkey,
+    pub enabled: bool,
 }
 
-/// Event emitted when tokens are purchased with SOL.
+/// Event emitted when an accepted currency is removed.
 #[event]
-pub struct BuyTokensEvent {
-    pub buyer: Pubkey,
-    pub tokens_purchased: u64, 
-    pub sol_spent: u64,
-    pub sol_price_lamports_per_nlov: u64, 
-    pub payment_type: u8,
+pub struct AcceptedCurrencyRemovedEvent {
+    pub admin: Pubkey,
+    pub currency: u8,
+    pub mint: Pubkey,
 }
 
-/// Event emitted when the sale price is updated.
+/// Event emitted when a token is registered as a payment token.
 #[event]
-pub struct UpdateSalePriceEvent {
+pub struct PaymentTokenAddedEvent {
     pub admin: Pubkey,
-    pub new_usd_price_cents: u64, 
-    pub new_sol_price_lamports: u64, 
-    pub sale_stage: u8,
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub price_feed: Pubkey, // Pyth `PriceUpdateV2` account pricing the token
+    pub max_price_age: i64,
+    pub max_confidence_bps: u16,
 }
 
-/// Event emitted when tokens are purchased with a stablecoin.
+/// Event emitted when purchases in a payment token are paused or resumed.
 #[event]
-pub struct BuyTokensByStableCoinEvent {
-    pub buyer: Pubkey,
-    pub tokens_purchased: u64,   
-    pub stable_coin_amount: u64, 
-    pub payment_type: u8,
+pub struct PaymentTokenEnabledEvent {
+    pub admin: Pubkey,
+    pub mint: Pubkey,
+    pub enabled: bool,
 }
 
-/// Event emitted when the presale is finalized.
+/// Event emitted when the admin changes the referral commission.
 #[event]
-pub struct FinalizePresaleEvent {
+pub struct ReferralCommissionUpdatedEvent {
     pub admin: Pubkey,
-    pub unsold_presale_tokens: u64, 
+    pub commission_bps: u16,
+}
+
+/// Event emitted when a referral commission is taken back because the referred purchase was refunded or reversed.
+#[event]
+pub struct ReferralReversedEvent {
+    pub referrer: Pubkey,
+    pub buyer: Pubkey,          // Referred buyer
+    pub commission_tokens: u64, // Commission credited on the purchase (raw units)
+    pub reversed_tokens: u64,   // Taken back; less if the referrer already claimed part of it (raw units)
 }
 
 /// Custom error codes for the presale program.
@@ -741,7 +13377,7 @@ pub enum PresaleError {
     #[msg("Not enough stablecoin available for purchase.")]
     InsufficientStableCoin,
 
-    #[msg("Invalid payment type. Please choose 0 for Web3 or 1 for Web2.")]
+    #[msg("Invalid payment type. Web2 purchases are recorded by the Web2 recorder.")]
     InvalidPaymentType,
 
     #[msg("Invalid price: Equivalent USD value must be at least $1.")]
@@ -758,4 +13394,412 @@ pub enum PresaleError {
 
     #[msg("Hardcap for tokens has been reached.")] 
     HardcapReached,
+
+    #[msg("No purchase has been recorded for this buyer.")]
+    NoPurchaseRecorded,
+
+    #[msg("A receipt NFT has already been minted for this buyer.")]
+    ReceiptAlreadyMinted,
+
+    #[msg("The presale has not been finalized yet.")]
+    PresaleNotFinalized,
+
+    #[msg("Invalid currency: not a CURRENCY_* code this instruction accepts.")]
+    InvalidCurrency,
+
+    #[msg("There are no escrowed proceeds to withdraw.")]
+    NothingToWithdraw,
+
+    #[msg("The buyer registry page is full. Create the next page first.")]
+    RegistryPageFull,
+
+    #[msg("The buyer registry page is not full yet.")]
+    RegistryPageNotFull,
+
+    #[msg("Wrong buyer registry page provided.")]
+    InvalidRegistryPage,
+
+    #[msg("Vesting can no longer be configured after finalization.")]
+    VestingScheduleLocked,
+
+    #[msg("Invalid vesting schedule.")]
+    InvalidVestingSchedule,
+
+    #[msg("Penalty must not exceed 10000 basis points.")]
+    InvalidPenalty,
+
+    #[msg("Early claims are not enabled for this presale.")]
+    EarlyClaimDisabled,
+
+    #[msg("No tokens available to claim.")]
+    NothingToClaim,
+
+    #[msg("Purchase history is full for this wallet.")]
+    PurchaseHistoryFull,
+
+    #[msg("Invalid claim recipient.")]
+    InvalidClaimRecipient,
+
+    #[msg("The presale is locked down.")]
+    LockedDown,
+
+    #[msg("The presale is not locked down.")]
+    NotLockedDown,
+
+    #[msg("The lockdown lift timelock has not elapsed.")]
+    LockdownTimelockActive,
+
+    #[msg("Claim amount must be greater than zero.")]
+    InvalidClaimAmount,
+
+    #[msg("Claim amount exceeds the unlocked, unclaimed allocation.")]
+    ClaimExceedsUnlocked,
+
+    #[msg("The purchase ticket has expired.")]
+    TicketExpired,
+
+    #[msg("The purchase ticket has not expired yet.")]
+    TicketNotExpired,
+
+    #[msg("Purchase exceeds the reserved amount.")]
+    ExceedsReservation,
+
+    #[msg("Not enough unclaimed allocation.")]
+    InsufficientAllocation,
+
+    #[msg("Allocation cannot be transferred to the same wallet.")]
+    InvalidTransferRecipient,
+
+    #[msg("The presale has been cancelled.")]
+    SaleCancelled,
+
+    #[msg("The softcap was not met.")]
+    SoftcapNotMet,
+
+    #[msg("Softcap cannot exceed the hardcap.")]
+    InvalidSoftcap,
+
+    #[msg("Refunds are only available for cancelled sales or sales below the softcap.")]
+    RefundsNotAvailable,
+
+    #[msg("Nothing to refund.")]
+    NothingToRefund,
+
+    #[msg("No unsold-token stream is scheduled.")]
+    NoUnsoldStream,
+
+    #[msg("No unsold-token tranche is due yet.")]
+    NoTrancheDue,
+
+    #[msg("This allocation has been revoked.")]
+    AllocationRevoked,
+
+    #[msg("The stage rollback window must be between 0 and MAX_STAGE_ROLLBACK_WINDOW seconds.")]
+    InvalidRollbackWindow,
+
+    #[msg("There is no stage transition to roll back.")]
+    NoStageToRollBack,
+
+    #[msg("The stage rollback window has elapsed.")]
+    RollbackWindowElapsed,
+
+    #[msg("Purchases occurred under the current stage.")]
+    PurchasesInStage,
+
+    #[msg("Invalid identity verification mode.")]
+    InvalidIdentityMode,
+
+    #[msg("Buyer identity could not be verified.")]
+    IdentityNotVerified,
+
+    #[msg("Pricing is immutable for this sale.")]
+    PricingImmutable,
+
+    #[msg("Bonus amount must be greater than zero.")]
+    InvalidBonus,
+
+    #[msg("No purchase at this index in the buyer's history.")]
+    PurchaseNotFound,
+
+    #[msg("This purchase has already been reversed.")]
+    PurchaseAlreadyReversed,
+
+    #[msg("The purchase reversal window has elapsed.")]
+    ReversalWindowElapsed,
+
+    #[msg("The purchase journal account is required.")]
+    PurchaseJournalRequired,
+
+    #[msg("Invalid TGE timestamp.")]
+    InvalidTgeTimestamp,
+
+    #[msg("The TGE has already passed.")]
+    TgeAlreadyPassed,
+
+    #[msg("Claims are not open before the TGE.")]
+    TgeNotReached,
+
+    #[msg("Batch claims take (buyer account, recipient token account) pairs for this presale.")]
+    InvalidClaimBatch,
+
+    #[msg("This purchase would exceed the per-buyer allocation cap.")]
+    WalletCapExceeded,
+
+    #[msg("Invalid referrer.")]
+    InvalidReferrer,
+
+    #[msg("Referral commission cannot exceed MAX_REFERRAL_COMMISSION_BPS.")]
+    InvalidReferralCommission,
+
+    #[msg("The sale has already been activated.")]
+    SaleAlreadyActivated,
+
+    #[msg("Terms hash must not be empty.")]
+    InvalidTermsHash,
+
+    #[msg("Claim deadline cannot be negative.")]
+    InvalidClaimDeadline,
+
+    #[msg("The claim deadline has not passed yet.")]
+    ClaimDeadlineNotReached,
+
+    #[msg("Buyer account still holds unclaimed tokens or refundable payments.")]
+    AllocationNotSettled,
+
+    #[msg("Max end timestamp cannot be negative.")]
+    InvalidMaxEndTimestamp,
+
+    #[msg("The sale schedule would end after the maximum end timestamp.")]
+    SaleEndExceedsMax,
+
+    #[msg("Claim authority cannot be the default pubkey.")]
+    InvalidClaimAuthority,
+
+    #[msg("A claim authority is already set for this allocation.")]
+    ClaimAuthorityAlreadySet,
+
+    #[msg("Claims for this allocation must be signed by its claim authority.")]
+    ClaimAuthorityRequired,
+
+    #[msg("Import batch entries do not match the buyer accounts passed.")]
+    InvalidImportBatch,
+
+    #[msg("Imported allocations cannot exceed tokens sold.")]
+    ImportExceedsTotalSold,
+
+    #[msg("The purchase deadline has passed.")]
+    PurchaseExpired,
+
+    #[msg("Aggregate positions take distinct (presale, presale wallet, buyer account) triples for sales of this token.")]
+    InvalidAggregateBatch,
+
+    #[msg("Vesting milestones need strictly increasing timestamps and shares summing to 100%.")]
+    InvalidVestingMilestones,
+
+    #[msg("The presale's vesting schedule account is required for milestone vesting.")]
+    VestingScheduleRequired,
+
+    #[msg("Proceeds have already been settled to the merchant.")]
+    ProceedsAlreadySettled,
+
+    #[msg("Token claims are paused.")]
+    ClaimsPaused,
+
+    #[msg("The unvested remainder of this allocation has already been revoked.")]
+    VestingAlreadyRevoked,
+
+    #[msg("Arithmetic overflow in a token amount calculation.")]
+    MathOverflow,
+
+    #[msg("The vesting start must be in the future.")]
+    InvalidVestingStart,
+
+    #[msg("Vesting has already started.")]
+    VestingAlreadyStarted,
+
+    #[msg("The vesting statistics account is required.")]
+    VestingStatsRequired,
+
+    #[msg("No staking program has been configured.")]
+    StakingNotConfigured,
+
+    #[msg("Stake bonus cannot exceed 10000 bps.")]
+    InvalidStakeBonus,
+
+    #[msg("The buyer is not on the private round whitelist.")]
+    NotWhitelisted,
+
+    #[msg("Invalid whitelist tier.")]
+    InvalidWhitelistTier,
+
+    #[msg("This purchase would exceed the buyer's whitelist tier cap.")]
+    WhitelistTierCapExceeded,
+
+    #[msg("A KYC attestation signed by the KYC authority is required.")]
+    KycAttestationRequired,
+
+    #[msg("The KYC attestation has expired.")]
+    KycAttestationExpired,
+
+    #[msg("The buyer is attested in a blocked region.")]
+    RegionRestricted,
+
+    #[msg("The wallet is blacklisted.")]
+    WalletBlacklisted,
+
+    #[msg("The buyer's blacklist account is required once the blacklist is on.")]
+    BlacklistRequired,
+
+    #[msg("The purchase cooldown must not be negative.")]
+    InvalidPurchaseCooldown,
+
+    #[msg("Wait for the purchase cooldown before buying again.")]
+    PurchaseCooldownActive,
+
+    #[msg("The wallet has reached the maximum number of purchases for this round.")]
+    PurchaseLimitReached,
+
+    #[msg("Registration is closed.")]
+    RegistrationClosed,
+
+    #[msg("The registration deposit is locked until the registration is approved or the sale starts.")]
+    RegistrationDepositLocked,
+
+    #[msg("Public-round purchases must be co-signed by the gatekeeper.")]
+    GatekeeperSignatureRequired,
+
+    #[msg("The commit-reveal delay must not be negative.")]
+    InvalidCommitRevealDelay,
+
+    #[msg("The purchase commitment cannot be revealed yet.")]
+    CommitmentTooRecent,
+
+    #[msg("The revealed amount and salt do not match the commitment.")]
+    CommitmentMismatch,
+
+    #[msg("Public-round purchases must consume a revealed commitment for the same amount.")]
+    PurchaseCommitmentRequired,
+
+    #[msg("Whitelist phases need a valid slot and the private or public stage.")]
+    InvalidWhitelistPhase,
+
+    #[msg("The oracle config needs a positive maximum price age, a confidence limit of at most 100%, and a primary feed for any fallback.")]
+    InvalidOracleConfig,
+
+    #[msg("The SOL/USD price account is required once a price feed is configured.")]
+    OraclePriceRequired,
+
+    #[msg("The SOL/USD price account is invalid.")]
+    InvalidOraclePrice,
+
+    #[msg("The SOL/USD price is too old.")]
+    StaleOraclePrice,
+
+    #[msg("The SOL/USD price's confidence interval is too wide.")]
+    OracleConfidenceTooWide,
+
+    #[msg("Stage prices need the private or public stage, positive prices, and at most one entry per stage.")]
+    InvalidStagePrice,
+
+    #[msg("Volume pricing needs both a step size and a step increase, or neither.")]
+    InvalidVolumePricing,
+
+    #[msg("Discount tiers need increasing thresholds and increasing discounts below 100%.")]
+    InvalidDiscountTiers,
+
+    #[msg("Price guardrails need each minimum at or below its maximum.")]
+    InvalidPriceGuardrails,
+
+    #[msg("The new price is outside the price guardrails.")]
+    PriceOutOfBounds,
+
+    #[msg("The new price changes too much in a single update.")]
+    PriceChangeTooLarge,
+
+    #[msg("A scheduled price update needs non-zero prices and an effective time at least the price change delay away.")]
+    InvalidPriceSchedule,
+
+    #[msg("The SOL/USD TWAP observation account is required for public-round SOL purchases.")]
+    TwapPriceRequired,
+
+    #[msg("The SOL/USD TWAP deviates too far from the oracle price.")]
+    TwapDeviationTooLarge,
+
+    #[msg("USD prices are still in cents; run migrate_usd_pricing first.")]
+    UsdPricingMigrationRequired,
+
+    #[msg("Unknown token rounding mode.")]
+    InvalidRoundingMode,
+
+    #[msg("The purchase would buy fewer tokens than the minimum requested.")]
+    SlippageExceeded,
+
+    #[msg("The price change delay can only be raised, up to MAX_PRICE_CHANGE_DELAY seconds.")]
+    InvalidPriceChangeDelay,
+
+    #[msg("The whitelist discount must be below 10000 bps.")]
+    InvalidWhitelistDiscount,
+
+    #[msg("Wrapped SOL payments need the buyer's wSOL account, the escrow's wSOL account and the wSOL mint.")]
+    WrappedSolAccountsRequired,
+
+    #[msg("Purchases in this currency are disabled.")]
+    CurrencyDisabled,
+
+    #[msg("Proceeds in this currency are still escrowed.")]
+    CurrencyStillEscrowed,
+
+    #[msg("Stablecoin mints charging transfer fees are not supported.")]
+    TransferFeeNotSupported,
+
+    #[msg("This purchase is only available in the public round.")]
+    PublicRoundOnly,
+
+    #[msg("The purchase would cost more than the maximum payment.")]
+    MaxPaymentExceeded,
+
+    #[msg("The escrow holds less than the requested withdrawal.")]
+    InsufficientEscrowBalance,
+
+    #[msg("Treasury splits need positive shares to distinct wallets adding up to 100%.")]
+    InvalidTreasurySplits,
+
+    #[msg("Expected one account per treasury wallet, in split order.")]
+    TreasuryAccountMismatch,
+
+    #[msg("The protocol fee exceeds the maximum or has no fee wallet.")]
+    InvalidProtocolFee,
+
+    #[msg("The fee wallet account is required while a protocol fee is set.")]
+    FeeAccountRequired,
+
+    #[msg("The Web2 purchase is still within its chargeback window.")]
+    ChargebackWindowOpen,
+
+    #[msg("The chargeback window must be between 0 and MAX_WEB2_CHARGEBACK_WINDOW seconds.")]
+    InvalidChargebackWindow,
+
+    #[msg("The staking vault does not match the configured one.")]
+    InvalidStakingVault,
+
+    #[msg("Purchase runs past the current volume price step; buy the rest at the next step's price.")]
+    VolumePriceStepCrossed,
+
+    #[msg("A SOL treasury share would leave its wallet below the rent-exempt minimum; fund the wallet first.")]
+    TreasuryShareBelowRent,
+
+    #[msg("Settlements take a (payment token, mint, escrow vault, recipients) group for each payment token with escrowed proceeds.")]
+    InvalidPaymentTokenSettlement,
+
+    #[msg("The referrer's buyer account is required to take back the commission on a referred purchase.")]
+    ReferrerAccountRequired,
+
+    #[msg("Proceeds can only be settled once the sale has ended.")]
+    SaleNotEnded,
+
+    #[msg("Quoting a payment token needs its PaymentToken entry and price update account.")]
+    PaymentTokenAccountsRequired,
+
+    #[msg("The public price ramp can mark prices up by at most MAX_PUBLIC_PRICE_RAMP_BPS.")]
+    InvalidPriceRamp,
 }
\ No newline at end of file
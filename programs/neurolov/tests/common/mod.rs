@@ -0,0 +1,335 @@
+//! Helpers shared by the test suites: fixture parameters, PDA derivations and instruction builders,
+//! plus the presale fixture for each test backend (`solana-program-test` and LiteSVM).
+#![allow(dead_code)] // Each test binary uses a different subset
+
+use anchor_lang::{prelude::*, solana_program::instruction::Instruction, system_program, InstructionData};
+use anchor_spl::token;
+use solana_sdk::signature::{Keypair, Signer};
+
+use presale::constant::{
+    PRESALE_SEED, BUYER_SEED, ESCROW_SEED, REGISTRY_SEED, TICKET_SEED, SETTLEMENT_SEED, WHITELIST_SEED, BLACKLIST_SEED, REGISTRATION_SEED, COMMITMENT_SEED,
+    PYTH_PRICE_UPDATE_DISCRIMINATOR, SOL_USD_FEED_ID, SWITCHBOARD_PULL_FEED_DISCRIMINATOR, SWITCHBOARD_LAST_UPDATE_OFFSET, SWITCHBOARD_RESULT_OFFSET,
+    RAYDIUM_OBSERVATION_DISCRIMINATOR, RAYDIUM_OBSERVATION_INDEX_OFFSET, RAYDIUM_OBSERVATIONS_OFFSET, RAYDIUM_OBSERVATION_LEN, RAYDIUM_OBSERVATION_NUM,
+    ACCEPTED_CURRENCY_SEED, PAYMENT_TOKEN_SEED, TOKEN_CONTRIBUTION_SEED, WEB2_PURCHASE_SEED,
+};
+
+#[cfg(feature = "test-bpf")]
+pub mod program_test;
+
+#[cfg(feature = "litesvm")]
+pub mod svm;
+
+pub const FIXTURE_SOL_PRICE_LAMPORTS: u64 = 182_000_000;
+
+pub const FIXTURE_HARDCAP_TOKENS: u64 = 1_000_000 * 10u64.pow(9);
+
+pub const FIXTURE_TOKEN_DECIMALS: u8 = 9;
+
+// Lamports for mock mint accounts added at genesis, enough to keep them rent-exempt
+pub const MOCK_MINT_LAMPORTS: u64 = 1_000_000_000;
+
+// Mock stablecoin mints, at the mainnet USDC and USDT addresses
+pub const USDC_ADDRESS: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+
+pub const USDT_ADDRESS: Pubkey = pubkey!("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB");
+
+// Helper function to derive a presale PDA
+pub fn presale_address(admin: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[PRESALE_SEED, admin.as_ref()], &presale::id()).0
+}
+
+// Helper function to derive the keypair of a presale's NLOV wallet. The wallet is a plain token
+// account created by `initialize`, so its keypair is seeded from the presale address to find it again.
+pub fn presale_wallet_keypair(presale_pda: &Pubkey) -> Keypair {
+    solana_sdk::signer::keypair::keypair_from_seed(presale_pda.as_ref()).unwrap()
+}
+
+// Helper function to derive a presale's NLOV wallet
+pub fn presale_wallet_address(presale_pda: &Pubkey) -> Pubkey {
+    presale_wallet_keypair(presale_pda).pubkey()
+}
+
+// Helper function to derive a buyer's allocation PDA
+pub fn buyer_account_address(presale_pda: &Pubkey, buyer: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[BUYER_SEED, presale_pda.as_ref(), buyer.as_ref()],
+        &presale::id(),
+    )
+    .0
+}
+
+// Helper function to derive a buyer registry page PDA
+pub fn registry_page_address(presale_pda: &Pubkey, page_index: u32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[REGISTRY_SEED, presale_pda.as_ref(), &page_index.to_le_bytes()],
+        &presale::id(),
+    )
+    .0
+}
+
+// Helper function to derive a presale's escrow PDA
+pub fn escrow_address(presale_pda: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[ESCROW_SEED, presale_pda.as_ref()], &presale::id()).0
+}
+
+// Helper function to derive a settlement PDA
+pub fn settlement_address(presale_pda: &Pubkey, index: u32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[SETTLEMENT_SEED, presale_pda.as_ref(), &index.to_le_bytes()],
+        &presale::id(),
+    )
+    .0
+}
+
+// Helper function to derive a wallet's whitelist entry PDA
+pub fn whitelist_entry_address(presale_pda: &Pubkey, wallet: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[WHITELIST_SEED, presale_pda.as_ref(), wallet.as_ref()],
+        &presale::id(),
+    )
+    .0
+}
+
+// Helper function to derive a presale's accepted currency entry
+pub fn accepted_currency_address(presale_pda: &Pubkey, currency: u8) -> Pubkey {
+    Pubkey::find_program_address(
+        &[ACCEPTED_CURRENCY_SEED, presale_pda.as_ref(), &[currency]],
+        &presale::id(),
+    )
+    .0
+}
+
+// Helper function to derive a presale's payment token entry
+pub fn payment_token_address(presale_pda: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[PAYMENT_TOKEN_SEED, presale_pda.as_ref(), mint.as_ref()],
+        &presale::id(),
+    )
+    .0
+}
+
+// Helper function to derive a buyer's payments in one payment token
+pub fn token_contribution_address(presale_pda: &Pubkey, mint: &Pubkey, buyer: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[TOKEN_CONTRIBUTION_SEED, presale_pda.as_ref(), mint.as_ref(), buyer.as_ref()],
+        &presale::id(),
+    )
+    .0
+}
+
+// Helper function to derive a wallet's blacklist PDA
+pub fn blacklist_address(presale_pda: &Pubkey, wallet: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[BLACKLIST_SEED, presale_pda.as_ref(), wallet.as_ref()],
+        &presale::id(),
+    )
+    .0
+}
+
+// Helper function to derive a wallet's registration PDA
+pub fn registration_address(presale_pda: &Pubkey, wallet: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[REGISTRATION_SEED, presale_pda.as_ref(), wallet.as_ref()],
+        &presale::id(),
+    )
+    .0
+}
+
+// Helper function to derive a buyer's purchase commitment PDA
+pub fn purchase_commitment_address(presale_pda: &Pubkey, buyer: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[COMMITMENT_SEED, presale_pda.as_ref(), buyer.as_ref()],
+        &presale::id(),
+    )
+    .0
+}
+
+// Helper function to derive a purchase ticket PDA
+pub fn ticket_address(presale_pda: &Pubkey, sequence: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[TICKET_SEED, presale_pda.as_ref(), &sequence.to_le_bytes()],
+        &presale::id(),
+    )
+    .0
+}
+
+// Helper function to derive a provisional Web2 purchase PDA
+pub fn web2_purchase_address(presale_pda: &Pubkey, sequence: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[WEB2_PURCHASE_SEED, presale_pda.as_ref(), &sequence.to_le_bytes()],
+        &presale::id(),
+    )
+    .0
+}
+
+// Helper function to build a fully verified Pyth SOL/USD `PriceUpdateV2` account's data
+pub fn pyth_price_update_data(price: i64, confidence: u64, exponent: i32, publish_time: i64) -> Vec<u8> {
+    pyth_feed_price_update_data(&SOL_USD_FEED_ID, price, confidence, exponent, publish_time)
+}
+
+// Helper function to build a fully verified Pyth `PriceUpdateV2` account's data for any feed
+pub fn pyth_feed_price_update_data(feed_id: &[u8; 32], price: i64, confidence: u64, exponent: i32, publish_time: i64) -> Vec<u8> {
+    let mut data = PYTH_PRICE_UPDATE_DISCRIMINATOR.to_vec();
+    data.extend_from_slice(Pubkey::new_unique().as_ref()); // Write authority
+    data.push(1); // VerificationLevel::Full
+    data.extend_from_slice(feed_id);
+    data.extend_from_slice(&price.to_le_bytes());
+    data.extend_from_slice(&confidence.to_le_bytes());
+    data.extend_from_slice(&exponent.to_le_bytes());
+    data.extend_from_slice(&publish_time.to_le_bytes());
+    data.extend_from_slice(&publish_time.to_le_bytes()); // Previous publish time
+    data.extend_from_slice(&price.to_le_bytes()); // EMA price
+    data.extend_from_slice(&confidence.to_le_bytes()); // EMA confidence
+    data.extend_from_slice(&0u64.to_le_bytes()); // Posted slot
+    data
+}
+
+// Helper function to build a Switchboard `PullFeedAccountData` account's data with the given result
+pub fn switchboard_feed_data(value: i128, std_dev: i128, last_update: i64) -> Vec<u8> {
+    let mut data = vec![0u8; 3208];
+    data[..8].copy_from_slice(&SWITCHBOARD_PULL_FEED_DISCRIMINATOR);
+    data[SWITCHBOARD_LAST_UPDATE_OFFSET..SWITCHBOARD_LAST_UPDATE_OFFSET + 8].copy_from_slice(&last_update.to_le_bytes());
+    data[SWITCHBOARD_RESULT_OFFSET..SWITCHBOARD_RESULT_OFFSET + 16].copy_from_slice(&value.to_le_bytes());
+    data[SWITCHBOARD_RESULT_OFFSET + 16..SWITCHBOARD_RESULT_OFFSET + 32].copy_from_slice(&std_dev.to_le_bytes());
+    data
+}
+
+// Helper function to build a Raydium CLMM `ObservationState` account's data from (timestamp, tick cumulative)
+// observations, oldest first
+pub fn raydium_observation_data(observations: &[(u32, i64)]) -> Vec<u8> {
+    let mut data = vec![0u8; RAYDIUM_OBSERVATIONS_OFFSET + RAYDIUM_OBSERVATION_NUM * RAYDIUM_OBSERVATION_LEN + 32];
+    data[..8].copy_from_slice(&RAYDIUM_OBSERVATION_DISCRIMINATOR);
+    data[8] = 1; // Initialized
+    let latest_index = (observations.len() - 1) as u16;
+    data[RAYDIUM_OBSERVATION_INDEX_OFFSET..RAYDIUM_OBSERVATION_INDEX_OFFSET + 2].copy_from_slice(&latest_index.to_le_bytes());
+    for (index, (timestamp, tick_cumulative)) in observations.iter().enumerate() {
+        let offset = RAYDIUM_OBSERVATIONS_OFFSET + index * RAYDIUM_OBSERVATION_LEN;
+        data[offset..offset + 4].copy_from_slice(&timestamp.to_le_bytes());
+        data[offset + 4..offset + 12].copy_from_slice(&tick_cumulative.to_le_bytes());
+    }
+    data
+}
+
+// Helper function to build the fixture's `initialize` instruction
+pub fn initialize_ix(admin: &Pubkey, token_mint: &Pubkey, merchant_wallet: &Pubkey) -> Instruction {
+    let presale_pda = presale_address(admin);
+    let presale_wallet = presale_wallet_address(&presale_pda);
+
+    Instruction {
+        program_id: presale::id(),
+        accounts: presale::accounts::Initialize {
+            admin: *admin,
+            presale: presale_pda,
+            registry_page: registry_page_address(&presale_pda, 0),
+            token_mint: *token_mint,
+            presale_wallet,
+            merchant_wallet: *merchant_wallet,
+            escrow: escrow_address(&presale_pda),
+            fee_authority: None,
+            system_program: system_program::ID,
+            token_program: token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+        }
+        .to_account_metas(None),
+        data: presale::instruction::Initialize {
+            usd_price_micros_per_nlov: 30_000,
+            sol_price_lamports_per_nlov: FIXTURE_SOL_PRICE_LAMPORTS,
+            private_sale_duration_days: 7,
+            public_sale_duration_days: 14,
+            hardcap_tokens: FIXTURE_HARDCAP_TOKENS,
+            immutable_pricing: false,
+            max_end_timestamp: 0,
+            stage_prices: vec![],
+        }
+        .data(),
+    }
+}
+
+// Helper function to build an `add_accepted_currency` instruction
+pub fn add_accepted_currency_ix(presale_pda: &Pubkey, admin: &Pubkey, currency: u8, mint: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: presale::id(),
+        accounts: presale::accounts::AddAcceptedCurrency {
+            admin: *admin,
+            presale: *presale_pda,
+            accepted_currency: accepted_currency_address(presale_pda, currency),
+            stable_coin_mint: *mint,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: presale::instruction::AddAcceptedCurrency { currency }.data(),
+    }
+}
+
+// Helper function to build a `set_stage` instruction
+pub fn set_stage_ix(admin: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: presale::id(),
+        accounts: presale::accounts::SetStage {
+            admin: *admin,
+            presale: presale_address(admin),
+        }
+        .to_account_metas(None),
+        data: presale::instruction::SetStage {}.data(),
+    }
+}
+
+// Helper function to build a Web3 SOL purchase of whole NLOV tokens at the fixture price
+pub fn sol_purchase_ix(
+    presale_pda: &Pubkey,
+    presale_wallet: &Pubkey,
+    token_mint: &Pubkey,
+    buyer: &Pubkey,
+    tokens_user_units: u64,
+) -> Instruction {
+    Instruction {
+        program_id: presale::id(),
+        accounts: presale::accounts::BuyTokens {
+            buyer: *buyer,
+            presale: *presale_pda,
+            buyer_account: buyer_account_address(presale_pda, buyer),
+            registry_page: registry_page_address(presale_pda, 0),
+            ticket: None,
+            attestation: None,
+            identity_verifier_program: None,
+            instructions_sysvar: None,
+            purchase_journal: None,
+            referrer_account: None,
+            whitelist_entry: None,
+            blacklist: None,
+            gatekeeper: None,
+            purchase_commitment: None,
+            sol_usd_price_update: None,
+            sol_usd_fallback_feed: None,
+            sol_usd_twap_observation: None,
+            buyer_wsol_account: None,
+            escrow_wsol_account: None,
+            wsol_mint: None,
+            fee_wallet: None,
+            presale_wallet: *presale_wallet,
+            escrow: escrow_address(presale_pda),
+            token_mint: *token_mint,
+            system_program: system_program::ID,
+            token_program: token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+        }
+        .to_account_metas(None),
+        data: presale::instruction::BuyTokens {
+            payment_type: 0,
+            lamports_sent: FIXTURE_SOL_PRICE_LAMPORTS * tokens_user_units,
+            valid_until_timestamp: None,
+            whitelist_proof: None,
+            min_tokens_out: None,
+        }
+        .data(),
+    }
+}
+
+// Helper function to swap one account of an instruction for another, for negative account checks
+pub fn with_account_replaced(instruction: &Instruction, from: &Pubkey, to: &Pubkey) -> Instruction {
+    let mut instruction = instruction.clone();
+    for meta in instruction.accounts.iter_mut().filter(|meta| meta.pubkey == *from) {
+        meta.pubkey = *to;
+    }
+    instruction
+}
@@ -0,0 +1,265 @@
+//! `solana-program-test` backend: the presale fixture and transaction helpers.
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{entrypoint::ProgramResult, instruction::Instruction},
+    system_program,
+};
+use anchor_spl::token;
+use solana_program_test::{self, BanksClient, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+use presale::PresaleError;
+
+use super::*;
+
+// Native entrypoint for the presale program, used unless `SBF_OUT_DIR` points at a built `presale.so`
+fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    // `presale::entry` ties the account infos to the lifetime of the slice holding them
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    presale::entry(program_id, accounts, data)
+}
+
+// Helper function to create a program test with the presale program loaded
+pub fn presale_program_test() -> ProgramTest {
+    ProgramTest::new("presale", presale::id(), solana_program_test::processor!(process_instruction))
+}
+
+// Typed reads of Anchor and SPL token accounts
+#[allow(async_fn_in_trait)]
+pub trait BanksClientExt {
+    async fn get_anchor_account<T: AccountDeserialize>(&mut self, address: Pubkey) -> std::result::Result<T, BanksClientError>;
+
+    async fn get_token_account(&mut self, address: Pubkey) -> std::result::Result<Option<token::TokenAccount>, BanksClientError>;
+}
+
+impl BanksClientExt for BanksClient {
+    async fn get_anchor_account<T: AccountDeserialize>(&mut self, address: Pubkey) -> std::result::Result<T, BanksClientError> {
+        let account = self
+            .get_account(address)
+            .await?
+            .ok_or(BanksClientError::ClientError("Account not found"))?;
+        T::try_deserialize(&mut account.data.as_slice())
+            .map_err(|_| BanksClientError::ClientError("Failed to deserialize account"))
+    }
+
+    async fn get_token_account(&mut self, address: Pubkey) -> std::result::Result<Option<token::TokenAccount>, BanksClientError> {
+        match self.get_account(address).await? {
+            Some(account) => token::TokenAccount::try_deserialize(&mut account.data.as_slice())
+                .map(Some)
+                .map_err(|_| BanksClientError::ClientError("Failed to deserialize token account")),
+            None => Ok(None),
+        }
+    }
+}
+
+// Overwrites an Anchor account in place, keeping its lamports, owner and allocated size
+#[allow(async_fn_in_trait)]
+pub trait ProgramTestContextExt {
+    async fn set_anchor_account<T: AccountSerialize>(&mut self, address: Pubkey, account: &T);
+}
+
+impl ProgramTestContextExt for ProgramTestContext {
+    async fn set_anchor_account<T: AccountSerialize>(&mut self, address: Pubkey, account: &T) {
+        let mut stored = self.banks_client.get_account(address).await.unwrap().unwrap();
+        let mut data = Vec::with_capacity(stored.data.len());
+        account.try_serialize(&mut data).unwrap();
+        assert!(data.len() <= stored.data.len(), "Account data does not fit the allocated space");
+        data.resize(stored.data.len(), 0);
+        stored.data = data;
+        self.set_account(&address, &stored.into());
+    }
+}
+
+// Helper function to create an owner's associated token account
+pub async fn create_token_account(
+    context: &mut ProgramTestContext,
+    mint_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+) -> Pubkey {
+    let create_ata_ix = anchor_spl::associated_token::spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+        &context.payer.pubkey(), // Payer to create ATA
+        owner_pubkey,            // Owner of the ATA
+        mint_pubkey,             // Mint for the ATA
+        &token::ID,              // Token program ID
+    );
+
+    let mut transaction = Transaction::new_with_payer(&[create_ata_ix], Some(&context.payer.pubkey()));
+    transaction.sign(&[&context.payer], context.last_blockhash);
+
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap();
+
+    anchor_spl::associated_token::get_associated_token_address(owner_pubkey, mint_pubkey)
+}
+
+// Helper function to mint tokens to an account
+pub async fn mint_to(
+    context: &mut ProgramTestContext,
+    mint_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    mint_authority_keypair: &Keypair,
+    amount: u64,
+) {
+    let mint_to_ix = token::spl_token::instruction::mint_to(
+        &token::ID,
+        mint_pubkey,
+        destination_pubkey,
+        &mint_authority_keypair.pubkey(),
+        &[],
+        amount,
+    )
+    .unwrap();
+
+    let mut transaction = Transaction::new_with_payer(
+        &[mint_to_ix],
+        Some(&context.payer.pubkey()),
+    );
+    transaction.sign(&[&context.payer, mint_authority_keypair], context.last_blockhash);
+
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap();
+}
+
+// Helper function to move the clock forward
+pub async fn warp_seconds(context: &mut ProgramTestContext, seconds: i64) {
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += seconds;
+    context.set_sysvar(&clock);
+}
+
+// Helper function to sign and send a transaction paid for by the context payer. The bank keeps
+// producing slots in the background, so every transaction is signed with the latest blockhash;
+// identical transactions are re-signed with a fresh one so they are processed again.
+pub async fn send_transaction(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> std::result::Result<(), BanksClientError> {
+    let payer = context.payer.insecure_clone();
+    let mut all_signers: Vec<&Keypair> = vec![&payer];
+    all_signers.extend_from_slice(signers);
+    context.last_blockhash = context.banks_client.get_latest_blockhash().await?;
+    let mut transaction = Transaction::new_with_payer(instructions, Some(&payer.pubkey()));
+    transaction.sign(&all_signers, context.last_blockhash);
+    // An identical transaction that already landed would be answered from the status cache
+    if context.banks_client.get_transaction_status(transaction.signatures[0]).await?.is_some() {
+        context.last_blockhash = context.get_new_latest_blockhash().await?;
+        transaction.sign(&all_signers, context.last_blockhash);
+    }
+    context.banks_client.process_transaction(transaction).await
+}
+
+// Helper function to assert that a transaction failed with the given presale error
+pub fn assert_presale_error(err: BanksClientError, expected: PresaleError) {
+    assert_custom_error(err, u32::from(expected));
+}
+
+// Helper function to assert that a transaction failed with the given Anchor framework error
+pub fn assert_anchor_error(err: BanksClientError, expected: anchor_lang::error::ErrorCode) {
+    assert_custom_error(err, u32::from(expected));
+}
+
+fn assert_custom_error(err: BanksClientError, expected_code: u32) {
+    match err.unwrap() {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+            assert_eq!(code, expected_code, "wrong program error")
+        }
+        other => panic!("expected custom program error {expected_code}, got {other:?}"),
+    }
+}
+
+// Shared setup for tests that need an initialized, funded presale in the private sale stage
+pub struct PresaleFixture {
+    pub context: ProgramTestContext,
+    pub admin: Keypair,
+    pub buyer: Keypair,
+    pub token_mint_authority: Keypair,
+    pub presale_token_mint: Keypair,
+    pub merchant_wallet: Keypair,
+    pub presale_pda: Pubkey,
+    pub presale_wallet: Pubkey,
+}
+
+pub async fn setup_active_presale(mut program_test: ProgramTest) -> PresaleFixture {
+    let admin = Keypair::new();
+    let buyer = Keypair::new();
+    let token_mint_authority = Keypair::new();
+    let presale_token_mint = Keypair::new();
+    let merchant_wallet = Keypair::new();
+
+    program_test.add_account(
+        presale_token_mint.pubkey(),
+        solana_sdk::account::Account::new(MOCK_MINT_LAMPORTS, token::Mint::LEN, &token::ID),
+    );
+    program_test.add_account(
+        admin.pubkey(),
+        solana_sdk::account::Account::new(1_000_000_000_000, 0, &system_program::ID),
+    );
+    program_test.add_account(
+        buyer.pubkey(),
+        solana_sdk::account::Account::new(100_000_000_000, 0, &system_program::ID),
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let create_mint_ix = token::spl_token::instruction::initialize_mint(
+        &token::ID,
+        &presale_token_mint.pubkey(),
+        &token_mint_authority.pubkey(),
+        None,
+        FIXTURE_TOKEN_DECIMALS,
+    )
+    .unwrap();
+    send_transaction(&mut context, &[create_mint_ix], &[]).await.unwrap();
+
+    let presale_pda = presale_address(&admin.pubkey());
+    let presale_wallet = presale_wallet_address(&presale_pda);
+
+    let init_ix = initialize_ix(&admin.pubkey(), &presale_token_mint.pubkey(), &merchant_wallet.pubkey());
+    send_transaction(&mut context, &[init_ix], &[&admin, &presale_wallet_keypair(&presale_pda)]).await.unwrap();
+
+    mint_to(
+        &mut context,
+        &presale_token_mint.pubkey(),
+        &presale_wallet,
+        &token_mint_authority,
+        FIXTURE_HARDCAP_TOKENS,
+    )
+    .await;
+
+    let start_sale_ix = set_stage_ix(&admin.pubkey());
+    send_transaction(&mut context, &[start_sale_ix], &[&admin]).await.unwrap();
+
+    PresaleFixture {
+        context,
+        admin,
+        buyer,
+        token_mint_authority,
+        presale_token_mint,
+        merchant_wallet,
+        presale_pda,
+        presale_wallet,
+    }
+}
+
+// Helper function to build a Web3 SOL purchase of whole NLOV tokens at the fixture price
+pub fn buy_tokens_ix(fixture: &PresaleFixture, buyer: &Pubkey, tokens_user_units: u64) -> Instruction {
+    sol_purchase_ix(
+        &fixture.presale_pda,
+        &fixture.presale_wallet,
+        &fixture.presale_token_mint.pubkey(),
+        buyer,
+        tokens_user_units,
+    )
+}
@@ -0,0 +1,178 @@
+//! LiteSVM backend: the same presale fixture running in-process, without a bank or async runtime.
+//! Requires the program to be built first (`anchor build`), since it loads `target/deploy/presale.so`.
+
+use anchor_lang::{prelude::*, solana_program::program_pack::Pack, AccountDeserialize, AccountSerialize};
+use anchor_spl::token;
+use litesvm::{types::TransactionResult, LiteSVM};
+use solana_sdk::{
+    account::Account,
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use solana_system_interface::instruction as system_instruction;
+
+use presale::PresaleError;
+
+use super::*;
+
+const PROGRAM_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../target/deploy/presale.so");
+
+// Shared setup for tests that need an initialized, funded presale in the private sale stage
+pub struct SvmFixture {
+    pub svm: LiteSVM,
+    pub admin: Keypair,
+    pub buyer: Keypair,
+    pub token_mint_authority: Keypair,
+    pub presale_token_mint: Keypair,
+    pub merchant_wallet: Keypair,
+    pub presale_pda: Pubkey,
+    pub presale_wallet: Pubkey,
+}
+
+pub fn setup_active_presale_svm() -> SvmFixture {
+    let mut svm = LiteSVM::new();
+    svm.add_program_from_file(presale::id(), PROGRAM_PATH).unwrap();
+
+    let admin = Keypair::new();
+    let buyer = Keypair::new();
+    let token_mint_authority = Keypair::new();
+    let presale_token_mint = Keypair::new();
+    let merchant_wallet = Keypair::new();
+
+    svm.airdrop(&admin.pubkey(), 1_000_000_000_000).unwrap();
+    svm.airdrop(&buyer.pubkey(), 100_000_000_000).unwrap();
+
+    let create_mint_ixs = [
+        system_instruction::create_account(
+            &admin.pubkey(),
+            &presale_token_mint.pubkey(),
+            svm.minimum_balance_for_rent_exemption(token::spl_token::state::Mint::LEN),
+            token::spl_token::state::Mint::LEN as u64,
+            &token::ID,
+        ),
+        token::spl_token::instruction::initialize_mint(
+            &token::ID,
+            &presale_token_mint.pubkey(),
+            &token_mint_authority.pubkey(),
+            None,
+            FIXTURE_TOKEN_DECIMALS,
+        )
+        .unwrap(),
+    ];
+    send_svm_transaction(&mut svm, &create_mint_ixs, &admin, &[&presale_token_mint]).unwrap();
+
+    let presale_pda = presale_address(&admin.pubkey());
+    let presale_wallet = presale_wallet_address(&presale_pda);
+
+    let init_ix = initialize_ix(&admin.pubkey(), &presale_token_mint.pubkey(), &merchant_wallet.pubkey());
+    send_svm_transaction(&mut svm, &[init_ix], &admin, &[&presale_wallet_keypair(&presale_pda)]).unwrap();
+
+    let mint_to_ix = token::spl_token::instruction::mint_to(
+        &token::ID,
+        &presale_token_mint.pubkey(),
+        &presale_wallet,
+        &token_mint_authority.pubkey(),
+        &[],
+        FIXTURE_HARDCAP_TOKENS,
+    )
+    .unwrap();
+    send_svm_transaction(&mut svm, &[mint_to_ix], &admin, &[&token_mint_authority]).unwrap();
+
+    send_svm_transaction(&mut svm, &[set_stage_ix(&admin.pubkey())], &admin, &[]).unwrap();
+
+    SvmFixture {
+        svm,
+        admin,
+        buyer,
+        token_mint_authority,
+        presale_token_mint,
+        merchant_wallet,
+        presale_pda,
+        presale_wallet,
+    }
+}
+
+// Helper function to sign and send a transaction. The blockhash is expired afterwards so an
+// identical transaction can be sent again without being rejected as a duplicate.
+#[allow(clippy::result_large_err)] // LiteSVM's own result type
+pub fn send_svm_transaction(
+    svm: &mut LiteSVM,
+    instructions: &[Instruction],
+    payer: &Keypair,
+    signers: &[&Keypair],
+) -> TransactionResult {
+    let mut all_signers: Vec<&Keypair> = vec![payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&payer.pubkey()),
+        &all_signers,
+        svm.latest_blockhash(),
+    );
+    let result = svm.send_transaction(transaction);
+    svm.expire_blockhash();
+    result
+}
+
+// Helper function to simulate a view instruction and decode its return data
+pub fn simulate_view<T: AnchorDeserialize>(svm: &LiteSVM, instruction: Instruction, payer: &Keypair) -> T {
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&payer.pubkey()),
+        &[payer],
+        svm.latest_blockhash(),
+    );
+    let simulation = svm.simulate_transaction(transaction).unwrap();
+    T::try_from_slice(&simulation.meta.return_data.data).unwrap()
+}
+
+// Helper function to read an Anchor account
+pub fn read_account<T: AccountDeserialize>(svm: &LiteSVM, address: &Pubkey) -> T {
+    let account = svm.get_account(address).unwrap();
+    T::try_deserialize(&mut account.data.as_slice()).unwrap()
+}
+
+// Helper function to overwrite an Anchor account's data, keeping its lamports and owner
+pub fn write_account<T: AccountSerialize>(svm: &mut LiteSVM, address: &Pubkey, value: &T) {
+    let account = svm.get_account(address).unwrap();
+    let mut data = Vec::with_capacity(account.data.len());
+    value.try_serialize(&mut data).unwrap();
+    data.resize(account.data.len(), 0);
+    svm.set_account(*address, Account { data, ..account }).unwrap();
+}
+
+// Helper function to read an SPL token account's balance
+pub fn token_balance(svm: &LiteSVM, address: &Pubkey) -> u64 {
+    let account = svm.get_account(address).unwrap();
+    token::spl_token::state::Account::unpack(&account.data).unwrap().amount
+}
+
+// Helper function to move the clock forward
+pub fn warp_seconds(svm: &mut LiteSVM, seconds: i64) {
+    let mut clock: Clock = svm.get_sysvar();
+    clock.unix_timestamp += seconds;
+    svm.set_sysvar(&clock);
+}
+
+// Helper function to assert that a transaction failed with the given presale error
+pub fn assert_presale_error(result: TransactionResult, expected: PresaleError) {
+    let failure = result.expect_err("transaction should have failed");
+    assert_eq!(
+        failure.err,
+        TransactionError::InstructionError(0, InstructionError::Custom(u32::from(expected))),
+        "logs: {:#?}",
+        failure.meta.logs
+    );
+}
+
+// Helper function to build a Web3 SOL purchase of whole NLOV tokens at the fixture price
+pub fn svm_buy_tokens_ix(fixture: &SvmFixture, buyer: &Pubkey, tokens_user_units: u64) -> Instruction {
+    sol_purchase_ix(
+        &fixture.presale_pda,
+        &fixture.presale_wallet,
+        &fixture.presale_token_mint.pubkey(),
+        buyer,
+        tokens_user_units,
+    )
+}
@@ -0,0 +1,220 @@
+#![cfg(all(feature = "test-bpf", feature = "fuzz"))]
+//! Stateful invariant harness: runs random sequences of valid and invalid instructions against
+//! a fresh presale and checks the global invariants after every step. Failing instructions are
+//! expected (e.g. buying after cancellation); a sequence only fails when an invariant breaks.
+//!
+//! Run with `cargo test --features test-bpf,fuzz --test invariants`; set `PROPTEST_CASES` for more sequences.
+
+use anchor_lang::{prelude::*, solana_program::instruction::Instruction, system_program, InstructionData};
+use anchor_spl::token;
+use proptest::prelude::*;
+use solana_sdk::{
+    signature::{Keypair, Signer},
+};
+use solana_system_interface::instruction as system_instruction;
+
+mod common;
+use common::{program_test::*, *};
+
+const BUYER_COUNT: usize = 3;
+
+const HARDCAP_TOKENS: u64 = 500 * 1_000_000_000; // Low enough for random buys to run into it
+
+/// A step of a random instruction sequence.
+#[derive(Clone, Debug)]
+enum Op {
+    Buy { buyer: usize, tokens: u64 },
+    SetStage,
+    Warp { days: i64 },
+    UpdateSalePrice { sol_price_lamports: u64 },
+    CancelPresale,
+    ClaimRefund { buyer: usize },
+}
+
+fn op_strategy() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (0..BUYER_COUNT, 0u64..200).prop_map(|(buyer, tokens)| Op::Buy { buyer, tokens }),
+        1 => Just(Op::SetStage),
+        1 => (0i64..15).prop_map(|days| Op::Warp { days }),
+        1 => (0u64..2 * FIXTURE_SOL_PRICE_LAMPORTS).prop_map(|sol_price_lamports| Op::UpdateSalePrice { sol_price_lamports }),
+        1 => Just(Op::CancelPresale),
+        2 => (0..BUYER_COUNT).prop_map(|buyer| Op::ClaimRefund { buyer }),
+    ]
+}
+
+/// Executes one step. Errors are ignored: the program is free to reject any step.
+async fn apply(fixture: &mut PresaleFixture, buyers: &[Keypair], op: &Op) {
+    let admin = fixture.admin.insecure_clone();
+    let presale_pda = fixture.presale_pda;
+
+    let (instruction, signer) = match *op {
+        Op::Buy { buyer, tokens } => (buy_tokens_ix(fixture, &buyers[buyer].pubkey(), tokens), &buyers[buyer]),
+        Op::SetStage => (
+            Instruction {
+                program_id: presale::id(),
+                accounts: presale::accounts::SetStage {
+                    admin: admin.pubkey(),
+                    presale: presale_pda,
+                }
+                .to_account_metas(None),
+                data: presale::instruction::SetStage {}.data(),
+            },
+            &admin,
+        ),
+        Op::Warp { days } => {
+            let mut clock: Clock = fixture.context.banks_client.get_sysvar().await.unwrap();
+            clock.unix_timestamp += days * 86400;
+            fixture.context.set_sysvar(&clock);
+            return;
+        }
+        Op::UpdateSalePrice { sol_price_lamports } => (
+            Instruction {
+                program_id: presale::id(),
+                accounts: presale::accounts::UpdateSalePrice {
+                    admin: admin.pubkey(),
+                    presale: presale_pda,
+                }
+                .to_account_metas(None),
+                data: presale::instruction::UpdateSalePrice {
+                    new_usd_price_micros: 30_000,
+                    new_sol_price_lamports: sol_price_lamports,
+                }
+                .data(),
+            },
+            &admin,
+        ),
+        Op::CancelPresale => (
+            Instruction {
+                program_id: presale::id(),
+                accounts: presale::accounts::CancelPresale {
+                    admin: admin.pubkey(),
+                    presale: presale_pda,
+                }
+                .to_account_metas(None),
+                data: presale::instruction::CancelPresale {}.data(),
+            },
+            &admin,
+        ),
+        Op::ClaimRefund { buyer } => (
+            Instruction {
+                program_id: presale::id(),
+                accounts: presale::accounts::ClaimRefund {
+                    buyer: buyers[buyer].pubkey(),
+                    presale: presale_pda,
+                    buyer_account: buyer_account_address(&presale_pda, &buyers[buyer].pubkey()),
+                    registry_page: registry_page_address(&presale_pda, 0),
+                    escrow: escrow_address(&presale_pda),
+                    escrow_usdc_account: None,
+                    buyer_usdc_account: None,
+                    escrow_usdt_account: None,
+                    buyer_usdt_account: None,
+                    escrow_pyusd_account: None,
+                    buyer_pyusd_account: None,
+                    pyusd_mint: None,
+                    token_2022_program: None,
+                    token_program: token::ID,
+                    system_program: system_program::ID,
+                }
+                .to_account_metas(None),
+                data: presale::instruction::ClaimRefund {}.data(),
+            },
+            &buyers[buyer],
+        ),
+    };
+
+    // A fresh blockhash so repeated identical steps are not deduplicated
+    fixture.context.last_blockhash = fixture.context.get_new_latest_blockhash().await.unwrap();
+    let _ = send_transaction(&mut fixture.context, &[instruction], &[signer]).await;
+}
+
+/// Checks the global invariants: no oversell, buyer accounting adds up to the totals,
+/// the escrow and presale wallet cover their obligations, and the stage only moves forward.
+async fn assert_invariants(fixture: &mut PresaleFixture, buyers: &[Keypair], last_stage: &mut u8) {
+    let presale_account: presale::Presale = fixture
+        .context
+        .banks_client
+        .get_anchor_account(fixture.presale_pda)
+        .await
+        .unwrap();
+
+    assert!(presale_account.total_sold <= presale_account.hardcap_tokens, "oversold");
+    assert!(presale_account.sale_stage >= *last_stage, "stage moved backwards");
+    *last_stage = presale_account.sale_stage;
+
+    let mut buyer_tokens = 0u64;
+    let mut buyer_lamports = 0u64;
+    for buyer in buyers {
+        let buyer_account_pda = buyer_account_address(&fixture.presale_pda, &buyer.pubkey());
+        if let Ok(buyer_account) = fixture
+            .context
+            .banks_client
+            .get_anchor_account::<presale::BuyerAccount>(buyer_account_pda)
+            .await
+        {
+            buyer_tokens += buyer_account.tokens_purchased;
+            buyer_lamports += buyer_account.contributed_lamports;
+        }
+    }
+    assert_eq!(buyer_tokens, presale_account.total_sold, "buyer allocations do not add up to total_sold");
+    assert_eq!(buyer_lamports, presale_account.escrowed_lamports, "buyer contributions do not add up to the escrow");
+
+    let escrow_lamports = fixture
+        .context
+        .banks_client
+        .get_balance(escrow_address(&fixture.presale_pda))
+        .await
+        .unwrap();
+    assert!(escrow_lamports >= presale_account.escrowed_lamports, "escrow insolvent");
+
+    let presale_wallet_balance = fixture
+        .context
+        .banks_client
+        .get_token_account(fixture.presale_wallet)
+        .await
+        .unwrap()
+        .unwrap()
+        .amount;
+    assert!(
+        presale_wallet_balance >= presale_account.outstanding_allocations(),
+        "presale wallet does not cover outstanding allocations"
+    );
+}
+
+async fn run_sequence(ops: Vec<Op>) {
+    let program_test = presale_program_test();
+    let mut fixture = setup_active_presale(program_test).await;
+    let admin = fixture.admin.insecure_clone();
+
+    let mut presale_account: presale::Presale = fixture
+        .context
+        .banks_client
+        .get_anchor_account(fixture.presale_pda)
+        .await
+        .unwrap();
+    presale_account.hardcap_tokens = HARDCAP_TOKENS;
+    fixture.context.set_anchor_account(fixture.presale_pda, &presale_account).await;
+
+    let mut buyers = vec![fixture.buyer.insecure_clone()];
+    while buyers.len() < BUYER_COUNT {
+        let buyer = Keypair::new();
+        let fund_ix = system_instruction::transfer(&admin.pubkey(), &buyer.pubkey(), 100_000_000_000);
+        send_transaction(&mut fixture.context, &[fund_ix], &[&admin]).await.unwrap();
+        buyers.push(buyer);
+    }
+
+    let mut last_stage = 0;
+    assert_invariants(&mut fixture, &buyers, &mut last_stage).await;
+    for op in &ops {
+        apply(&mut fixture, &buyers, op).await;
+        assert_invariants(&mut fixture, &buyers, &mut last_stage).await;
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn instruction_sequences_preserve_invariants(ops in prop::collection::vec(op_strategy(), 1..24)) {
+        tokio::runtime::Runtime::new().unwrap().block_on(run_sequence(ops));
+    }
+}
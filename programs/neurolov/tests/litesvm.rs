@@ -0,0 +1,206 @@
+#![cfg(feature = "litesvm")]
+//! Fast in-process tests on the LiteSVM backend, sharing fixtures and instruction builders with
+//! the `solana-program-test` suite. Build the program first, then run
+//! `cargo test --features litesvm --test litesvm`.
+
+use anchor_lang::{prelude::*, solana_program::instruction::Instruction, system_program, InstructionData};
+use anchor_spl::token;
+use solana_sdk::signature::Signer;
+
+use presale::{BuyerAccount, BuyerAllocation, PresaleError};
+
+mod common;
+use common::{svm::*, *};
+
+#[test]
+fn test_buy_tokens_with_sol() {
+    let mut fixture = setup_active_presale_svm();
+    let buyer = fixture.buyer.insecure_clone();
+    let buyer_account_pda = buyer_account_address(&fixture.presale_pda, &buyer.pubkey());
+
+    let buy_ix = svm_buy_tokens_ix(&fixture, &buyer.pubkey(), 100);
+    send_svm_transaction(&mut fixture.svm, &[buy_ix], &buyer, &[]).unwrap();
+
+    let presale_account: presale::Presale = read_account(&fixture.svm, &fixture.presale_pda);
+    assert_eq!(presale_account.total_sold, 100 * 10u64.pow(9));
+
+    let buyer_account: BuyerAccount = read_account(&fixture.svm, &buyer_account_pda);
+    assert_eq!(buyer_account.tokens_purchased, 100 * 10u64.pow(9));
+    assert_eq!(buyer_account.contributed_lamports, 100 * FIXTURE_SOL_PRICE_LAMPORTS);
+    assert_eq!(
+        fixture.svm.get_balance(&escrow_address(&fixture.presale_pda)).unwrap(),
+        fixture.svm.minimum_balance_for_rent_exemption(0) + 100 * FIXTURE_SOL_PRICE_LAMPORTS
+    );
+}
+
+#[test]
+fn test_buy_tokens_rejects_beyond_hardcap() {
+    let mut fixture = setup_active_presale_svm();
+    let buyer = fixture.buyer.insecure_clone();
+
+    let mut presale_account: presale::Presale = read_account(&fixture.svm, &fixture.presale_pda);
+    presale_account.hardcap_tokens = 50 * 10u64.pow(9);
+    write_account(&mut fixture.svm, &fixture.presale_pda, &presale_account);
+
+    let buy_ix = svm_buy_tokens_ix(&fixture, &buyer.pubkey(), 51);
+    let result = send_svm_transaction(&mut fixture.svm, &[buy_ix], &buyer, &[]);
+    assert_presale_error(result, PresaleError::HardcapReached);
+
+    let buy_ix = svm_buy_tokens_ix(&fixture, &buyer.pubkey(), 50);
+    send_svm_transaction(&mut fixture.svm, &[buy_ix], &buyer, &[]).unwrap();
+}
+
+#[test]
+fn test_vested_claims_over_time() {
+    let mut fixture = setup_active_presale_svm();
+    let buyer = fixture.buyer.insecure_clone();
+    let admin = fixture.admin.insecure_clone();
+    let buyer_account_pda = buyer_account_address(&fixture.presale_pda, &buyer.pubkey());
+
+    let configure_vesting_ix = Instruction {
+        program_id: presale::id(),
+        accounts: presale::accounts::ConfigureVesting {
+            admin: admin.pubkey(),
+            presale: fixture.presale_pda,
+            presale_wallet: fixture.presale_wallet,
+            penalty_wallet: None,
+        }
+        .to_account_metas(None),
+        data: presale::instruction::ConfigureVesting {
+            vesting_duration_days: 100,
+            early_claim_penalty_bps: 0,
+            vesting_cliff_days: 0,
+            tge_unlock_bps: 0,
+        }
+        .data(),
+    };
+    send_svm_transaction(&mut fixture.svm, &[configure_vesting_ix], &admin, &[]).unwrap();
+
+    let buy_ix = svm_buy_tokens_ix(&fixture, &buyer.pubkey(), 100);
+    send_svm_transaction(&mut fixture.svm, &[buy_ix], &buyer, &[]).unwrap();
+
+    // Finalize manually, starting vesting now
+    let clock: Clock = fixture.svm.get_sysvar();
+    let mut presale_account: presale::Presale = read_account(&fixture.svm, &fixture.presale_pda);
+    presale_account.sale_stage = 3;
+    presale_account.pool_created = true;
+    presale_account.vesting_start = clock.unix_timestamp;
+    write_account(&mut fixture.svm, &fixture.presale_pda, &presale_account);
+
+    let recipient_token_account = anchor_spl::associated_token::get_associated_token_address(
+        &buyer.pubkey(),
+        &fixture.presale_token_mint.pubkey(),
+    );
+    let claim_ix = |amount: u64| Instruction {
+        program_id: presale::id(),
+        accounts: presale::accounts::ClaimTokens {
+            buyer: buyer.pubkey(),
+            presale: fixture.presale_pda,
+            buyer_account: buyer_account_pda,
+            presale_wallet: fixture.presale_wallet,
+            token_mint: fixture.presale_token_mint.pubkey(),
+            recipient: buyer.pubkey(),
+            recipient_token_account,
+            token_program: token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+            vesting_schedule: None,
+            vesting_stats: None,
+            blacklist: None,
+        }
+        .to_account_metas(None),
+        data: presale::instruction::ClaimTokens { amount }.data(),
+    };
+
+    let result = send_svm_transaction(&mut fixture.svm, &[claim_ix(1)], &buyer, &[]);
+    assert_presale_error(result, PresaleError::NothingToClaim);
+
+    // A quarter of the way through, a quarter has vested
+    warp_seconds(&mut fixture.svm, 25 * 86400);
+    let get_allocation_ix = Instruction {
+        program_id: presale::id(),
+        accounts: presale::accounts::GetBuyerAllocation {
+            presale: fixture.presale_pda,
+            buyer_account: buyer_account_pda,
+            vesting_schedule: None,
+        }
+        .to_account_metas(None),
+        data: presale::instruction::GetBuyerAllocation {}.data(),
+    };
+    let allocation: BuyerAllocation = simulate_view(&fixture.svm, get_allocation_ix, &buyer);
+    assert_eq!(allocation.claimable, 25 * 10u64.pow(9));
+
+    send_svm_transaction(&mut fixture.svm, &[claim_ix(25 * 10u64.pow(9))], &buyer, &[]).unwrap();
+    assert_eq!(token_balance(&fixture.svm, &recipient_token_account), 25 * 10u64.pow(9));
+
+    let result = send_svm_transaction(&mut fixture.svm, &[claim_ix(1)], &buyer, &[]);
+    assert_presale_error(result, PresaleError::NothingToClaim);
+
+    // Fully vested
+    warp_seconds(&mut fixture.svm, 75 * 86400);
+    send_svm_transaction(&mut fixture.svm, &[claim_ix(75 * 10u64.pow(9))], &buyer, &[]).unwrap();
+    assert_eq!(token_balance(&fixture.svm, &recipient_token_account), 100 * 10u64.pow(9));
+}
+
+#[test]
+fn test_cancel_and_claim_refund() {
+    let mut fixture = setup_active_presale_svm();
+    let buyer = fixture.buyer.insecure_clone();
+    let admin = fixture.admin.insecure_clone();
+    let buyer_account_pda = buyer_account_address(&fixture.presale_pda, &buyer.pubkey());
+    let escrow_pda = escrow_address(&fixture.presale_pda);
+
+    let buy_ix = svm_buy_tokens_ix(&fixture, &buyer.pubkey(), 10);
+    send_svm_transaction(&mut fixture.svm, &[buy_ix], &buyer, &[]).unwrap();
+
+    let refund_ix = Instruction {
+        program_id: presale::id(),
+        accounts: presale::accounts::ClaimRefund {
+            buyer: buyer.pubkey(),
+            presale: fixture.presale_pda,
+            buyer_account: buyer_account_pda,
+            registry_page: registry_page_address(&fixture.presale_pda, 0),
+            escrow: escrow_pda,
+            escrow_usdc_account: None,
+            buyer_usdc_account: None,
+            escrow_usdt_account: None,
+            buyer_usdt_account: None,
+            escrow_pyusd_account: None,
+            buyer_pyusd_account: None,
+            pyusd_mint: None,
+            token_2022_program: None,
+            token_program: token::ID,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: presale::instruction::ClaimRefund {}.data(),
+    };
+
+    // No refunds while the sale is live
+    let result = send_svm_transaction(&mut fixture.svm, std::slice::from_ref(&refund_ix), &buyer, &[]);
+    assert_presale_error(result, PresaleError::RefundsNotAvailable);
+
+    let cancel_ix = Instruction {
+        program_id: presale::id(),
+        accounts: presale::accounts::CancelPresale {
+            admin: admin.pubkey(),
+            presale: fixture.presale_pda,
+        }
+        .to_account_metas(None),
+        data: presale::instruction::CancelPresale {}.data(),
+    };
+    send_svm_transaction(&mut fixture.svm, &[cancel_ix], &admin, &[]).unwrap();
+
+    // Fees are paid by the admin so the buyer's balance only reflects the refund
+    let balance_before = fixture.svm.get_balance(&buyer.pubkey()).unwrap();
+    send_svm_transaction(&mut fixture.svm, &[refund_ix], &admin, &[&buyer]).unwrap();
+    let balance_after = fixture.svm.get_balance(&buyer.pubkey()).unwrap();
+    assert_eq!(balance_after - balance_before, 10 * FIXTURE_SOL_PRICE_LAMPORTS);
+    assert_eq!(
+        fixture.svm.get_balance(&escrow_pda).unwrap(),
+        fixture.svm.minimum_balance_for_rent_exemption(0)
+    );
+
+    let buyer_account: BuyerAccount = read_account(&fixture.svm, &buyer_account_pda);
+    assert_eq!(buyer_account.contributed_lamports, 0);
+}